@@ -0,0 +1,33 @@
+use crate::condvar::Condvar;
+use crate::mutex::Mutex;
+
+struct BarrierState { arrived: usize, generation: u64 }
+
+/// Lets `n` threads meet: each `wait` blocks until all `n` have called it, then the barrier resets
+/// for the next round.
+pub struct Barrier { n: usize, state: Mutex<BarrierState>, cv: Condvar }
+
+/// Returned by `Barrier::wait`; exactly one thread per round is the leader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult { pub fn is_leader(&self) -> bool { self.0 } }
+
+impl Barrier {
+    pub const fn new(n: usize) -> Self { Self { n, state: Mutex::new(BarrierState { arrived: 0, generation: 0 }), cv: Condvar::new() } }
+
+    pub fn wait(&self) -> BarrierWaitResult {
+        let mut s = self.state.lock();
+        let gen = s.generation;
+        s.arrived += 1;
+        if s.arrived >= self.n {
+            s.arrived = 0;
+            s.generation = s.generation.wrapping_add(1);
+            drop(s);
+            self.cv.notify_all();
+            return BarrierWaitResult(true);
+        }
+        let _s = self.cv.wait_while(s, |s| s.generation == gen);
+        BarrierWaitResult(false)
+    }
+}
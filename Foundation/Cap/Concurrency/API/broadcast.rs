@@ -0,0 +1,115 @@
+use cap_containers::CapArc;
+use cap_memory::{Allocator, MemoryError};
+use crate::channel::{parker, Budget, LazyParker, RecvError, RecvTimeoutError, Ring, SendError, TryRecvError, WaitSet, FOREVER};
+use crate::mutex::Mutex;
+use crate::once::OnceCell;
+
+// `buf` holds the last `capacity` messages; `first` is the sequence number of `buf[0]`.
+struct Inner<'a, T> { buf: Ring<'a, T>, capacity: usize, first: u64, senders: usize, receivers: usize, waiting: WaitSet<'a> }
+
+type Chan<'a, T> = Mutex<Inner<'a, T>>;
+
+/// Sending half of a broadcast channel: every receiver sees every message sent after it subscribed.
+///
+/// Senders never block. The channel keeps the last `capacity` messages; a receiver that falls
+/// further behind skips the overwritten ones and can read how many via `missed`.
+pub struct BroadcastSender<'a, T> { chan: CapArc<'a, Chan<'a, T>> }
+
+/// Receiving half of a broadcast channel; clones start at the same position.
+pub struct BroadcastReceiver<'a, T> { chan: CapArc<'a, Chan<'a, T>>, next: u64, missed: u64, parker: LazyParker }
+
+unsafe impl<'a, T: Send + Sync> Send for BroadcastSender<'a, T> {}
+unsafe impl<'a, T: Send + Sync> Sync for BroadcastSender<'a, T> {}
+unsafe impl<'a, T: Send + Sync> Send for BroadcastReceiver<'a, T> {}
+
+pub fn broadcast_channel<'a, T: Clone>(alloc: Allocator<'a>, capacity: usize) -> Result<(BroadcastSender<'a, T>, BroadcastReceiver<'a, T>), MemoryError> {
+    if capacity == 0 { return Err(MemoryError::InvalidArgument); }
+    let inner = Inner { buf: Ring::with_capacity(alloc, capacity)?, capacity, first: 0, senders: 1, receivers: 1, waiting: WaitSet::new(alloc)? };
+    let chan = CapArc::new(alloc, Mutex::new(inner))?;
+    Ok((BroadcastSender { chan: chan.clone() }, BroadcastReceiver { chan, next: 0, missed: 0, parker: OnceCell::new() }))
+}
+
+impl<'a, T: Clone> BroadcastSender<'a, T> {
+    /// Publishes `v` to all current receivers; fails if there are none.
+    pub fn send(&self, v: T) -> Result<(), SendError<T>> {
+        let mut g = self.chan.lock();
+        if g.receivers == 0 { return Err(SendError(v)); }
+        if g.buf.len() == g.capacity { g.buf.pop(); g.first += 1; }
+        g.buf.push(v).map_err(SendError)?;
+        g.waiting.wake_all();
+        Ok(())
+    }
+
+    /// A new receiver that sees messages sent from now on.
+    pub fn subscribe(&self) -> BroadcastReceiver<'a, T> {
+        let mut g = self.chan.lock();
+        g.receivers += 1;
+        let next = g.first + g.buf.len() as u64;
+        BroadcastReceiver { chan: self.chan.clone(), next, missed: 0, parker: OnceCell::new() }
+    }
+
+    pub fn receiver_count(&self) -> usize { self.chan.lock().receivers }
+}
+
+impl<'a, T> Clone for BroadcastSender<'a, T> {
+    fn clone(&self) -> Self { self.chan.lock().senders += 1; Self { chan: self.chan.clone() } }
+}
+
+impl<'a, T> Drop for BroadcastSender<'a, T> {
+    fn drop(&mut self) {
+        let mut g = self.chan.lock();
+        g.senders -= 1;
+        if g.senders == 0 { g.waiting.wake_all(); }
+    }
+}
+
+// Next message at `next`, skipping (and counting in `missed`) any that were already overwritten.
+fn take<T: Clone>(g: &mut Inner<'_, T>, next: &mut u64, missed: &mut u64) -> Option<T> {
+    if *next < g.first { *missed += g.first - *next; *next = g.first; }
+    let v = g.buf.get((*next - g.first) as usize)?.clone();
+    *next += 1;
+    Some(v)
+}
+
+impl<'a, T: Clone> BroadcastReceiver<'a, T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut g = self.chan.lock();
+        match take(&mut g, &mut self.next, &mut self.missed) {
+            Some(v) => Ok(v),
+            None if g.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn recv(&mut self) -> Result<T, RecvError> { self.recv_timeout(FOREVER).map_err(|_| RecvError) }
+
+    pub fn recv_timeout(&mut self, timeout_ms: u32) -> Result<T, RecvTimeoutError> {
+        let p = parker(&self.parker);
+        let mut budget = Budget::new(timeout_ms);
+        loop {
+            let mut g = self.chan.lock();
+            if let Some(p) = p { g.waiting.remove(p); }
+            match take(&mut g, &mut self.next, &mut self.missed) {
+                Some(v) => return Ok(v),
+                None if g.senders == 0 => return Err(RecvTimeoutError::Disconnected),
+                None if budget.expired() => return Err(RecvTimeoutError::Timeout),
+                None => {}
+            }
+            let reg = p.filter(|p| g.waiting.add(p));
+            drop(g);
+            budget.sleep(reg);
+        }
+    }
+
+    /// Messages overwritten before this receiver got to them, in total.
+    pub fn missed(&self) -> u64 { self.missed }
+}
+
+impl<'a, T> Clone for BroadcastReceiver<'a, T> {
+    fn clone(&self) -> Self {
+        self.chan.lock().receivers += 1;
+        Self { chan: self.chan.clone(), next: self.next, missed: 0, parker: OnceCell::new() }
+    }
+}
+
+impl<'a, T> Drop for BroadcastReceiver<'a, T> { fn drop(&mut self) { self.chan.lock().receivers -= 1; } }
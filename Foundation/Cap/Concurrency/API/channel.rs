@@ -0,0 +1,270 @@
+use core::mem::{align_of, size_of};
+use cap_containers::{CapArc, Vector};
+use cap_memory::{Allocator, MemoryBlock, MemoryError};
+use crate::mutex::Mutex;
+use crate::once::OnceCell;
+use crate::parker::Parker;
+
+/// The receiving side is gone; the unsent value is handed back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrySendError<T> { Full(T), Disconnected(T) }
+
+/// Every sender is gone and nothing is left to receive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryRecvError { Empty, Disconnected }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvTimeoutError { Timeout, Disconnected }
+
+pub(crate) const FOREVER: u32 = u32::MAX;
+
+// An endpoint's parker, created the first time it blocks. Platforms without events get `None` and
+// poll instead.
+pub(crate) type LazyParker = OnceCell<Option<Parker>>;
+
+pub(crate) fn parker(p: &LazyParker) -> Option<&Parker> { p.get_or_init(|| Parker::new().ok()).as_ref() }
+
+// Time left for a blocking call. `FOREVER` is never used up.
+pub(crate) struct Budget { left: u32 }
+
+impl Budget {
+    pub(crate) fn new(timeout_ms: u32) -> Self { Self { left: timeout_ms } }
+    pub(crate) fn expired(&self) -> bool { self.left == 0 }
+
+    // Sleeps until unparked or the budget runs out. Without a parker a timed wait sleeps in 1 ms
+    // steps and an untimed one just yields.
+    pub(crate) fn sleep(&mut self, p: Option<&Parker>) {
+        let forever = self.left == FOREVER;
+        match p {
+            Some(p) => {
+                let t0 = prm_time::now();
+                let timed_out = p.park(self.left).is_err();
+                if forever { return; }
+                let spent = ((prm_time::now() - t0).max(0) / 1_000_000).min(u32::MAX as i64) as u32;
+                self.left = if timed_out { 0 } else { self.left - spent.min(self.left) };
+            }
+            None if forever => prm_threading::thread_yield(),
+            None => { prm_threading::thread_sleep_ms(1); self.left -= 1; }
+        }
+    }
+}
+
+// Parkers of endpoints blocked on a channel, kept under the channel's lock. Whoever pops an entry
+// unparks it while still holding the lock, so the owner (which re-takes the lock before returning)
+// cannot free it in between.
+pub(crate) struct WaitSet<'a> { list: Vector<'a, *const Parker> }
+
+impl<'a> WaitSet<'a> {
+    pub(crate) fn new(alloc: Allocator<'a>) -> Result<Self, MemoryError> { Ok(Self { list: Vector::with_capacity(alloc, 4)? }) }
+    // False if the waiter could not be recorded; it should poll instead of parking.
+    pub(crate) fn add(&mut self, p: &Parker) -> bool { self.list.push(p as *const Parker).is_ok() }
+    pub(crate) fn remove(&mut self, p: &Parker) {
+        if let Some(i) = self.list.as_slice().iter().position(|&q| core::ptr::eq(q, p)) { self.list.swap_remove(i); }
+    }
+    pub(crate) fn wake_one(&mut self) { if let Some(p) = self.list.pop() { let _ = unsafe { (*p).unpark() }; } }
+    pub(crate) fn wake_all(&mut self) { while let Some(p) = self.list.pop() { let _ = unsafe { (*p).unpark() }; } }
+}
+
+// Growable ring of owned values.
+pub(crate) struct Ring<'a, T> { ptr: *mut T, cap: usize, head: usize, len: usize, blk: MemoryBlock, alloc: Allocator<'a> }
+
+impl<'a, T> Ring<'a, T> {
+    pub(crate) fn with_capacity(alloc: Allocator<'a>, capacity: usize) -> Result<Self, MemoryError> {
+        let cap = capacity.max(1).checked_next_power_of_two().ok_or(MemoryError::InvalidArgument)?;
+        let blk = Self::alloc_slots(alloc, cap)?;
+        Ok(Self { ptr: blk.ptr.cast::<T>(), cap, head: 0, len: 0, blk, alloc })
+    }
+    fn alloc_slots(alloc: Allocator<'a>, cap: usize) -> Result<MemoryBlock, MemoryError> {
+        let bytes = cap.checked_mul(size_of::<T>()).ok_or(MemoryError::Failed)?;
+        alloc.alloc(bytes.max(1), align_of::<T>())
+    }
+    pub(crate) fn len(&self) -> usize { self.len }
+    pub(crate) fn get(&self, i: usize) -> Option<&T> { if i < self.len { Some(unsafe { &*self.ptr.add((self.head + i) & (self.cap - 1)) }) } else { None } }
+
+    /// Appends `v`, doubling the storage when full; hands `v` back if that allocation fails.
+    pub(crate) fn push(&mut self, v: T) -> Result<(), T> {
+        if self.len == self.cap && self.grow().is_err() { return Err(v); }
+        unsafe { self.ptr.add((self.head + self.len) & (self.cap - 1)).write(v); }
+        self.len += 1;
+        Ok(())
+    }
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        if self.len == 0 { return None; }
+        let v = unsafe { self.ptr.add(self.head).read() };
+        self.head = (self.head + 1) & (self.cap - 1);
+        self.len -= 1;
+        Some(v)
+    }
+    fn grow(&mut self) -> Result<(), MemoryError> {
+        let cap = self.cap.checked_mul(2).ok_or(MemoryError::OutOfMemory)?;
+        let blk = Self::alloc_slots(self.alloc, cap)?;
+        let dst = blk.ptr.cast::<T>();
+        let first = (self.cap - self.head).min(self.len);
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.ptr.add(self.head), dst, first);
+            core::ptr::copy_nonoverlapping(self.ptr, dst.add(first), self.len - first);
+        }
+        self.alloc.free(self.blk, align_of::<T>());
+        self.ptr = dst;
+        self.cap = cap;
+        self.head = 0;
+        self.blk = blk;
+        Ok(())
+    }
+}
+
+impl<'a, T> Drop for Ring<'a, T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+        if !self.blk.is_empty() { self.alloc.free(self.blk, align_of::<T>()); }
+    }
+}
+
+struct Inner<'a, T> { buf: Ring<'a, T>, bound: usize, senders: usize, receiver: bool, rx_waiting: *const Parker, tx_waiting: WaitSet<'a> }
+
+struct Chan<'a, T> { inner: Mutex<Inner<'a, T>> }
+
+/// Sending half of a multi-producer, single-consumer channel; clone it for more producers.
+pub struct Sender<'a, T> { chan: CapArc<'a, Chan<'a, T>>, parker: LazyParker }
+
+/// Receiving half of a multi-producer, single-consumer channel.
+pub struct Receiver<'a, T> { chan: CapArc<'a, Chan<'a, T>>, parker: LazyParker }
+
+unsafe impl<'a, T: Send> Send for Sender<'a, T> {}
+unsafe impl<'a, T: Send> Send for Receiver<'a, T> {}
+
+/// A channel holding at most `capacity` messages; `send` blocks while it is full.
+pub fn channel<'a, T>(alloc: Allocator<'a>, capacity: usize) -> Result<(Sender<'a, T>, Receiver<'a, T>), MemoryError> {
+    if capacity == 0 { return Err(MemoryError::InvalidArgument); }
+    new_chan(alloc, capacity, capacity)
+}
+
+/// A channel whose buffer grows on demand. `send` only blocks if growing fails, until the receiver
+/// makes room.
+pub fn unbounded_channel<'a, T>(alloc: Allocator<'a>) -> Result<(Sender<'a, T>, Receiver<'a, T>), MemoryError> { new_chan(alloc, 16, usize::MAX) }
+
+fn new_chan<'a, T>(alloc: Allocator<'a>, initial: usize, bound: usize) -> Result<(Sender<'a, T>, Receiver<'a, T>), MemoryError> {
+    let inner = Inner { buf: Ring::with_capacity(alloc, initial)?, bound, senders: 1, receiver: true, rx_waiting: core::ptr::null(), tx_waiting: WaitSet::new(alloc)? };
+    let chan = CapArc::new(alloc, Chan { inner: Mutex::new(inner) })?;
+    Ok((Sender { chan: chan.clone(), parker: OnceCell::new() }, Receiver { chan, parker: OnceCell::new() }))
+}
+
+impl<'a, T> Inner<'a, T> {
+    fn wake_receiver(&mut self) {
+        if !self.rx_waiting.is_null() { let _ = unsafe { (*self.rx_waiting).unpark() }; self.rx_waiting = core::ptr::null(); }
+    }
+    fn try_push(&mut self, v: T) -> Result<(), TrySendError<T>> {
+        if !self.receiver { return Err(TrySendError::Disconnected(v)); }
+        if self.buf.len() >= self.bound { return Err(TrySendError::Full(v)); }
+        self.buf.push(v).map_err(TrySendError::Full)?;
+        self.wake_receiver();
+        Ok(())
+    }
+}
+
+impl<'a, T> Sender<'a, T> {
+    pub fn try_send(&self, v: T) -> Result<(), TrySendError<T>> { self.chan.inner.lock().try_push(v) }
+
+    /// Queues `v`, waiting for room if the channel is full. Fails only once the receiver is dropped.
+    pub fn send(&self, mut v: T) -> Result<(), SendError<T>> {
+        let p = parker(&self.parker);
+        let mut budget = Budget::new(FOREVER);
+        loop {
+            {
+                let mut g = self.chan.inner.lock();
+                if let Some(p) = p { g.tx_waiting.remove(p); }
+                match g.try_push(v) {
+                    Ok(()) => {
+                        // Pass the turn on while there is room, in case several senders woke at once.
+                        if g.buf.len() < g.bound { g.tx_waiting.wake_one(); }
+                        return Ok(());
+                    }
+                    Err(TrySendError::Disconnected(x)) => return Err(SendError(x)),
+                    Err(TrySendError::Full(x)) => v = x,
+                }
+                let reg = p.filter(|p| g.tx_waiting.add(p));
+                drop(g);
+                budget.sleep(reg);
+            }
+        }
+    }
+
+    pub fn is_disconnected(&self) -> bool { !self.chan.inner.lock().receiver }
+}
+
+impl<'a, T> Clone for Sender<'a, T> {
+    fn clone(&self) -> Self {
+        self.chan.inner.lock().senders += 1;
+        Self { chan: self.chan.clone(), parker: OnceCell::new() }
+    }
+}
+
+impl<'a, T> Drop for Sender<'a, T> {
+    fn drop(&mut self) {
+        let mut g = self.chan.inner.lock();
+        g.senders -= 1;
+        if g.senders == 0 { g.wake_receiver(); }
+    }
+}
+
+impl<'a, T> Receiver<'a, T> {
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut g = self.chan.inner.lock();
+        match g.buf.pop() {
+            Some(v) => { g.tx_waiting.wake_one(); Ok(v) }
+            None if g.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Waits for the next message. Messages sent before the last sender dropped are still delivered.
+    pub fn recv(&self) -> Result<T, RecvError> { self.recv_timeout(FOREVER).map_err(|_| RecvError) }
+
+    pub fn recv_timeout(&self, timeout_ms: u32) -> Result<T, RecvTimeoutError> {
+        let p = parker(&self.parker);
+        let mut budget = Budget::new(timeout_ms);
+        loop {
+            {
+                let mut g = self.chan.inner.lock();
+                g.rx_waiting = core::ptr::null();
+                match g.buf.pop() {
+                    Some(v) => { g.tx_waiting.wake_one(); return Ok(v); }
+                    None if g.senders == 0 => return Err(RecvTimeoutError::Disconnected),
+                    None if budget.expired() => return Err(RecvTimeoutError::Timeout),
+                    None => {}
+                }
+                if let Some(p) = p { g.rx_waiting = p; }
+            }
+            budget.sleep(p);
+        }
+    }
+
+    pub fn len(&self) -> usize { self.chan.inner.lock().buf.len() }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+    pub fn is_disconnected(&self) -> bool { self.chan.inner.lock().senders == 0 }
+
+    /// Iterates until every sender is gone.
+    pub fn iter(&self) -> Iter<'_, 'a, T> { Iter { rx: self } }
+}
+
+/// Blocking iterator over a `Receiver`; ends when every sender is gone.
+pub struct Iter<'r, 'a, T> { rx: &'r Receiver<'a, T> }
+
+impl<'r, 'a, T> Iterator for Iter<'r, 'a, T> { type Item = T; fn next(&mut self) -> Option<T> { self.rx.recv().ok() } }
+
+impl<'a, T> Drop for Receiver<'a, T> {
+    fn drop(&mut self) {
+        let mut g = self.chan.inner.lock();
+        g.receiver = false;
+        g.rx_waiting = core::ptr::null();
+        g.tx_waiting.wake_all();
+        // Undeliverable now; release them rather than waiting for the last sender.
+        while g.buf.pop().is_some() {}
+    }
+}
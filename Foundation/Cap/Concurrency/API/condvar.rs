@@ -0,0 +1,37 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::futex;
+use crate::mutex::MutexGuard;
+
+/// Condition variable for `Mutex<T>`: a sequence word that notifications bump and waiters sleep on.
+///
+/// Wakeups may be spurious; wait in a loop or use `wait_while`.
+pub struct Condvar { seq: AtomicU32 }
+
+impl Condvar {
+    pub const fn new() -> Self { Self { seq: AtomicU32::new(0) } }
+
+    /// Unlocks the guard's mutex, sleeps until notified, and locks it again.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> { self.wait_timeout_ms(guard, futex::FOREVER).0 }
+
+    /// `wait` for at most `timeout_ms`; the flag is true when no notification arrived in time.
+    pub fn wait_timeout_ms<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>, timeout_ms: u32) -> (MutexGuard<'a, T>, bool) {
+        // Read the sequence before unlocking so a notify between unlock and sleep is not lost.
+        let seq = self.seq.load(Ordering::Relaxed);
+        let m = guard.mutex();
+        drop(guard);
+        futex::wait(&self.seq, seq, timeout_ms);
+        let timed_out = self.seq.load(Ordering::Relaxed) == seq;
+        (m.lock(), timed_out)
+    }
+
+    /// Waits until `cond` returns false.
+    pub fn wait_while<'a, T: ?Sized>(&self, mut guard: MutexGuard<'a, T>, mut cond: impl FnMut(&mut T) -> bool) -> MutexGuard<'a, T> {
+        while cond(&mut *guard) { guard = self.wait(guard); }
+        guard
+    }
+
+    pub fn notify_one(&self) { self.seq.fetch_add(1, Ordering::Release); futex::wake_one(&self.seq); }
+    pub fn notify_all(&self) { self.seq.fetch_add(1, Ordering::Release); futex::wake_all(&self.seq); }
+}
+
+impl Default for Condvar { fn default() -> Self { Self::new() } }
@@ -1,6 +1,125 @@
+use core::cell::Cell;
+use core::ffi::c_void;
+use core::mem::MaybeUninit;
+use cap_memory::{Allocator, MemoryError};
+use cap_containers::{CapBox, Vector};
+use prm_threading::{FiberHandle, ThreadFunc, ThreadingError};
+use crate::fiber::Fiber;
+
 pub struct FiberStackPool { pub default_stack_size: usize }
 
 impl FiberStackPool {
     pub fn new(default_stack_size: usize) -> Self { Self { default_stack_size } }
     pub fn stack_size(&self) -> usize { self.default_stack_size }
 }
+
+type JobSlot = Option<(fn(*mut c_void), *mut c_void)>;
+
+/// Fiber whose stack is reused job after job.
+///
+/// Its entry never returns: it runs the job handed over by `run`, then switches back to the
+/// context that started or last resumed it and waits for the next one.
+#[repr(C)]
+pub struct PooledFiber<'a> {
+    // First field, so `as_fiber` / `from_fiber` are plain casts.
+    pub fiber: Fiber,
+    job: Cell<JobSlot>,
+    done: Cell<bool>,
+    ret: Cell<FiberHandle>,
+    alloc: Allocator<'a>,
+}
+
+fn pooled_entry(arg: *mut c_void) {
+    let pf = arg as *const PooledFiber;
+    loop {
+        unsafe {
+            if let Some((func, a)) = (*pf).job.take() { func(a); }
+            (*pf).done.set(true);
+            prm_threading::switch_to_fiber((*pf).ret.get());
+        }
+    }
+}
+
+impl<'a> PooledFiber<'a> {
+    /// Runs `func(arg)` on this fiber until it finishes or suspends, then comes back to `ret`.
+    /// Returns true if the job finished; false means it suspended and still owns the fiber.
+    pub fn run(&self, func: fn(*mut c_void), arg: *mut c_void, ret: FiberHandle) -> bool {
+        self.job.set(Some((func, arg)));
+        self.done.set(false);
+        self.resume(ret)
+    }
+
+    /// Continues a suspended job; same contract as `run`.
+    pub fn resume(&self, ret: FiberHandle) -> bool {
+        self.ret.set(ret);
+        self.fiber.start_switch();
+        self.done.get()
+    }
+
+    /// The context a suspending job should switch to.
+    pub fn return_to(&self) -> FiberHandle { self.ret.get() }
+    pub fn is_done(&self) -> bool { self.done.get() }
+    pub fn as_fiber(&self) -> *mut Fiber { &self.fiber as *const Fiber as *mut Fiber }
+
+    /// # Safety
+    /// `f` must come from `as_fiber` on a pooled fiber that is still alive for `'x`.
+    pub unsafe fn from_fiber<'x>(f: *mut Fiber) -> &'x PooledFiber<'a> { &*(f as *const PooledFiber<'a>) }
+
+    /// Takes back ownership of a fiber leaked with `CapBox::into_raw` (e.g. while its job was suspended).
+    ///
+    /// # Safety
+    /// `p` must come from `CapBox::into_raw` on a box handed out by `FiberPool::acquire`, and not be reclaimed twice.
+    pub unsafe fn reclaim(p: *mut PooledFiber<'a>) -> CapBox<'a, PooledFiber<'a>> { CapBox::from_raw((*p).alloc, p) }
+}
+
+/// Per-thread cache of idle `PooledFiber`s.
+///
+/// `acquire` reuses an idle fiber (creating one only when the cache is empty); `release` keeps up
+/// to `max_idle` of them and destroys the rest. Not thread-safe: each worker owns its own pool.
+pub struct FiberPool<'a> { idle: Vector<'a, CapBox<'a, PooledFiber<'a>>>, alloc: Allocator<'a>, stack_size: usize, max_idle: usize, created: usize }
+
+impl<'a> FiberPool<'a> {
+    pub fn new(alloc: Allocator<'a>, stack_size: usize, max_idle: usize) -> Result<Self, MemoryError> {
+        Ok(Self { idle: Vector::with_capacity(alloc, max_idle)?, alloc, stack_size, max_idle, created: 0 })
+    }
+
+    pub fn stack_size(&self) -> usize { self.stack_size }
+    pub fn idle_count(&self) -> usize { self.idle.len() }
+    /// Fibers created over the pool's lifetime; stays flat once the working set is warm.
+    pub fn created_count(&self) -> usize { self.created }
+
+    pub fn acquire(&mut self) -> Result<CapBox<'a, PooledFiber<'a>>, ThreadingError> {
+        match self.idle.pop() { Some(f) => Ok(f), None => self.create() }
+    }
+
+    fn create(&mut self) -> Result<CapBox<'a, PooledFiber<'a>>, ThreadingError> {
+        // The entry needs the fiber's final address, so allocate first and fill in once the OS fiber exists.
+        let slot = CapBox::new(self.alloc, MaybeUninit::<PooledFiber<'a>>::uninit()).map_err(|_| ThreadingError::Failed)?;
+        let h = prm_threading::create_fiber(self.stack_size, pooled_entry as ThreadFunc, slot.as_ptr() as *mut c_void)?;
+        let (p, alloc) = CapBox::into_raw(slot);
+        let host = prm_threading::host_fiber();
+        unsafe {
+            p.write(MaybeUninit::new(PooledFiber {
+                fiber: Fiber { handle: h, owner: core::ptr::null_mut(), prio: 0, ret: host },
+                job: Cell::new(None), done: Cell::new(true), ret: Cell::new(host), alloc,
+            }));
+            self.created += 1;
+            Ok(CapBox::from_raw(alloc, p.cast::<PooledFiber<'a>>()))
+        }
+    }
+
+    /// Hands back a fiber whose job has finished.
+    pub fn release(&mut self, f: CapBox<'a, PooledFiber<'a>>) {
+        debug_assert!(f.is_done());
+        if self.idle.len() < self.max_idle { let _ = self.idle.push(f); }
+    }
+
+    /// Creates fibers up front so the first `n` jobs do not pay for fiber creation.
+    pub fn prewarm(&mut self, n: usize) -> Result<(), ThreadingError> {
+        while self.idle.len() < n.min(self.max_idle) {
+            let f = self.create()?;
+            self.idle.push(f).map_err(|_| ThreadingError::Failed)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+use core::sync::atomic::AtomicU32;
+
+pub(crate) const FOREVER: u32 = u32::MAX;
+
+// Sleeps while `a` still holds `expected`. May return early (timeout, spurious wake, or a platform
+// without address waits, where it just yields), so callers always re-check their condition.
+pub(crate) fn wait(a: &AtomicU32, expected: u32, timeout_ms: u32) {
+    let p = a as *const AtomicU32 as *const u8;
+    if prm_threading::wait_on_address(p, &expected as *const u32 as *const u8, 4, timeout_ms).is_err() { prm_threading::thread_yield(); }
+}
+
+pub(crate) fn wake_one(a: &AtomicU32) { prm_threading::wake_by_address_single(a as *const AtomicU32 as *mut u8) }
+pub(crate) fn wake_all(a: &AtomicU32) { prm_threading::wake_by_address_all(a as *const AtomicU32 as *mut u8) }
@@ -2,6 +2,14 @@
 pub mod counter;
 pub mod parker;
 pub mod mutex;
+pub mod rwlock;
+pub mod condvar;
+pub mod barrier;
+pub mod once;
+pub mod channel;
+pub mod spsc;
+pub mod broadcast;
+mod futex;
 pub mod event;
 pub mod fiber;
 pub mod fiber_pool;
@@ -9,6 +17,13 @@ pub mod fiber_pool;
 pub use counter::*;
 pub use parker::*;
 pub use mutex::*;
+pub use rwlock::*;
+pub use condvar::*;
+pub use barrier::*;
+pub use once::*;
+pub use channel::*;
+pub use spsc::*;
+pub use broadcast::*;
 pub use event::*;
 pub use fiber::*;
 pub use fiber_pool::*;
@@ -1,8 +1,13 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
 use prm_threading::{MutexHandle, ThreadingError};
+use crate::futex;
 
-pub struct Mutex { h: MutexHandle }
+/// Handle to a platform mutex, for APIs that need the OS object itself.
+pub struct OsMutex { h: MutexHandle }
 
-impl Mutex {
+impl OsMutex {
     pub fn new() -> Result<Self, ThreadingError> { Ok(Self { h: prm_threading::mutex_create()? }) }
     pub fn lock(&self) -> Result<(), ThreadingError> { prm_threading::mutex_lock(self.h) }
     pub fn unlock(&self) -> Result<(), ThreadingError> { prm_threading::mutex_unlock(self.h) }
@@ -10,4 +15,58 @@ impl Mutex {
     pub fn handle(&self) -> MutexHandle { self.h }
 }
 
-impl Drop for Mutex { fn drop(&mut self) { let _ = prm_threading::mutex_destroy(self.h); } }
+impl Drop for OsMutex { fn drop(&mut self) { let _ = prm_threading::mutex_destroy(self.h); } }
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+// Locked, and someone may be asleep on the word: unlock has to wake.
+const CONTENDED: u32 = 2;
+
+/// Mutual exclusion lock owning its data, parked on the lock word with `wait_on_address`.
+///
+/// Not poisoned by panics; a guard dropped during unwinding simply unlocks.
+pub struct Mutex<T: ?Sized> { state: AtomicU32, data: UnsafeCell<T> }
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(v: T) -> Self { Self { state: AtomicU32::new(UNLOCKED), data: UnsafeCell::new(v) } }
+    pub fn into_inner(self) -> T { self.data.into_inner() }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed).is_err() { self.lock_slow(); }
+        MutexGuard { m: self }
+    }
+    fn lock_slow(&self) {
+        for _ in 0..64 {
+            if self.state.load(Ordering::Relaxed) == UNLOCKED && self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed).is_ok() { return; }
+            core::hint::spin_loop();
+        }
+        // Taking it as CONTENDED is conservative: the next unlock may wake nobody.
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED { futex::wait(&self.state, CONTENDED, futex::FOREVER); }
+    }
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed).ok().map(|_| MutexGuard { m: self })
+    }
+    pub fn is_locked(&self) -> bool { self.state.load(Ordering::Relaxed) != UNLOCKED }
+    pub fn get_mut(&mut self) -> &mut T { self.data.get_mut() }
+    fn unlock(&self) { if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED { futex::wake_one(&self.state); } }
+}
+
+impl<T: Default> Default for Mutex<T> { fn default() -> Self { Self::new(T::default()) } }
+
+/// Access to a `Mutex`'s data; unlocks on drop.
+pub struct MutexGuard<'a, T: ?Sized> { m: &'a Mutex<T> }
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    pub(crate) fn mutex(&self) -> &'a Mutex<T> { self.m }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> { type Target = T; fn deref(&self) -> &T { unsafe { &*self.m.data.get() } } }
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> { fn deref_mut(&mut self) -> &mut T { unsafe { &mut *self.m.data.get() } } }
+impl<T: ?Sized> Drop for MutexGuard<'_, T> { fn drop(&mut self) { self.m.unlock(); } }
@@ -0,0 +1,106 @@
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::futex;
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+// RUNNING, and at least one thread is asleep waiting for it.
+const WAITING: u32 = 2;
+const COMPLETE: u32 = 3;
+
+/// Runs an initializer exactly once; concurrent callers sleep until it has finished.
+///
+/// If the initializer panics, the `Once` goes back to incomplete and the next caller retries.
+pub struct Once { state: AtomicU32 }
+
+// Resets the state if the initializer unwinds.
+struct Reset<'a> { once: &'a Once, set: u32 }
+impl Drop for Reset<'_> {
+    fn drop(&mut self) {
+        if self.once.state.swap(self.set, Ordering::Release) == WAITING { futex::wake_all(&self.once.state); }
+    }
+}
+
+impl Once {
+    pub const fn new() -> Self { Self { state: AtomicU32::new(INCOMPLETE) } }
+    pub fn is_completed(&self) -> bool { self.state.load(Ordering::Acquire) == COMPLETE }
+
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.is_completed() { return; }
+        let mut f = Some(f);
+        self.call_slow(&mut || (f.take().unwrap())());
+    }
+
+    fn call_slow(&self, f: &mut dyn FnMut()) {
+        loop {
+            match self.state.compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => {
+                    let mut reset = Reset { once: self, set: INCOMPLETE };
+                    f();
+                    reset.set = COMPLETE;
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(RUNNING) => { let _ = self.state.compare_exchange(RUNNING, WAITING, Ordering::Acquire, Ordering::Acquire); }
+                Err(_) => {}
+            }
+            if self.state.load(Ordering::Acquire) == WAITING { futex::wait(&self.state, WAITING, futex::FOREVER); }
+        }
+    }
+}
+
+impl Default for Once { fn default() -> Self { Self::new() } }
+
+/// A value written at most once, readable without locking afterwards.
+pub struct OnceCell<T> { once: Once, value: UnsafeCell<MaybeUninit<T>> }
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self { Self { once: Once::new(), value: UnsafeCell::new(MaybeUninit::uninit()) } }
+
+    pub fn get(&self) -> Option<&T> { if self.once.is_completed() { Some(unsafe { (*self.value.get()).assume_init_ref() }) } else { None } }
+
+    /// Stores `v` unless the cell is already set, in which case `v` comes back.
+    pub fn set(&self, v: T) -> Result<(), T> {
+        let mut v = Some(v);
+        self.once.call_once(|| unsafe { (*self.value.get()).write(v.take().unwrap()); });
+        match v { None => Ok(()), Some(v) => Err(v) }
+    }
+
+    /// The stored value, running `f` to produce it if the cell is empty. Other callers wait for `f`.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        self.once.call_once(|| unsafe { (*self.value.get()).write(f()); });
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    pub fn into_inner(mut self) -> Option<T> { self.take() }
+    pub fn take(&mut self) -> Option<T> {
+        if !self.once.is_completed() { return None; }
+        self.once = Once::new();
+        Some(unsafe { (*self.value.get()).assume_init_read() })
+    }
+}
+
+impl<T> Default for OnceCell<T> { fn default() -> Self { Self::new() } }
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) { if self.once.is_completed() { unsafe { (*self.value.get()).assume_init_drop(); } } }
+}
+
+/// A value computed by `init` on first access, e.g. a `static` table.
+pub struct Lazy<T, F = fn() -> T> { cell: OnceCell<T>, init: Cell<Option<F>> }
+
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    pub const fn new(init: F) -> Self { Self { cell: OnceCell::new(), init: Cell::new(Some(init)) } }
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.take() { Some(f) => f(), None => panic!("Lazy instance previously poisoned") })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> { type Target = T; fn deref(&self) -> &T { Lazy::force(self) } }
@@ -2,6 +2,10 @@ use prm_threading::{EventHandle, ThreadingError};
 
 pub struct Parker { h: EventHandle }
 
+// An auto-reset event: the kernel object may be signalled and waited on from any thread.
+unsafe impl Send for Parker {}
+unsafe impl Sync for Parker {}
+
 impl Parker {
     pub fn new() -> Result<Self, ThreadingError> { Ok(Self { h: prm_threading::event_create(false, false)? }) }
     pub fn park(&self, timeout_ms: u32) -> Result<(), ThreadingError> { prm_threading::event_wait(self.h, timeout_ms) }
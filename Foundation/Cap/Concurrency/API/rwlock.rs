@@ -0,0 +1,85 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::futex;
+
+const READERS: u32 = (1 << 29) - 1;
+const WRITE_LOCKED: u32 = 1 << 29;
+const READER_WAITING: u32 = 1 << 30;
+// A writer is asleep: new readers queue behind it so a steady stream of readers cannot starve it.
+const WRITER_WAITING: u32 = 1 << 31;
+
+/// Reader-writer lock owning its data, with writer preference. All state lives in one word that
+/// waiters sleep on with `wait_on_address`.
+pub struct RwLock<T: ?Sized> { state: AtomicU32, data: UnsafeCell<T> }
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(v: T) -> Self { Self { state: AtomicU32::new(0), data: UnsafeCell::new(v) } }
+    pub fn into_inner(self) -> T { self.data.into_inner() }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        loop {
+            if let Some(g) = self.try_read() { return g; }
+            let s = self.state.load(Ordering::Relaxed);
+            if s & (WRITE_LOCKED | WRITER_WAITING) == 0 { continue; }
+            if s & READER_WAITING == 0 && self.state.compare_exchange_weak(s, s | READER_WAITING, Ordering::Relaxed, Ordering::Relaxed).is_err() { continue; }
+            futex::wait(&self.state, s | READER_WAITING, futex::FOREVER);
+        }
+    }
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s & (WRITE_LOCKED | WRITER_WAITING) == 0 {
+            assert!(s & READERS != READERS, "RwLock reader count overflow");
+            match self.state.compare_exchange_weak(s, s + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(RwLockReadGuard { l: self }),
+                Err(cur) => s = cur,
+            }
+        }
+        None
+    }
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        loop {
+            let s = self.state.load(Ordering::Relaxed);
+            if s & (READERS | WRITE_LOCKED) == 0 {
+                // The waiting flags stay set: they may belong to others, and unlock clears them all anyway.
+                if self.state.compare_exchange_weak(s, s | WRITE_LOCKED, Ordering::Acquire, Ordering::Relaxed).is_ok() { return RwLockWriteGuard { l: self }; }
+                continue;
+            }
+            if s & WRITER_WAITING == 0 && self.state.compare_exchange_weak(s, s | WRITER_WAITING, Ordering::Relaxed, Ordering::Relaxed).is_err() { continue; }
+            futex::wait(&self.state, s | WRITER_WAITING, futex::FOREVER);
+        }
+    }
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let s = self.state.load(Ordering::Relaxed);
+        if s & (READERS | WRITE_LOCKED) != 0 { return None; }
+        self.state.compare_exchange(s, s | WRITE_LOCKED, Ordering::Acquire, Ordering::Relaxed).ok().map(|_| RwLockWriteGuard { l: self })
+    }
+    pub fn get_mut(&mut self) -> &mut T { self.data.get_mut() }
+    fn read_unlock(&self) {
+        let s = self.state.fetch_sub(1, Ordering::Release) - 1;
+        if s & READERS == 0 && s & WRITER_WAITING != 0 { futex::wake_all(&self.state); }
+    }
+    fn write_unlock(&self) {
+        // Readers and writers share the word, so every sleeper is woken and races for it again.
+        if self.state.swap(0, Ordering::Release) & (READER_WAITING | WRITER_WAITING) != 0 { futex::wake_all(&self.state); }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> { fn default() -> Self { Self::new(T::default()) } }
+
+pub struct RwLockReadGuard<'a, T: ?Sized> { l: &'a RwLock<T> }
+pub struct RwLockWriteGuard<'a, T: ?Sized> { l: &'a RwLock<T> }
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> { type Target = T; fn deref(&self) -> &T { unsafe { &*self.l.data.get() } } }
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> { fn drop(&mut self) { self.l.read_unlock(); } }
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> { type Target = T; fn deref(&self) -> &T { unsafe { &*self.l.data.get() } } }
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> { fn deref_mut(&mut self) -> &mut T { unsafe { &mut *self.l.data.get() } } }
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> { fn drop(&mut self) { self.l.write_unlock(); } }
@@ -0,0 +1,143 @@
+use core::cell::UnsafeCell;
+use core::mem::{align_of, size_of, MaybeUninit};
+use core::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use cap_containers::CapArc;
+use cap_memory::{Allocator, MemoryBlock, MemoryError};
+use crate::channel::{parker, Budget, LazyParker, RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError, FOREVER};
+use crate::once::OnceCell;
+
+// Lock-free ring: only the sender moves `tail`, only the receiver moves `head`. A side about to
+// sleep raises its `*_sleeping` flag and re-checks; the other side publishes, fences, then looks at
+// the flag, so one of them always sees the other.
+struct Chan<'a, T> {
+    slots: *mut UnsafeCell<MaybeUninit<T>>,
+    mask: usize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    tx_alive: AtomicBool,
+    rx_alive: AtomicBool,
+    tx_sleeping: AtomicBool,
+    rx_sleeping: AtomicBool,
+    tx_parker: LazyParker,
+    rx_parker: LazyParker,
+    blk: MemoryBlock,
+    alloc: Allocator<'a>,
+}
+
+/// Sending half of a single-producer, single-consumer ring channel.
+pub struct SpscSender<'a, T> { chan: CapArc<'a, Chan<'a, T>> }
+
+/// Receiving half of a single-producer, single-consumer ring channel.
+pub struct SpscReceiver<'a, T> { chan: CapArc<'a, Chan<'a, T>> }
+
+unsafe impl<'a, T: Send> Send for SpscSender<'a, T> {}
+unsafe impl<'a, T: Send> Send for SpscReceiver<'a, T> {}
+
+/// A lock-free channel for exactly one producer and one consumer, e.g. feeding an audio thread.
+/// `capacity` is rounded up to a power of two.
+pub fn spsc_channel<'a, T>(alloc: Allocator<'a>, capacity: usize) -> Result<(SpscSender<'a, T>, SpscReceiver<'a, T>), MemoryError> {
+    if capacity == 0 { return Err(MemoryError::InvalidArgument); }
+    let cap = capacity.checked_next_power_of_two().ok_or(MemoryError::InvalidArgument)?;
+    let bytes = cap.checked_mul(size_of::<T>()).ok_or(MemoryError::Failed)?;
+    let blk = alloc.alloc(bytes.max(1), align_of::<T>())?;
+    let chan = Chan {
+        slots: blk.ptr.cast(), mask: cap - 1, head: AtomicUsize::new(0), tail: AtomicUsize::new(0),
+        tx_alive: AtomicBool::new(true), rx_alive: AtomicBool::new(true), tx_sleeping: AtomicBool::new(false), rx_sleeping: AtomicBool::new(false),
+        tx_parker: OnceCell::new(), rx_parker: OnceCell::new(), blk, alloc,
+    };
+    let chan = CapArc::new(alloc, chan)?;
+    Ok((SpscSender { chan: chan.clone() }, SpscReceiver { chan }))
+}
+
+impl<'a, T> Chan<'a, T> {
+    fn wake(&self, sleeping: &AtomicBool, p: &LazyParker) {
+        fence(Ordering::SeqCst);
+        if sleeping.load(Ordering::Relaxed) { if let Some(p) = p.get().and_then(|p| p.as_ref()) { let _ = p.unpark(); } }
+    }
+}
+
+impl<'a, T> SpscSender<'a, T> {
+    pub fn try_send(&self, v: T) -> Result<(), TrySendError<T>> {
+        let c = &*self.chan;
+        if !c.rx_alive.load(Ordering::Acquire) { return Err(TrySendError::Disconnected(v)); }
+        let t = c.tail.load(Ordering::Relaxed);
+        if t.wrapping_sub(c.head.load(Ordering::Acquire)) > c.mask { return Err(TrySendError::Full(v)); }
+        unsafe { (*c.slots.add(t & c.mask)).get().write(MaybeUninit::new(v)); }
+        c.tail.store(t.wrapping_add(1), Ordering::Release);
+        c.wake(&c.rx_sleeping, &c.rx_parker);
+        Ok(())
+    }
+
+    /// Queues `v`, waiting for room while the ring is full. Fails once the receiver is dropped.
+    pub fn send(&self, mut v: T) -> Result<(), SendError<T>> {
+        let c = &*self.chan;
+        let p = parker(&c.tx_parker);
+        let mut budget = Budget::new(FOREVER);
+        loop {
+            match self.try_send(v) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(x)) => return Err(SendError(x)),
+                Err(TrySendError::Full(x)) => v = x,
+            }
+            c.tx_sleeping.store(true, Ordering::SeqCst);
+            let full = c.tail.load(Ordering::Relaxed).wrapping_sub(c.head.load(Ordering::SeqCst)) > c.mask;
+            if full && c.rx_alive.load(Ordering::SeqCst) { budget.sleep(p); }
+            c.tx_sleeping.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<'a, T> Drop for SpscSender<'a, T> {
+    fn drop(&mut self) { let c = &*self.chan; c.tx_alive.store(false, Ordering::Release); c.wake(&c.rx_sleeping, &c.rx_parker); }
+}
+
+impl<'a, T> SpscReceiver<'a, T> {
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let c = &*self.chan;
+        let h = c.head.load(Ordering::Relaxed);
+        if h == c.tail.load(Ordering::Acquire) {
+            // Re-check after seeing the sender gone: it may have sent right before dropping.
+            if c.tx_alive.load(Ordering::Acquire) || h != c.tail.load(Ordering::Acquire) { return Err(TryRecvError::Empty); }
+            return Err(TryRecvError::Disconnected);
+        }
+        let v = unsafe { (*c.slots.add(h & c.mask)).get().read().assume_init() };
+        c.head.store(h.wrapping_add(1), Ordering::Release);
+        c.wake(&c.tx_sleeping, &c.tx_parker);
+        Ok(v)
+    }
+
+    pub fn recv(&self) -> Result<T, RecvError> { self.recv_timeout(FOREVER).map_err(|_| RecvError) }
+
+    pub fn recv_timeout(&self, timeout_ms: u32) -> Result<T, RecvTimeoutError> {
+        let c = &*self.chan;
+        let p = parker(&c.rx_parker);
+        let mut budget = Budget::new(timeout_ms);
+        loop {
+            match self.try_recv() {
+                Ok(v) => return Ok(v),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) if budget.expired() => return Err(RecvTimeoutError::Timeout),
+                Err(TryRecvError::Empty) => {}
+            }
+            c.rx_sleeping.store(true, Ordering::SeqCst);
+            let empty = c.head.load(Ordering::Relaxed) == c.tail.load(Ordering::SeqCst);
+            if empty && c.tx_alive.load(Ordering::SeqCst) { budget.sleep(p); }
+            c.rx_sleeping.store(false, Ordering::Relaxed);
+        }
+    }
+
+    pub fn len(&self) -> usize { let c = &*self.chan; c.tail.load(Ordering::Acquire).wrapping_sub(c.head.load(Ordering::Relaxed)) }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+impl<'a, T> Drop for SpscReceiver<'a, T> {
+    fn drop(&mut self) { let c = &*self.chan; c.rx_alive.store(false, Ordering::Release); c.wake(&c.tx_sleeping, &c.tx_parker); }
+}
+
+impl<'a, T> Drop for Chan<'a, T> {
+    fn drop(&mut self) {
+        let (mut h, t) = (*self.head.get_mut(), *self.tail.get_mut());
+        while h != t { unsafe { (*self.slots.add(h & self.mask)).get_mut().assume_init_drop(); } h = h.wrapping_add(1); }
+        self.alloc.free(self.blk, align_of::<T>());
+    }
+}
@@ -6,7 +6,81 @@ static SUM: AtomicUsize = AtomicUsize::new(0);
 
 fn fiber_fn(_: *mut c_void) { SUM.fetch_add(1, Ordering::Relaxed); }
 
+static TABLE: Lazy<[u32; 4]> = Lazy::new(|| [1, 2, 3, 4]);
+
+fn sync_primitives() {
+    let m = std::sync::Arc::new(Mutex::new(0usize));
+    let rw = std::sync::Arc::new(RwLock::new(0usize));
+    let cv = std::sync::Arc::new((Mutex::new(false), Condvar::new()));
+    let bar = std::sync::Arc::new(Barrier::new(4));
+    let once = std::sync::Arc::new(OnceCell::new());
+    let leaders = std::sync::Arc::new(AtomicUsize::new(0));
+    let threads: Vec<_> = (0..4).map(|i| {
+        let (m, rw, cv, bar, once, leaders) = (m.clone(), rw.clone(), cv.clone(), bar.clone(), once.clone(), leaders.clone());
+        std::thread::spawn(move || {
+            for _ in 0..1000 { *m.lock() += 1; *rw.write() += 1; assert!(*rw.read() > 0); }
+            once.get_or_init(|| i);
+            if bar.wait().is_leader() { leaders.fetch_add(1, Ordering::Relaxed); }
+            let (l, c) = &*cv;
+            let _g = c.wait_while(l.lock(), |ready| !*ready);
+        })
+    }).collect();
+    { let (l, c) = &*cv; *l.lock() = true; c.notify_all(); }
+    for t in threads { t.join().unwrap(); }
+    assert_eq!(*m.lock(), 4000);
+    assert_eq!(*rw.read(), 4000);
+    assert_eq!(leaders.load(Ordering::Relaxed), 1);
+    assert!(once.get().is_some_and(|&v| v < 4) && once.set(9).is_err());
+    assert_eq!(TABLE[3], 4);
+    let (l, c) = &*cv;
+    let (_g, timed_out) = c.wait_timeout_ms(l.lock(), 1);
+    println!("sync ok timed_out {}", timed_out);
+}
+
+fn channels() {
+    let mut frame = cap_memory::FrameAllocatorResource::new(4 << 20);
+    let a = cap_memory::Allocator::new(&mut frame);
+    std::thread::scope(|s| {
+        let (tx, rx) = channel::<std::string::String>(a, 8).unwrap();
+        for p in 0..3 {
+            let tx = tx.clone();
+            s.spawn(move || for i in 0..500 { tx.send(format!("{}:{}", p, i)).unwrap(); });
+        }
+        drop(tx);
+        assert_eq!(rx.iter().count(), 1500);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (tx, rx) = unbounded_channel::<Vec<u32>>(a).unwrap();
+        for i in 0..100 { tx.send(vec![i; 3]).unwrap(); }
+        assert_eq!(rx.len(), 100);
+        assert_eq!(rx.recv_timeout(5).unwrap(), vec![0; 3]);
+        drop(rx);
+        assert!(tx.send(vec![]).is_err() && tx.is_disconnected());
+
+        let (tx, rx) = spsc_channel::<Box<u64>>(a, 4).unwrap();
+        s.spawn(move || for i in 0..2000u64 { tx.send(Box::new(i)).unwrap(); });
+        let mut sum = 0;
+        while let Ok(v) = rx.recv() { sum += *v; }
+        assert_eq!(sum, 1999 * 2000 / 2);
+
+        let (tx, mut rx) = broadcast_channel::<u32>(a, 4).unwrap();
+        let mut late = tx.subscribe();
+        for i in 0..6 { tx.send(i).unwrap(); }
+        assert_eq!(rx.recv().unwrap(), 2);
+        assert_eq!(rx.missed(), 2);
+        let mut rx2 = rx.clone();
+        let h = s.spawn(move || { let mut n = 0; while rx2.recv().is_ok() { n += 1; } n });
+        drop(tx);
+        assert_eq!(h.join().unwrap(), 3);
+        assert_eq!(late.recv_timeout(1), Ok(2));
+        assert_eq!(rx.recv_timeout(1), Ok(3));
+    });
+    println!("channels ok");
+}
+
 fn main() {
+    sync_primitives();
+    channels();
     prm_threading::ensure_thread_is_fiber();
     let host = prm_threading::host_fiber();
     let pool = FiberStackPool::new(64 << 10);
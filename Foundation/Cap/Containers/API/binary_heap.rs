@@ -0,0 +1,101 @@
+use cap_memory::{Allocator, MemoryError};
+use core::cmp::Ordering;
+use crate::Vector;
+
+// Binary min-heap: the smallest element is always at index 0.
+pub struct MinHeap<'a, T: Ord> { data: Vector<'a, T> }
+
+impl<'a, T: Ord> MinHeap<'a, T> {
+    pub fn with_capacity(alloc: Allocator<'a>, capacity: usize) -> Result<Self, MemoryError> { Ok(Self { data: Vector::with_capacity(alloc, capacity)? }) }
+
+    pub fn len(&self) -> usize { self.data.len() }
+    pub fn is_empty(&self) -> bool { self.data.is_empty() }
+    pub fn peek(&self) -> Option<&T> { self.data.get(0) }
+    /// Elements in heap order (not sorted).
+    pub fn as_slice(&self) -> &[T] { self.data.as_slice() }
+
+    pub fn push(&mut self, v: T) -> Result<(), MemoryError> {
+        self.data.push(v)?;
+        self.sift_up(self.data.len() - 1);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let n = self.data.len();
+        if n == 0 { return None; }
+        self.data.swap(0, n - 1);
+        let v = self.data.pop();
+        if !self.data.is_empty() { self.sift_down(0); }
+        v
+    }
+
+    /// Removes and returns the element at heap position `i` (see `as_slice`).
+    pub fn remove_at(&mut self, i: usize) -> Option<T> {
+        let n = self.data.len();
+        if i >= n { return None; }
+        self.data.swap(i, n - 1);
+        let v = self.data.pop();
+        if i < self.data.len() { self.sift_down(i); self.sift_up(i); }
+        v
+    }
+
+    pub fn clear(&mut self) { while self.data.pop().is_some() {} }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let p = (i - 1) >> 1;
+            if self.data[i] < self.data[p] { self.data.swap(i, p); i = p; } else { break; }
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        let len = self.data.len();
+        loop {
+            let l = (i << 1) + 1;
+            if l >= len { break; }
+            let r = l + 1;
+            let m = if r < len && self.data[r] < self.data[l] { r } else { l };
+            if self.data[m] < self.data[i] { self.data.swap(i, m); i = m; } else { break; }
+        }
+    }
+}
+
+// --- PriorityQueue: lowest priority first, FIFO among equal priorities ---
+
+struct Entry<P, T> { pri: P, seq: u64, val: T }
+
+impl<P: Ord, T> PartialEq for Entry<P, T> { fn eq(&self, o: &Self) -> bool { self.pri == o.pri && self.seq == o.seq } }
+impl<P: Ord, T> Eq for Entry<P, T> {}
+impl<P: Ord, T> PartialOrd for Entry<P, T> { fn partial_cmp(&self, o: &Self) -> Option<Ordering> { Some(self.cmp(o)) } }
+impl<P: Ord, T> Ord for Entry<P, T> { fn cmp(&self, o: &Self) -> Ordering { self.pri.cmp(&o.pri).then(self.seq.cmp(&o.seq)) } }
+
+pub struct PriorityQueue<'a, P: Ord, T> { heap: MinHeap<'a, Entry<P, T>>, seq: u64 }
+
+impl<'a, P: Ord, T> PriorityQueue<'a, P, T> {
+    pub fn with_capacity(alloc: Allocator<'a>, capacity: usize) -> Result<Self, MemoryError> { Ok(Self { heap: MinHeap::with_capacity(alloc, capacity)?, seq: 0 }) }
+
+    pub fn len(&self) -> usize { self.heap.len() }
+    pub fn is_empty(&self) -> bool { self.heap.is_empty() }
+
+    pub fn push(&mut self, pri: P, val: T) -> Result<(), MemoryError> {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        self.heap.push(Entry { pri, seq, val })
+    }
+
+    pub fn peek(&self) -> Option<(&P, &T)> { self.heap.peek().map(|e| (&e.pri, &e.val)) }
+    pub fn pop(&mut self) -> Option<(P, T)> { self.heap.pop().map(|e| (e.pri, e.val)) }
+
+    /// Pops the front entry only if its priority is `<= limit` (e.g. timers due at `now`).
+    pub fn pop_if_le(&mut self, limit: &P) -> Option<(P, T)> {
+        match self.heap.peek() { Some(e) if e.pri <= *limit => self.pop(), _ => None }
+    }
+
+    /// Removes the first entry whose value matches `pred`. O(n).
+    pub fn remove_where<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> Option<(P, T)> {
+        let i = self.heap.as_slice().iter().position(|e| pred(&e.val))?;
+        self.heap.remove_at(i).map(|e| (e.pri, e.val))
+    }
+
+    pub fn clear(&mut self) { self.heap.clear(); }
+}
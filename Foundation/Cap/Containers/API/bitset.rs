@@ -0,0 +1,215 @@
+use cap_memory::{Allocator, MemoryBlock, MemoryError};
+use prm_simd::{U64x4, load_u64x4, store_u64x4, and_u64x4, or_u64x4, xor_u64x4, andnot_u64x4, is_zero_u64x4, popcnt_u64x4};
+
+const WORD_BITS: usize = 64;
+
+#[inline]
+fn words_for(bits: usize) -> usize { bits.div_ceil(WORD_BITS) }
+
+// --- word kernels shared by BitSet and FixedBitSet (4 words per SIMD lane group, scalar tail) ---
+
+#[derive(Clone, Copy)]
+enum WordOp { And, Or, Xor, AndNot }
+
+#[inline(always)]
+fn op_simd(op: WordOp, a: U64x4, b: U64x4) -> U64x4 {
+    match op { WordOp::And => and_u64x4(a, b), WordOp::Or => or_u64x4(a, b), WordOp::Xor => xor_u64x4(a, b), WordOp::AndNot => andnot_u64x4(a, b) }
+}
+
+#[inline(always)]
+fn op_scalar(op: WordOp, a: u64, b: u64) -> u64 {
+    match op { WordOp::And => a & b, WordOp::Or => a | b, WordOp::Xor => a ^ b, WordOp::AndNot => a & !b }
+}
+
+// dst = dst <op> src over the common prefix; words of dst past src behave as if src were zero there
+fn apply_words(dst: &mut [u64], src: &[u64], op: WordOp) {
+    let n = dst.len().min(src.len());
+    let mut i = 0;
+    while i + 4 <= n {
+        unsafe {
+            let a = load_u64x4(dst.as_ptr().add(i));
+            let b = load_u64x4(src.as_ptr().add(i));
+            store_u64x4(dst.as_mut_ptr().add(i), op_simd(op, a, b));
+        }
+        i += 4;
+    }
+    while i < n { dst[i] = op_scalar(op, dst[i], src[i]); i += 1; }
+    if let WordOp::And = op { for w in &mut dst[n..] { *w = 0; } }
+}
+
+fn popcount_words(words: &[u64]) -> usize {
+    let mut total = 0usize;
+    let mut i = 0;
+    while i + 4 <= words.len() {
+        total += unsafe { popcnt_u64x4(load_u64x4(words.as_ptr().add(i))) } as usize;
+        i += 4;
+    }
+    for w in &words[i..] { total += w.count_ones() as usize; }
+    total
+}
+
+// a ⊆ b  <=>  (a & !b) == 0 for every word
+fn subset_words(a: &[u64], b: &[u64]) -> bool {
+    let n = a.len().min(b.len());
+    let mut i = 0;
+    while i + 4 <= n {
+        let d = unsafe { andnot_u64x4(load_u64x4(a.as_ptr().add(i)), load_u64x4(b.as_ptr().add(i))) };
+        if !is_zero_u64x4(d) { return false; }
+        i += 4;
+    }
+    while i < n { if a[i] & !b[i] != 0 { return false; } i += 1; }
+    a[n..].iter().all(|&w| w == 0)
+}
+
+fn intersects_words(a: &[u64], b: &[u64]) -> bool {
+    let n = a.len().min(b.len());
+    let mut i = 0;
+    while i + 4 <= n {
+        let d = unsafe { and_u64x4(load_u64x4(a.as_ptr().add(i)), load_u64x4(b.as_ptr().add(i))) };
+        if !is_zero_u64x4(d) { return true; }
+        i += 4;
+    }
+    while i < n { if a[i] & b[i] != 0 { return true; } i += 1; }
+    false
+}
+
+fn eq_words(a: &[u64], b: &[u64]) -> bool {
+    let n = a.len().min(b.len());
+    a[..n] == b[..n] && a[n..].iter().all(|&w| w == 0) && b[n..].iter().all(|&w| w == 0)
+}
+
+// --- BitSet: allocator-backed, grows on demand ---
+
+pub struct BitSet<'a> { ptr: *mut u64, words: usize, blk: MemoryBlock, alloc: Allocator<'a> }
+
+unsafe impl<'a> Send for BitSet<'a> {}
+unsafe impl<'a> Sync for BitSet<'a> {}
+
+impl<'a> BitSet<'a> {
+    pub fn with_capacity(alloc: Allocator<'a>, bits: usize) -> Result<Self, MemoryError> {
+        let mut s = Self { ptr: core::ptr::null_mut(), words: 0, blk: MemoryBlock::empty(), alloc };
+        s.reserve_words(words_for(bits))?;
+        Ok(s)
+    }
+
+    /// Number of addressable bits before the set has to grow.
+    pub fn capacity(&self) -> usize { self.words * WORD_BITS }
+    pub fn as_words(&self) -> &[u64] { if self.words == 0 { &[] } else { unsafe { core::slice::from_raw_parts(self.ptr, self.words) } } }
+    pub fn as_words_mut(&mut self) -> &mut [u64] { if self.words == 0 { &mut [] } else { unsafe { core::slice::from_raw_parts_mut(self.ptr, self.words) } } }
+
+    fn reserve_words(&mut self, words: usize) -> Result<(), MemoryError> {
+        if words <= self.words { return Ok(()); }
+        let bytes = words.checked_mul(core::mem::size_of::<u64>()).ok_or(MemoryError::Failed)?;
+        let nb = if self.blk.is_empty() {
+            self.alloc.alloc(bytes, core::mem::align_of::<u64>())?
+        } else {
+            self.alloc.realloc(self.blk, bytes, core::mem::align_of::<u64>())?
+        };
+        self.ptr = nb.ptr.cast::<u64>();
+        unsafe { core::ptr::write_bytes(self.ptr.add(self.words), 0, words - self.words); }
+        self.words = words;
+        self.blk = nb;
+        Ok(())
+    }
+
+    /// Grows the set so that bit `bits - 1` is addressable. Never shrinks.
+    pub fn grow(&mut self, bits: usize) -> Result<(), MemoryError> { self.reserve_words(words_for(bits)) }
+
+    pub fn insert(&mut self, bit: usize) -> Result<(), MemoryError> {
+        self.grow(bit.checked_add(1).ok_or(MemoryError::Failed)?)?;
+        unsafe { *self.ptr.add(bit / WORD_BITS) |= 1u64 << (bit % WORD_BITS); }
+        Ok(())
+    }
+    pub fn remove(&mut self, bit: usize) {
+        if bit < self.capacity() { unsafe { *self.ptr.add(bit / WORD_BITS) &= !(1u64 << (bit % WORD_BITS)); } }
+    }
+    pub fn contains(&self, bit: usize) -> bool {
+        bit < self.capacity() && unsafe { *self.ptr.add(bit / WORD_BITS) } & (1u64 << (bit % WORD_BITS)) != 0
+    }
+    pub fn clear(&mut self) { for w in self.as_words_mut() { *w = 0; } }
+    pub fn is_empty(&self) -> bool { self.as_words().iter().all(|&w| w == 0) }
+
+    pub fn and(&mut self, other: &BitSet<'_>) { apply_words(self.as_words_mut(), other.as_words(), WordOp::And); }
+    pub fn andnot(&mut self, other: &BitSet<'_>) { apply_words(self.as_words_mut(), other.as_words(), WordOp::AndNot); }
+    pub fn or(&mut self, other: &BitSet<'_>) -> Result<(), MemoryError> {
+        self.reserve_words(other.words)?;
+        apply_words(self.as_words_mut(), other.as_words(), WordOp::Or);
+        Ok(())
+    }
+    pub fn xor(&mut self, other: &BitSet<'_>) -> Result<(), MemoryError> {
+        self.reserve_words(other.words)?;
+        apply_words(self.as_words_mut(), other.as_words(), WordOp::Xor);
+        Ok(())
+    }
+
+    pub fn count_ones(&self) -> usize { popcount_words(self.as_words()) }
+    pub fn is_subset(&self, other: &BitSet<'_>) -> bool { subset_words(self.as_words(), other.as_words()) }
+    pub fn is_superset(&self, other: &BitSet<'_>) -> bool { subset_words(other.as_words(), self.as_words()) }
+    pub fn intersects(&self, other: &BitSet<'_>) -> bool { intersects_words(self.as_words(), other.as_words()) }
+    pub fn ones(&self) -> Ones<'_> { Ones::new(self.as_words()) }
+}
+
+impl<'a> PartialEq for BitSet<'a> {
+    fn eq(&self, other: &Self) -> bool { eq_words(self.as_words(), other.as_words()) }
+}
+
+impl<'a> Drop for BitSet<'a> {
+    fn drop(&mut self) { if !self.blk.is_empty() { self.alloc.free(self.blk, core::mem::align_of::<u64>()); } }
+}
+
+// --- FixedBitSet: inline storage of N 64-bit words (N * 64 bits), no allocator ---
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedBitSet<const N: usize> { words: [u64; N] }
+
+impl<const N: usize> FixedBitSet<N> {
+    pub const BITS: usize = N * WORD_BITS;
+
+    pub const fn new() -> Self { Self { words: [0; N] } }
+    pub fn as_words(&self) -> &[u64] { &self.words }
+    pub fn as_words_mut(&mut self) -> &mut [u64] { &mut self.words }
+
+    /// Panics if `bit >= Self::BITS`.
+    pub fn insert(&mut self, bit: usize) { self.words[bit / WORD_BITS] |= 1u64 << (bit % WORD_BITS); }
+    pub fn remove(&mut self, bit: usize) { if bit < Self::BITS { self.words[bit / WORD_BITS] &= !(1u64 << (bit % WORD_BITS)); } }
+    pub fn contains(&self, bit: usize) -> bool { bit < Self::BITS && self.words[bit / WORD_BITS] & (1u64 << (bit % WORD_BITS)) != 0 }
+    pub fn clear(&mut self) { self.words = [0; N]; }
+    pub fn is_empty(&self) -> bool { self.words.iter().all(|&w| w == 0) }
+
+    pub fn and(&mut self, other: &Self) { apply_words(&mut self.words, &other.words, WordOp::And); }
+    pub fn or(&mut self, other: &Self) { apply_words(&mut self.words, &other.words, WordOp::Or); }
+    pub fn xor(&mut self, other: &Self) { apply_words(&mut self.words, &other.words, WordOp::Xor); }
+    pub fn andnot(&mut self, other: &Self) { apply_words(&mut self.words, &other.words, WordOp::AndNot); }
+
+    pub fn count_ones(&self) -> usize { popcount_words(&self.words) }
+    pub fn is_subset(&self, other: &Self) -> bool { subset_words(&self.words, &other.words) }
+    pub fn is_superset(&self, other: &Self) -> bool { subset_words(&other.words, &self.words) }
+    pub fn intersects(&self, other: &Self) -> bool { intersects_words(&self.words, &other.words) }
+    pub fn ones(&self) -> Ones<'_> { Ones::new(&self.words) }
+}
+
+impl<const N: usize> Default for FixedBitSet<N> { fn default() -> Self { Self::new() } }
+
+// --- iteration over set bits ---
+
+pub struct Ones<'s> { words: &'s [u64], idx: usize, cur: u64 }
+
+impl<'s> Ones<'s> {
+    fn new(words: &'s [u64]) -> Self { Self { words, idx: 0, cur: words.first().copied().unwrap_or(0) } }
+}
+
+impl<'s> Iterator for Ones<'s> {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            if self.cur != 0 {
+                let tz = self.cur.trailing_zeros() as usize;
+                self.cur &= self.cur - 1;
+                return Some(self.idx * WORD_BITS + tz);
+            }
+            self.idx += 1;
+            if self.idx >= self.words.len() { return None; }
+            self.cur = self.words[self.idx];
+        }
+    }
+}
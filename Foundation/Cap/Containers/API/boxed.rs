@@ -0,0 +1,126 @@
+use cap_memory::{Allocator, MemoryBlock, MemoryError};
+use core::alloc::Layout;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+
+// Allocate room for `layout` from `alloc`; zero-sized layouts never touch the allocator.
+pub(crate) fn alloc_layout(alloc: Allocator<'_>, layout: Layout) -> Result<*mut u8, MemoryError> {
+    if layout.size() == 0 { return Ok(layout.align() as *mut u8); }
+    Ok(alloc.alloc(layout.size(), layout.align())?.ptr)
+}
+
+pub(crate) fn free_layout(alloc: Allocator<'_>, p: *mut u8, layout: Layout) {
+    if layout.size() != 0 { alloc.free(MemoryBlock::new(p, layout.size()), layout.align()); }
+}
+
+/// Owning pointer whose storage comes from (and returns to) an `Allocator`.
+///
+/// Convert to a trait object or slice with `cap_unsize!(b, dyn Trait)`.
+pub struct CapBox<'a, T: ?Sized> { ptr: NonNull<T>, alloc: Allocator<'a>, _marker: PhantomData<T> }
+
+unsafe impl<'a, T: ?Sized + Send> Send for CapBox<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync> Sync for CapBox<'a, T> {}
+
+impl<'a, T> CapBox<'a, T> {
+    pub fn new(alloc: Allocator<'a>, v: T) -> Result<Self, MemoryError> {
+        let p = alloc_layout(alloc, Layout::new::<T>())?.cast::<T>();
+        unsafe { ptr::write(p, v); }
+        Ok(Self { ptr: unsafe { NonNull::new_unchecked(p) }, alloc, _marker: PhantomData })
+    }
+
+    pub fn into_inner(b: Self) -> T {
+        let (p, alloc) = Self::into_raw(b);
+        unsafe {
+            let v = ptr::read(p);
+            free_layout(alloc, p.cast::<u8>(), Layout::new::<T>());
+            v
+        }
+    }
+}
+
+impl<'a, T: ?Sized> CapBox<'a, T> {
+    /// Releases ownership; pair with `from_raw` using the same allocator.
+    pub fn into_raw(b: Self) -> (*mut T, Allocator<'a>) {
+        let out = (b.ptr.as_ptr(), b.alloc);
+        core::mem::forget(b);
+        out
+    }
+
+    /// # Safety
+    /// `p` must come from `CapBox::into_raw` with an allocator that frees into the same resource as `alloc`.
+    pub unsafe fn from_raw(alloc: Allocator<'a>, p: *mut T) -> Self { Self { ptr: NonNull::new_unchecked(p), alloc, _marker: PhantomData } }
+
+    pub fn allocator(b: &Self) -> Allocator<'a> { b.alloc }
+
+    /// Re-types the pointer without touching the allocation, e.g. `T` to `dyn Trait`. Prefer `cap_unsize!`.
+    ///
+    /// # Safety
+    /// `f` must return the pointer it was given, only changing its metadata through an unsizing coercion.
+    pub unsafe fn unsize_with<U: ?Sized, F: FnOnce(*mut T) -> *mut U>(b: Self, f: F) -> CapBox<'a, U> {
+        let (p, alloc) = Self::into_raw(b);
+        let q = f(p);
+        debug_assert!(q.cast::<u8>() == p.cast::<u8>());
+        CapBox::from_raw(alloc, q)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for CapBox<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            let layout = Layout::for_value(self.ptr.as_ref());
+            ptr::drop_in_place(self.ptr.as_ptr());
+            free_layout(self.alloc, self.ptr.as_ptr().cast::<u8>(), layout);
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for CapBox<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T { unsafe { self.ptr.as_ref() } }
+}
+
+impl<'a, T: ?Sized> DerefMut for CapBox<'a, T> {
+    fn deref_mut(&mut self) -> &mut T { unsafe { self.ptr.as_mut() } }
+}
+
+impl<'a, T: ?Sized> AsRef<T> for CapBox<'a, T> { fn as_ref(&self) -> &T { self } }
+impl<'a, T: ?Sized> AsMut<T> for CapBox<'a, T> { fn as_mut(&mut self) -> &mut T { self } }
+
+impl<'a, T: ?Sized + core::fmt::Debug> core::fmt::Debug for CapBox<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result { (**self).fmt(f) }
+}
+
+impl<'a, T: ?Sized + core::fmt::Display> core::fmt::Display for CapBox<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result { (**self).fmt(f) }
+}
+
+/// Unsizes a `CapBox`, `CapRc` or `CapArc` to `$t` (a trait object or slice) in place.
+///
+/// `let s: CapBox<dyn Storage> = cap_unsize!(CapBox::new(alloc, v)?, dyn Storage);`
+#[macro_export]
+macro_rules! cap_unsize {
+    ($b:expr, $t:ty) => {{
+        // Evaluated outside the unsafe block so the caller's expression gets no unsafe context.
+        let b = $b;
+        // The closure body is a bare coercion, which is what makes the unsafe call sound.
+        unsafe { $crate::CapUnsize::unsize_with::<$t, _>(b, |p| p) }
+    }};
+}
+
+/// Implemented by the allocator-aware owning pointers so `cap_unsize!` can re-type any of them.
+pub trait CapUnsize<'a> {
+    type Raw<U: ?Sized + 'a>;
+    type Out<U: ?Sized + 'a>;
+    type In;
+    /// # Safety
+    /// See `CapBox::unsize_with`.
+    unsafe fn unsize_with<U: ?Sized + 'a, F: FnOnce(Self::In) -> Self::Raw<U>>(self, f: F) -> Self::Out<U>;
+}
+
+impl<'a, T: ?Sized + 'a> CapUnsize<'a> for CapBox<'a, T> {
+    type Raw<U: ?Sized + 'a> = *mut U;
+    type Out<U: ?Sized + 'a> = CapBox<'a, U>;
+    type In = *mut T;
+    unsafe fn unsize_with<U: ?Sized + 'a, F: FnOnce(*mut T) -> *mut U>(self, f: F) -> CapBox<'a, U> { CapBox::unsize_with(self, f) }
+}
@@ -0,0 +1,435 @@
+use cap_memory::{Allocator, MemoryBlock, MemoryError};
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::marker::PhantomData;
+use core::mem::{size_of, align_of, MaybeUninit};
+use core::ops::{Bound, RangeBounds};
+use core::ptr::{self, write};
+
+// Minimum degree: every node except the root holds between B-1 and 2B-1 keys.
+const B: usize = 6;
+const CAP: usize = 2 * B - 1;
+// Height bound for iterator stacks: log_B(usize::MAX) < 32.
+const MAX_DEPTH: usize = 32;
+
+struct Node<K, V> {
+    len: usize,
+    leaf: bool,
+    keys: [MaybeUninit<K>; CAP],
+    vals: [MaybeUninit<V>; CAP],
+    children: [*mut Node<K, V>; CAP + 1],
+}
+
+impl<K, V> Node<K, V> {
+    #[inline] unsafe fn key(&self, i: usize) -> &K { self.keys[i].assume_init_ref() }
+    #[inline] unsafe fn val(&self, i: usize) -> &V { self.vals[i].assume_init_ref() }
+    #[inline] unsafe fn val_mut(&mut self, i: usize) -> &mut V { self.vals[i].assume_init_mut() }
+
+    // Ok(i): keys[i] == q, Err(i): q belongs in children[i]
+    fn search<Q: Ord + ?Sized>(&self, q: &Q) -> Result<usize, usize> where K: Borrow<Q> {
+        let (mut lo, mut hi) = (0usize, self.len);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match unsafe { self.key(mid) }.borrow().cmp(q) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    // Shift keys/vals [i..len) one slot right and write (k, v) at i. Children are left to the caller.
+    unsafe fn insert_kv(&mut self, i: usize, k: K, v: V) {
+        let kp = self.keys.as_mut_ptr();
+        let vp = self.vals.as_mut_ptr();
+        ptr::copy(kp.add(i), kp.add(i + 1), self.len - i);
+        ptr::copy(vp.add(i), vp.add(i + 1), self.len - i);
+        self.keys[i].write(k);
+        self.vals[i].write(v);
+        self.len += 1;
+    }
+
+    unsafe fn remove_kv(&mut self, i: usize) -> (K, V) {
+        let k = self.keys[i].assume_init_read();
+        let v = self.vals[i].assume_init_read();
+        let kp = self.keys.as_mut_ptr();
+        let vp = self.vals.as_mut_ptr();
+        ptr::copy(kp.add(i + 1), kp.add(i), self.len - i - 1);
+        ptr::copy(vp.add(i + 1), vp.add(i), self.len - i - 1);
+        self.len -= 1;
+        (k, v)
+    }
+
+    unsafe fn insert_child(&mut self, i: usize, c: *mut Node<K, V>) {
+        let cp = self.children.as_mut_ptr();
+        ptr::copy(cp.add(i), cp.add(i + 1), self.len + 1 - i);
+        self.children[i] = c;
+    }
+
+    unsafe fn remove_child(&mut self, i: usize) -> *mut Node<K, V> {
+        let c = self.children[i];
+        let cp = self.children.as_mut_ptr();
+        ptr::copy(cp.add(i + 1), cp.add(i), self.len + 1 - i - 1);
+        c
+    }
+}
+
+enum Target<'q, Q: ?Sized> { Key(&'q Q), Min, Max }
+
+pub struct BTreeMap<'a, K: Ord, V> { root: *mut Node<K, V>, len: usize, alloc: Allocator<'a> }
+
+unsafe impl<'a, K: Ord + Send, V: Send> Send for BTreeMap<'a, K, V> {}
+unsafe impl<'a, K: Ord + Sync, V: Sync> Sync for BTreeMap<'a, K, V> {}
+
+impl<'a, K: Ord, V> BTreeMap<'a, K, V> {
+    pub fn new(alloc: Allocator<'a>) -> Self { Self { root: ptr::null_mut(), len: 0, alloc } }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    fn alloc_node(&self, leaf: bool) -> Result<*mut Node<K, V>, MemoryError> {
+        let blk = self.alloc.alloc(size_of::<Node<K, V>>(), align_of::<Node<K, V>>())?;
+        let n = blk.ptr.cast::<Node<K, V>>();
+        unsafe {
+            write(n, Node {
+                len: 0,
+                leaf,
+                keys: [const { MaybeUninit::uninit() }; CAP],
+                vals: [const { MaybeUninit::uninit() }; CAP],
+                children: [ptr::null_mut(); CAP + 1],
+            });
+        }
+        Ok(n)
+    }
+
+    fn free_node(&self, n: *mut Node<K, V>) {
+        self.alloc.free(MemoryBlock::new(n.cast::<u8>(), size_of::<Node<K, V>>()), align_of::<Node<K, V>>());
+    }
+
+    pub fn get<Q: Ord + ?Sized>(&self, k: &Q) -> Option<&V> where K: Borrow<Q> {
+        let mut n = self.root;
+        while !n.is_null() {
+            let node = unsafe { &*n };
+            match node.search(k) {
+                Ok(i) => return Some(unsafe { node.val(i) }),
+                Err(i) => { if node.leaf { return None; } n = node.children[i]; }
+            }
+        }
+        None
+    }
+
+    pub fn get_mut<Q: Ord + ?Sized>(&mut self, k: &Q) -> Option<&mut V> where K: Borrow<Q> {
+        let mut n = self.root;
+        while !n.is_null() {
+            let node = unsafe { &mut *n };
+            match node.search(k) {
+                Ok(i) => return Some(unsafe { node.val_mut(i) }),
+                Err(i) => { if node.leaf { return None; } n = node.children[i]; }
+            }
+        }
+        None
+    }
+
+    pub fn contains_key<Q: Ord + ?Sized>(&self, k: &Q) -> bool where K: Borrow<Q> { self.get(k).is_some() }
+
+    // Split the full child `i` of `parent` around its median, which moves up into `parent`.
+    unsafe fn split_child(&self, p: &mut Node<K, V>, i: usize) -> Result<(), MemoryError> {
+        let left = &mut *p.children[i];
+        let right_ptr = self.alloc_node(left.leaf)?;
+        let right = &mut *right_ptr;
+        ptr::copy_nonoverlapping(left.keys.as_ptr().add(B), right.keys.as_mut_ptr(), B - 1);
+        ptr::copy_nonoverlapping(left.vals.as_ptr().add(B), right.vals.as_mut_ptr(), B - 1);
+        if !left.leaf { ptr::copy_nonoverlapping(left.children.as_ptr().add(B), right.children.as_mut_ptr(), B); }
+        right.len = B - 1;
+        left.len = B - 1;
+        let mk = left.keys[B - 1].assume_init_read();
+        let mv = left.vals[B - 1].assume_init_read();
+        p.insert_child(i + 1, right_ptr);
+        p.insert_kv(i, mk, mv);
+        Ok(())
+    }
+
+    /// Inserts `k -> v`, returning the previous value if the key was present.
+    pub fn insert(&mut self, k: K, v: V) -> Result<Option<V>, MemoryError> {
+        unsafe {
+            if self.root.is_null() { self.root = self.alloc_node(true)?; }
+            if (*self.root).len == CAP {
+                let r = self.alloc_node(false)?;
+                (*r).children[0] = self.root;
+                if let Err(e) = self.split_child(&mut *r, 0) { self.free_node(r); return Err(e); }
+                self.root = r;
+            }
+            let mut n = self.root;
+            loop {
+                let node = &mut *n;
+                match node.search(&k) {
+                    Ok(i) => return Ok(Some(core::mem::replace(node.val_mut(i), v))),
+                    Err(mut i) => {
+                        if node.leaf {
+                            node.insert_kv(i, k, v);
+                            self.len += 1;
+                            return Ok(None);
+                        }
+                        if (*node.children[i]).len == CAP {
+                            self.split_child(node, i)?;
+                            match k.cmp(node.key(i)) {
+                                Ordering::Equal => return Ok(Some(core::mem::replace(node.val_mut(i), v))),
+                                Ordering::Greater => i += 1,
+                                Ordering::Less => {}
+                            }
+                        }
+                        n = node.children[i];
+                    }
+                }
+            }
+        }
+    }
+
+    // Merge children[i], keys[i] and children[i + 1] into children[i]; both children hold B-1 keys.
+    unsafe fn merge(&self, parent: &mut Node<K, V>, i: usize) {
+        let left = &mut *parent.children[i];
+        let right_ptr = parent.children[i + 1];
+        let right = &mut *right_ptr;
+        parent.remove_child(i + 1);
+        let (k, v) = parent.remove_kv(i);
+        let base = left.len;
+        left.keys[base].write(k);
+        left.vals[base].write(v);
+        ptr::copy_nonoverlapping(right.keys.as_ptr(), left.keys.as_mut_ptr().add(base + 1), right.len);
+        ptr::copy_nonoverlapping(right.vals.as_ptr(), left.vals.as_mut_ptr().add(base + 1), right.len);
+        if !left.leaf { ptr::copy_nonoverlapping(right.children.as_ptr(), left.children.as_mut_ptr().add(base + 1), right.len + 1); }
+        left.len = base + 1 + right.len;
+        self.free_node(right_ptr);
+    }
+
+    // Make sure children[i] holds at least B keys before descending into it. Returns the child index to descend into.
+    unsafe fn fill(&self, parent: &mut Node<K, V>, i: usize) -> usize {
+        let c = &mut *parent.children[i];
+        if c.len >= B { return i; }
+        if i > 0 && (*parent.children[i - 1]).len >= B {
+            let l = &mut *parent.children[i - 1];
+            let lk = l.keys[l.len - 1].assume_init_read();
+            let lv = l.vals[l.len - 1].assume_init_read();
+            let lc = l.children[l.len];
+            l.len -= 1;
+            let pk = core::mem::replace(parent.keys[i - 1].assume_init_mut(), lk);
+            let pv = core::mem::replace(parent.vals[i - 1].assume_init_mut(), lv);
+            if !c.leaf { c.insert_child(0, lc); }
+            c.insert_kv(0, pk, pv);
+            return i;
+        }
+        if i < parent.len && (*parent.children[i + 1]).len >= B {
+            let r = &mut *parent.children[i + 1];
+            let rc = if r.leaf { ptr::null_mut() } else { r.remove_child(0) };
+            let (rk, rv) = r.remove_kv(0);
+            let pk = core::mem::replace(parent.keys[i].assume_init_mut(), rk);
+            let pv = core::mem::replace(parent.vals[i].assume_init_mut(), rv);
+            let at = c.len;
+            c.keys[at].write(pk);
+            c.vals[at].write(pv);
+            if !c.leaf { c.children[at + 1] = rc; }
+            c.len += 1;
+            return i;
+        }
+        if i < parent.len { self.merge(parent, i); i } else { self.merge(parent, i - 1); i - 1 }
+    }
+
+    unsafe fn remove_in<Q: Ord + ?Sized>(&self, n: *mut Node<K, V>, t: Target<'_, Q>) -> Option<(K, V)> where K: Borrow<Q> {
+        let node = &mut *n;
+        let (found, i) = match t {
+            Target::Key(q) => match node.search(q) { Ok(i) => (true, i), Err(i) => (false, i) },
+            Target::Min => (node.leaf && node.len > 0, 0),
+            Target::Max => if node.leaf { (node.len > 0, node.len.wrapping_sub(1)) } else { (false, node.len) },
+        };
+        if node.leaf { return if found { Some(node.remove_kv(i)) } else { None }; }
+        if found {
+            if (*node.children[i]).len >= B {
+                let (pk, pv) = self.remove_in::<Q>(node.children[i], Target::Max)?;
+                let k = core::mem::replace(node.keys[i].assume_init_mut(), pk);
+                let v = core::mem::replace(node.vals[i].assume_init_mut(), pv);
+                return Some((k, v));
+            }
+            if (*node.children[i + 1]).len >= B {
+                let (sk, sv) = self.remove_in::<Q>(node.children[i + 1], Target::Min)?;
+                let k = core::mem::replace(node.keys[i].assume_init_mut(), sk);
+                let v = core::mem::replace(node.vals[i].assume_init_mut(), sv);
+                return Some((k, v));
+            }
+            self.merge(node, i);
+            return self.remove_in(node.children[i], t);
+        }
+        let ci = self.fill(node, i);
+        self.remove_in(node.children[ci], t)
+    }
+
+    fn remove_target<Q: Ord + ?Sized>(&mut self, t: Target<'_, Q>) -> Option<(K, V)> where K: Borrow<Q> {
+        if self.root.is_null() { return None; }
+        let out = unsafe { self.remove_in(self.root, t) };
+        unsafe {
+            let r = &*self.root;
+            if r.len == 0 {
+                let old = self.root;
+                self.root = if r.leaf { ptr::null_mut() } else { r.children[0] };
+                self.free_node(old);
+            }
+        }
+        if out.is_some() { self.len -= 1; }
+        out
+    }
+
+    pub fn remove<Q: Ord + ?Sized>(&mut self, k: &Q) -> Option<V> where K: Borrow<Q> { self.remove_target(Target::Key(k)).map(|(_, v)| v) }
+    pub fn remove_entry<Q: Ord + ?Sized>(&mut self, k: &Q) -> Option<(K, V)> where K: Borrow<Q> { self.remove_target(Target::Key(k)) }
+    pub fn pop_first(&mut self) -> Option<(K, V)> { self.remove_target::<K>(Target::Min) }
+    pub fn pop_last(&mut self) -> Option<(K, V)> { self.remove_target::<K>(Target::Max) }
+
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut n = self.root;
+        if n.is_null() || unsafe { (*n).len } == 0 { return None; }
+        unsafe {
+            while !(*n).leaf { n = (*n).children[0]; }
+            Some(((*n).key(0), (*n).val(0)))
+        }
+    }
+
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut n = self.root;
+        if n.is_null() || unsafe { (*n).len } == 0 { return None; }
+        unsafe {
+            while !(*n).leaf { n = (*n).children[(*n).len]; }
+            let l = (*n).len - 1;
+            Some(((*n).key(l), (*n).val(l)))
+        }
+    }
+
+    pub fn iter(&self) -> Range<'_, K, V> { self.range::<K, _>(..) }
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ { self.iter().map(|(k, _)| k) }
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ { self.iter().map(|(_, v)| v) }
+
+    // First key k with k >= q (inclusive) or k > q (exclusive), as a stable slot address; null if none.
+    fn seek_key<Q: Ord + ?Sized>(&self, q: &Q, inclusive: bool) -> *const K where K: Borrow<Q> {
+        let mut best = ptr::null();
+        let mut n = self.root as *const Node<K, V>;
+        while !n.is_null() {
+            let node = unsafe { &*n };
+            let i = match node.search(q) {
+                Ok(i) if inclusive => return node.keys[i].as_ptr(),
+                Ok(i) => i + 1,
+                Err(i) => i,
+            };
+            if i < node.len { best = node.keys[i].as_ptr(); }
+            if node.leaf { break; }
+            n = node.children[i];
+        }
+        best
+    }
+
+    /// In-order iterator over the entries whose keys fall into `range`. An inverted range such as
+    /// `5..3` yields nothing (std's `BTreeMap::range` panics on it).
+    pub fn range<Q: Ord + ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Range<'_, K, V> where K: Borrow<Q> {
+        let mut it = Range { stack: [(ptr::null(), 0); MAX_DEPTH], depth: 0, end: ptr::null(), _marker: PhantomData };
+        // The walk below would start past `end` and never meet it.
+        let inverted = match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(s), Bound::Excluded(e)) => s >= e,
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => s > e,
+            _ => false,
+        };
+        if inverted { return it; }
+        it.end = match range.end_bound() {
+            Bound::Unbounded => ptr::null(),
+            Bound::Included(q) => self.seek_key(q, false),
+            Bound::Excluded(q) => self.seek_key(q, true),
+        };
+        let mut n = self.root as *const Node<K, V>;
+        while !n.is_null() {
+            let node = unsafe { &*n };
+            let (i, hit) = match range.start_bound() {
+                Bound::Unbounded => (0, false),
+                Bound::Included(q) => match node.search(q) { Ok(i) => (i, true), Err(i) => (i, false) },
+                Bound::Excluded(q) => match node.search(q) { Ok(i) => (i + 1, false), Err(i) => (i, false) },
+            };
+            it.stack[it.depth] = (n, i);
+            it.depth += 1;
+            // An inclusive hit is itself the first entry; nothing left of it can match.
+            if node.leaf || hit { break; }
+            n = node.children[i];
+        }
+        it
+    }
+
+    pub fn clear(&mut self) {
+        unsafe { if !self.root.is_null() { self.free_subtree(self.root); } }
+        self.root = ptr::null_mut();
+        self.len = 0;
+    }
+
+    unsafe fn free_subtree(&self, n: *mut Node<K, V>) {
+        let node = &mut *n;
+        for i in 0..node.len {
+            node.keys[i].assume_init_drop();
+            node.vals[i].assume_init_drop();
+        }
+        if !node.leaf { for i in 0..=node.len { self.free_subtree(node.children[i]); } }
+        self.free_node(n);
+    }
+}
+
+impl<'a, K: Ord, V> Drop for BTreeMap<'a, K, V> {
+    fn drop(&mut self) { self.clear(); }
+}
+
+/// Borrowing in-order iterator returned by `BTreeMap::range` and `BTreeMap::iter`.
+pub struct Range<'m, K, V> {
+    stack: [(*const Node<K, V>, usize); MAX_DEPTH],
+    depth: usize,
+    // Slot of the first key past the range; null when the range is open-ended.
+    end: *const K,
+    _marker: PhantomData<&'m Node<K, V>>,
+}
+
+impl<'m, K, V> Iterator for Range<'m, K, V> {
+    type Item = (&'m K, &'m V);
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.depth > 0 {
+            let (n, i) = self.stack[self.depth - 1];
+            let node = unsafe { &*n };
+            if i < node.len {
+                if node.keys[i].as_ptr() == self.end { self.depth = 0; return None; }
+                self.stack[self.depth - 1].1 = i + 1;
+                if !node.leaf {
+                    let mut c = node.children[i + 1] as *const Node<K, V>;
+                    loop {
+                        self.stack[self.depth] = (c, 0);
+                        self.depth += 1;
+                        if unsafe { (*c).leaf } { break; }
+                        c = unsafe { (*c).children[0] };
+                    }
+                }
+                return Some(unsafe { (node.key(i), node.val(i)) });
+            }
+            self.depth -= 1;
+        }
+        None
+    }
+}
+
+// --- BTreeSet: ordered set over BTreeMap<K, ()> ---
+
+pub struct BTreeSet<'a, K: Ord> { map: BTreeMap<'a, K, ()> }
+
+impl<'a, K: Ord> BTreeSet<'a, K> {
+    pub fn new(alloc: Allocator<'a>) -> Self { Self { map: BTreeMap::new(alloc) } }
+    pub fn len(&self) -> usize { self.map.len() }
+    pub fn is_empty(&self) -> bool { self.map.is_empty() }
+    /// Returns `true` if the value was not already present.
+    pub fn insert(&mut self, k: K) -> Result<bool, MemoryError> { Ok(self.map.insert(k, ())?.is_none()) }
+    pub fn remove<Q: Ord + ?Sized>(&mut self, k: &Q) -> bool where K: Borrow<Q> { self.map.remove(k).is_some() }
+    pub fn contains<Q: Ord + ?Sized>(&self, k: &Q) -> bool where K: Borrow<Q> { self.map.contains_key(k) }
+    pub fn first(&self) -> Option<&K> { self.map.first_key_value().map(|(k, _)| k) }
+    pub fn last(&self) -> Option<&K> { self.map.last_key_value().map(|(k, _)| k) }
+    pub fn pop_first(&mut self) -> Option<K> { self.map.pop_first().map(|(k, _)| k) }
+    pub fn pop_last(&mut self) -> Option<K> { self.map.pop_last().map(|(k, _)| k) }
+    pub fn iter(&self) -> impl Iterator<Item = &K> + '_ { self.map.keys() }
+    pub fn range<Q: Ord + ?Sized, R: RangeBounds<Q>>(&self, range: R) -> impl Iterator<Item = &K> + '_ where K: Borrow<Q> { self.map.range(range).map(|(k, _)| k) }
+    pub fn clear(&mut self) { self.map.clear(); }
+}
@@ -1,56 +1,120 @@
-use core::sync::atomic::{AtomicIsize, Ordering};
+use core::sync::atomic::{fence, AtomicIsize, AtomicPtr, Ordering};
 use cap_memory::{Allocator, MemoryBlock, MemoryError};
+use crate::CapBox;
 
+// Ring storage. A buffer replaced by `grow` stays linked through `prev` until the deque drops,
+// since a thief may still be reading from the one it loaded before the swap.
+struct Buffer<T> { ptr: *mut T, mask: isize, blk: MemoryBlock, prev: *mut Buffer<T> }
+
+impl<T: Copy> Buffer<T> {
+    fn cap(&self) -> isize { self.mask + 1 }
+    unsafe fn get(&self, i: isize) -> T { core::ptr::read(self.ptr.add((i & self.mask) as usize)) }
+    unsafe fn put(&self, i: isize, v: T) { core::ptr::write(self.ptr.add((i & self.mask) as usize), v) }
+}
+
+/// Work-stealing deque: the owning thread pushes and pops at the bottom, any thread steals from the top.
+///
+/// `push_bottom` grows the ring (doubling) when full, so queued work is never overwritten.
 pub struct ChaseLevDeque<'a, T: Copy> {
-    ptr: *mut T,
-    cap: isize,
-    mask: isize,
+    buf: AtomicPtr<Buffer<T>>,
     top: AtomicIsize,
     bottom: AtomicIsize,
-    blk: MemoryBlock,
     alloc: Allocator<'a>,
 }
 
+unsafe impl<'a, T: Copy + Send> Send for ChaseLevDeque<'a, T> {}
+unsafe impl<'a, T: Copy + Send> Sync for ChaseLevDeque<'a, T> {}
+
 impl<'a, T: Copy> ChaseLevDeque<'a, T> {
     pub fn with_capacity(alloc: Allocator<'a>, capacity_pow2: usize) -> Result<Self, MemoryError> {
         if capacity_pow2 == 0 || (capacity_pow2 & (capacity_pow2 - 1)) != 0 { return Err(MemoryError::InvalidArgument); }
-        let bytes = capacity_pow2.checked_mul(core::mem::size_of::<T>()).ok_or(MemoryError::Failed)?;
+        let buf = Self::alloc_buffer(alloc, capacity_pow2)?;
+        Ok(Self { buf: AtomicPtr::new(buf), top: AtomicIsize::new(0), bottom: AtomicIsize::new(0), alloc })
+    }
+
+    fn alloc_buffer(alloc: Allocator<'a>, cap: usize) -> Result<*mut Buffer<T>, MemoryError> {
+        let bytes = cap.checked_mul(core::mem::size_of::<T>()).ok_or(MemoryError::Failed)?;
         let blk = alloc.alloc(bytes, core::mem::align_of::<T>())?;
-        let ptr = blk.ptr.cast::<T>();
-        Ok(Self { ptr, cap: capacity_pow2 as isize, mask: (capacity_pow2 as isize) - 1, top: AtomicIsize::new(0), bottom: AtomicIsize::new(0), blk, alloc })
+        let hdr = Buffer { ptr: blk.ptr.cast::<T>(), mask: cap as isize - 1, blk, prev: core::ptr::null_mut() };
+        match CapBox::new(alloc, hdr) {
+            Ok(b) => Ok(CapBox::into_raw(b).0),
+            Err(e) => { alloc.free(blk, core::mem::align_of::<T>()); Err(e) }
+        }
     }
 
-    pub fn push_bottom(&self, v: T) -> bool {
+    /// Current ring capacity (grows on demand).
+    pub fn capacity(&self) -> usize { unsafe { (*self.buf.load(Ordering::Relaxed)).cap() as usize } }
+
+    /// Approximate number of queued items; exact only when called by the owner with no concurrent thieves.
+    pub fn len(&self) -> usize {
         let b = self.bottom.load(Ordering::Relaxed);
-        unsafe { core::ptr::write(self.ptr.add((b & self.mask) as usize), v); }
+        let t = self.top.load(Ordering::Relaxed);
+        (b - t).max(0) as usize
+    }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    // Owner only: copy live items [t, b) into a ring twice the size and publish it.
+    fn grow(&self, old: *mut Buffer<T>, t: isize, b: isize) -> Result<*mut Buffer<T>, MemoryError> {
+        unsafe {
+            let nb = Self::alloc_buffer(self.alloc, ((*old).cap() as usize) << 1)?;
+            (*nb).prev = old;
+            for i in t..b { (*nb).put(i, (*old).get(i)); }
+            self.buf.store(nb, Ordering::Release);
+            Ok(nb)
+        }
+    }
+
+    /// Owner only. Fails (leaving the deque unchanged) only if growing the ring cannot allocate.
+    pub fn push_bottom(&self, v: T) -> Result<(), MemoryError> {
+        let b = self.bottom.load(Ordering::Relaxed);
+        let t = self.top.load(Ordering::Acquire);
+        let mut buf = self.buf.load(Ordering::Relaxed);
+        if b - t >= unsafe { (*buf).cap() } { buf = self.grow(buf, t, b)?; }
+        unsafe { (*buf).put(b, v); }
         self.bottom.store(b + 1, Ordering::Release);
-        true
+        Ok(())
     }
 
+    /// Owner only. LIFO end.
     pub fn pop_bottom(&self, out: &mut T) -> bool {
         let b = self.bottom.load(Ordering::Relaxed) - 1;
+        let buf = self.buf.load(Ordering::Relaxed);
         self.bottom.store(b, Ordering::Relaxed);
-        let t = self.top.load(Ordering::Acquire);
-        let size = b - t;
-        if size < 0 {
-            self.bottom.store(t, Ordering::Relaxed);
+        // The bottom store must be visible before reading top, or a thief and the owner can both take the last item.
+        fence(Ordering::SeqCst);
+        let t = self.top.load(Ordering::Relaxed);
+        if t > b {
+            self.bottom.store(b + 1, Ordering::Relaxed);
             return false;
         }
-        let v = unsafe { core::ptr::read(self.ptr.add((b & self.mask) as usize)) };
-        if size > 0 { *out = v; return true; }
+        let v = unsafe { (*buf).get(b) };
+        if t < b { *out = v; return true; }
         let ok = self.top.compare_exchange(t, t + 1, Ordering::SeqCst, Ordering::Relaxed).is_ok();
-        self.bottom.store(t + 1, Ordering::Relaxed);
+        self.bottom.store(b + 1, Ordering::Relaxed);
         if ok { *out = v; true } else { false }
     }
 
+    /// Any thread. FIFO end.
     pub fn steal(&self, out: &mut T) -> bool {
         let t = self.top.load(Ordering::Acquire);
+        fence(Ordering::SeqCst);
         let b = self.bottom.load(Ordering::Acquire);
-        let size = b - t;
-        if size <= 0 { return false; }
-        let v = unsafe { core::ptr::read(self.ptr.add((t & self.mask) as usize)) };
+        if b - t <= 0 { return false; }
+        let buf = self.buf.load(Ordering::Acquire);
+        let v = unsafe { (*buf).get(t) };
         if self.top.compare_exchange(t, t + 1, Ordering::SeqCst, Ordering::Relaxed).is_ok() { *out = v; true } else { false }
     }
 }
 
-impl<'a, T: Copy> Drop for ChaseLevDeque<'a, T> { fn drop(&mut self) { if !self.blk.is_empty() { self.alloc.free(self.blk, core::mem::align_of::<T>()); } } }
+impl<'a, T: Copy> Drop for ChaseLevDeque<'a, T> {
+    fn drop(&mut self) {
+        let mut cur = *self.buf.get_mut();
+        while !cur.is_null() {
+            unsafe {
+                let hdr = CapBox::from_raw(self.alloc, cur);
+                self.alloc.free(hdr.blk, core::mem::align_of::<T>());
+                cur = hdr.prev;
+            }
+        }
+    }
+}
@@ -3,8 +3,8 @@ use core::hash::{Hash, Hasher};
 use core::mem::MaybeUninit;
 use core::ptr::{read, write, drop_in_place};
 
-struct FnvHasher(u64);
-impl FnvHasher { fn new() -> Self { Self(0xcbf29ce484222325) } }
+pub(crate) struct FnvHasher(u64);
+impl FnvHasher { pub(crate) fn new() -> Self { Self(0xcbf29ce484222325) } }
 impl Hasher for FnvHasher {
     fn write(&mut self, bytes: &[u8]) { for &b in bytes { self.0 ^= b as u64; self.0 = self.0.wrapping_mul(1099511628211); } }
     fn finish(&self) -> u64 { self.0 }
@@ -6,6 +6,13 @@ pub mod chase_lev_deque;
 pub mod ring_buffer;
 pub mod string;
 pub mod hash_map;
+pub mod bitset;
+pub mod btree_map;
+pub mod binary_heap;
+pub mod boxed;
+pub mod rc;
+pub mod persistent_vector;
+pub mod persistent_hash_map;
 pub use vector::*;
 pub use mpmc_queue::*;
 pub use deque::*;
@@ -13,6 +20,13 @@ pub use chase_lev_deque::*;
 pub use ring_buffer::*;
 pub use string::*;
 pub use hash_map::*;
+pub use bitset::*;
+pub use btree_map::*;
+pub use binary_heap::*;
+pub use boxed::*;
+pub use rc::*;
+pub use persistent_vector::*;
+pub use persistent_hash_map::*;
 
 use cap_memory::{Allocator, MemoryError};
 
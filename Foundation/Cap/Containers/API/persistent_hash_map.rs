@@ -0,0 +1,260 @@
+use cap_memory::{Allocator, MemoryError};
+use core::hash::{Hash, Hasher};
+use crate::hash_map::FnvHasher;
+use crate::{CapArc, Vector};
+
+// Hash array mapped trie: 32-way nodes indexed by 5 hash bits per level, children packed by bitmap.
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+// 64-bit hashes are exhausted after 13 levels; equal full hashes end up in a `Collide` bucket.
+const MAX_DEPTH: usize = 13;
+
+enum Slot<'a, K, V> {
+    Leaf { hash: u64, key: K, val: V },
+    Collide { hash: u64, items: Vector<'a, (K, V)> },
+    Node(CapArc<'a, Node<'a, K, V>>),
+}
+
+struct Node<'a, K, V> { bitmap: u32, slots: Vector<'a, Slot<'a, K, V>> }
+
+impl<'a, K: Clone, V: Clone> Slot<'a, K, V> {
+    fn clone_in(&self, alloc: Allocator<'a>) -> Result<Self, MemoryError> {
+        Ok(match self {
+            Slot::Leaf { hash, key, val } => Slot::Leaf { hash: *hash, key: key.clone(), val: val.clone() },
+            Slot::Collide { hash, items } => {
+                let mut v = Vector::with_capacity(alloc, items.len())?;
+                for (k, x) in items.iter() { v.push((k.clone(), x.clone()))?; }
+                Slot::Collide { hash: *hash, items: v }
+            }
+            Slot::Node(n) => Slot::Node(n.clone()),
+        })
+    }
+    fn hash(&self) -> u64 { match self { Slot::Leaf { hash, .. } | Slot::Collide { hash, .. } => *hash, Slot::Node(_) => unreachable!() } }
+}
+
+impl<'a, K: Clone, V: Clone> Node<'a, K, V> {
+    fn empty(alloc: Allocator<'a>) -> Result<Self, MemoryError> { Ok(Self { bitmap: 0, slots: Vector::with_capacity(alloc, 0)? }) }
+
+    // Shallow copy: leaves are cloned, child nodes are shared.
+    fn clone_in(&self, alloc: Allocator<'a>) -> Result<Self, MemoryError> {
+        let mut slots = Vector::with_capacity(alloc, self.slots.len())?;
+        for s in self.slots.iter() { slots.push(s.clone_in(alloc)?)?; }
+        Ok(Self { bitmap: self.bitmap, slots })
+    }
+
+    fn insert_slot(&mut self, bit: u32, pos: usize, s: Slot<'a, K, V>) -> Result<(), MemoryError> {
+        self.slots.push(s)?;
+        self.slots.as_mut_slice()[pos..].rotate_right(1);
+        self.bitmap |= bit;
+        Ok(())
+    }
+
+    fn remove_slot(&mut self, bit: u32, pos: usize) -> Slot<'a, K, V> {
+        self.slots.as_mut_slice()[pos..].rotate_left(1);
+        self.bitmap &= !bit;
+        self.slots.pop().unwrap()
+    }
+}
+
+fn index(hash: u64, shift: u32, bitmap: u32) -> (u32, usize) {
+    let bit = 1u32 << ((hash >> shift) & MASK);
+    (bit, (bitmap & (bit - 1)).count_ones() as usize)
+}
+
+// Copy-on-write: ensure `n` is uniquely owned before mutating through it.
+fn make_unique<'n, 'a, K: Clone, V: Clone>(n: &'n mut CapArc<'a, Node<'a, K, V>>, alloc: Allocator<'a>) -> Result<&'n mut Node<'a, K, V>, MemoryError> {
+    if CapArc::strong_count(n) != 1 { *n = CapArc::new(alloc, n.clone_in(alloc)?)?; }
+    Ok(CapArc::get_mut(n).unwrap())
+}
+
+/// Persistent hash map (HAMT) with structural sharing.
+///
+/// `fork` is O(1); mutations copy only the path from the root to the touched entry, so a fork and
+/// its parent keep sharing every untouched subtree.
+pub struct PersistentHashMap<'a, K: Eq + Hash + Clone, V: Clone> { root: CapArc<'a, Node<'a, K, V>>, len: usize, alloc: Allocator<'a> }
+
+unsafe impl<'a, K: Eq + Hash + Clone + Send + Sync, V: Clone + Send + Sync> Send for PersistentHashMap<'a, K, V> {}
+unsafe impl<'a, K: Eq + Hash + Clone + Send + Sync, V: Clone + Send + Sync> Sync for PersistentHashMap<'a, K, V> {}
+
+impl<'a, K: Eq + Hash + Clone, V: Clone> PersistentHashMap<'a, K, V> {
+    pub fn new(alloc: Allocator<'a>) -> Result<Self, MemoryError> { Ok(Self { root: CapArc::new(alloc, Node::empty(alloc)?)?, len: 0, alloc }) }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    fn hash_key(k: &K) -> u64 { let mut h = FnvHasher::new(); k.hash(&mut h); h.finish() }
+
+    /// O(1) snapshot; the result may allocate its future copies from a different allocator.
+    pub fn fork(&self, alloc: Allocator<'a>) -> Self { Self { root: self.root.clone(), len: self.len, alloc } }
+
+    /// True if both maps still share the same root node (neither has been written since the fork).
+    pub fn ptr_eq(&self, other: &Self) -> bool { CapArc::ptr_eq(&self.root, &other.root) }
+
+    pub fn get(&self, k: &K) -> Option<&V> {
+        let h = Self::hash_key(k);
+        let mut node: &Node<'a, K, V> = &self.root;
+        let mut shift = 0;
+        loop {
+            let (bit, pos) = index(h, shift, node.bitmap);
+            if node.bitmap & bit == 0 { return None; }
+            match &node.slots[pos] {
+                Slot::Leaf { hash, key, val } => return if *hash == h && key == k { Some(val) } else { None },
+                Slot::Collide { hash, items } => return if *hash == h { items.iter().find(|(x, _)| x == k).map(|(_, v)| v) } else { None },
+                Slot::Node(n) => { node = n; shift += BITS; }
+            }
+        }
+    }
+
+    pub fn contains_key(&self, k: &K) -> bool { self.get(k).is_some() }
+
+    /// Mutable access to a value, copying shared nodes on the way down.
+    pub fn get_mut(&mut self, k: &K) -> Result<Option<&mut V>, MemoryError> {
+        let h = Self::hash_key(k);
+        let root = make_unique(&mut self.root, self.alloc)?;
+        Self::get_mut_at(root, self.alloc, 0, h, k)
+    }
+
+    fn get_mut_at<'n>(node: &'n mut Node<'a, K, V>, alloc: Allocator<'a>, shift: u32, h: u64, k: &K) -> Result<Option<&'n mut V>, MemoryError> {
+        let (bit, pos) = index(h, shift, node.bitmap);
+        if node.bitmap & bit == 0 { return Ok(None); }
+        match &mut node.slots[pos] {
+            Slot::Leaf { hash, key, val } => Ok(if *hash == h && key == k { Some(val) } else { None }),
+            Slot::Collide { hash, items } => Ok(if *hash == h { items.iter_mut().find(|(x, _)| x == k).map(|(_, v)| v) } else { None }),
+            Slot::Node(n) => Self::get_mut_at(make_unique(n, alloc)?, alloc, shift + BITS, h, k),
+        }
+    }
+
+    /// Inserts or replaces; returns the previous value for `k`.
+    pub fn insert(&mut self, k: K, v: V) -> Result<Option<V>, MemoryError> {
+        let h = Self::hash_key(&k);
+        let alloc = self.alloc;
+        let root = make_unique(&mut self.root, alloc)?;
+        let old = Self::insert_at(root, alloc, 0, h, k, v)?;
+        if old.is_none() { self.len += 1; }
+        Ok(old)
+    }
+
+    fn insert_at(node: &mut Node<'a, K, V>, alloc: Allocator<'a>, shift: u32, h: u64, k: K, v: V) -> Result<Option<V>, MemoryError> {
+        let (bit, pos) = index(h, shift, node.bitmap);
+        if node.bitmap & bit == 0 {
+            node.insert_slot(bit, pos, Slot::Leaf { hash: h, key: k, val: v })?;
+            return Ok(None);
+        }
+        let slot = &mut node.slots[pos];
+        match slot {
+            Slot::Node(n) => return Self::insert_at(make_unique(n, alloc)?, alloc, shift + BITS, h, k, v),
+            Slot::Leaf { hash, key, val } if *hash == h && *key == k => return Ok(Some(core::mem::replace(val, v))),
+            Slot::Collide { hash, items } if *hash == h => {
+                if let Some((_, x)) = items.iter_mut().find(|(x, _)| *x == k) { return Ok(Some(core::mem::replace(x, v))); }
+                items.push((k, v))?;
+                return Ok(None);
+            }
+            _ => {}
+        }
+        // Occupied by a different key. Build the replacement from a copy of the resident entry so
+        // an allocation failure part-way leaves the map unchanged.
+        let resident = slot.clone_in(alloc)?;
+        *slot = if resident.hash() == h {
+            let Slot::Leaf { key, val, .. } = resident else { unreachable!() };
+            let mut items = Vector::with_capacity(alloc, 2)?;
+            items.push((key, val))?;
+            items.push((k, v))?;
+            Slot::Collide { hash: h, items }
+        } else {
+            Self::split(alloc, shift + BITS, resident, Slot::Leaf { hash: h, key: k, val: v })?
+        };
+        Ok(None)
+    }
+
+    // Builds the subtree holding two entries whose hashes agree on every bit above `shift`.
+    fn split(alloc: Allocator<'a>, shift: u32, a: Slot<'a, K, V>, b: Slot<'a, K, V>) -> Result<Slot<'a, K, V>, MemoryError> {
+        let ia = (a.hash() >> shift) & MASK;
+        let ib = (b.hash() >> shift) & MASK;
+        let mut node = Node { bitmap: 0, slots: Vector::with_capacity(alloc, 2)? };
+        if ia == ib {
+            node.bitmap = 1 << ia;
+            node.slots.push(Self::split(alloc, shift + BITS, a, b)?)?;
+        } else {
+            node.bitmap = (1 << ia) | (1 << ib);
+            let (lo, hi) = if ia < ib { (a, b) } else { (b, a) };
+            node.slots.push(lo)?;
+            node.slots.push(hi)?;
+        }
+        Ok(Slot::Node(CapArc::new(alloc, node)?))
+    }
+
+    pub fn remove(&mut self, k: &K) -> Result<Option<V>, MemoryError> {
+        let h = Self::hash_key(k);
+        let alloc = self.alloc;
+        let root = make_unique(&mut self.root, alloc)?;
+        let old = Self::remove_at(root, alloc, 0, h, k)?;
+        if old.is_some() { self.len -= 1; }
+        Ok(old)
+    }
+
+    fn remove_at(node: &mut Node<'a, K, V>, alloc: Allocator<'a>, shift: u32, h: u64, k: &K) -> Result<Option<V>, MemoryError> {
+        let (bit, pos) = index(h, shift, node.bitmap);
+        if node.bitmap & bit == 0 { return Ok(None); }
+        match &mut node.slots[pos] {
+            Slot::Leaf { hash, key, .. } => {
+                if *hash != h || key != k { return Ok(None); }
+                let Slot::Leaf { val, .. } = node.remove_slot(bit, pos) else { unreachable!() };
+                Ok(Some(val))
+            }
+            Slot::Collide { hash, items } => {
+                if *hash != h { return Ok(None); }
+                let Some(i) = items.iter().position(|(x, _)| x == k) else { return Ok(None) };
+                let (_, val) = items.swap_remove(i).unwrap();
+                if items.len() == 1 {
+                    let (key, v) = items.pop().unwrap();
+                    node.slots[pos] = Slot::Leaf { hash: h, key, val: v };
+                }
+                Ok(Some(val))
+            }
+            Slot::Node(n) => {
+                let child = make_unique(n, alloc)?;
+                let old = Self::remove_at(child, alloc, shift + BITS, h, k)?;
+                // Keep the trie canonical: drop empty children and pull a lone entry up a level.
+                if child.slots.is_empty() {
+                    node.remove_slot(bit, pos);
+                } else if child.slots.len() == 1 && !matches!(child.slots[0], Slot::Node(_)) {
+                    let only = child.slots.pop().unwrap();
+                    node.slots[pos] = only;
+                }
+                Ok(old)
+            }
+        }
+    }
+
+    pub fn clear(&mut self) -> Result<(), MemoryError> {
+        self.root = CapArc::new(self.alloc, Node::empty(self.alloc)?)?;
+        self.len = 0;
+        Ok(())
+    }
+
+    pub fn iter(&self) -> PersistentHashMapIter<'_, 'a, K, V> {
+        let mut it = PersistentHashMapIter { stack: [(&[], 0); MAX_DEPTH], depth: 1, bucket: [].iter() };
+        it.stack[0] = (self.root.slots.as_slice(), 0);
+        it
+    }
+}
+
+pub struct PersistentHashMapIter<'m, 'a, K, V> { stack: [(&'m [Slot<'a, K, V>], usize); MAX_DEPTH], depth: usize, bucket: core::slice::Iter<'m, (K, V)> }
+
+impl<'m, 'a, K, V> Iterator for PersistentHashMapIter<'m, 'a, K, V> {
+    type Item = (&'m K, &'m V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.bucket.next() { return Some((k, v)); }
+            if self.depth == 0 { return None; }
+            let (slots, i) = self.stack[self.depth - 1];
+            if i >= slots.len() { self.depth -= 1; continue; }
+            self.stack[self.depth - 1].1 = i + 1;
+            match &slots[i] {
+                Slot::Leaf { key, val, .. } => return Some((key, val)),
+                Slot::Collide { items, .. } => self.bucket = items.as_slice().iter(),
+                Slot::Node(n) => { self.stack[self.depth] = (n.slots.as_slice(), 0); self.depth += 1; }
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use cap_memory::{Allocator, MemoryError};
+use core::mem::MaybeUninit;
+use core::ptr::{read, drop_in_place};
+use crate::{CapArc, Vector};
+
+const CHUNK: usize = 64;
+
+// Fixed-size element block; every chunk but the last is full.
+struct Chunk<T> { len: usize, items: [MaybeUninit<T>; CHUNK] }
+
+impl<T> Chunk<T> {
+    fn new() -> Self { Self { len: 0, items: [const { MaybeUninit::uninit() }; CHUNK] } }
+    fn as_slice(&self) -> &[T] { unsafe { core::slice::from_raw_parts(self.items.as_ptr().cast::<T>(), self.len) } }
+    fn as_mut_slice(&mut self) -> &mut [T] { unsafe { core::slice::from_raw_parts_mut(self.items.as_mut_ptr().cast::<T>(), self.len) } }
+    fn push(&mut self, v: T) { self.items[self.len].write(v); self.len += 1; }
+    fn pop(&mut self) -> T { self.len -= 1; unsafe { read(self.items[self.len].as_ptr()) } }
+}
+
+impl<T: Clone> Clone for Chunk<T> {
+    fn clone(&self) -> Self {
+        let mut c = Self::new();
+        for v in self.as_slice() { c.push(v.clone()); }
+        c
+    }
+}
+
+impl<T> Drop for Chunk<T> {
+    fn drop(&mut self) { for i in 0..self.len { unsafe { drop_in_place(self.items[i].as_mut_ptr()); } } }
+}
+
+/// Chunked vector with copy-on-write chunks.
+///
+/// `fork` copies only the chunk table (one refcount bump per 64 elements); a chunk is cloned the
+/// first time either side writes to it while it is still shared.
+pub struct PersistentVector<'a, T: Clone> { spine: Vector<'a, CapArc<'a, Chunk<T>>>, len: usize, alloc: Allocator<'a> }
+
+unsafe impl<'a, T: Clone + Send + Sync> Send for PersistentVector<'a, T> {}
+unsafe impl<'a, T: Clone + Send + Sync> Sync for PersistentVector<'a, T> {}
+
+impl<'a, T: Clone> PersistentVector<'a, T> {
+    pub fn with_capacity(alloc: Allocator<'a>, capacity: usize) -> Result<Self, MemoryError> {
+        Ok(Self { spine: Vector::with_capacity(alloc, capacity.div_ceil(CHUNK))?, len: 0, alloc })
+    }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+    pub fn chunk_count(&self) -> usize { self.spine.len() }
+
+    /// Number of chunks physically shared with `other` (e.g. a fork that has not written there yet).
+    pub fn shared_chunks(&self, other: &Self) -> usize {
+        self.spine.iter().zip(other.spine.iter()).filter(|(a, b)| CapArc::ptr_eq(a, b)).count()
+    }
+
+    // Makes chunk `c` exclusively owned, cloning it into this vector's allocator if shared.
+    fn chunk_mut(&mut self, c: usize) -> Result<&mut Chunk<T>, MemoryError> {
+        let alloc = self.alloc;
+        let slot = &mut self.spine[c];
+        if CapArc::strong_count(slot) != 1 { *slot = CapArc::new(alloc, (**slot).clone())?; }
+        Ok(CapArc::get_mut(slot).unwrap())
+    }
+
+    pub fn get(&self, i: usize) -> Option<&T> {
+        if i >= self.len { return None; }
+        Some(&self.spine[i / CHUNK].as_slice()[i % CHUNK])
+    }
+
+    /// Mutable access; errors only if a shared chunk has to be copied and allocation fails.
+    pub fn get_mut(&mut self, i: usize) -> Result<Option<&mut T>, MemoryError> {
+        if i >= self.len { return Ok(None); }
+        Ok(Some(&mut self.chunk_mut(i / CHUNK)?.as_mut_slice()[i % CHUNK]))
+    }
+
+    pub fn push(&mut self, v: T) -> Result<(), MemoryError> {
+        if self.len.is_multiple_of(CHUNK) {
+            let c = CapArc::new(self.alloc, Chunk::new())?;
+            self.spine.push(c)?;
+        }
+        let last = self.spine.len() - 1;
+        self.chunk_mut(last)?.push(v);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<Option<T>, MemoryError> {
+        if self.len == 0 { return Ok(None); }
+        let last = self.spine.len() - 1;
+        let v = self.chunk_mut(last)?.pop();
+        self.len -= 1;
+        if self.len.is_multiple_of(CHUNK) { self.spine.pop(); }
+        Ok(Some(v))
+    }
+
+    pub fn swap_remove(&mut self, i: usize) -> Result<Option<T>, MemoryError> {
+        if i >= self.len { return Ok(None); }
+        // Copy the target chunk up front so a failed copy leaves the vector untouched.
+        self.chunk_mut(i / CHUNK)?;
+        let last = self.pop()?.unwrap();
+        if i == self.len { return Ok(Some(last)); }
+        let slot = &mut self.chunk_mut(i / CHUNK)?.as_mut_slice()[i % CHUNK];
+        Ok(Some(core::mem::replace(slot, last)))
+    }
+
+    pub fn clear(&mut self) {
+        while self.spine.pop().is_some() {}
+        self.len = 0;
+    }
+
+    /// O(len / 64) snapshot sharing every chunk with `self`; later writes on either side copy on demand.
+    pub fn fork(&self, alloc: Allocator<'a>) -> Result<Self, MemoryError> {
+        let mut spine = Vector::with_capacity(alloc, self.spine.len())?;
+        for c in self.spine.iter() { spine.push(c.clone())?; }
+        Ok(Self { spine, len: self.len, alloc })
+    }
+
+    pub fn iter(&self) -> PersistentVectorIter<'_, 'a, T> { PersistentVectorIter { chunks: self.spine.as_slice(), cur: [].iter() } }
+}
+
+impl<'a, T: Clone> core::ops::Index<usize> for PersistentVector<'a, T> {
+    type Output = T;
+    fn index(&self, i: usize) -> &T { self.get(i).expect("PersistentVector index out of bounds") }
+}
+
+pub struct PersistentVectorIter<'v, 'a, T> { chunks: &'v [CapArc<'a, Chunk<T>>], cur: core::slice::Iter<'v, T> }
+
+impl<'v, 'a, T> Iterator for PersistentVectorIter<'v, 'a, T> {
+    type Item = &'v T;
+    fn next(&mut self) -> Option<&'v T> {
+        loop {
+            if let Some(v) = self.cur.next() { return Some(v); }
+            let (first, rest) = self.chunks.split_first()?;
+            self.cur = first.as_slice().iter();
+            self.chunks = rest;
+        }
+    }
+}
+
@@ -0,0 +1,199 @@
+use cap_memory::{Allocator, MemoryError};
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+use crate::boxed::{alloc_layout, free_layout, CapUnsize};
+
+// The allocator travels with the shared block so handles stay one pointer wide.
+
+#[doc(hidden)]
+pub struct RcInner<'a, T: ?Sized> { strong: Cell<usize>, alloc: Allocator<'a>, value: T }
+
+#[doc(hidden)]
+pub struct ArcInner<'a, T: ?Sized> { strong: AtomicUsize, alloc: Allocator<'a>, value: T }
+
+/// Single-threaded reference-counted pointer allocated from an `Allocator`.
+pub struct CapRc<'a, T: ?Sized> { ptr: NonNull<RcInner<'a, T>>, _marker: PhantomData<RcInner<'a, T>> }
+
+impl<'a, T> CapRc<'a, T> {
+    pub fn new(alloc: Allocator<'a>, v: T) -> Result<Self, MemoryError> {
+        let p = alloc_layout(alloc, Layout::new::<RcInner<'a, T>>())?.cast::<RcInner<'a, T>>();
+        unsafe { ptr::write(p, RcInner { strong: Cell::new(1), alloc, value: v }); }
+        Ok(Self { ptr: unsafe { NonNull::new_unchecked(p) }, _marker: PhantomData })
+    }
+
+    /// Returns the value if this is the only handle, otherwise gives the handle back.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if this.inner().strong.get() != 1 { return Err(this); }
+        let p = this.ptr.as_ptr();
+        core::mem::forget(this);
+        unsafe {
+            let v = ptr::read(&(*p).value);
+            let alloc = (*p).alloc;
+            free_layout(alloc, p.cast::<u8>(), Layout::new::<RcInner<'a, T>>());
+            Ok(v)
+        }
+    }
+}
+
+impl<'a, T: ?Sized> CapRc<'a, T> {
+    fn inner(&self) -> &RcInner<'a, T> { unsafe { self.ptr.as_ref() } }
+    pub fn strong_count(this: &Self) -> usize { this.inner().strong.get() }
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool { ptr::addr_eq(a.ptr.as_ptr(), b.ptr.as_ptr()) }
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.inner().strong.get() == 1 { Some(unsafe { &mut (*this.ptr.as_ptr()).value }) } else { None }
+    }
+
+    /// # Safety
+    /// See `CapBox::unsize_with`; prefer `cap_unsize!`.
+    pub unsafe fn unsize_with<U: ?Sized, F: FnOnce(*mut RcInner<'a, T>) -> *mut RcInner<'a, U>>(this: Self, f: F) -> CapRc<'a, U> {
+        let p = this.ptr.as_ptr();
+        core::mem::forget(this);
+        let q = f(p);
+        debug_assert!(q.cast::<u8>() == p.cast::<u8>());
+        CapRc { ptr: NonNull::new_unchecked(q), _marker: PhantomData }
+    }
+}
+
+impl<'a, T: ?Sized> Clone for CapRc<'a, T> {
+    fn clone(&self) -> Self {
+        let s = &self.inner().strong;
+        s.set(s.get() + 1);
+        Self { ptr: self.ptr, _marker: PhantomData }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for CapRc<'a, T> {
+    fn drop(&mut self) {
+        let s = &self.inner().strong;
+        let n = s.get() - 1;
+        s.set(n);
+        if n != 0 { return; }
+        unsafe {
+            let p = self.ptr.as_ptr();
+            let layout = Layout::for_value(&*p);
+            let alloc = (*p).alloc;
+            ptr::drop_in_place(&mut (*p).value);
+            free_layout(alloc, p.cast::<u8>(), layout);
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for CapRc<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T { &self.inner().value }
+}
+
+impl<'a, T: ?Sized + core::fmt::Debug> core::fmt::Debug for CapRc<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result { (**self).fmt(f) }
+}
+
+impl<'a, T: ?Sized + 'a> CapUnsize<'a> for CapRc<'a, T> {
+    type Raw<U: ?Sized + 'a> = *mut RcInner<'a, U>;
+    type Out<U: ?Sized + 'a> = CapRc<'a, U>;
+    type In = *mut RcInner<'a, T>;
+    unsafe fn unsize_with<U: ?Sized + 'a, F: FnOnce(*mut RcInner<'a, T>) -> *mut RcInner<'a, U>>(self, f: F) -> CapRc<'a, U> { CapRc::unsize_with(self, f) }
+}
+
+/// Thread-safe reference-counted pointer allocated from an `Allocator`.
+///
+/// The last handle to drop frees the block, on whichever thread that happens, so the allocator
+/// resource must tolerate cross-thread frees.
+pub struct CapArc<'a, T: ?Sized> { ptr: NonNull<ArcInner<'a, T>>, _marker: PhantomData<ArcInner<'a, T>> }
+
+unsafe impl<'a, T: ?Sized + Send + Sync> Send for CapArc<'a, T> {}
+unsafe impl<'a, T: ?Sized + Send + Sync> Sync for CapArc<'a, T> {}
+
+impl<'a, T> CapArc<'a, T> {
+    pub fn new(alloc: Allocator<'a>, v: T) -> Result<Self, MemoryError> {
+        let p = alloc_layout(alloc, Layout::new::<ArcInner<'a, T>>())?.cast::<ArcInner<'a, T>>();
+        unsafe { ptr::write(p, ArcInner { strong: AtomicUsize::new(1), alloc, value: v }); }
+        Ok(Self { ptr: unsafe { NonNull::new_unchecked(p) }, _marker: PhantomData })
+    }
+
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if this.inner().strong.compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed).is_err() { return Err(this); }
+        let p = this.ptr.as_ptr();
+        core::mem::forget(this);
+        unsafe {
+            let v = ptr::read(&(*p).value);
+            let alloc = (*p).alloc;
+            free_layout(alloc, p.cast::<u8>(), Layout::new::<ArcInner<'a, T>>());
+            Ok(v)
+        }
+    }
+
+    /// Leaks the handle as a pointer to the value; `from_raw` takes the reference back.
+    pub fn into_raw(this: Self) -> *const T {
+        let p = Self::as_ptr(&this);
+        core::mem::forget(this);
+        p
+    }
+
+    /// # Safety
+    /// `p` must come from `into_raw` on a `CapArc<'a, T>`, and each `into_raw` is undone at most once.
+    pub unsafe fn from_raw(p: *const T) -> Self {
+        let inner = p.byte_sub(core::mem::offset_of!(ArcInner<'a, T>, value)) as *mut ArcInner<'a, T>;
+        Self { ptr: NonNull::new_unchecked(inner), _marker: PhantomData }
+    }
+}
+
+impl<'a, T: ?Sized> CapArc<'a, T> {
+    fn inner(&self) -> &ArcInner<'a, T> { unsafe { self.ptr.as_ref() } }
+    pub fn as_ptr(this: &Self) -> *const T { unsafe { &raw const (*this.ptr.as_ptr()).value } }
+    pub fn strong_count(this: &Self) -> usize { this.inner().strong.load(Ordering::Acquire) }
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool { ptr::addr_eq(a.ptr.as_ptr(), b.ptr.as_ptr()) }
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.inner().strong.load(Ordering::Acquire) == 1 { Some(unsafe { &mut (*this.ptr.as_ptr()).value }) } else { None }
+    }
+
+    /// # Safety
+    /// See `CapBox::unsize_with`; prefer `cap_unsize!`.
+    pub unsafe fn unsize_with<U: ?Sized, F: FnOnce(*mut ArcInner<'a, T>) -> *mut ArcInner<'a, U>>(this: Self, f: F) -> CapArc<'a, U> {
+        let p = this.ptr.as_ptr();
+        core::mem::forget(this);
+        let q = f(p);
+        debug_assert!(q.cast::<u8>() == p.cast::<u8>());
+        CapArc { ptr: NonNull::new_unchecked(q), _marker: PhantomData }
+    }
+}
+
+impl<'a, T: ?Sized> Clone for CapArc<'a, T> {
+    fn clone(&self) -> Self {
+        self.inner().strong.fetch_add(1, Ordering::Relaxed);
+        Self { ptr: self.ptr, _marker: PhantomData }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for CapArc<'a, T> {
+    fn drop(&mut self) {
+        if self.inner().strong.fetch_sub(1, Ordering::Release) != 1 { return; }
+        fence(Ordering::Acquire);
+        unsafe {
+            let p = self.ptr.as_ptr();
+            let layout = Layout::for_value(&*p);
+            let alloc = (*p).alloc;
+            ptr::drop_in_place(&mut (*p).value);
+            free_layout(alloc, p.cast::<u8>(), layout);
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for CapArc<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T { &self.inner().value }
+}
+
+impl<'a, T: ?Sized + core::fmt::Debug> core::fmt::Debug for CapArc<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result { (**self).fmt(f) }
+}
+
+impl<'a, T: ?Sized + 'a> CapUnsize<'a> for CapArc<'a, T> {
+    type Raw<U: ?Sized + 'a> = *mut ArcInner<'a, U>;
+    type Out<U: ?Sized + 'a> = CapArc<'a, U>;
+    type In = *mut ArcInner<'a, T>;
+    unsafe fn unsize_with<U: ?Sized + 'a, F: FnOnce(*mut ArcInner<'a, T>) -> *mut ArcInner<'a, U>>(self, f: F) -> CapArc<'a, U> { CapArc::unsize_with(self, f) }
+}
@@ -1,5 +1,5 @@
 use cap_memory::{Allocator, MemoryBlock, MemoryError};
-use core::ops::{Deref, DerefMut};
+use core::ops::Deref;
 
 const SSO_CAP: usize = 24;
 
@@ -19,6 +19,20 @@ impl<'a> CapString<'a> {
     pub fn from_str(alloc: Allocator<'a>, s: &str) -> Result<Self, MemoryError> {
         let mut cs = Self::with_capacity(alloc, s.len())?; cs.push_str(s)?; Ok(cs)
     }
+    /// Copies `bytes` after validating them as UTF-8; invalid input is `InvalidArgument`.
+    pub fn from_utf8(alloc: Allocator<'a>, bytes: &[u8]) -> Result<Self, MemoryError> {
+        let s = core::str::from_utf8(bytes).map_err(|_| MemoryError::InvalidArgument)?;
+        Self::from_str(alloc, s)
+    }
+    /// Copies `bytes`, replacing each invalid UTF-8 sequence with U+FFFD.
+    pub fn from_utf8_lossy(alloc: Allocator<'a>, bytes: &[u8]) -> Result<Self, MemoryError> {
+        let mut cs = Self::with_capacity(alloc, bytes.len())?;
+        for chunk in bytes.utf8_chunks() {
+            cs.push_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() { cs.push('\u{FFFD}')?; }
+        }
+        Ok(cs)
+    }
     pub fn as_bytes(&self) -> &[u8] {
         match &self.repr {
             Repr::Inline { buf, len } => &buf[..*len],
@@ -28,38 +42,124 @@ impl<'a> CapString<'a> {
     pub fn as_str(&self) -> &str {
         unsafe { core::str::from_utf8_unchecked(self.as_bytes()) }
     }
-    pub fn push_str(&mut self, s: &str) -> Result<(), MemoryError> {
+    pub fn len(&self) -> usize { match &self.repr { Repr::Inline { len, .. } | Repr::Heap { len, .. } => *len } }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+    pub fn capacity(&self) -> usize { match &self.repr { Repr::Inline { .. } => SSO_CAP, Repr::Heap { cap, .. } => *cap } }
+    /// Number of Unicode scalar values (not bytes).
+    pub fn char_count(&self) -> usize { self.as_str().chars().count() }
+
+    // Raw view of the whole buffer; bytes past `len` are uninitialised scratch space.
+    fn buf_mut(&mut self) -> (*mut u8, &mut usize) {
         match &mut self.repr {
-            Repr::Inline { buf, len } => {
-                let need = (*len).checked_add(s.len()).ok_or(MemoryError::Failed)?;
-                if need <= SSO_CAP {
-                    unsafe { core::ptr::copy_nonoverlapping(s.as_ptr(), buf.as_mut_ptr().add(*len), s.len()); }
-                    *len += s.len();
-                    Ok(())
-                } else {
-                    let nb = self.alloc.alloc(need, 1)?;
-                    unsafe {
-                        core::ptr::copy_nonoverlapping(buf.as_ptr(), nb.ptr, *len);
-                        core::ptr::copy_nonoverlapping(s.as_ptr(), nb.ptr.add(*len), s.len());
-                    }
-                    self.repr = Repr::Heap { ptr: nb.ptr, len: need, cap: need, blk: nb, _marker: core::marker::PhantomData };
-                    Ok(())
-                }
+            Repr::Inline { buf, len } => (buf.as_mut_ptr(), len),
+            Repr::Heap { ptr, len, .. } => (*ptr, len),
+        }
+    }
+
+    /// Ensures room for `additional` more bytes, growing geometrically once on the heap.
+    pub fn reserve(&mut self, additional: usize) -> Result<(), MemoryError> {
+        let len = self.len();
+        let need = len.checked_add(additional).ok_or(MemoryError::Failed)?;
+        match &mut self.repr {
+            Repr::Inline { buf, .. } => {
+                if need <= SSO_CAP { return Ok(()); }
+                let nb = self.alloc.alloc(need, 1)?;
+                unsafe { core::ptr::copy_nonoverlapping(buf.as_ptr(), nb.ptr, len); }
+                self.repr = Repr::Heap { ptr: nb.ptr, len, cap: need, blk: nb, _marker: core::marker::PhantomData };
+                Ok(())
             }
-            Repr::Heap { ptr, len, cap, blk, .. } => {
-                let need = (*len).checked_add(s.len()).ok_or(MemoryError::Failed)?;
-                if need > *cap {
-                    let nb = self.alloc.realloc(*blk, need, 1)?;
-                    *ptr = nb.ptr;
-                    *cap = need;
-                    *blk = nb;
-                }
-                unsafe { core::ptr::copy_nonoverlapping(s.as_ptr(), (*ptr).add(*len), s.len()); }
-                *len += s.len();
+            Repr::Heap { ptr, cap, blk, .. } => {
+                if need <= *cap { return Ok(()); }
+                let new_cap = need.max(cap.saturating_mul(2));
+                let nb = self.alloc.realloc(*blk, new_cap, 1)?;
+                *ptr = nb.ptr;
+                *cap = new_cap;
+                *blk = nb;
                 Ok(())
             }
         }
     }
+
+    pub fn push_str(&mut self, s: &str) -> Result<(), MemoryError> {
+        self.reserve(s.len())?;
+        let (p, len) = self.buf_mut();
+        unsafe { core::ptr::copy_nonoverlapping(s.as_ptr(), p.add(*len), s.len()); }
+        *len += s.len();
+        Ok(())
+    }
+    pub fn push(&mut self, c: char) -> Result<(), MemoryError> {
+        let mut tmp = [0u8; 4];
+        self.push_str(c.encode_utf8(&mut tmp))
+    }
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.as_str().chars().next_back()?;
+        let (_, len) = self.buf_mut();
+        *len -= c.len_utf8();
+        Some(c)
+    }
+
+    /// Inserts `s` at byte offset `idx`, which must lie on a char boundary.
+    pub fn insert_str(&mut self, idx: usize, s: &str) -> Result<(), MemoryError> {
+        if !self.as_str().is_char_boundary(idx) { return Err(MemoryError::InvalidArgument); }
+        self.reserve(s.len())?;
+        let (p, len) = self.buf_mut();
+        unsafe {
+            core::ptr::copy(p.add(idx), p.add(idx + s.len()), *len - idx);
+            core::ptr::copy_nonoverlapping(s.as_ptr(), p.add(idx), s.len());
+        }
+        *len += s.len();
+        Ok(())
+    }
+    pub fn insert(&mut self, idx: usize, c: char) -> Result<(), MemoryError> {
+        let mut tmp = [0u8; 4];
+        self.insert_str(idx, c.encode_utf8(&mut tmp))
+    }
+
+    /// Removes the byte range `start..end` (both on char boundaries).
+    pub fn remove_range(&mut self, start: usize, end: usize) -> Result<(), MemoryError> {
+        let s = self.as_str();
+        if start > end || !s.is_char_boundary(start) || !s.is_char_boundary(end) { return Err(MemoryError::InvalidArgument); }
+        let (p, len) = self.buf_mut();
+        unsafe { core::ptr::copy(p.add(end), p.add(start), *len - end); }
+        *len -= end - start;
+        Ok(())
+    }
+
+    /// Shortens to `new_len` bytes; no-op if already shorter. Panics if `new_len` is not on a char boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len >= self.len() { return; }
+        assert!(self.as_str().is_char_boundary(new_len), "CapString::truncate: not a char boundary");
+        let (_, len) = self.buf_mut();
+        *len = new_len;
+    }
+
+    /// Splits off the tail starting at byte `at` into a new string from the same allocator.
+    pub fn split_off(&mut self, at: usize) -> Result<CapString<'a>, MemoryError> {
+        if !self.as_str().is_char_boundary(at) { return Err(MemoryError::InvalidArgument); }
+        let tail = Self::from_str(self.alloc, &self.as_str()[at..])?;
+        self.truncate(at);
+        Ok(tail)
+    }
+
+    pub fn find(&self, pat: &str) -> Option<usize> { self.as_str().find(pat) }
+    pub fn rfind(&self, pat: &str) -> Option<usize> { self.as_str().rfind(pat) }
+    pub fn contains(&self, pat: &str) -> bool { self.as_str().contains(pat) }
+    pub fn split<'s>(&'s self, sep: &'s str) -> core::str::Split<'s, &'s str> { self.as_str().split(sep) }
+    pub fn split_once<'s>(&'s self, sep: &str) -> Option<(&'s str, &'s str)> { self.as_str().split_once(sep) }
+
+    /// Replaces every occurrence of `from` with `to` in place.
+    pub fn replace_all(&mut self, from: &str, to: &str) -> Result<(), MemoryError> {
+        if from.is_empty() { return Ok(()); }
+        let mut at = 0usize;
+        while let Some(off) = self.as_str()[at..].find(from) {
+            let i = at + off;
+            self.remove_range(i, i + from.len())?;
+            self.insert_str(i, to)?;
+            at = i + to.len();
+        }
+        Ok(())
+    }
+
     pub fn clear(&mut self) {
         match &mut self.repr {
             Repr::Inline { len, .. } => { *len = 0; }
@@ -79,6 +179,23 @@ impl<'a> Deref for CapString<'a> {
     fn deref(&self) -> &Self::Target { self.as_str() }
 }
 
+impl<'a> core::fmt::Write for CapString<'a> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result { self.push_str(s).map_err(|_| core::fmt::Error) }
+    fn write_char(&mut self, c: char) -> core::fmt::Result { self.push(c).map_err(|_| core::fmt::Error) }
+}
+
+impl<'a> PartialEq for CapString<'a> {
+    fn eq(&self, other: &Self) -> bool { self.as_str() == other.as_str() }
+}
+
+impl<'a> PartialEq<str> for CapString<'a> {
+    fn eq(&self, other: &str) -> bool { self.as_str() == other }
+}
+
+impl<'a> PartialEq<&str> for CapString<'a> {
+    fn eq(&self, other: &&str) -> bool { self.as_str() == *other }
+}
+
 impl<'a> core::fmt::Display for CapString<'a> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", self.as_str())
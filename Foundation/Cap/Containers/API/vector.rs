@@ -12,13 +12,13 @@ impl<'a, T> Vector<'a, T> {
     pub fn with_capacity(alloc: Allocator<'a>, capacity: usize) -> Result<Self, MemoryError> {
         let bytes = capacity.checked_mul(size_of::<T>()).ok_or(MemoryError::Failed)?;
         let blk = if bytes == 0 { MemoryBlock::empty() } else { alloc.alloc(bytes, core::mem::align_of::<T>())? };
-        let ptr = if blk.is_empty() { core::ptr::null_mut() } else { blk.ptr.cast::<T>() };
+        let ptr = if blk.is_empty() { core::ptr::NonNull::<T>::dangling().as_ptr() } else { blk.ptr.cast::<T>() };
         Ok(Self { ptr, len: 0, cap: capacity, blk, alloc })
     }
     pub fn with_capacity_aligned(alloc: Allocator<'a>, capacity: usize, align: usize) -> Result<Self, MemoryError> {
         let bytes = capacity.checked_mul(size_of::<T>()).ok_or(MemoryError::Failed)?;
         let blk = if bytes == 0 { MemoryBlock::empty() } else { alloc.alloc(bytes, align)? };
-        let ptr = if blk.is_empty() { core::ptr::null_mut() } else { blk.ptr.cast::<T>() };
+        let ptr = if blk.is_empty() { core::ptr::NonNull::<T>::dangling().as_ptr() } else { blk.ptr.cast::<T>() };
         Ok(Self { ptr, len: 0, cap: capacity, blk, alloc })
     }
     
@@ -57,6 +57,10 @@ impl<'a, T> Vector<'a, T> {
         Some(unsafe { read(self.ptr.add(self.len)) })
     }
     
+    pub fn truncate(&mut self, len: usize) {
+        while self.len > len { drop(self.pop()); }
+    }
+    
     pub fn swap_remove(&mut self, index: usize) -> Option<T> {
         if index >= self.len { return None; }
         unsafe {
@@ -96,6 +100,16 @@ impl<'a, T> DerefMut for Vector<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target { self.as_mut_slice() }
 }
 
+impl<'a, T: core::fmt::Debug> core::fmt::Debug for Vector<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result { self.as_slice().fmt(f) }
+}
+
+impl<'a, 'b, T: PartialEq> PartialEq<Vector<'b, T>> for Vector<'a, T> {
+    fn eq(&self, other: &Vector<'b, T>) -> bool { self.as_slice() == other.as_slice() }
+}
+
+impl<'a, T: Eq> Eq for Vector<'a, T> {}
+
 impl<'a, T> Index<usize> for Vector<'a, T> {
     type Output = T;
     fn index(&self, index: usize) -> &Self::Output { &self.as_slice()[index] }
@@ -25,12 +25,71 @@ fn main() {
     let mut s = cap_containers::CapString::from_str(a, "hello").unwrap();
     s.push_str(" world").unwrap();
     println!("string {}", s.as_bytes().len());
+    {
+        use core::fmt::Write;
+        write!(s, " #{} {:.1}", 7, 2.5).unwrap();
+        s.insert_str(5, ",").unwrap();
+        let parts: Vec<&str> = s.split(" ").collect();
+        println!("string fmt {:?} find={:?} parts={}", s.as_str(), s.find("world"), parts.len());
+        s.truncate(5);
+        println!("string truncate {}", s);
+    }
 
     let mut m = cap_containers::HashMap::<u64, u64>::with_capacity(a, 64).unwrap();
     m.insert(42, 99).unwrap();
-    println!("hash {}", m.get(42).unwrap());
+    println!("hash {}", m.get(&42).unwrap());
 
     let mut d = cap_containers::Deque::<i32>::with_capacity(a, 8).unwrap();
     for i in 0..5 { d.push_back(i).unwrap(); }
     println!("deque {} {}", d.len(), d.get(2).unwrap());
+
+    let mut bs = cap_containers::BitSet::with_capacity(a, 64).unwrap();
+    for i in [1usize, 5, 130, 700] { bs.insert(i).unwrap(); }
+    let mut req = cap_containers::BitSet::with_capacity(a, 0).unwrap();
+    req.insert(5).unwrap(); req.insert(130).unwrap();
+    let ones: Vec<usize> = bs.ones().collect();
+    println!("bitset {} {:?} subset={} max_bit_err={}", bs.count_ones(), ones, req.is_subset(&bs), bs.insert(usize::MAX).is_err());
+    let mut fm = cap_containers::FixedBitSet::<2>::new();
+    fm.insert(3); fm.insert(64);
+    let mut fq = fm; fq.andnot(&cap_containers::FixedBitSet::<2>::new());
+    println!("fixed_bitset {} {}", fq.count_ones(), fm.is_subset(&fq));
+
+    let mut bt = cap_containers::BTreeMap::<u32, u32>::new(a);
+    for i in 0..100u32 { bt.insert((i * 37) % 100, i).unwrap(); }
+    bt.remove(&50);
+    let r: Vec<u32> = bt.range(48..53).map(|(k, _)| *k).collect();
+    #[allow(clippy::reversed_empty_ranges)]
+    let inverted = bt.range(5..3).count() + bt.range((core::ops::Bound::Excluded(3), core::ops::Bound::Excluded(3))).count();
+    println!("btree {} {:?} first={:?} inverted={}", bt.len(), r, bt.first_key_value().map(|(k, _)| *k), inverted);
+
+    let mut pq = cap_containers::PriorityQueue::<u64, &str>::with_capacity(a, 8).unwrap();
+    pq.push(30, "c").unwrap(); pq.push(10, "a").unwrap(); pq.push(20, "b").unwrap();
+    println!("pqueue {:?} {:?}", pq.pop(), pq.pop_if_le(&15));
+
+    let mut sys = SystemMemoryResource;
+    let sa = Allocator::new(&mut sys);
+    let bx: CapBox<dyn core::fmt::Display> = cap_unsize!(CapBox::new(sa, 42u32).unwrap(), dyn core::fmt::Display);
+    let sl: CapBox<[u8]> = cap_unsize!(CapBox::new(sa, [1u8, 2, 3]).unwrap(), [u8]);
+    let rc = CapRc::new(sa, 7i32).unwrap();
+    let rc2 = rc.clone();
+    let arc = CapArc::new(sa, core::sync::atomic::AtomicU32::new(0)).unwrap();
+    std::thread::scope(|sc| for _ in 0..4 { let c = arc.clone(); sc.spawn(move || { c.fetch_add(1, core::sync::atomic::Ordering::Relaxed); }); });
+    println!("boxed {} {} rc={} {} arc={}", bx, sl.len(), CapRc::strong_count(&rc), *rc2, arc.load(core::sync::atomic::Ordering::Relaxed));
+
+    let cl = ChaseLevDeque::<u32>::with_capacity(sa, 4).unwrap();
+    for i in 0..100u32 { cl.push_bottom(i).unwrap(); }
+    let (mut x, mut y) = (0u32, 0u32);
+    let (popped, stolen) = (cl.pop_bottom(&mut x), cl.steal(&mut y));
+    println!("chase_lev cap={} len={} {}:{} {}:{}", cl.capacity(), cl.len(), popped, x, stolen, y);
+
+    let mut pv = PersistentVector::with_capacity(sa, 1000).unwrap();
+    for i in 0..1000u32 { pv.push(i).unwrap(); }
+    let mut snap = pv.fork(sa).unwrap();
+    *snap.get_mut(3).unwrap().unwrap() = 99;
+    println!("pvec {} {} shared={}/{}", pv[3], snap[3], snap.shared_chunks(&pv), pv.chunk_count());
+    let mut pm = PersistentHashMap::<u32, u32>::new(sa).unwrap();
+    for i in 0..1000u32 { pm.insert(i, i * 2).unwrap(); }
+    let old = pm.fork(sa);
+    pm.remove(&7).unwrap();
+    println!("pmap {} {} {:?} {:?}", pm.len(), old.len(), pm.get(&7), old.get(&7));
 }
@@ -7,10 +7,12 @@ mod backend;
 #[cfg(not(target_os = "windows"))]
 mod backend {
     use super::*;
-    pub struct Iocp { pub port: *mut c_void }
+    pub use super::Iocp;
     pub fn attach() -> Option<Iocp> { None }
     pub fn register_handle(_iocp: &Iocp, _h: *mut c_void) -> bool { false }
     pub fn get_queued(_iocp: &Iocp, _timeout_ms: u32) -> Option<(*mut c_void, u32, u32)> { None }
+    pub fn read_overlapped(_h: *mut c_void, _buf: *mut c_void, _len: u32, _offset: u64, _ov: *mut c_void) -> bool { false }
+    pub fn write_overlapped(_h: *mut c_void, _buf: *const c_void, _len: u32, _offset: u64, _ov: *mut c_void) -> bool { false }
 }
 
 pub struct Iocp { pub port: *mut c_void }
@@ -22,5 +24,19 @@ pub fn get_queued(iocp: &Iocp, timeout_ms: u32) -> Option<(*mut c_void, u32, u32
 #[repr(C)]
 pub struct Overlapped { pub internal: usize, pub internal_high: usize, pub offset: u32, pub offset_high: u32, pub h_event: *mut c_void }
 
+// NTSTATUS left in `internal` by a read that starts at or past end of file.
+const STATUS_END_OF_FILE: usize = 0xC000_0011;
+
+impl Overlapped {
+    pub const fn new() -> Self { Self { internal: 0, internal_high: 0, offset: 0, offset_high: 0, h_event: std::ptr::null_mut() } }
+    /// Bytes moved by a completed operation, or `None` if it failed. A read at end of file counts as 0 bytes.
+    pub fn transferred(&self) -> Option<usize> {
+        match self.internal { 0 => Some(self.internal_high), STATUS_END_OF_FILE => Some(0), _ => None }
+    }
+}
+
+impl Default for Overlapped { fn default() -> Self { Self::new() } }
+
+/// Queues a read; true means the completion will arrive through the port the handle is registered with.
 pub fn read_overlapped(h: prm_file::FileHandle, buf: &mut [u8], offset: u64, ov: &mut Overlapped) -> bool { backend::read_overlapped(h.0, buf.as_mut_ptr() as *mut c_void, buf.len() as u32, offset, ov as *mut Overlapped as *mut c_void) }
 pub fn write_overlapped(h: prm_file::FileHandle, buf: &[u8], offset: u64, ov: &mut Overlapped) -> bool { backend::write_overlapped(h.0, buf.as_ptr() as *const c_void, buf.len() as u32, offset, ov as *mut Overlapped as *mut c_void) }
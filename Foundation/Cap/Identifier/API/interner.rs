@@ -0,0 +1,114 @@
+use cap_containers::{BTreeMap, Vector};
+use cap_memory::{Allocator, IMemoryResource, MemoryError, SystemMemoryResource};
+use prm_sync::{SpinLock, ScopedLock};
+use crate::string_id64;
+
+/// Compact handle for an interned string. Index into the global interner, stable for the process lifetime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(pub u32);
+
+const CHUNK_BYTES: usize = 16 << 10;
+
+struct Entry { ptr: *const u8, len: usize, id64: u64 }
+
+// Strings live in append-only chunks that are never freed, so resolved `&'static str`s stay valid.
+struct Interner {
+    alloc: Allocator<'static>,
+    by_id: BTreeMap<'static, u64, u32>,
+    entries: Vector<'static, Entry>,
+    chunk: *mut u8,
+    chunk_used: usize,
+    chunk_cap: usize,
+}
+
+impl Interner {
+    fn new(alloc: Allocator<'static>) -> Result<Self, MemoryError> {
+        Ok(Self { alloc, by_id: BTreeMap::new(alloc), entries: Vector::with_capacity(alloc, 256)?, chunk: core::ptr::null_mut(), chunk_used: 0, chunk_cap: 0 })
+    }
+
+    fn store(&mut self, s: &str) -> Result<*const u8, MemoryError> {
+        if s.is_empty() { return Ok(core::ptr::NonNull::<u8>::dangling().as_ptr()); }
+        if s.len() > self.chunk_cap - self.chunk_used {
+            let cap = s.len().max(CHUNK_BYTES);
+            let blk = self.alloc.alloc(cap, 1)?;
+            self.chunk = blk.ptr;
+            self.chunk_used = 0;
+            self.chunk_cap = cap;
+        }
+        let p = unsafe { self.chunk.add(self.chunk_used) };
+        unsafe { core::ptr::copy_nonoverlapping(s.as_ptr(), p, s.len()); }
+        self.chunk_used += s.len();
+        Ok(p)
+    }
+
+    fn text(&self, i: u32) -> &'static str {
+        let e = &self.entries[i as usize];
+        unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(e.ptr, e.len)) }
+    }
+
+    fn intern(&mut self, s: &str) -> Result<Symbol, MemoryError> {
+        let id = string_id64(s);
+        if let Some(&i) = self.by_id.get(&id) {
+            // 64-bit id collision between two different strings: refuse rather than alias them.
+            if self.text(i) != s { return Err(MemoryError::Failed); }
+            return Ok(Symbol(i));
+        }
+        let i = u32::try_from(self.entries.len()).map_err(|_| MemoryError::OutOfMemory)?;
+        let ptr = self.store(s)?;
+        // On failure the text was the last thing stored, so handing its bytes back undoes it.
+        if let Err(e) = self.entries.push(Entry { ptr, len: s.len(), id64: id }) {
+            self.chunk_used -= s.len();
+            return Err(e);
+        }
+        if let Err(e) = self.by_id.insert(id, i) {
+            // Undo the push too, so every entry stays reachable by id.
+            self.entries.pop();
+            self.chunk_used -= s.len();
+            return Err(e);
+        }
+        Ok(Symbol(i))
+    }
+}
+
+static LOCK: SpinLock = SpinLock::new();
+static mut SYSTEM: SystemMemoryResource = SystemMemoryResource;
+static mut INTERNER: Option<Interner> = None;
+
+fn with_interner<R>(f: impl FnOnce(&mut Interner) -> R) -> Result<R, MemoryError> {
+    let _g = ScopedLock::new(&LOCK);
+    unsafe {
+        let slot = &mut *core::ptr::addr_of_mut!(INTERNER);
+        if slot.is_none() {
+            let res: *mut (dyn IMemoryResource + 'static) = core::ptr::addr_of_mut!(SYSTEM);
+            *slot = Some(Interner::new(Allocator::from_raw(res))?);
+        }
+        Ok(f(slot.as_mut().unwrap()))
+    }
+}
+
+/// Interns `s`, returning the same `Symbol` for equal strings. Thread-safe.
+pub fn intern(s: &str) -> Result<Symbol, MemoryError> { with_interner(|i| i.intern(s))? }
+
+/// Looks up an already-interned string without inserting it.
+pub fn lookup_symbol(s: &str) -> Option<Symbol> {
+    let id = string_id64(s);
+    with_interner(|i| i.by_id.get(&id).copied().filter(|&x| i.text(x) == s).map(Symbol)).ok().flatten()
+}
+
+/// Reverse lookup for logs and debugging; `None` for symbols not produced by `intern`.
+pub fn resolve_symbol(sym: Symbol) -> Option<&'static str> {
+    with_interner(|i| if (sym.0 as usize) < i.entries.len() { Some(i.text(sym.0)) } else { None }).ok().flatten()
+}
+
+/// Number of distinct strings interned so far.
+pub fn interned_count() -> usize { with_interner(|i| i.entries.len()).unwrap_or(0) }
+
+impl Symbol {
+    pub fn as_str(self) -> &'static str { resolve_symbol(self).unwrap_or("<unknown symbol>") }
+    /// The stable `string_id64` of the text, suitable for serialization across runs.
+    pub fn id64(self) -> u64 { with_interner(|i| i.entries.get(self.0 as usize).map(|e| e.id64)).ok().flatten().unwrap_or(0) }
+}
+
+impl core::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result { f.write_str(self.as_str()) }
+}
@@ -1,6 +1,9 @@
 use cap_random::*;
 use cap_crypto::*;
 
+mod interner;
+pub use interner::*;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Guid { pub bytes: [u8; 16] }
 
@@ -7,5 +7,9 @@ fn main() {
     let s = uuid_to_string(&g);
     let id = string_id64("example");
     println!("uuid {} id64 {}", s, id);
+    let a = intern("Transform").unwrap();
+    let b = intern("Velocity").unwrap();
+    let c = intern("Transform").unwrap();
+    println!("intern {} {} same={} {} id64={}", a.0, b.0, a == c, resolve_symbol(b).unwrap(), a.id64() == string_id64("Transform"));
 }
 
@@ -5,10 +5,15 @@ pub use types::*;
 #[path = "../Impl/Windows/File.rs"]
 mod backend;
 
-#[cfg(not(target_os = "windows"))]
+#[cfg(target_os = "linux")]
+#[path = "../Impl/Linux/File.rs"]
+mod backend;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
 mod backend {
     use super::*;
     pub fn impl_open(_path: &str, _mode: FileOpenMode, _share: FileShareMode) -> Result<FileHandle, FileError> { Err(FileError::Unsupported) }
+    pub fn impl_open_overlapped(_path: &str, _mode: FileOpenMode, _share: FileShareMode) -> Result<FileHandle, FileError> { Err(FileError::Unsupported) }
     pub fn impl_close(_h: FileHandle) -> Result<(), FileError> { Err(FileError::Unsupported) }
     pub fn impl_read(_h: FileHandle, _buf: &mut [u8]) -> Result<usize, FileError> { Err(FileError::Unsupported) }
     pub fn impl_write(_h: FileHandle, _buf: &[u8]) -> Result<usize, FileError> { Err(FileError::Unsupported) }
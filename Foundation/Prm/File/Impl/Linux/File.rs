@@ -0,0 +1,149 @@
+#![allow(non_camel_case_types)]
+use std::ffi::{c_char, c_void, CString};
+use crate::*;
+
+extern "C" {
+    fn open(path: *const c_char, flags: i32, ...) -> i32;
+    fn close(fd: i32) -> i32;
+    fn read(fd: i32, buf: *mut c_void, count: usize) -> isize;
+    fn write(fd: i32, buf: *const c_void, count: usize) -> isize;
+    fn lseek(fd: i32, offset: i64, whence: i32) -> i64;
+    fn fsync(fd: i32) -> i32;
+    fn ftruncate(fd: i32, len: i64) -> i32;
+    fn flock(fd: i32, op: i32) -> i32;
+    fn statx(dirfd: i32, path: *const c_char, flags: i32, mask: u32, buf: *mut statx_t) -> i32;
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, off: i64) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+    fn msync(addr: *mut c_void, len: usize, flags: i32) -> i32;
+    fn sysconf(name: i32) -> i64;
+    fn __errno_location() -> *mut i32;
+}
+
+// Same layout on every architecture, unlike `struct stat`; only the size is read here.
+#[repr(C)]
+struct statx_t { mask: u32, blksize: u32, attributes: u64, nlink: u32, uid: u32, gid: u32, mode: u16, _pad0: u16, ino: u64, size: u64, _rest: [u64; 26] }
+
+const O_RDONLY: i32 = 0;
+const O_WRONLY: i32 = 1;
+const O_RDWR: i32 = 2;
+const O_CREAT: i32 = 0x40;
+const O_EXCL: i32 = 0x80;
+const O_APPEND: i32 = 0x400;
+const O_CLOEXEC: i32 = 0x80000;
+const LOCK_SH: i32 = 1;
+const LOCK_EX: i32 = 2;
+const LOCK_NB: i32 = 4;
+const AT_EMPTY_PATH: i32 = 0x1000;
+const STATX_SIZE: u32 = 0x200;
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const MAP_SHARED: i32 = 1;
+const MAP_FAILED: *mut c_void = !0usize as *mut c_void;
+const MS_SYNC: i32 = 4;
+const _SC_PAGESIZE: i32 = 30;
+const EINTR: i32 = 4;
+
+// Handles carry the descriptor itself.
+fn fd(h: FileHandle) -> i32 { h.0 as isize as i32 }
+fn handle(fd: i32) -> FileHandle { FileHandle(fd as isize as *mut c_void) }
+fn errno() -> i32 { unsafe { *__errno_location() } }
+fn page_size() -> usize { unsafe { sysconf(_SC_PAGESIZE) as usize } }
+fn ok(r: i32) -> Result<(), FileError> { if r == 0 { Ok(()) } else { Err(FileError::Failed) } }
+
+// Same dispositions as the Windows backend: only `Create`/`CreateNew` make the file, and
+// `Truncate` needs it to exist. The truncation itself waits until the lock is held.
+fn open_flags(mode: FileOpenMode) -> (i32, bool) {
+    match mode {
+        FileOpenMode::Read => (O_RDONLY, false),
+        FileOpenMode::Write => (O_WRONLY, false),
+        FileOpenMode::ReadWrite => (O_RDWR, false),
+        FileOpenMode::Append => (O_WRONLY | O_APPEND, false),
+        FileOpenMode::Create => (O_RDWR | O_CREAT, false),
+        FileOpenMode::CreateNew => (O_RDWR | O_CREAT | O_EXCL, false),
+        FileOpenMode::Truncate => (O_WRONLY, true),
+    }
+}
+
+// What others may still do with the file, as an advisory lock: denying writers takes a shared lock,
+// denying everyone an exclusive one. Only other `flock` users (every handle opened here) see it.
+fn share_lock(share: FileShareMode) -> Option<i32> {
+    match share {
+        FileShareMode::None | FileShareMode::Delete => Some(LOCK_EX),
+        FileShareMode::Read => Some(LOCK_SH),
+        FileShareMode::Write | FileShareMode::ReadWrite => None,
+    }
+}
+
+pub fn impl_open(path: &str, mode: FileOpenMode, share: FileShareMode) -> Result<FileHandle, FileError> {
+    let c = CString::new(path).map_err(|_| FileError::Failed)?;
+    let (flags, truncate) = open_flags(mode);
+    let f = unsafe { open(c.as_ptr(), flags | O_CLOEXEC, 0o666u32) };
+    if f < 0 { return Err(FileError::Failed); }
+    // A conflicting lock fails the open, like a sharing violation would.
+    let locked = share_lock(share).is_none_or(|op| unsafe { flock(f, op | LOCK_NB) } == 0);
+    if !locked || (truncate && unsafe { ftruncate(f, 0) } != 0) {
+        unsafe { close(f); }
+        return Err(FileError::Failed);
+    }
+    Ok(handle(f))
+}
+
+// No completion-port equivalent is wired up on Linux, so this is a plain handle.
+pub fn impl_open_overlapped(path: &str, mode: FileOpenMode, share: FileShareMode) -> Result<FileHandle, FileError> { impl_open(path, mode, share) }
+
+pub fn impl_close(h: FileHandle) -> Result<(), FileError> { ok(unsafe { close(fd(h)) }) }
+
+pub fn impl_read(h: FileHandle, buf: &mut [u8]) -> Result<usize, FileError> {
+    loop {
+        let n = unsafe { read(fd(h), buf.as_mut_ptr() as *mut c_void, buf.len()) };
+        if n >= 0 { return Ok(n as usize); }
+        if errno() != EINTR { return Err(FileError::Failed); }
+    }
+}
+
+pub fn impl_write(h: FileHandle, buf: &[u8]) -> Result<usize, FileError> {
+    loop {
+        let n = unsafe { write(fd(h), buf.as_ptr() as *const c_void, buf.len()) };
+        if n >= 0 { return Ok(n as usize); }
+        if errno() != EINTR { return Err(FileError::Failed); }
+    }
+}
+
+pub fn impl_size(h: FileHandle) -> Result<u64, FileError> {
+    let mut st = std::mem::MaybeUninit::<statx_t>::zeroed();
+    ok(unsafe { statx(fd(h), c"".as_ptr(), AT_EMPTY_PATH, STATX_SIZE, st.as_mut_ptr()) })?;
+    Ok(unsafe { st.assume_init() }.size)
+}
+
+pub fn impl_seek(h: FileHandle, offset: i64, origin: SeekOrigin) -> Result<u64, FileError> {
+    let whence = match origin { SeekOrigin::Begin => 0, SeekOrigin::Current => 1, SeekOrigin::End => 2 };
+    let pos = unsafe { lseek(fd(h), offset, whence) };
+    if pos < 0 { Err(FileError::Failed) } else { Ok(pos as u64) }
+}
+
+pub fn impl_flush(h: FileHandle) -> Result<(), FileError> { ok(unsafe { fsync(fd(h)) }) }
+
+/// `mmap` wants a page-aligned offset, so the view starts at the page holding `offset` and
+/// `native_mapping_handle` keeps that base for `impl_unmap`. A `size` of 0 maps to the end of the file.
+pub fn impl_map(h: FileHandle, offset: u64, size: usize, access: MapAccess) -> Result<Mapping, FileError> {
+    let size = if size == 0 { impl_size(h)?.checked_sub(offset).ok_or(FileError::Failed)? as usize } else { size };
+    if size == 0 { return Err(FileError::Failed); }
+    let delta = (offset % page_size() as u64) as usize;
+    let prot = match access { MapAccess::Read => PROT_READ, MapAccess::Write | MapAccess::ReadWrite => PROT_READ | PROT_WRITE };
+    let base = unsafe { mmap(std::ptr::null_mut(), size + delta, prot, MAP_SHARED, fd(h), (offset - delta as u64) as i64) };
+    if base == MAP_FAILED { return Err(FileError::Failed); }
+    Ok(Mapping { address: unsafe { (base as *mut u8).add(delta) } as *mut c_void, length: size, native_mapping_handle: base })
+}
+
+pub fn impl_unmap(m: &Mapping) -> Result<(), FileError> {
+    let delta = m.address as usize - m.native_mapping_handle as usize;
+    ok(unsafe { munmap(m.native_mapping_handle, m.length + delta) })
+}
+
+pub fn impl_flush_mapped(addr: *mut c_void, size: usize) -> Result<(), FileError> {
+    let delta = addr as usize % page_size();
+    ok(unsafe { msync((addr as *mut u8).sub(delta) as *mut c_void, size + delta, MS_SYNC) })
+}
+
+pub fn impl_stdout_handle() -> FileHandle { handle(1) }
+pub fn impl_stderr_handle() -> FileHandle { handle(2) }
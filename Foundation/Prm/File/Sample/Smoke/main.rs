@@ -14,6 +14,18 @@ fn main() {
     let s = unsafe { std::slice::from_raw_parts(m.address as *const u8, m.length) };
     println!("map: {}", std::str::from_utf8(s).unwrap());
     unmap(&m).unwrap();
+    let m = map(h, 1, 0, MapAccess::Read).unwrap();
+    assert_eq!(unsafe { std::slice::from_raw_parts(m.address as *const u8, m.length) }, b"ello");
+    unmap(&m).unwrap();
+    close(h).unwrap();
+
+    // Nobody else may open a file held with FileShareMode::None.
+    let h = open(path, FileOpenMode::ReadWrite, FileShareMode::None).unwrap();
+    assert!(open(path, FileOpenMode::Read, FileShareMode::Read).is_err());
+    close(h).unwrap();
+    let h = open(path, FileOpenMode::Truncate, FileShareMode::Read).unwrap();
+    assert_eq!(size(h).unwrap(), 0);
     close(h).unwrap();
+    write(stdout_handle(), b"file ok\n").unwrap();
 }
 
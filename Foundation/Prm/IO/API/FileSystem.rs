@@ -1,5 +1,4 @@
-use std::ffi::{c_void, OsStr};
-use std::os::windows::ffi::OsStrExt;
+use std::ffi::c_void;
 
 #[derive(Clone, Copy)]
 pub struct FileHandle(pub *mut c_void);
@@ -18,95 +17,199 @@ pub enum MapAccess { Read, Write, ReadWrite }
 
 pub struct Mapping { pub address: *mut c_void, pub length: usize, pub native_mapping_handle: *mut c_void }
 
-extern "system" {
-    fn CreateFileW(lpFileName: *const u16, dwDesiredAccess: u32, dwShareMode: u32, lpSecurityAttributes: *mut c_void, dwCreationDisposition: u32, dwFlagsAndAttributes: u32, hTemplateFile: *mut c_void) -> *mut c_void;
-    fn ReadFile(hFile: *mut c_void, lpBuffer: *mut c_void, nNumberOfBytesToRead: u32, lpNumberOfBytesRead: *mut u32, lpOverlapped: *mut c_void) -> i32;
-    fn WriteFile(hFile: *mut c_void, lpBuffer: *const c_void, nNumberOfBytesToWrite: u32, lpNumberOfBytesWritten: *mut u32, lpOverlapped: *mut c_void) -> i32;
-    fn CloseHandle(hObject: *mut c_void) -> i32;
-    fn SetFilePointerEx(hFile: *mut c_void, liDistanceToMove: i64, lpNewFilePointer: *mut i64, dwMoveMethod: u32) -> i32;
-    fn GetFileSizeEx(hFile: *mut c_void, lpFileSize: *mut i64) -> i32;
-    fn GetStdHandle(nStdHandle: i32) -> *mut c_void;
-
-    fn CreateFileMappingW(hFile: *mut c_void, lpFileMappingAttributes: *mut c_void, flProtect: u32, dwMaximumSizeHigh: u32, dwMaximumSizeLow: u32, lpName: *const u16) -> *mut c_void;
-    fn MapViewOfFile(hFileMappingObject: *mut c_void, dwDesiredAccess: u32, dwFileOffsetHigh: u32, dwFileOffsetLow: u32, dwNumberOfBytesToMap: usize) -> *mut c_void;
-    fn UnmapViewOfFile(lpBaseAddress: *mut c_void) -> i32;
-    fn FlushViewOfFile(lpBaseAddress: *mut c_void, dwNumberOfBytesToFlush: usize) -> i32;
-
-    fn GetFileAttributesW(lpFileName: *const u16) -> u32;
-    fn CreateDirectoryW(lpPathName: *const u16, lpSecurityAttributes: *mut c_void) -> i32;
-    fn DeleteFileW(lpFileName: *const u16) -> i32;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind { File, Directory, Symlink, Other }
+
+/// What `metadata` reports about a path. Symlinks are described themselves, not their targets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: FileKind,
+    pub size: u64,
+    /// Last write time, in nanoseconds since the Unix epoch.
+    pub modified_ns: i64,
+    pub readonly: bool,
 }
 
-const GENERIC_READ: u32 = 0x8000_0000;
-const GENERIC_WRITE: u32 = 0x4000_0000;
-const FILE_SHARE_READ: u32 = 0x00000001;
-const FILE_SHARE_WRITE: u32 = 0x00000002;
-const FILE_SHARE_DELETE: u32 = 0x00000004;
-const CREATE_NEW: u32 = 1;
-const OPEN_EXISTING: u32 = 3;
-const OPEN_ALWAYS: u32 = 4;
-const TRUNCATE_EXISTING: u32 = 5;
-const FILE_ATTRIBUTE_NORMAL: u32 = 0x00000080;
-const STD_OUTPUT_HANDLE: i32 = -11;
-const STD_ERROR_HANDLE: i32 = -12;
-const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x00000010;
-
-const PAGE_READONLY: u32 = 0x02;
-const PAGE_READWRITE: u32 = 0x04;
-const FILE_MAP_READ: u32 = 0x0004;
-const FILE_MAP_WRITE: u32 = 0x0002;
-
-fn wide(s: &str) -> Vec<u16> { OsStr::new(s).encode_wide().chain(std::iter::once(0)).collect() }
-
-pub fn open(path: &str, mode: FileOpenMode, share: FileShareMode) -> Result<FileHandle, ()> {
-    let w = wide(path);
-    let access = match mode { FileOpenMode::Read => GENERIC_READ, FileOpenMode::Write => GENERIC_WRITE, FileOpenMode::ReadWrite => GENERIC_READ | GENERIC_WRITE, FileOpenMode::Append => GENERIC_WRITE, FileOpenMode::Create => GENERIC_READ | GENERIC_WRITE, FileOpenMode::CreateNew => GENERIC_READ | GENERIC_WRITE, FileOpenMode::Truncate => GENERIC_WRITE };
-    let share_flags = match share { FileShareMode::None => 0, FileShareMode::Read => FILE_SHARE_READ, FileShareMode::Write => FILE_SHARE_WRITE, FileShareMode::ReadWrite => FILE_SHARE_READ | FILE_SHARE_WRITE, FileShareMode::Delete => FILE_SHARE_DELETE };
-    let disposition = match mode { FileOpenMode::Create => OPEN_ALWAYS, FileOpenMode::CreateNew => CREATE_NEW, FileOpenMode::Truncate => TRUNCATE_EXISTING, _ => OPEN_EXISTING };
-    let h = unsafe { CreateFileW(w.as_ptr(), access, share_flags, std::ptr::null_mut(), disposition, FILE_ATTRIBUTE_NORMAL, std::ptr::null_mut()) };
-    if h.is_null() { Err(()) } else { Ok(FileHandle(h)) }
+#[cfg(target_os = "windows")]
+#[path = "../Impl/Windows/FileSystem.rs"]
+mod backend;
+
+#[cfg(target_os = "linux")]
+#[path = "../Impl/Linux/FileSystem.rs"]
+mod backend;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod backend {
+    use super::*;
+    pub fn impl_open(_path: &str, _mode: FileOpenMode, _share: FileShareMode) -> Result<FileHandle, ()> { Err(()) }
+    pub fn impl_close(_h: FileHandle) -> Result<(), ()> { Err(()) }
+    pub fn impl_read(_h: FileHandle, _buf: &mut [u8]) -> Result<usize, ()> { Err(()) }
+    pub fn impl_write(_h: FileHandle, _buf: &[u8]) -> Result<usize, ()> { Err(()) }
+    pub fn impl_size(_h: FileHandle) -> Result<u64, ()> { Err(()) }
+    pub fn impl_seek(_h: FileHandle, _offset: i64, _origin: SeekOrigin) -> Result<u64, ()> { Err(()) }
+    pub fn impl_map(_h: FileHandle, _offset: u64, _size: usize, _access: MapAccess) -> Result<Mapping, ()> { Err(()) }
+    pub fn impl_unmap(_m: &Mapping) -> Result<(), ()> { Err(()) }
+    pub fn impl_flush_mapped(_h: FileHandle, _addr: *mut c_void, _size: usize) -> Result<(), ()> { Err(()) }
+    pub fn impl_stdout_handle() -> FileHandle { FileHandle(std::ptr::null_mut()) }
+    pub fn impl_stderr_handle() -> FileHandle { FileHandle(std::ptr::null_mut()) }
+    pub fn impl_path_exists(_path: &str) -> bool { false }
+    pub fn impl_path_is_directory(_path: &str) -> bool { false }
+    pub fn impl_path_create_directory(_path: &str) -> Result<(), ()> { Err(()) }
+    pub fn impl_path_remove_file(_path: &str) -> Result<(), ()> { Err(()) }
+    pub fn impl_metadata(_path: &str) -> Result<Metadata, ()> { Err(()) }
+    pub fn impl_rename(_from: &str, _to: &str) -> Result<(), ()> { Err(()) }
+    pub fn impl_copy_file(_from: &str, _to: &str) -> Result<u64, ()> { Err(()) }
+    pub fn impl_remove_dir(_path: &str) -> Result<(), ()> { Err(()) }
+    pub fn impl_remove_link(_path: &str) -> Result<(), ()> { Err(()) }
+    pub struct DirIter;
+    impl DirIter { pub fn next_entry(&mut self) -> Option<(String, FileKind)> { None } }
+    pub fn impl_read_dir(_path: &str) -> Result<DirIter, ()> { Err(()) }
 }
 
-pub fn close(h: FileHandle) -> Result<(), ()> { let ok = unsafe { CloseHandle(h.0) }; if ok == 0 { Err(()) } else { Ok(()) } }
+#[allow(clippy::result_unit_err)]
+pub fn open(path: &str, mode: FileOpenMode, share: FileShareMode) -> Result<FileHandle, ()> { backend::impl_open(path, mode, share) }
+#[allow(clippy::result_unit_err)]
+pub fn close(h: FileHandle) -> Result<(), ()> { backend::impl_close(h) }
+#[allow(clippy::result_unit_err)]
+pub fn read(h: FileHandle, buf: &mut [u8]) -> Result<usize, ()> { backend::impl_read(h, buf) }
+#[allow(clippy::result_unit_err)]
+pub fn write(h: FileHandle, buf: &[u8]) -> Result<usize, ()> { backend::impl_write(h, buf) }
+#[allow(clippy::result_unit_err)]
+pub fn size(h: FileHandle) -> Result<u64, ()> { backend::impl_size(h) }
+#[allow(clippy::result_unit_err)]
+pub fn seek(h: FileHandle, offset: i64, origin: SeekOrigin) -> Result<u64, ()> { backend::impl_seek(h, offset, origin) }
+#[allow(clippy::result_unit_err)]
+pub fn map(h: FileHandle, offset: u64, size: usize, access: MapAccess) -> Result<Mapping, ()> { backend::impl_map(h, offset, size, access) }
+#[allow(clippy::result_unit_err)]
+pub fn unmap(m: &Mapping) -> Result<(), ()> { backend::impl_unmap(m) }
+#[allow(clippy::result_unit_err)]
+pub fn flush_mapped(h: FileHandle, addr: *mut c_void, size: usize) -> Result<(), ()> { backend::impl_flush_mapped(h, addr, size) }
+
+pub fn stdout_handle() -> FileHandle { backend::impl_stdout_handle() }
+pub fn stderr_handle() -> FileHandle { backend::impl_stderr_handle() }
+
+pub fn path_exists(path: &str) -> bool { backend::impl_path_exists(path) }
+pub fn path_is_directory(path: &str) -> bool { backend::impl_path_is_directory(path) }
+#[allow(clippy::result_unit_err)]
+pub fn path_create_directory(path: &str) -> Result<(), ()> { backend::impl_path_create_directory(path) }
+#[allow(clippy::result_unit_err)]
+pub fn path_remove_file(path: &str) -> Result<(), ()> { backend::impl_path_remove_file(path) }
+
+/// Describes `path` without following a final symlink.
+#[allow(clippy::result_unit_err)]
+pub fn metadata(path: &str) -> Result<Metadata, ()> { backend::impl_metadata(path) }
+/// Moves `from` to `to`, replacing an existing file at `to`.
+#[allow(clippy::result_unit_err)]
+pub fn rename(from: &str, to: &str) -> Result<(), ()> { backend::impl_rename(from, to) }
+/// Copies the contents of `from` over `to`, creating it if needed; returns the bytes copied.
+#[allow(clippy::result_unit_err)]
+pub fn copy_file(from: &str, to: &str) -> Result<u64, ()> { backend::impl_copy_file(from, to) }
+/// Removes an empty directory.
+#[allow(clippy::result_unit_err)]
+pub fn remove_dir(path: &str) -> Result<(), ()> { backend::impl_remove_dir(path) }
+
+/// Removes `path` and everything below it. Symlinks are removed, never followed; if `path` itself
+/// is a symlink (or not a directory at all), only it is removed.
+#[allow(clippy::result_unit_err)]
+pub fn remove_dir_all(path: &str) -> Result<(), ()> {
+    match metadata(path)?.kind {
+        FileKind::Directory => remove_tree(path),
+        FileKind::Symlink => backend::impl_remove_link(path),
+        FileKind::File | FileKind::Other => path_remove_file(path),
+    }
+}
+
+fn remove_tree(path: &str) -> Result<(), ()> {
+    // Collect first: removing entries while the listing is open is unspecified on both backends.
+    let entries: Vec<DirEntry> = read_dir(path)?.collect();
+    for e in entries {
+        match e.kind {
+            FileKind::Directory => remove_tree(&e.path)?,
+            FileKind::Symlink => backend::impl_remove_link(&e.path)?,
+            FileKind::File | FileKind::Other => path_remove_file(&e.path)?,
+        }
+    }
+    remove_dir(path)
+}
 
-pub fn read(h: FileHandle, buf: &mut [u8]) -> Result<usize, ()> {
-    let mut n: u32 = 0;
-    let ok = unsafe { ReadFile(h.0, buf.as_mut_ptr() as *mut c_void, buf.len() as u32, &mut n as *mut u32, std::ptr::null_mut()) };
-    if ok == 0 { Err(()) } else { Ok(n as usize) }
+/// One entry of a directory listing; `kind` describes a symlink itself, not its target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The listed directory joined with the entry's name.
+    pub path: String,
+    pub kind: FileKind,
+    /// 1 for entries directly inside the listed or walked directory, 2 one level below, and so on.
+    pub depth: usize,
+    name_at: usize,
 }
 
-pub fn write(h: FileHandle, buf: &[u8]) -> Result<usize, ()> {
-    let mut n: u32 = 0;
-    let ok = unsafe { WriteFile(h.0, buf.as_ptr() as *const c_void, buf.len() as u32, &mut n as *mut u32, std::ptr::null_mut()) };
-    if ok == 0 { Err(()) } else { Ok(n as usize) }
+impl DirEntry {
+    pub fn name(&self) -> &str { &self.path[self.name_at..] }
+    #[allow(clippy::result_unit_err)]
+    pub fn metadata(&self) -> Result<Metadata, ()> { metadata(&self.path) }
 }
 
-pub fn size(h: FileHandle) -> Result<u64, ()> { let mut s: i64 = 0; let ok = unsafe { GetFileSizeEx(h.0, &mut s as *mut i64) }; if ok == 0 { Err(()) } else { Ok(s as u64) } }
+/// Entries of one directory, without `.` and `..`, in whatever order the OS returns them.
+pub struct ReadDir { inner: backend::DirIter, base: String, depth: usize }
 
-pub fn seek(h: FileHandle, offset: i64, origin: SeekOrigin) -> Result<u64, ()> {
-    let mut newpos: i64 = 0;
-    let method = match origin { SeekOrigin::Begin => 0, SeekOrigin::Current => 1, SeekOrigin::End => 2 };
-    let ok = unsafe { SetFilePointerEx(h.0, offset, &mut newpos as *mut i64, method) };
-    if ok == 0 { Err(()) } else { Ok(newpos as u64) }
+#[allow(clippy::result_unit_err)]
+pub fn read_dir(path: &str) -> Result<ReadDir, ()> { read_dir_at(path, 1) }
+
+fn read_dir_at(path: &str, depth: usize) -> Result<ReadDir, ()> {
+    let inner = backend::impl_read_dir(path)?;
+    let mut base = String::from(path);
+    if !base.is_empty() && !base.ends_with(['/', std::path::MAIN_SEPARATOR]) { base.push(std::path::MAIN_SEPARATOR); }
+    Ok(ReadDir { inner, base, depth })
 }
 
-pub fn map(h: FileHandle, offset: u64, size: usize, access: MapAccess) -> Result<Mapping, ()> {
-    let protect = match access { MapAccess::Read => PAGE_READONLY, MapAccess::Write | MapAccess::ReadWrite => PAGE_READWRITE };
-    let map = unsafe { CreateFileMappingW(h.0, std::ptr::null_mut(), protect, 0, 0, std::ptr::null()) };
-    if map.is_null() { return Err(()); }
-    let desired = match access { MapAccess::Read => FILE_MAP_READ, MapAccess::Write => FILE_MAP_WRITE, MapAccess::ReadWrite => FILE_MAP_READ | FILE_MAP_WRITE };
-    let addr = unsafe { MapViewOfFile(map, desired, (offset >> 32) as u32, (offset & 0xFFFF_FFFF) as u32, size) };
-    if addr.is_null() { unsafe { CloseHandle(map) }; return Err(()); }
-    Ok(Mapping { address: addr, length: size, native_mapping_handle: map })
+impl Iterator for ReadDir {
+    type Item = DirEntry;
+    fn next(&mut self) -> Option<DirEntry> {
+        loop {
+            let (name, kind) = self.inner.next_entry()?;
+            if name == "." || name == ".." { continue; }
+            let mut path = String::with_capacity(self.base.len() + name.len());
+            path.push_str(&self.base);
+            path.push_str(&name);
+            return Some(DirEntry { path, kind, depth: self.depth, name_at: self.base.len() });
+        }
+    }
 }
 
-pub fn unmap(m: &Mapping) -> Result<(), ()> { let ok = unsafe { UnmapViewOfFile(m.address) }; if ok == 0 { Err(()) } else { Ok(()) } }
-pub fn flush_mapped(_h: FileHandle, addr: *mut c_void, size: usize) -> Result<(), ()> { let ok = unsafe { FlushViewOfFile(addr, size) }; if ok == 0 { Err(()) } else { Ok(()) } }
+type EntryFilter<'f> = Box<dyn FnMut(&DirEntry) -> bool + 'f>;
+
+/// Depth-first walk below `root`, built by `walk`. Directories come before their contents and
+/// symlinks are never followed. Directories that can't be listed are skipped.
+pub struct Walk<'f> { root: Option<String>, stack: Vec<ReadDir>, max_depth: usize, prune: Option<EntryFilter<'f>>, keep: Option<EntryFilter<'f>> }
 
-pub fn stdout_handle() -> FileHandle { FileHandle(unsafe { GetStdHandle(STD_OUTPUT_HANDLE) }) }
-pub fn stderr_handle() -> FileHandle { FileHandle(unsafe { GetStdHandle(STD_ERROR_HANDLE) }) }
+/// Walks everything below `root`; narrow it with `max_depth`, `filter_entry` and `filter`.
+pub fn walk<'f>(root: &str) -> Walk<'f> { Walk { root: Some(String::from(root)), stack: Vec::new(), max_depth: usize::MAX, prune: None, keep: None } }
 
-pub fn path_exists(path: &str) -> bool { unsafe { let w = wide(path); let a = GetFileAttributesW(w.as_ptr()); a != 0xFFFF_FFFF } }
-pub fn path_is_directory(path: &str) -> bool { unsafe { let w = wide(path); let a = GetFileAttributesW(w.as_ptr()); a != 0xFFFF_FFFF && (a & FILE_ATTRIBUTE_DIRECTORY) != 0 } }
-pub fn path_create_directory(path: &str) -> Result<(), ()> { let w = wide(path); let ok = unsafe { CreateDirectoryW(w.as_ptr(), std::ptr::null_mut()) }; if ok == 0 { Err(()) } else { Ok(()) } }
-pub fn path_remove_file(path: &str) -> Result<(), ()> { let w = wide(path); let ok = unsafe { DeleteFileW(w.as_ptr()) }; if ok == 0 { Err(()) } else { Ok(()) } }
+impl<'f> Walk<'f> {
+    /// Goes no deeper than this; 1 yields only the entries directly inside `root`.
+    pub fn max_depth(mut self, depth: usize) -> Self { self.max_depth = depth; self }
+
+    /// Entries for which `f` is false are neither yielded nor, for directories, descended into.
+    pub fn filter_entry(mut self, f: impl FnMut(&DirEntry) -> bool + 'f) -> Self { self.prune = Some(Box::new(f)); self }
+
+    /// Yields only entries for which `f` is true; directories are still descended into.
+    pub fn filter(mut self, f: impl FnMut(&DirEntry) -> bool + 'f) -> Self { self.keep = Some(Box::new(f)); self }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = DirEntry;
+    fn next(&mut self) -> Option<DirEntry> {
+        if let Some(root) = self.root.take() {
+            if let Ok(rd) = read_dir(&root) { self.stack.push(rd); }
+        }
+        while let Some(top) = self.stack.last_mut() {
+            let Some(e) = top.next() else { self.stack.pop(); continue; };
+            if let Some(prune) = &mut self.prune { if !prune(&e) { continue; } }
+            if e.kind == FileKind::Directory && e.depth < self.max_depth {
+                if let Ok(rd) = read_dir_at(&e.path, e.depth + 1) { self.stack.push(rd); }
+            }
+            if self.keep.as_mut().is_none_or(|keep| keep(&e)) { return Some(e); }
+        }
+        None
+    }
+}
@@ -0,0 +1,191 @@
+#![allow(non_camel_case_types)]
+use std::ffi::{c_char, c_void, CStr, CString};
+use crate::file_system::*;
+
+extern "C" {
+    fn open(path: *const c_char, flags: i32, ...) -> i32;
+    fn close(fd: i32) -> i32;
+    fn statx(dirfd: i32, path: *const c_char, flags: i32, mask: u32, buf: *mut statx_t) -> i32;
+    fn mkdir(path: *const c_char, mode: u32) -> i32;
+    fn rmdir(path: *const c_char) -> i32;
+    fn unlink(path: *const c_char) -> i32;
+    fn rename(from: *const c_char, to: *const c_char) -> i32;
+    fn copy_file_range(fd_in: i32, off_in: *mut i64, fd_out: i32, off_out: *mut i64, len: usize, flags: u32) -> isize;
+    fn opendir(path: *const c_char) -> *mut c_void;
+    fn readdir(dir: *mut c_void) -> *const dirent;
+    fn closedir(dir: *mut c_void) -> i32;
+    fn __errno_location() -> *mut i32;
+}
+
+// Same layout on every architecture, unlike `struct stat`.
+#[repr(C)]
+struct statx_timestamp { sec: i64, nsec: u32, _pad: i32 }
+
+#[repr(C)]
+struct statx_t { mask: u32, blksize: u32, attributes: u64, nlink: u32, uid: u32, gid: u32, mode: u16, _pad0: u16, ino: u64, size: u64, blocks: u64, attributes_mask: u64, atime: statx_timestamp, btime: statx_timestamp, ctime: statx_timestamp, mtime: statx_timestamp, _rest: [u64; 16] }
+
+// glibc's 64-bit `struct dirent`, the same on x86_64 and aarch64.
+#[repr(C)]
+struct dirent { ino: u64, off: i64, reclen: u16, kind: u8, name: [c_char; 256] }
+
+const O_WRONLY: i32 = 1;
+const O_CREAT: i32 = 0x40;
+const O_CLOEXEC: i32 = 0x80000;
+const AT_FDCWD: i32 = -100;
+const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+const STATX_TYPE: u32 = 0x1;
+const STATX_MODE: u32 = 0x2;
+const STATX_MTIME: u32 = 0x40;
+const STATX_SIZE: u32 = 0x200;
+const S_IFMT: u16 = 0o170000;
+const S_IFDIR: u16 = 0o040000;
+const S_IFREG: u16 = 0o100000;
+const S_IFLNK: u16 = 0o120000;
+const DT_UNKNOWN: u8 = 0;
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+const DT_LNK: u8 = 10;
+const O_TRUNC: i32 = 0x200;
+const EINTR: i32 = 4;
+
+fn fd(h: FileHandle) -> i32 { h.0 as isize as i32 }
+fn handle(fd: i32) -> FileHandle { FileHandle(fd as isize as *mut c_void) }
+fn errno() -> i32 { unsafe { *__errno_location() } }
+fn ok(r: i32) -> Result<(), ()> { if r == 0 { Ok(()) } else { Err(()) } }
+fn cpath(path: &str) -> Result<CString, ()> { CString::new(path).map_err(|_| ()) }
+
+fn stat_path(path: &str, flags: i32, mask: u32) -> Option<statx_t> {
+    let c = cpath(path).ok()?;
+    let mut st = std::mem::MaybeUninit::<statx_t>::zeroed();
+    if unsafe { statx(AT_FDCWD, c.as_ptr(), flags, mask, st.as_mut_ptr()) } != 0 { return None; }
+    Some(unsafe { st.assume_init() })
+}
+
+fn kind_of(mode: u16) -> FileKind {
+    match mode & S_IFMT { S_IFREG => FileKind::File, S_IFDIR => FileKind::Directory, S_IFLNK => FileKind::Symlink, _ => FileKind::Other }
+}
+
+// Handle I/O is prm_file's Linux backend; its handle, mode and mapping types mirror ours.
+fn file_handle(h: FileHandle) -> prm_file::FileHandle { prm_file::FileHandle(h.0) }
+
+pub fn impl_open(path: &str, mode: FileOpenMode, share: FileShareMode) -> Result<FileHandle, ()> {
+    let mode = match mode {
+        FileOpenMode::Read => prm_file::FileOpenMode::Read,
+        FileOpenMode::Write => prm_file::FileOpenMode::Write,
+        FileOpenMode::ReadWrite => prm_file::FileOpenMode::ReadWrite,
+        FileOpenMode::Append => prm_file::FileOpenMode::Append,
+        FileOpenMode::Create => prm_file::FileOpenMode::Create,
+        FileOpenMode::CreateNew => prm_file::FileOpenMode::CreateNew,
+        FileOpenMode::Truncate => prm_file::FileOpenMode::Truncate,
+    };
+    let share = match share {
+        FileShareMode::None => prm_file::FileShareMode::None,
+        FileShareMode::Read => prm_file::FileShareMode::Read,
+        FileShareMode::Write => prm_file::FileShareMode::Write,
+        FileShareMode::ReadWrite => prm_file::FileShareMode::ReadWrite,
+        FileShareMode::Delete => prm_file::FileShareMode::Delete,
+    };
+    prm_file::open(path, mode, share).map(|h| FileHandle(h.0)).map_err(|_| ())
+}
+
+pub fn impl_close(h: FileHandle) -> Result<(), ()> { prm_file::close(file_handle(h)).map_err(|_| ()) }
+pub fn impl_read(h: FileHandle, buf: &mut [u8]) -> Result<usize, ()> { prm_file::read(file_handle(h), buf).map_err(|_| ()) }
+pub fn impl_write(h: FileHandle, buf: &[u8]) -> Result<usize, ()> { prm_file::write(file_handle(h), buf).map_err(|_| ()) }
+pub fn impl_size(h: FileHandle) -> Result<u64, ()> { prm_file::size(file_handle(h)).map_err(|_| ()) }
+
+pub fn impl_seek(h: FileHandle, offset: i64, origin: SeekOrigin) -> Result<u64, ()> {
+    let origin = match origin { SeekOrigin::Begin => prm_file::SeekOrigin::Begin, SeekOrigin::Current => prm_file::SeekOrigin::Current, SeekOrigin::End => prm_file::SeekOrigin::End };
+    prm_file::seek(file_handle(h), offset, origin).map_err(|_| ())
+}
+
+pub fn impl_map(h: FileHandle, offset: u64, size: usize, access: MapAccess) -> Result<Mapping, ()> {
+    let access = match access { MapAccess::Read => prm_file::MapAccess::Read, MapAccess::Write => prm_file::MapAccess::Write, MapAccess::ReadWrite => prm_file::MapAccess::ReadWrite };
+    let m = prm_file::map(file_handle(h), offset, size, access).map_err(|_| ())?;
+    Ok(Mapping { address: m.address, length: m.length, native_mapping_handle: m.native_mapping_handle })
+}
+
+pub fn impl_unmap(m: &Mapping) -> Result<(), ()> {
+    prm_file::unmap(&prm_file::Mapping { address: m.address, length: m.length, native_mapping_handle: m.native_mapping_handle }).map_err(|_| ())
+}
+
+pub fn impl_flush_mapped(_h: FileHandle, addr: *mut c_void, size: usize) -> Result<(), ()> { prm_file::flush_mapped(addr, size).map_err(|_| ()) }
+
+pub fn impl_stdout_handle() -> FileHandle { FileHandle(prm_file::stdout_handle().0) }
+pub fn impl_stderr_handle() -> FileHandle { FileHandle(prm_file::stderr_handle().0) }
+
+pub fn impl_path_exists(path: &str) -> bool { stat_path(path, 0, STATX_TYPE).is_some() }
+pub fn impl_path_is_directory(path: &str) -> bool { stat_path(path, 0, STATX_TYPE).is_some_and(|st| st.mode & S_IFMT == S_IFDIR) }
+pub fn impl_path_create_directory(path: &str) -> Result<(), ()> { let c = cpath(path)?; ok(unsafe { mkdir(c.as_ptr(), 0o777) }) }
+pub fn impl_path_remove_file(path: &str) -> Result<(), ()> { let c = cpath(path)?; ok(unsafe { unlink(c.as_ptr()) }) }
+
+pub fn impl_metadata(path: &str) -> Result<Metadata, ()> {
+    let st = stat_path(path, AT_SYMLINK_NOFOLLOW, STATX_TYPE | STATX_MODE | STATX_MTIME | STATX_SIZE).ok_or(())?;
+    let modified_ns = st.mtime.sec.saturating_mul(1_000_000_000).saturating_add(st.mtime.nsec as i64);
+    Ok(Metadata { kind: kind_of(st.mode), size: st.size, modified_ns, readonly: st.mode & 0o222 == 0 })
+}
+
+pub fn impl_rename(from: &str, to: &str) -> Result<(), ()> { let (a, b) = (cpath(from)?, cpath(to)?); ok(unsafe { rename(a.as_ptr(), b.as_ptr()) }) }
+pub fn impl_remove_dir(path: &str) -> Result<(), ()> { let c = cpath(path)?; ok(unsafe { rmdir(c.as_ptr()) }) }
+pub fn impl_remove_link(path: &str) -> Result<(), ()> { impl_path_remove_file(path) }
+
+// Lets the kernel copy (or share extents) with `copy_file_range`, falling back to a read/write loop
+// where that isn't supported, e.g. across filesystems on older kernels.
+pub fn impl_copy_file(from: &str, to: &str) -> Result<u64, ()> {
+    let c = cpath(to)?;
+    let mode = stat_path(from, 0, STATX_MODE).ok_or(())?.mode & 0o7777;
+    let src = impl_open(from, FileOpenMode::Read, FileShareMode::Read)?;
+    let dst = unsafe { open(c.as_ptr(), O_WRONLY | O_CREAT | O_TRUNC | O_CLOEXEC, mode as u32) };
+    if dst < 0 { let _ = impl_close(src); return Err(()); }
+    let r = copy_fds(fd(src), dst);
+    unsafe { close(dst); }
+    let _ = impl_close(src);
+    r
+}
+
+fn copy_fds(src: i32, dst: i32) -> Result<u64, ()> {
+    let mut total = 0u64;
+    loop {
+        let n = unsafe { copy_file_range(src, std::ptr::null_mut(), dst, std::ptr::null_mut(), 1 << 30, 0) };
+        if n == 0 { return Ok(total); }
+        if n > 0 { total += n as u64; continue; }
+        if errno() == EINTR { continue; }
+        if total != 0 { return Err(()); }
+        break;
+    }
+    let mut buf = vec![0u8; 64 << 10];
+    loop {
+        let n = impl_read(handle(src), &mut buf)?;
+        if n == 0 { return Ok(total); }
+        let mut done = 0;
+        while done < n { done += impl_write(handle(dst), &buf[done..n])?; }
+        total += n as u64;
+    }
+}
+
+pub struct DirIter { dir: *mut c_void, base: String }
+
+impl DirIter {
+    pub fn next_entry(&mut self) -> Option<(String, FileKind)> {
+        let d = unsafe { readdir(self.dir) };
+        if d.is_null() { return None; }
+        let d = unsafe { &*d };
+        let name = unsafe { CStr::from_ptr(d.name.as_ptr()) }.to_string_lossy().into_owned();
+        let kind = match d.kind {
+            DT_REG => FileKind::File,
+            DT_DIR => FileKind::Directory,
+            DT_LNK => FileKind::Symlink,
+            // Some filesystems don't fill in the type; ask for it.
+            DT_UNKNOWN => stat_path(&format!("{}/{}", self.base, name), AT_SYMLINK_NOFOLLOW, STATX_TYPE).map_or(FileKind::Other, |st| kind_of(st.mode)),
+            _ => FileKind::Other,
+        };
+        Some((name, kind))
+    }
+}
+
+impl Drop for DirIter { fn drop(&mut self) { unsafe { closedir(self.dir); } } }
+
+pub fn impl_read_dir(path: &str) -> Result<DirIter, ()> {
+    let c = cpath(path)?;
+    let dir = unsafe { opendir(c.as_ptr()) };
+    if dir.is_null() { Err(()) } else { Ok(DirIter { dir, base: String::from(path) }) }
+}
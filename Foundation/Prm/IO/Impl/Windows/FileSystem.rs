@@ -1,2 +1,180 @@
-pub use crate::file_system::*;
+#![allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+use std::ffi::{c_void, OsStr};
+use std::os::windows::ffi::OsStrExt;
+use crate::file_system::*;
 
+extern "system" {
+    fn CreateFileW(lpFileName: *const u16, dwDesiredAccess: u32, dwShareMode: u32, lpSecurityAttributes: *mut c_void, dwCreationDisposition: u32, dwFlagsAndAttributes: u32, hTemplateFile: *mut c_void) -> *mut c_void;
+    fn ReadFile(hFile: *mut c_void, lpBuffer: *mut c_void, nNumberOfBytesToRead: u32, lpNumberOfBytesRead: *mut u32, lpOverlapped: *mut c_void) -> i32;
+    fn WriteFile(hFile: *mut c_void, lpBuffer: *const c_void, nNumberOfBytesToWrite: u32, lpNumberOfBytesWritten: *mut u32, lpOverlapped: *mut c_void) -> i32;
+    fn CloseHandle(hObject: *mut c_void) -> i32;
+    fn SetFilePointerEx(hFile: *mut c_void, liDistanceToMove: i64, lpNewFilePointer: *mut i64, dwMoveMethod: u32) -> i32;
+    fn GetFileSizeEx(hFile: *mut c_void, lpFileSize: *mut i64) -> i32;
+    fn GetStdHandle(nStdHandle: i32) -> *mut c_void;
+
+    fn CreateFileMappingW(hFile: *mut c_void, lpFileMappingAttributes: *mut c_void, flProtect: u32, dwMaximumSizeHigh: u32, dwMaximumSizeLow: u32, lpName: *const u16) -> *mut c_void;
+    fn MapViewOfFile(hFileMappingObject: *mut c_void, dwDesiredAccess: u32, dwFileOffsetHigh: u32, dwFileOffsetLow: u32, dwNumberOfBytesToMap: usize) -> *mut c_void;
+    fn UnmapViewOfFile(lpBaseAddress: *mut c_void) -> i32;
+    fn FlushViewOfFile(lpBaseAddress: *mut c_void, dwNumberOfBytesToFlush: usize) -> i32;
+
+    fn GetFileAttributesW(lpFileName: *const u16) -> u32;
+    fn CreateDirectoryW(lpPathName: *const u16, lpSecurityAttributes: *mut c_void) -> i32;
+    fn DeleteFileW(lpFileName: *const u16) -> i32;
+    fn RemoveDirectoryW(lpPathName: *const u16) -> i32;
+    fn MoveFileExW(lpExistingFileName: *const u16, lpNewFileName: *const u16, dwFlags: u32) -> i32;
+    fn CopyFileW(lpExistingFileName: *const u16, lpNewFileName: *const u16, bFailIfExists: i32) -> i32;
+    fn GetFileAttributesExW(lpFileName: *const u16, fInfoLevelId: i32, lpFileInformation: *mut WIN32_FILE_ATTRIBUTE_DATA) -> i32;
+    fn FindFirstFileW(lpFileName: *const u16, lpFindFileData: *mut WIN32_FIND_DATAW) -> *mut c_void;
+    fn FindNextFileW(hFindFile: *mut c_void, lpFindFileData: *mut WIN32_FIND_DATAW) -> i32;
+    fn FindClose(hFindFile: *mut c_void) -> i32;
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FILETIME { dwLowDateTime: u32, dwHighDateTime: u32 }
+
+#[repr(C)]
+struct WIN32_FILE_ATTRIBUTE_DATA { dwFileAttributes: u32, ftCreationTime: FILETIME, ftLastAccessTime: FILETIME, ftLastWriteTime: FILETIME, nFileSizeHigh: u32, nFileSizeLow: u32 }
+
+#[repr(C)]
+struct WIN32_FIND_DATAW { dwFileAttributes: u32, ftCreationTime: FILETIME, ftLastAccessTime: FILETIME, ftLastWriteTime: FILETIME, nFileSizeHigh: u32, nFileSizeLow: u32, dwReserved0: u32, dwReserved1: u32, cFileName: [u16; 260], cAlternateFileName: [u16; 14] }
+
+const GENERIC_READ: u32 = 0x8000_0000;
+const GENERIC_WRITE: u32 = 0x4000_0000;
+const FILE_SHARE_READ: u32 = 0x00000001;
+const FILE_SHARE_WRITE: u32 = 0x00000002;
+const FILE_SHARE_DELETE: u32 = 0x00000004;
+const CREATE_NEW: u32 = 1;
+const OPEN_EXISTING: u32 = 3;
+const OPEN_ALWAYS: u32 = 4;
+const TRUNCATE_EXISTING: u32 = 5;
+const FILE_ATTRIBUTE_NORMAL: u32 = 0x00000080;
+const STD_OUTPUT_HANDLE: i32 = -11;
+const STD_ERROR_HANDLE: i32 = -12;
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x00000010;
+const FILE_ATTRIBUTE_READONLY: u32 = 0x00000001;
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x00000400;
+const IO_REPARSE_TAG_SYMLINK: u32 = 0xA000_000C;
+const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+const GET_FILE_EX_INFO_STANDARD: i32 = 0;
+const MOVEFILE_REPLACE_EXISTING: u32 = 0x1;
+const MOVEFILE_COPY_ALLOWED: u32 = 0x2;
+const INVALID_HANDLE_VALUE: *mut c_void = !0usize as *mut c_void;
+// 100 ns ticks between 1601-01-01 and the Unix epoch.
+const FILETIME_UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+
+const PAGE_READONLY: u32 = 0x02;
+const PAGE_READWRITE: u32 = 0x04;
+const FILE_MAP_READ: u32 = 0x0004;
+const FILE_MAP_WRITE: u32 = 0x0002;
+
+fn wide(s: &str) -> Vec<u16> { OsStr::new(s).encode_wide().chain(std::iter::once(0)).collect() }
+fn ok(r: i32) -> Result<(), ()> { if r == 0 { Err(()) } else { Ok(()) } }
+
+fn kind_of(attrs: u32, reparse_tag: u32) -> FileKind {
+    // Without the tag (GetFileAttributesExW doesn't return it), any reparse point counts as a link.
+    let link = attrs & FILE_ATTRIBUTE_REPARSE_POINT != 0 && (reparse_tag == 0 || reparse_tag == IO_REPARSE_TAG_SYMLINK || reparse_tag == IO_REPARSE_TAG_MOUNT_POINT);
+    if link { FileKind::Symlink } else if attrs & FILE_ATTRIBUTE_DIRECTORY != 0 { FileKind::Directory } else { FileKind::File }
+}
+
+pub fn impl_open(path: &str, mode: FileOpenMode, share: FileShareMode) -> Result<FileHandle, ()> {
+    let w = wide(path);
+    let access = match mode { FileOpenMode::Read => GENERIC_READ, FileOpenMode::Write => GENERIC_WRITE, FileOpenMode::ReadWrite => GENERIC_READ | GENERIC_WRITE, FileOpenMode::Append => GENERIC_WRITE, FileOpenMode::Create => GENERIC_READ | GENERIC_WRITE, FileOpenMode::CreateNew => GENERIC_READ | GENERIC_WRITE, FileOpenMode::Truncate => GENERIC_WRITE };
+    let share_flags = match share { FileShareMode::None => 0, FileShareMode::Read => FILE_SHARE_READ, FileShareMode::Write => FILE_SHARE_WRITE, FileShareMode::ReadWrite => FILE_SHARE_READ | FILE_SHARE_WRITE, FileShareMode::Delete => FILE_SHARE_DELETE };
+    let disposition = match mode { FileOpenMode::Create => OPEN_ALWAYS, FileOpenMode::CreateNew => CREATE_NEW, FileOpenMode::Truncate => TRUNCATE_EXISTING, _ => OPEN_EXISTING };
+    let h = unsafe { CreateFileW(w.as_ptr(), access, share_flags, std::ptr::null_mut(), disposition, FILE_ATTRIBUTE_NORMAL, std::ptr::null_mut()) };
+    if h.is_null() { Err(()) } else { Ok(FileHandle(h)) }
+}
+
+pub fn impl_close(h: FileHandle) -> Result<(), ()> { let ok = unsafe { CloseHandle(h.0) }; if ok == 0 { Err(()) } else { Ok(()) } }
+
+pub fn impl_read(h: FileHandle, buf: &mut [u8]) -> Result<usize, ()> {
+    let mut n: u32 = 0;
+    let ok = unsafe { ReadFile(h.0, buf.as_mut_ptr() as *mut c_void, buf.len() as u32, &mut n as *mut u32, std::ptr::null_mut()) };
+    if ok == 0 { Err(()) } else { Ok(n as usize) }
+}
+
+pub fn impl_write(h: FileHandle, buf: &[u8]) -> Result<usize, ()> {
+    let mut n: u32 = 0;
+    let ok = unsafe { WriteFile(h.0, buf.as_ptr() as *const c_void, buf.len() as u32, &mut n as *mut u32, std::ptr::null_mut()) };
+    if ok == 0 { Err(()) } else { Ok(n as usize) }
+}
+
+pub fn impl_size(h: FileHandle) -> Result<u64, ()> { let mut s: i64 = 0; let ok = unsafe { GetFileSizeEx(h.0, &mut s as *mut i64) }; if ok == 0 { Err(()) } else { Ok(s as u64) } }
+
+pub fn impl_seek(h: FileHandle, offset: i64, origin: SeekOrigin) -> Result<u64, ()> {
+    let mut newpos: i64 = 0;
+    let method = match origin { SeekOrigin::Begin => 0, SeekOrigin::Current => 1, SeekOrigin::End => 2 };
+    let ok = unsafe { SetFilePointerEx(h.0, offset, &mut newpos as *mut i64, method) };
+    if ok == 0 { Err(()) } else { Ok(newpos as u64) }
+}
+
+pub fn impl_map(h: FileHandle, offset: u64, size: usize, access: MapAccess) -> Result<Mapping, ()> {
+    let protect = match access { MapAccess::Read => PAGE_READONLY, MapAccess::Write | MapAccess::ReadWrite => PAGE_READWRITE };
+    let map = unsafe { CreateFileMappingW(h.0, std::ptr::null_mut(), protect, 0, 0, std::ptr::null()) };
+    if map.is_null() { return Err(()); }
+    let desired = match access { MapAccess::Read => FILE_MAP_READ, MapAccess::Write => FILE_MAP_WRITE, MapAccess::ReadWrite => FILE_MAP_READ | FILE_MAP_WRITE };
+    let addr = unsafe { MapViewOfFile(map, desired, (offset >> 32) as u32, (offset & 0xFFFF_FFFF) as u32, size) };
+    if addr.is_null() { unsafe { CloseHandle(map) }; return Err(()); }
+    Ok(Mapping { address: addr, length: size, native_mapping_handle: map })
+}
+
+pub fn impl_unmap(m: &Mapping) -> Result<(), ()> { let ok = unsafe { UnmapViewOfFile(m.address) }; if ok == 0 { Err(()) } else { Ok(()) } }
+pub fn impl_flush_mapped(_h: FileHandle, addr: *mut c_void, size: usize) -> Result<(), ()> { let ok = unsafe { FlushViewOfFile(addr, size) }; if ok == 0 { Err(()) } else { Ok(()) } }
+
+pub fn impl_stdout_handle() -> FileHandle { FileHandle(unsafe { GetStdHandle(STD_OUTPUT_HANDLE) }) }
+pub fn impl_stderr_handle() -> FileHandle { FileHandle(unsafe { GetStdHandle(STD_ERROR_HANDLE) }) }
+
+pub fn impl_path_exists(path: &str) -> bool { unsafe { let w = wide(path); let a = GetFileAttributesW(w.as_ptr()); a != 0xFFFF_FFFF } }
+pub fn impl_path_is_directory(path: &str) -> bool { unsafe { let w = wide(path); let a = GetFileAttributesW(w.as_ptr()); a != 0xFFFF_FFFF && (a & FILE_ATTRIBUTE_DIRECTORY) != 0 } }
+pub fn impl_path_create_directory(path: &str) -> Result<(), ()> { let w = wide(path); let ok = unsafe { CreateDirectoryW(w.as_ptr(), std::ptr::null_mut()) }; if ok == 0 { Err(()) } else { Ok(()) } }
+pub fn impl_path_remove_file(path: &str) -> Result<(), ()> { let w = wide(path); let ok = unsafe { DeleteFileW(w.as_ptr()) }; if ok == 0 { Err(()) } else { Ok(()) } }
+
+pub fn impl_metadata(path: &str) -> Result<Metadata, ()> {
+    let w = wide(path);
+    let mut d = std::mem::MaybeUninit::<WIN32_FILE_ATTRIBUTE_DATA>::zeroed();
+    ok(unsafe { GetFileAttributesExW(w.as_ptr(), GET_FILE_EX_INFO_STANDARD, d.as_mut_ptr()) })?;
+    let d = unsafe { d.assume_init() };
+    let ticks = ((d.ftLastWriteTime.dwHighDateTime as i64) << 32) | d.ftLastWriteTime.dwLowDateTime as i64;
+    let size = ((d.nFileSizeHigh as u64) << 32) | d.nFileSizeLow as u64;
+    Ok(Metadata { kind: kind_of(d.dwFileAttributes, 0), size, modified_ns: (ticks - FILETIME_UNIX_EPOCH) * 100, readonly: d.dwFileAttributes & FILE_ATTRIBUTE_READONLY != 0 })
+}
+
+pub fn impl_rename(from: &str, to: &str) -> Result<(), ()> { let (a, b) = (wide(from), wide(to)); ok(unsafe { MoveFileExW(a.as_ptr(), b.as_ptr(), MOVEFILE_REPLACE_EXISTING | MOVEFILE_COPY_ALLOWED) }) }
+pub fn impl_remove_dir(path: &str) -> Result<(), ()> { let w = wide(path); ok(unsafe { RemoveDirectoryW(w.as_ptr()) }) }
+
+pub fn impl_copy_file(from: &str, to: &str) -> Result<u64, ()> {
+    let (a, b) = (wide(from), wide(to));
+    ok(unsafe { CopyFileW(a.as_ptr(), b.as_ptr(), 0) })?;
+    impl_metadata(to).map(|m| m.size)
+}
+
+// Directory links have to go through RemoveDirectoryW, file links through DeleteFileW.
+pub fn impl_remove_link(path: &str) -> Result<(), ()> {
+    let w = wide(path);
+    let a = unsafe { GetFileAttributesW(w.as_ptr()) };
+    if a != 0xFFFF_FFFF && a & FILE_ATTRIBUTE_DIRECTORY != 0 { ok(unsafe { RemoveDirectoryW(w.as_ptr()) }) } else { ok(unsafe { DeleteFileW(w.as_ptr()) }) }
+}
+
+// FindFirstFileW already returns the first entry, so it is held back for the first `next_entry`.
+pub struct DirIter { h: *mut c_void, data: Box<WIN32_FIND_DATAW>, pending: bool }
+
+impl DirIter {
+    pub fn next_entry(&mut self) -> Option<(String, FileKind)> {
+        if !self.pending && unsafe { FindNextFileW(self.h, &mut *self.data) } == 0 { return None; }
+        self.pending = false;
+        let d = &*self.data;
+        let len = d.cFileName.iter().position(|&c| c == 0).unwrap_or(d.cFileName.len());
+        Some((String::from_utf16_lossy(&d.cFileName[..len]), kind_of(d.dwFileAttributes, d.dwReserved0)))
+    }
+}
+
+impl Drop for DirIter { fn drop(&mut self) { unsafe { FindClose(self.h); } } }
+
+pub fn impl_read_dir(path: &str) -> Result<DirIter, ()> {
+    let pattern = if path.is_empty() { String::from("*") } else if path.ends_with(['/', '\\']) { format!("{}*", path) } else { format!("{}\\*", path) };
+    let w = wide(&pattern);
+    let mut data: Box<WIN32_FIND_DATAW> = Box::new(unsafe { std::mem::zeroed() });
+    let h = unsafe { FindFirstFileW(w.as_ptr(), &mut *data) };
+    if h == INVALID_HANDLE_VALUE { Err(()) } else { Ok(DirIter { h, data, pending: true }) }
+}
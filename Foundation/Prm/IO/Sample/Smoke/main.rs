@@ -10,7 +10,60 @@ fn main() {
     let n = read(h2, &mut buf).unwrap();
     assert_eq!(&buf[..n], b"abc");
     close(h2).unwrap();
+    assert!(path_exists(path) && !path_is_directory(path));
     path_remove_file(path).unwrap();
+    assert!(!path_exists(path));
+    let dir = "io_smoke_dir";
+    if !path_exists(dir) { path_create_directory(dir).unwrap(); }
+    assert!(path_is_directory(dir));
+
+    // A small tree: root/{a.txt, sub/{b.bin, deep/c.txt}, skip/d.txt}
+    let root = "io_smoke_tree";
+    if path_exists(root) { remove_dir_all(root).unwrap(); }
+    for d in ["", "/sub", "/sub/deep", "/skip"] { path_create_directory(&format!("{}{}", root, d)).unwrap(); }
+    for (f, body) in [("/a.txt", &b"alpha"[..]), ("/sub/b.bin", b"bb"), ("/sub/deep/c.txt", b"c"), ("/skip/d.txt", b"d")] {
+        let h = open(&format!("{}{}", root, f), FileOpenMode::CreateNew, FileShareMode::None).unwrap();
+        write(h, body).unwrap();
+        close(h).unwrap();
+    }
+    let a = format!("{}/a.txt", root);
+    let m = metadata(&a).unwrap();
+    assert!(m.kind == FileKind::File && m.size == 5 && !m.readonly && m.modified_ns > 0);
+    assert_eq!(metadata(root).unwrap().kind, FileKind::Directory);
+
+    let mut top: Vec<String> = read_dir(root).unwrap().map(|e| e.name().to_string()).collect();
+    top.sort();
+    assert_eq!(top, ["a.txt", "skip", "sub"]);
+
+    let mut txt: Vec<(String, usize)> = walk(root).filter_entry(|e| e.name() != "skip").filter(|e| e.name().ends_with(".txt")).map(|e| (e.name().to_string(), e.depth)).collect();
+    txt.sort();
+    assert_eq!(txt, [("a.txt".to_string(), 1), ("c.txt".to_string(), 3)]);
+    assert_eq!(walk(root).count(), 7);
+    assert_eq!(walk(root).max_depth(2).count(), 6);
+
+    let copy = format!("{}/sub/a_copy.txt", root);
+    assert_eq!(copy_file(&a, &copy).unwrap(), 5);
+    let moved = format!("{}/moved.txt", root);
+    rename(&copy, &moved).unwrap();
+    assert!(!path_exists(&copy) && metadata(&moved).unwrap().size == 5);
+    remove_dir_all(root).unwrap();
+    assert!(!path_exists(root));
+
+    // Given a symlink to a directory, `remove_dir_all` removes the link and leaves the target alone.
+    #[cfg(unix)]
+    {
+        let (keep, link) = ("io_smoke_keep", "io_smoke_link");
+        if path_exists(keep) { remove_dir_all(keep).unwrap(); }
+        path_create_directory(keep).unwrap();
+        let kept = format!("{}/kept.txt", keep);
+        close(open(&kept, FileOpenMode::CreateNew, FileShareMode::None).unwrap()).unwrap();
+        if metadata(link).is_ok() { path_remove_file(link).unwrap(); }
+        std::os::unix::fs::symlink(keep, link).unwrap();
+        assert_eq!(metadata(link).unwrap().kind, FileKind::Symlink);
+        remove_dir_all(link).unwrap();
+        assert!(metadata(link).is_err() && path_exists(&kept));
+        remove_dir_all(keep).unwrap();
+    }
     println!("IO OK");
 }
 
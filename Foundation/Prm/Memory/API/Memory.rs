@@ -15,7 +15,11 @@ pub fn align_up(value: usize, align: usize) -> usize { if align > 0 { (value + (
 #[path = "../Impl/Windows/Memory.rs"]
 mod backend;
 
-#[cfg(not(target_os = "windows"))]
+#[cfg(target_os = "linux")]
+#[path = "../Impl/Linux/Memory.rs"]
+mod backend;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
 mod backend {
     use super::*;
     pub fn vm_reserve(_size: usize) -> Result<*mut c_void, MemoryError> { Err(MemoryError::ReserveFailed) }
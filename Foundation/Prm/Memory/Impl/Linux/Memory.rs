@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::sync::Mutex;
+use crate::*;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, off: i64) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn madvise(addr: *mut c_void, len: usize, advice: i32) -> i32;
+    fn sysconf(name: i32) -> i64;
+    fn syscall(num: i64, ...) -> i64;
+    fn malloc(size: usize) -> *mut c_void;
+    fn free(p: *mut c_void);
+}
+
+const PROT_NONE: i32 = 0;
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+const MAP_NORESERVE: i32 = 0x4000;
+const MAP_HUGETLB: i32 = 0x40000;
+const MAP_FAILED: *mut c_void = !0usize as *mut c_void;
+const MADV_DONTNEED: i32 = 4;
+const MADV_HUGEPAGE: i32 = 14;
+const MPOL_PREFERRED: i32 = 1;
+const _SC_PAGESIZE: i32 = 30;
+// Windows' NUMA_NO_PREFERRED_NODE: leave placement to the kernel.
+const NO_NUMA_NODE: u32 = u32::MAX;
+
+#[cfg(target_arch = "x86_64")]
+const SYS_MBIND: i64 = 237;
+#[cfg(target_arch = "aarch64")]
+const SYS_MBIND: i64 = 235;
+
+// munmap needs the length that VirtualFree(MEM_RELEASE) works out by itself, so every mapping
+// handed out is recorded here by base address.
+static RESERVATIONS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+fn posix_protect(p: PageProtection) -> i32 {
+    match p { PageProtection::NoAccess => PROT_NONE, PageProtection::ReadOnly => PROT_READ, PageProtection::ReadWrite => PROT_READ | PROT_WRITE, PageProtection::ExecuteRead => PROT_READ | PROT_EXEC, PageProtection::ExecuteReadWrite => PROT_READ | PROT_WRITE | PROT_EXEC }
+}
+
+// Widens [base, base + size) to whole pages, as the Windows calls do implicitly.
+fn page_span(base: *mut c_void, size: usize) -> (*mut c_void, usize) {
+    let ps = vm_page_size();
+    let start = base as usize & !(ps - 1);
+    (start as *mut c_void, align_up(base as usize + size, ps) - start)
+}
+
+fn map(len: usize, prot: i32, flags: i32) -> Option<*mut c_void> {
+    let p = unsafe { mmap(std::ptr::null_mut(), len, prot, MAP_PRIVATE | MAP_ANONYMOUS | flags, -1, 0) };
+    if p == MAP_FAILED { None } else { Some(p) }
+}
+
+fn register(p: *mut c_void, len: usize) -> *mut c_void { RESERVATIONS.lock().unwrap_or_else(|e| e.into_inner()).insert(p as usize, len); p }
+
+pub fn vm_reserve(size: usize) -> Result<*mut c_void, MemoryError> {
+    if size == 0 { return Err(MemoryError::InvalidArgument); }
+    let len = align_up(size, vm_page_size());
+    // PROT_NONE + MAP_NORESERVE: address space only, no commit charge until `vm_commit`.
+    let p = map(len, PROT_NONE, MAP_NORESERVE).ok_or(MemoryError::ReserveFailed)?;
+    Ok(register(p, len))
+}
+
+pub fn vm_commit(base: *mut c_void, size: usize, protection: PageProtection) -> Result<(), MemoryError> {
+    if base.is_null() || size == 0 { return Err(MemoryError::InvalidArgument); }
+    let (start, len) = page_span(base, size);
+    if unsafe { mprotect(start, len, posix_protect(protection)) } == 0 { Ok(()) } else { Err(MemoryError::CommitFailed) }
+}
+
+pub fn vm_protect(base: *mut c_void, size: usize, protection: PageProtection) -> Result<(), MemoryError> {
+    if base.is_null() || size == 0 { return Err(MemoryError::InvalidArgument); }
+    let (start, len) = page_span(base, size);
+    if unsafe { mprotect(start, len, posix_protect(protection)) } == 0 { Ok(()) } else { Err(MemoryError::ProtectFailed) }
+}
+
+// Drops the pages (they read back as zeros once committed again) and makes the range inaccessible.
+pub fn vm_decommit(base: *mut c_void, size: usize) -> Result<(), MemoryError> {
+    if base.is_null() || size == 0 { return Err(MemoryError::InvalidArgument); }
+    let (start, len) = page_span(base, size);
+    if unsafe { madvise(start, len, MADV_DONTNEED) } != 0 { return Err(MemoryError::DecommitFailed); }
+    if unsafe { mprotect(start, len, PROT_NONE) } == 0 { Ok(()) } else { Err(MemoryError::DecommitFailed) }
+}
+
+pub fn vm_release(base: *mut c_void) -> Result<(), MemoryError> {
+    if base.is_null() { return Err(MemoryError::InvalidArgument); }
+    let len = RESERVATIONS.lock().unwrap_or_else(|e| e.into_inner()).remove(&(base as usize)).ok_or(MemoryError::ReleaseFailed)?;
+    if unsafe { munmap(base, len) } == 0 { Ok(()) } else { Err(MemoryError::ReleaseFailed) }
+}
+
+pub fn vm_page_size() -> usize { let ps = unsafe { sysconf(_SC_PAGESIZE) }; if ps <= 0 { 4096 } else { ps as usize } }
+// mmap places mappings on any page boundary, unlike VirtualAlloc's 64 KiB.
+pub fn vm_alloc_granularity() -> usize { vm_page_size() }
+
+fn kib_field(text: &str, key: &str) -> Option<usize> {
+    let rest = text.lines().find_map(|l| l.strip_prefix(key))?;
+    rest.trim().trim_end_matches("kB").trim().parse::<usize>().ok().map(|k| k * 1024)
+}
+
+/// The default explicit huge page size, or the transparent huge page size when hugetlbfs is absent.
+pub fn vm_large_page_size() -> Result<usize, MemoryError> {
+    let explicit = std::fs::read_to_string("/proc/meminfo").ok().and_then(|m| kib_field(&m, "Hugepagesize:"));
+    let thp = || std::fs::read_to_string("/sys/kernel/mm/transparent_hugepage/hpage_pmd_size").ok().and_then(|s| s.trim().parse::<usize>().ok());
+    explicit.or_else(thp).filter(|&n| n > 0).ok_or(MemoryError::LargePagesNotSupported)
+}
+
+// Highest node id the kernel could bring online, from a "0", "0-3" or "0,2-5" style list.
+fn max_possible_node() -> Option<u32> {
+    let list = std::fs::read_to_string("/sys/devices/system/node/possible").ok()?;
+    list.trim().rsplit([',', '-']).next()?.parse().ok()
+}
+
+// Prefers `node` for pages faulted in later, like VirtualAllocExNuma's preferred node.
+fn bind_node(p: *mut c_void, len: usize, node: u32) -> Result<(), MemoryError> {
+    // Checked first: the mask below is sized by the id.
+    if node > max_possible_node().ok_or(MemoryError::NumaUnavailable)? { return Err(MemoryError::InvalidArgument); }
+    let mut mask = vec![0u64; node as usize / 64 + 1];
+    mask[node as usize / 64] = 1 << (node % 64);
+    // The kernel ignores the last bit of `maxnode`, hence the + 1.
+    let r = unsafe { syscall(SYS_MBIND, p, len, MPOL_PREFERRED as i64, mask.as_ptr(), (mask.len() * 64 + 1) as u64, 0u32) };
+    if r == 0 { Ok(()) } else { Err(MemoryError::NumaUnavailable) }
+}
+
+// Large pages come committed and read-write, as with MEM_LARGE_PAGES. Explicit (hugetlbfs) pages
+// are tried first; without a reserved pool the range is aligned to the huge page size and left to
+// transparent huge pages.
+fn map_large(size: usize) -> Result<(*mut c_void, usize), MemoryError> {
+    let lp = vm_large_page_size()?;
+    let len = align_up(size, lp);
+    if let Some(p) = map(len, PROT_READ | PROT_WRITE, MAP_HUGETLB) { return Ok((p, len)); }
+    let raw = map(len + lp, PROT_READ | PROT_WRITE, 0).ok_or(MemoryError::ReserveFailed)?;
+    let start = align_up(raw as usize, lp);
+    let (head, tail) = (start - raw as usize, raw as usize + len + lp - (start + len));
+    unsafe {
+        if head > 0 { munmap(raw, head); }
+        if tail > 0 { munmap((start + len) as *mut c_void, tail); }
+    }
+    let p = start as *mut c_void;
+    if unsafe { madvise(p, len, MADV_HUGEPAGE) } != 0 { unsafe { munmap(p, len); } return Err(MemoryError::LargePagesNotEnabled); }
+    Ok((p, len))
+}
+
+pub fn vm_reserve_ex(size: usize, numa_node_id: u32, use_large_pages: bool) -> Result<*mut c_void, MemoryError> {
+    if size == 0 { return Err(MemoryError::InvalidArgument); }
+    let (p, len) = if use_large_pages { map_large(size)? } else {
+        let len = align_up(size, vm_page_size());
+        (map(len, PROT_NONE, MAP_NORESERVE).ok_or(MemoryError::ReserveFailed)?, len)
+    };
+    if numa_node_id != NO_NUMA_NODE {
+        if let Err(e) = bind_node(p, len, numa_node_id) { unsafe { munmap(p, len); } return Err(e); }
+    }
+    Ok(register(p, len))
+}
+
+// There are no private heaps to ask the OS for, so a created heap keeps its live blocks on a list and
+// heap_destroy frees whatever is left, as HeapDestroy does. The header keeps malloc's 16-byte alignment.
+// The process heap is never destroyed, so it is plain malloc and free with no header, list or lock.
+#[repr(C, align(16))]
+struct Node { prev: *mut Node, next: *mut Node }
+
+struct Heap { head: Mutex<NodePtr> }
+struct NodePtr(*mut Node);
+unsafe impl Send for NodePtr {}
+
+// Only its address matters: it tells the process heap's handle apart from created ones.
+static PROCESS_HEAP: u8 = 0;
+const HEADER: usize = std::mem::size_of::<Node>();
+
+fn is_process_heap(h: HeapHandle) -> bool { std::ptr::eq(h.0 as *const u8, &PROCESS_HEAP) }
+fn heap_of(h: HeapHandle) -> &'static Heap { unsafe { &*(h.0 as *const Heap) } }
+
+pub fn heap_create() -> Result<HeapHandle, MemoryError> { Ok(HeapHandle(Box::into_raw(Box::new(Heap { head: Mutex::new(NodePtr(std::ptr::null_mut())) })) as *mut c_void)) }
+
+pub fn heap_destroy(h: HeapHandle) -> Result<(), MemoryError> {
+    if h.0.is_null() { return Err(MemoryError::InvalidArgument); }
+    if is_process_heap(h) { return Err(MemoryError::HeapDestroyFailed); }
+    let heap = unsafe { Box::from_raw(h.0 as *mut Heap) };
+    let mut n = heap.head.into_inner().unwrap_or_else(|e| e.into_inner()).0;
+    while !n.is_null() { let next = unsafe { (*n).next }; unsafe { free(n as *mut c_void) }; n = next; }
+    Ok(())
+}
+
+pub fn heap_process_default() -> HeapHandle { HeapHandle(&PROCESS_HEAP as *const u8 as *mut c_void) }
+
+pub fn heap_alloc_raw(h: HeapHandle, size: usize) -> Result<*mut c_void, MemoryError> {
+    if h.0.is_null() || size == 0 { return Err(MemoryError::InvalidArgument); }
+    if is_process_heap(h) {
+        let p = unsafe { malloc(size) };
+        return if p.is_null() { Err(MemoryError::HeapAllocFailed) } else { Ok(p) };
+    }
+    let n = unsafe { malloc(size.checked_add(HEADER).ok_or(MemoryError::OutOfMemory)?) } as *mut Node;
+    if n.is_null() { return Err(MemoryError::HeapAllocFailed); }
+    let mut head = heap_of(h).head.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe {
+        n.write(Node { prev: std::ptr::null_mut(), next: head.0 });
+        if !head.0.is_null() { (*head.0).prev = n; }
+    }
+    head.0 = n;
+    Ok(unsafe { (n as *mut u8).add(HEADER) } as *mut c_void)
+}
+
+pub fn heap_free_raw(h: HeapHandle, p: *mut c_void) -> Result<(), MemoryError> {
+    if h.0.is_null() || p.is_null() { return Err(MemoryError::InvalidArgument); }
+    if is_process_heap(h) { unsafe { free(p); } return Ok(()); }
+    let n = unsafe { (p as *mut u8).sub(HEADER) } as *mut Node;
+    let mut head = heap_of(h).head.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe {
+        let Node { prev, next } = n.read();
+        if prev.is_null() { head.0 = next; } else { (*prev).next = next; }
+        if !next.is_null() { (*next).prev = prev; }
+        free(n as *mut c_void);
+    }
+    Ok(())
+}
+
+pub fn heap_alloc(h: HeapHandle, size: usize, alignment: usize) -> Result<*mut c_void, MemoryError> {
+    if h.0.is_null() || size == 0 { return Err(MemoryError::InvalidArgument); }
+    if alignment == 0 || (alignment & (alignment - 1)) != 0 { return Err(MemoryError::AlignmentNotPowerOfTwo); }
+    if alignment > heap_max_alignment() { return Err(MemoryError::AlignmentTooLarge); }
+    let extra = alignment - 1 + std::mem::size_of::<*mut c_void>();
+    let raw = heap_alloc_raw(h, size.checked_add(extra).ok_or(MemoryError::OutOfMemory)?)?;
+    let base = (raw as usize) + std::mem::size_of::<*mut c_void>();
+    let aligned = (base + (alignment - 1)) & !(alignment - 1);
+    let marker = aligned as *mut *mut c_void;
+    unsafe { std::ptr::write(marker.offset(-1), raw); }
+    Ok(aligned as *mut c_void)
+}
+
+pub fn heap_free(h: HeapHandle, p: *mut c_void) -> Result<(), MemoryError> {
+    if h.0.is_null() || p.is_null() { return Err(MemoryError::InvalidArgument); }
+    let raw = unsafe { *(p as *mut *mut c_void).offset(-1) };
+    heap_free_raw(h, raw)
+}
+
+pub fn heap_max_alignment() -> usize { vm_alloc_granularity() }
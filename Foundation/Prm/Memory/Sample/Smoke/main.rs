@@ -7,13 +7,35 @@ fn main() {
     unsafe { std::ptr::write_bytes(p, 0xAB, ps) };
     protect(p, ps, PageProtection::ReadOnly).unwrap();
     decommit(p, ps).unwrap();
+    // Committed again, decommitted pages read back as zeros.
+    commit(p, ps * 2, PageProtection::ReadWrite).unwrap();
+    assert_eq!(unsafe { *(p as *const u8) }, 0);
     release(p).unwrap();
 
+    let n = reserve_ex(ps * 4, 0, false).unwrap();
+    commit(n, ps * 4, PageProtection::ReadWrite).unwrap();
+    unsafe { std::ptr::write_bytes(n, 0x11, ps * 4) };
+    release(n).unwrap();
+    // A node id far past any real machine fails instead of sizing anything by it.
+    assert!(reserve_ex(ps, 1 << 30, false).is_err());
+    match large_page_size().and_then(|lp| reserve_ex(lp, u32::MAX, true).map(|p| (lp, p))) {
+        Ok((lp, p)) => { unsafe { std::ptr::write_bytes(p, 0x22, lp) }; release(p).unwrap(); println!("large pages {} KiB", lp >> 10); }
+        Err(e) => println!("large pages unavailable: {:?}", e),
+    }
+
     let h = heap_create().unwrap();
     let a = heap_alloc(h, 1024, heap_max_alignment().min(4096)).unwrap();
     unsafe { std::ptr::write_bytes(a, 0xCD, 1024) };
     heap_free(h, a).unwrap();
+    let _leaked = heap_alloc_raw(h, 256).unwrap();
     heap_destroy(h).unwrap();
+    let d = heap_alloc(heap_process_default(), 100, 64).unwrap();
+    assert_eq!(d as usize % 64, 0);
+    heap_free(heap_process_default(), d).unwrap();
+    let r = heap_alloc_raw(heap_process_default(), 48).unwrap();
+    unsafe { std::ptr::write_bytes(r, 0xEF, 48) };
+    heap_free_raw(heap_process_default(), r).unwrap();
+    assert!(heap_destroy(heap_process_default()).is_err());
     println!("Memory OK");
 }
 
@@ -48,6 +48,52 @@ mod backend {
         F32x8 { lanes: [*ptr, *ptr.add(1), *ptr.add(2), *ptr.add(3), *ptr.add(4), *ptr.add(5), *ptr.add(6), *ptr.add(7)] }
     }
     pub fn impl_hsum8(a: F32x8) -> f32 { let mut s = 0.0; for i in 0..8 { s += a.lanes[i]; } s }
+    fn lanes4(f: impl FnMut(usize) -> f32) -> F32x4 { F32x4 { lanes: std::array::from_fn(f) } }
+    fn lanes8(f: impl FnMut(usize) -> f32) -> F32x8 { F32x8 { lanes: std::array::from_fn(f) } }
+    fn bits4(a: F32x4, b: F32x4, op: impl Fn(u32, u32) -> u32) -> F32x4 { lanes4(|i| f32::from_bits(op(a.lanes[i].to_bits(), b.lanes[i].to_bits()))) }
+    fn bits8(a: F32x8, b: F32x8, op: impl Fn(u32, u32) -> u32) -> F32x8 { lanes8(|i| f32::from_bits(op(a.lanes[i].to_bits(), b.lanes[i].to_bits()))) }
+    pub fn impl_abs(a: F32x4) -> F32x4 { lanes4(|i| a.lanes[i].abs()) }
+    pub fn impl_rcp(a: F32x4) -> F32x4 { lanes4(|i| 1.0 / a.lanes[i]) }
+    pub fn impl_rsqrt(a: F32x4) -> F32x4 { lanes4(|i| 1.0 / a.lanes[i].sqrt()) }
+    pub fn impl_floor(a: F32x4) -> F32x4 { lanes4(|i| a.lanes[i].floor()) }
+    pub fn impl_ceil(a: F32x4) -> F32x4 { lanes4(|i| a.lanes[i].ceil()) }
+    pub fn impl_round(a: F32x4) -> F32x4 { lanes4(|i| a.lanes[i].round()) }
+    pub fn impl_and(a: F32x4, b: F32x4) -> F32x4 { bits4(a, b, |x, y| x & y) }
+    pub fn impl_or(a: F32x4, b: F32x4) -> F32x4 { bits4(a, b, |x, y| x | y) }
+    pub fn impl_xor(a: F32x4, b: F32x4) -> F32x4 { bits4(a, b, |x, y| x ^ y) }
+    pub fn impl_not(a: F32x4) -> F32x4 { bits4(a, a, |x, _| !x) }
+    pub fn impl_add8(a: F32x8, b: F32x8) -> F32x8 { lanes8(|i| a.lanes[i] + b.lanes[i]) }
+    pub fn impl_mul8(a: F32x8, b: F32x8) -> F32x8 { lanes8(|i| a.lanes[i] * b.lanes[i]) }
+    pub fn impl_fma8(a: F32x8, b: F32x8, c: F32x8) -> F32x8 { lanes8(|i| a.lanes[i] * b.lanes[i] + c.lanes[i]) }
+    pub fn impl_min8(a: F32x8, b: F32x8) -> F32x8 { lanes8(|i| if a.lanes[i] < b.lanes[i] { a.lanes[i] } else { b.lanes[i] }) }
+    pub fn impl_max8(a: F32x8, b: F32x8) -> F32x8 { lanes8(|i| if a.lanes[i] > b.lanes[i] { a.lanes[i] } else { b.lanes[i] }) }
+    pub fn impl_cmp_lt8(a: F32x8, b: F32x8) -> Mask8 { Mask8 { lanes: std::array::from_fn(|i| if a.lanes[i] < b.lanes[i] { 0xFFFF_FFFF } else { 0 }) } }
+    pub fn impl_cmp_eq8(a: F32x8, b: F32x8) -> Mask8 { Mask8 { lanes: std::array::from_fn(|i| if a.lanes[i] == b.lanes[i] { 0xFFFF_FFFF } else { 0 }) } }
+    pub fn impl_select8(mask: Mask8, a: F32x8, b: F32x8) -> F32x8 { lanes8(|i| if mask.lanes[i] != 0 { a.lanes[i] } else { b.lanes[i] }) }
+    pub fn impl_abs8(a: F32x8) -> F32x8 { lanes8(|i| a.lanes[i].abs()) }
+    pub fn impl_rcp8(a: F32x8) -> F32x8 { lanes8(|i| 1.0 / a.lanes[i]) }
+    pub fn impl_rsqrt8(a: F32x8) -> F32x8 { lanes8(|i| 1.0 / a.lanes[i].sqrt()) }
+    pub fn impl_floor8(a: F32x8) -> F32x8 { lanes8(|i| a.lanes[i].floor()) }
+    pub fn impl_ceil8(a: F32x8) -> F32x8 { lanes8(|i| a.lanes[i].ceil()) }
+    pub fn impl_round8(a: F32x8) -> F32x8 { lanes8(|i| a.lanes[i].round()) }
+    pub fn impl_trunc8(a: F32x8) -> F32x8 { lanes8(|i| a.lanes[i].trunc()) }
+    pub fn impl_and8(a: F32x8, b: F32x8) -> F32x8 { bits8(a, b, |x, y| x & y) }
+    pub fn impl_or8(a: F32x8, b: F32x8) -> F32x8 { bits8(a, b, |x, y| x | y) }
+    pub fn impl_xor8(a: F32x8, b: F32x8) -> F32x8 { bits8(a, b, |x, y| x ^ y) }
+    pub fn impl_not8(a: F32x8) -> F32x8 { bits8(a, a, |x, _| !x) }
+    pub unsafe fn impl_dot3_fma8_aligned(px: *const f32, py: *const f32, pz: *const f32, cx: f32, cy: f32, cz: f32) -> F32x8 {
+        lanes8(|i| *px.add(i) * cx + *py.add(i) * cy + *pz.add(i) * cz)
+    }
+    #[inline(always)]
+    pub unsafe fn impl_load_u64x4(ptr: *const u64) -> U64x4 { U64x4 { lanes: [*ptr, *ptr.add(1), *ptr.add(2), *ptr.add(3)] } }
+    #[inline(always)]
+    pub unsafe fn impl_store_u64x4(ptr: *mut u64, v: U64x4) { *ptr = v.lanes[0]; *ptr.add(1) = v.lanes[1]; *ptr.add(2) = v.lanes[2]; *ptr.add(3) = v.lanes[3]; }
+    pub fn impl_and_u64x4(a: U64x4, b: U64x4) -> U64x4 { U64x4 { lanes: std::array::from_fn(|i| a.lanes[i] & b.lanes[i]) } }
+    pub fn impl_or_u64x4(a: U64x4, b: U64x4) -> U64x4 { U64x4 { lanes: std::array::from_fn(|i| a.lanes[i] | b.lanes[i]) } }
+    pub fn impl_xor_u64x4(a: U64x4, b: U64x4) -> U64x4 { U64x4 { lanes: std::array::from_fn(|i| a.lanes[i] ^ b.lanes[i]) } }
+    pub fn impl_andnot_u64x4(a: U64x4, b: U64x4) -> U64x4 { U64x4 { lanes: std::array::from_fn(|i| a.lanes[i] & !b.lanes[i]) } }
+    pub fn impl_is_zero_u64x4(a: U64x4) -> bool { (a.lanes[0] | a.lanes[1] | a.lanes[2] | a.lanes[3]) == 0 }
+    pub fn impl_popcnt_u64x4(a: U64x4) -> u32 { a.lanes[0].count_ones() + a.lanes[1].count_ones() + a.lanes[2].count_ones() + a.lanes[3].count_ones() }
 }
 
 pub unsafe fn load(ptr: *const f32) -> F32x4 { backend::impl_load(ptr) }
@@ -99,3 +145,17 @@ pub fn not8(a: F32x8) -> F32x8 { backend::impl_not8(a) }
 
 pub unsafe fn dot3_fma8_aligned(px: *const f32, py: *const f32, pz: *const f32, cx: f32, cy: f32, cz: f32) -> F32x8 { backend::impl_dot3_fma8_aligned(px, py, pz, cx, cy, cz) }
 pub fn hsum8(a: F32x8) -> f32 { backend::impl_hsum8(a) }
+
+/// # Safety
+/// `ptr` must be valid for reading four `u64`s; no alignment is required.
+pub unsafe fn load_u64x4(ptr: *const u64) -> U64x4 { backend::impl_load_u64x4(ptr) }
+/// # Safety
+/// `ptr` must be valid for writing four `u64`s; no alignment is required.
+pub unsafe fn store_u64x4(ptr: *mut u64, v: U64x4) { backend::impl_store_u64x4(ptr, v) }
+pub fn and_u64x4(a: U64x4, b: U64x4) -> U64x4 { backend::impl_and_u64x4(a, b) }
+pub fn or_u64x4(a: U64x4, b: U64x4) -> U64x4 { backend::impl_or_u64x4(a, b) }
+pub fn xor_u64x4(a: U64x4, b: U64x4) -> U64x4 { backend::impl_xor_u64x4(a, b) }
+/// `a & !b` per lane.
+pub fn andnot_u64x4(a: U64x4, b: U64x4) -> U64x4 { backend::impl_andnot_u64x4(a, b) }
+pub fn is_zero_u64x4(a: U64x4) -> bool { backend::impl_is_zero_u64x4(a) }
+pub fn popcnt_u64x4(a: U64x4) -> u32 { backend::impl_popcnt_u64x4(a) }
@@ -12,3 +12,6 @@ pub struct F32x8 { pub lanes: [f32; 8] }
 
 #[derive(Clone, Copy)]
 pub struct Mask8 { pub lanes: [u32; 8] }
+
+#[derive(Clone, Copy)]
+pub struct U64x4 { pub lanes: [u64; 4] }
@@ -605,3 +605,92 @@ pub fn impl_or8(a: F32x8, b: F32x8) -> F32x8 { let mut r = [0.0;8]; for i in 0..
 pub fn impl_xor8(a: F32x8, b: F32x8) -> F32x8 { let mut r = [0.0;8]; for i in 0..8 { r[i] = f32::from_bits(a.lanes[i].to_bits() ^ b.lanes[i].to_bits()) } F32x8 { lanes: r } }
 #[inline(always)]
 pub fn impl_not8(a: F32x8) -> F32x8 { let mut r = [0.0;8]; for i in 0..8 { r[i] = f32::from_bits(!a.lanes[i].to_bits()) } F32x8 { lanes: r } }
+
+#[inline(always)]
+pub unsafe fn impl_load_u64x4(ptr: *const u64) -> U64x4 {
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+    {
+        let r = _mm256_loadu_si256(ptr as *const __m256i);
+        let mut out = U64x4 { lanes: [0; 4] };
+        _mm256_storeu_si256(out.lanes.as_mut_ptr() as *mut __m256i, r);
+        return out;
+    }
+    U64x4 { lanes: [*ptr, *ptr.add(1), *ptr.add(2), *ptr.add(3)] }
+}
+#[inline(always)]
+pub unsafe fn impl_store_u64x4(ptr: *mut u64, v: U64x4) {
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+    {
+        let r = _mm256_loadu_si256(v.lanes.as_ptr() as *const __m256i);
+        _mm256_storeu_si256(ptr as *mut __m256i, r);
+        return;
+    }
+    *ptr = v.lanes[0];
+    *ptr.add(1) = v.lanes[1];
+    *ptr.add(2) = v.lanes[2];
+    *ptr.add(3) = v.lanes[3];
+}
+#[inline(always)]
+pub fn impl_and_u64x4(a: U64x4, b: U64x4) -> U64x4 {
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    unsafe {
+        let va = _mm256_loadu_si256(a.lanes.as_ptr() as *const __m256i);
+        let vb = _mm256_loadu_si256(b.lanes.as_ptr() as *const __m256i);
+        let vr = _mm256_and_si256(va, vb);
+        let mut out = U64x4 { lanes: [0; 4] };
+        _mm256_storeu_si256(out.lanes.as_mut_ptr() as *mut __m256i, vr);
+        return out;
+    }
+    U64x4 { lanes: std::array::from_fn(|i| a.lanes[i] & b.lanes[i]) }
+}
+#[inline(always)]
+pub fn impl_or_u64x4(a: U64x4, b: U64x4) -> U64x4 {
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    unsafe {
+        let va = _mm256_loadu_si256(a.lanes.as_ptr() as *const __m256i);
+        let vb = _mm256_loadu_si256(b.lanes.as_ptr() as *const __m256i);
+        let vr = _mm256_or_si256(va, vb);
+        let mut out = U64x4 { lanes: [0; 4] };
+        _mm256_storeu_si256(out.lanes.as_mut_ptr() as *mut __m256i, vr);
+        return out;
+    }
+    U64x4 { lanes: std::array::from_fn(|i| a.lanes[i] | b.lanes[i]) }
+}
+#[inline(always)]
+pub fn impl_xor_u64x4(a: U64x4, b: U64x4) -> U64x4 {
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    unsafe {
+        let va = _mm256_loadu_si256(a.lanes.as_ptr() as *const __m256i);
+        let vb = _mm256_loadu_si256(b.lanes.as_ptr() as *const __m256i);
+        let vr = _mm256_xor_si256(va, vb);
+        let mut out = U64x4 { lanes: [0; 4] };
+        _mm256_storeu_si256(out.lanes.as_mut_ptr() as *mut __m256i, vr);
+        return out;
+    }
+    U64x4 { lanes: std::array::from_fn(|i| a.lanes[i] ^ b.lanes[i]) }
+}
+#[inline(always)]
+pub fn impl_andnot_u64x4(a: U64x4, b: U64x4) -> U64x4 {
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    unsafe {
+        let va = _mm256_loadu_si256(a.lanes.as_ptr() as *const __m256i);
+        let vb = _mm256_loadu_si256(b.lanes.as_ptr() as *const __m256i);
+        // _mm256_andnot_si256 computes !first & second
+        let vr = _mm256_andnot_si256(vb, va);
+        let mut out = U64x4 { lanes: [0; 4] };
+        _mm256_storeu_si256(out.lanes.as_mut_ptr() as *mut __m256i, vr);
+        return out;
+    }
+    U64x4 { lanes: std::array::from_fn(|i| a.lanes[i] & !b.lanes[i]) }
+}
+#[inline(always)]
+pub fn impl_is_zero_u64x4(a: U64x4) -> bool {
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+    unsafe {
+        let va = _mm256_loadu_si256(a.lanes.as_ptr() as *const __m256i);
+        return _mm256_testz_si256(va, va) != 0;
+    }
+    (a.lanes[0] | a.lanes[1] | a.lanes[2] | a.lanes[3]) == 0
+}
+#[inline(always)]
+pub fn impl_popcnt_u64x4(a: U64x4) -> u32 { a.lanes[0].count_ones() + a.lanes[1].count_ones() + a.lanes[2].count_ones() + a.lanes[3].count_ones() }
@@ -19,4 +19,8 @@ fn main() {
     let m = cmp_lt(a, b);
     let sel = select(m, a, b);
     println!("sel: {:.1} {:.1} {:.1} {:.1}", sel.lanes[0], sel.lanes[1], sel.lanes[2], sel.lanes[3]);
+    let ua = U64x4 { lanes: [0xFF, 0xF0, 1, 0] };
+    let ub = U64x4 { lanes: [0x0F, 0xFF, 1, 3] };
+    let un = andnot_u64x4(ua, ub);
+    println!("u64x4: and={} or={} andnot={:x}", popcnt_u64x4(and_u64x4(ua, ub)), popcnt_u64x4(or_u64x4(ua, ub)), un.lanes[0]);
 }
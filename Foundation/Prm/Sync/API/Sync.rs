@@ -20,7 +20,7 @@ impl Backoff {
 pub struct SpinLock { flag: AtomicBool }
 
 impl SpinLock {
-    pub fn new() -> Self { Self { flag: AtomicBool::new(false) } }
+    pub const fn new() -> Self { Self { flag: AtomicBool::new(false) } }
     pub fn try_lock(&self) -> bool { self.flag.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() }
     pub fn lock(&self) { let mut b = Backoff::new(); while !self.try_lock() { b.snooze(); } }
     pub fn unlock(&self) { self.flag.store(false, Ordering::Release) }
@@ -8,7 +8,11 @@ pub struct FiberHandle(pub *mut c_void);
 #[path = "../Impl/Windows/Fiber.rs"]
 mod backend;
 
-#[cfg(not(target_os = "windows"))]
+#[cfg(target_os = "linux")]
+#[path = "../Impl/Linux/Fiber.rs"]
+mod backend;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
 mod backend {
     use super::*;
     pub fn impl_ensure_thread_is_fiber() {}
@@ -16,6 +20,7 @@ mod backend {
     pub fn impl_create_fiber(_stack_size: usize, _f: crate::thread_sync::ThreadFunc, _u: *mut c_void) -> Result<FiberHandle, ThreadingError> { Err(ThreadingError::Unsupported) }
     pub fn impl_switch_to_fiber(_fiber: FiberHandle) {}
     pub fn impl_delete_fiber(_fiber: FiberHandle) {}
+    pub fn impl_host_fiber() -> FiberHandle { FiberHandle(std::ptr::null_mut()) }
 }
 
 pub fn ensure_thread_is_fiber() { backend::impl_ensure_thread_is_fiber() }
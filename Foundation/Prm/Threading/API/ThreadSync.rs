@@ -7,7 +7,11 @@ use std::ffi::c_void;
 #[path = "../Impl/Windows/ThreadSync.rs"]
 mod backend;
 
-#[cfg(not(target_os = "windows"))]
+#[cfg(target_os = "linux")]
+#[path = "../Impl/Linux/ThreadSync.rs"]
+mod backend;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
 mod backend {
     use super::*;
     pub fn impl_thread_create(_f: ThreadFunc, _u: *mut c_void) -> Result<ThreadHandle, ThreadingError> { Err(ThreadingError::Unsupported) }
@@ -0,0 +1,215 @@
+use std::cell::Cell;
+use std::ffi::c_void;
+use std::ptr::null_mut;
+use crate::thread_sync::{ThreadingError, ThreadFunc};
+
+const PROT_NONE: i32 = 0;
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+const MAP_STACK: i32 = 0x20000;
+const MAP_FAILED: *mut c_void = !0usize as *mut c_void;
+const _SC_PAGESIZE: i32 = 30;
+// Same default as a Windows fiber created with a stack size of 0.
+const DEFAULT_STACK: usize = 1 << 20;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, off: i64) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+    fn sysconf(name: i32) -> i64;
+    // Saves the callee-saved registers on the current stack, stores its top in `*save`, then loads
+    // the registers found at `to` and returns into whatever context saved them.
+    fn prm_fiber_switch(save: *mut *mut u8, to: *mut u8);
+    fn prm_fiber_entry();
+}
+
+// Frame layout shared by `prm_fiber_switch` and `impl_create_fiber`, low address first:
+// mxcsr (u32), x87 control word (u16), pad, r15, r14, r13, r12, rbx, rbp, return address.
+#[cfg(target_arch = "x86_64")]
+std::arch::global_asm!(
+    ".text",
+    ".p2align 4",
+    ".globl prm_fiber_switch",
+    ".hidden prm_fiber_switch",
+    "prm_fiber_switch:",
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "sub rsp, 8",
+    "stmxcsr [rsp]",
+    "fnstcw [rsp + 4]",
+    "mov [rdi], rsp",
+    "mov rsp, rsi",
+    "ldmxcsr [rsp]",
+    "fldcw [rsp + 4]",
+    "add rsp, 8",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "ret",
+    ".p2align 4",
+    ".globl prm_fiber_entry",
+    ".hidden prm_fiber_entry",
+    "prm_fiber_entry:",
+    "mov rdi, r12",
+    "call {main}",
+    "ud2",
+    main = sym fiber_main,
+);
+
+#[cfg(target_arch = "x86_64")]
+const FRAME_WORDS: usize = 8;
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn init_frame(sp: *mut usize, ctx: *mut Context) {
+    *sp = 0x037F << 32 | 0x1F80; // default x87 control word and mxcsr
+    *sp.add(4) = ctx as usize; // r12
+    *sp.add(6) = 0; // rbp: ends frame-pointer walks here
+    *sp.add(7) = prm_fiber_entry as *const () as usize;
+}
+
+// Frame layout, low address first: x19..x28, x29 (fp), x30 (lr), d8..d15.
+#[cfg(target_arch = "aarch64")]
+std::arch::global_asm!(
+    ".text",
+    ".p2align 4",
+    ".globl prm_fiber_switch",
+    ".hidden prm_fiber_switch",
+    "prm_fiber_switch:",
+    "sub sp, sp, #160",
+    "stp x19, x20, [sp, #0]",
+    "stp x21, x22, [sp, #16]",
+    "stp x23, x24, [sp, #32]",
+    "stp x25, x26, [sp, #48]",
+    "stp x27, x28, [sp, #64]",
+    "stp x29, x30, [sp, #80]",
+    "stp d8, d9, [sp, #96]",
+    "stp d10, d11, [sp, #112]",
+    "stp d12, d13, [sp, #128]",
+    "stp d14, d15, [sp, #144]",
+    "mov x2, sp",
+    "str x2, [x0]",
+    "mov sp, x1",
+    "ldp x19, x20, [sp, #0]",
+    "ldp x21, x22, [sp, #16]",
+    "ldp x23, x24, [sp, #32]",
+    "ldp x25, x26, [sp, #48]",
+    "ldp x27, x28, [sp, #64]",
+    "ldp x29, x30, [sp, #80]",
+    "ldp d8, d9, [sp, #96]",
+    "ldp d10, d11, [sp, #112]",
+    "ldp d12, d13, [sp, #128]",
+    "ldp d14, d15, [sp, #144]",
+    "add sp, sp, #160",
+    "ret",
+    ".p2align 4",
+    ".globl prm_fiber_entry",
+    ".hidden prm_fiber_entry",
+    "prm_fiber_entry:",
+    "mov x0, x19",
+    "bl {main}",
+    "brk #1",
+    main = sym fiber_main,
+);
+
+#[cfg(target_arch = "aarch64")]
+const FRAME_WORDS: usize = 20;
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn init_frame(sp: *mut usize, ctx: *mut Context) {
+    *sp = ctx as usize; // x19
+    *sp.add(10) = 0; // x29: ends frame-pointer walks here
+    *sp.add(11) = prm_fiber_entry as *const () as usize; // x30
+}
+
+struct FiberStart { f: ThreadFunc, u: *mut c_void }
+
+// What a `FiberHandle` points at. A thread's host context has no stack of its own.
+struct Context { sp: *mut u8, stack: *mut c_void, len: usize, start: Option<FiberStart> }
+
+thread_local! {
+    static HOST_FIBER: Cell<*mut Context> = const { Cell::new(null_mut()) };
+    static CURRENT: Cell<*mut Context> = const { Cell::new(null_mut()) };
+}
+
+extern "C" fn fiber_main(ctx: *mut Context) -> ! {
+    // Take the start out first: a pooled fiber's entry never returns.
+    if let Some(FiberStart { f, u }) = unsafe { (*ctx).start.take() } { f(u); }
+    // An entry that does return hands the thread back to its host; there is nothing to resume after it.
+    impl_switch_to_fiber(impl_host_fiber());
+    std::process::abort()
+}
+
+fn page_size() -> usize { unsafe { sysconf(_SC_PAGESIZE) as usize } }
+
+pub fn impl_ensure_thread_is_fiber() {
+    if !HOST_FIBER.with(|c| c.get()).is_null() { return; }
+    let host = Box::into_raw(Box::new(Context { sp: null_mut(), stack: null_mut(), len: 0, start: None }));
+    HOST_FIBER.with(|c| c.set(host));
+    CURRENT.with(|c| if c.get().is_null() { c.set(host) });
+}
+
+pub fn impl_revert_fiber_to_thread() {
+    let host = HOST_FIBER.with(|c| c.get());
+    if host.is_null() || CURRENT.with(|c| c.get()) != host { return; }
+    HOST_FIBER.with(|c| c.set(null_mut()));
+    CURRENT.with(|c| c.set(null_mut()));
+    drop(unsafe { Box::from_raw(host) });
+}
+
+/// Maps `stack_size` bytes (rounded up to pages) plus one `PROT_NONE` guard page below them, so an
+/// overflow faults instead of running into the neighbouring mapping.
+pub fn impl_create_fiber(stack_size: usize, f: ThreadFunc, u: *mut c_void) -> Result<super::FiberHandle, ThreadingError> {
+    impl_ensure_thread_is_fiber();
+    let page = page_size();
+    let size = if stack_size == 0 { DEFAULT_STACK } else { stack_size };
+    let len = size.checked_add(2 * page - 1).ok_or(ThreadingError::Failed)? & !(page - 1);
+    let stack = unsafe { mmap(null_mut(), len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_STACK, -1, 0) };
+    if stack == MAP_FAILED { return Err(ThreadingError::Failed); }
+    if unsafe { mprotect(stack, page, PROT_NONE) } != 0 {
+        unsafe { munmap(stack, len); }
+        return Err(ThreadingError::Failed);
+    }
+    let ctx = Box::into_raw(Box::new(Context { sp: null_mut(), stack, len, start: Some(FiberStart { f, u }) }));
+    unsafe {
+        // The top is page aligned, so the first frame leaves the entry stub on a 16-byte boundary.
+        let sp = (stack as *mut usize).add(len / size_of::<usize>() - FRAME_WORDS);
+        sp.write_bytes(0, FRAME_WORDS);
+        init_frame(sp, ctx);
+        (*ctx).sp = sp as *mut u8;
+    }
+    Ok(super::FiberHandle(ctx as *mut c_void))
+}
+
+// Never inlined: a fiber may come back on another thread, so nothing may reuse a thread-local
+// address computed before the switch.
+#[inline(never)]
+pub fn impl_switch_to_fiber(fiber: super::FiberHandle) {
+    let to = fiber.0 as *mut Context;
+    impl_ensure_thread_is_fiber();
+    let cur = CURRENT.with(|c| c.get());
+    if to.is_null() || to == cur { return; }
+    CURRENT.with(|c| c.set(to));
+    unsafe { prm_fiber_switch(&mut (*cur).sp, (*to).sp) }
+}
+
+pub fn impl_delete_fiber(fiber: super::FiberHandle) {
+    let ctx = fiber.0 as *mut Context;
+    // Host contexts belong to their thread and go away with `impl_revert_fiber_to_thread`.
+    if ctx.is_null() || unsafe { (*ctx).stack.is_null() } { return; }
+    let ctx = unsafe { Box::from_raw(ctx) };
+    unsafe { munmap(ctx.stack, ctx.len); }
+}
+
+pub fn impl_host_fiber() -> super::FiberHandle {
+    impl_ensure_thread_is_fiber();
+    super::FiberHandle(HOST_FIBER.with(|c| c.get()) as *mut c_void)
+}
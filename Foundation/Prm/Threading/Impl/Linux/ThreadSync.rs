@@ -0,0 +1,321 @@
+#![allow(non_camel_case_types)]
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+
+type pthread_t = usize;
+type pid_t = i32;
+
+// glibc object sizes differ between x86_64 and aarch64; these buffers fit either, and every object
+// is initialised through its `*_init` call rather than relying on a static initialiser.
+#[repr(C, align(16))]
+struct Opaque<const N: usize>([u8; N]);
+
+impl<const N: usize> Opaque<N> { const fn new() -> Self { Self([0; N]) } }
+
+type pthread_attr_t = Opaque<64>;
+type pthread_mutex_t = Opaque<64>;
+type pthread_cond_t = Opaque<64>;
+type pthread_mutexattr_t = Opaque<8>;
+type pthread_condattr_t = Opaque<8>;
+
+#[repr(C)]
+struct timespec { tv_sec: i64, tv_nsec: i64 }
+
+const CLOCK_MONOTONIC: i32 = 1;
+const PTHREAD_MUTEX_RECURSIVE: i32 = 1;
+const ETIMEDOUT: i32 = 110;
+const EBUSY: i32 = 16;
+const PTHREAD_STACK_MIN: usize = 16 << 10;
+
+#[cfg(target_arch = "x86_64")]
+const SYS_FUTEX: i64 = 202;
+#[cfg(target_arch = "x86_64")]
+const SYS_GETTID: i64 = 186;
+#[cfg(target_arch = "aarch64")]
+const SYS_FUTEX: i64 = 98;
+#[cfg(target_arch = "aarch64")]
+const SYS_GETTID: i64 = 178;
+const FUTEX_WAIT_PRIVATE: i32 = 128;
+const FUTEX_WAKE_PRIVATE: i32 = 129;
+
+#[link(name = "pthread")]
+extern "C" {
+    fn pthread_create(thread: *mut pthread_t, attr: *const pthread_attr_t, start: extern "C" fn(*mut c_void) -> *mut c_void, arg: *mut c_void) -> i32;
+    fn pthread_join(thread: pthread_t, retval: *mut *mut c_void) -> i32;
+    fn pthread_attr_init(attr: *mut pthread_attr_t) -> i32;
+    fn pthread_attr_setstacksize(attr: *mut pthread_attr_t, size: usize) -> i32;
+    fn pthread_attr_destroy(attr: *mut pthread_attr_t) -> i32;
+
+    fn pthread_mutexattr_init(attr: *mut pthread_mutexattr_t) -> i32;
+    fn pthread_mutexattr_settype(attr: *mut pthread_mutexattr_t, kind: i32) -> i32;
+    fn pthread_mutexattr_destroy(attr: *mut pthread_mutexattr_t) -> i32;
+    fn pthread_mutex_init(m: *mut pthread_mutex_t, attr: *const pthread_mutexattr_t) -> i32;
+    fn pthread_mutex_destroy(m: *mut pthread_mutex_t) -> i32;
+    fn pthread_mutex_lock(m: *mut pthread_mutex_t) -> i32;
+    fn pthread_mutex_trylock(m: *mut pthread_mutex_t) -> i32;
+    fn pthread_mutex_unlock(m: *mut pthread_mutex_t) -> i32;
+
+    fn pthread_condattr_init(attr: *mut pthread_condattr_t) -> i32;
+    fn pthread_condattr_setclock(attr: *mut pthread_condattr_t, clock: i32) -> i32;
+    fn pthread_condattr_destroy(attr: *mut pthread_condattr_t) -> i32;
+    fn pthread_cond_init(c: *mut pthread_cond_t, attr: *const pthread_condattr_t) -> i32;
+    fn pthread_cond_destroy(c: *mut pthread_cond_t) -> i32;
+    fn pthread_cond_wait(c: *mut pthread_cond_t, m: *mut pthread_mutex_t) -> i32;
+    fn pthread_cond_timedwait(c: *mut pthread_cond_t, m: *mut pthread_mutex_t, abstime: *const timespec) -> i32;
+    fn pthread_cond_signal(c: *mut pthread_cond_t) -> i32;
+    fn pthread_cond_broadcast(c: *mut pthread_cond_t) -> i32;
+}
+
+extern "C" {
+    fn sched_yield() -> i32;
+    fn sched_setaffinity(pid: pid_t, cpusetsize: usize, mask: *const u64) -> i32;
+    fn nanosleep(req: *const timespec, rem: *mut timespec) -> i32;
+    fn clock_gettime(clock: i32, tp: *mut timespec) -> i32;
+    fn syscall(num: i64, ...) -> i64;
+    fn __errno_location() -> *mut i32;
+}
+
+use crate::thread_sync::*;
+
+const INFINITE: u32 = 0xFFFFFFFF;
+
+fn errno() -> i32 { unsafe { *__errno_location() } }
+
+// Absolute CLOCK_MONOTONIC deadline `ms` from now.
+fn deadline(ms: u32) -> timespec {
+    let mut t = timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { clock_gettime(CLOCK_MONOTONIC, &mut t); }
+    let ns = t.tv_nsec + (ms as i64 % 1000) * 1_000_000;
+    timespec { tv_sec: t.tv_sec + ms as i64 / 1000 + ns / 1_000_000_000, tv_nsec: ns % 1_000_000_000 }
+}
+
+// What a `ThreadHandle` points at. Affinity goes through `sched_setaffinity`, which wants the
+// kernel thread id, so the new thread publishes it before `thread_create` returns.
+struct Thread { pt: pthread_t, tid: AtomicI32 }
+
+struct ThreadStart { f: ThreadFunc, u: *mut c_void, thread: *const Thread }
+
+extern "C" fn thread_trampoline(param: *mut c_void) -> *mut c_void {
+    let start: Box<ThreadStart> = unsafe { Box::from_raw(param as *mut ThreadStart) };
+    let tid = unsafe { syscall(SYS_GETTID) } as i32;
+    let t = unsafe { &*start.thread };
+    t.tid.store(tid, Ordering::Release);
+    futex_wake(t.tid.as_ptr() as *const AtomicU32, i32::MAX);
+    (start.f)(start.u);
+    std::ptr::null_mut()
+}
+
+pub fn impl_thread_create(f: ThreadFunc, u: *mut c_void) -> Result<ThreadHandle, ThreadingError> { impl_thread_create_with_stack(0, f, u) }
+
+pub fn impl_thread_create_with_stack(stack_size: usize, f: ThreadFunc, u: *mut c_void) -> Result<ThreadHandle, ThreadingError> {
+    let thread = Box::into_raw(Box::new(Thread { pt: 0, tid: AtomicI32::new(0) }));
+    let param = Box::into_raw(Box::new(ThreadStart { f, u, thread })) as *mut c_void;
+    let mut attr = pthread_attr_t::new();
+    let r = unsafe {
+        pthread_attr_init(&mut attr);
+        if stack_size != 0 { pthread_attr_setstacksize(&mut attr, stack_size.max(PTHREAD_STACK_MIN)); }
+        let r = pthread_create(&mut (*thread).pt, &attr, thread_trampoline, param);
+        pthread_attr_destroy(&mut attr);
+        r
+    };
+    if r != 0 {
+        unsafe { drop(Box::from_raw(param as *mut ThreadStart)); drop(Box::from_raw(thread)); }
+        return Err(ThreadingError::Failed);
+    }
+    let tid = unsafe { &(*thread).tid };
+    while tid.load(Ordering::Acquire) == 0 { futex_wait(tid.as_ptr() as *const AtomicU32, 0, INFINITE); }
+    Ok(ThreadHandle(thread as *mut c_void))
+}
+
+pub fn impl_thread_join(h: ThreadHandle) -> Result<(), ThreadingError> {
+    let t = unsafe { Box::from_raw(h.0 as *mut Thread) };
+    if unsafe { pthread_join(t.pt, std::ptr::null_mut()) } != 0 { return Err(ThreadingError::Failed); }
+    Ok(())
+}
+
+pub fn impl_thread_yield() { unsafe { sched_yield(); } }
+pub fn impl_thread_sleep_ms(ms: u32) {
+    let mut req = timespec { tv_sec: ms as i64 / 1000, tv_nsec: (ms as i64 % 1000) * 1_000_000 };
+    let mut rem = timespec { tv_sec: 0, tv_nsec: 0 };
+    while unsafe { nanosleep(&req, &mut rem) } != 0 { req = timespec { tv_sec: rem.tv_sec, tv_nsec: rem.tv_nsec }; }
+}
+
+// CPU `group * 64 + bit` for each set bit, matching how Windows numbers processor groups.
+fn set_affinity(h: ThreadHandle, group: u16, mask: u64) -> Result<(), ThreadingError> {
+    let mut set = [0u64; 16];
+    let word = group as usize;
+    if word >= set.len() { return Err(ThreadingError::Failed); }
+    set[word] = mask;
+    let tid = unsafe { (*(h.0 as *const Thread)).tid.load(Ordering::Acquire) };
+    if unsafe { sched_setaffinity(tid, core::mem::size_of_val(&set), set.as_ptr()) } != 0 { Err(ThreadingError::Failed) } else { Ok(()) }
+}
+
+pub fn impl_set_affinity_mask(h: ThreadHandle, mask: u64) -> Result<(), ThreadingError> { set_affinity(h, 0, mask) }
+pub fn impl_set_group_affinity(h: ThreadHandle, group: u16, mask: u64) -> Result<(), ThreadingError> { set_affinity(h, group, mask) }
+
+// Recursive, like the Windows mutex object it stands in for.
+pub fn impl_mutex_create() -> Result<MutexHandle, ThreadingError> {
+    let m = Box::into_raw(Box::new(pthread_mutex_t::new()));
+    if unsafe { init_mutex(m, true) } != 0 { unsafe { drop(Box::from_raw(m)); } return Err(ThreadingError::Failed); }
+    Ok(MutexHandle(m as *mut c_void))
+}
+
+unsafe fn init_mutex(m: *mut pthread_mutex_t, recursive: bool) -> i32 {
+    if !recursive { return pthread_mutex_init(m, std::ptr::null()); }
+    let mut a = pthread_mutexattr_t::new();
+    pthread_mutexattr_init(&mut a);
+    pthread_mutexattr_settype(&mut a, PTHREAD_MUTEX_RECURSIVE);
+    let r = pthread_mutex_init(m, &a);
+    pthread_mutexattr_destroy(&mut a);
+    r
+}
+
+pub fn impl_mutex_destroy(h: MutexHandle) -> Result<(), ThreadingError> {
+    let m = h.0 as *mut pthread_mutex_t;
+    unsafe { pthread_mutex_destroy(m); drop(Box::from_raw(m)); }
+    Ok(())
+}
+
+pub fn impl_mutex_lock(h: MutexHandle) -> Result<(), ThreadingError> { check(unsafe { pthread_mutex_lock(h.0 as *mut pthread_mutex_t) }) }
+pub fn impl_mutex_unlock(h: MutexHandle) -> Result<(), ThreadingError> { check(unsafe { pthread_mutex_unlock(h.0 as *mut pthread_mutex_t) }) }
+
+pub fn impl_mutex_try_lock(h: MutexHandle) -> Result<bool, ThreadingError> {
+    match unsafe { pthread_mutex_trylock(h.0 as *mut pthread_mutex_t) } { 0 => Ok(true), EBUSY => Ok(false), _ => Err(ThreadingError::Failed) }
+}
+
+fn check(r: i32) -> Result<(), ThreadingError> { if r == 0 { Ok(()) } else { Err(ThreadingError::Failed) } }
+
+// Mutex + condition variable pair behind semaphores and events. `state` is the semaphore count, or
+// 0/1 for an event.
+struct Waitable { m: pthread_mutex_t, c: pthread_cond_t, state: u32, max: u32, manual: bool }
+
+impl Waitable {
+    fn create(state: u32, max: u32, manual: bool) -> Result<*mut Waitable, ThreadingError> {
+        let w = Box::into_raw(Box::new(Waitable { m: pthread_mutex_t::new(), c: pthread_cond_t::new(), state, max, manual }));
+        let ok = unsafe {
+            let mut a = pthread_condattr_t::new();
+            pthread_condattr_init(&mut a);
+            pthread_condattr_setclock(&mut a, CLOCK_MONOTONIC);
+            let r = pthread_cond_init(&mut (*w).c, &a);
+            pthread_condattr_destroy(&mut a);
+            r == 0 && init_mutex(&mut (*w).m, false) == 0
+        };
+        if !ok { unsafe { drop(Box::from_raw(w)); } return Err(ThreadingError::Failed); }
+        Ok(w)
+    }
+
+    unsafe fn destroy(w: *mut Waitable) {
+        pthread_cond_destroy(&mut (*w).c);
+        pthread_mutex_destroy(&mut (*w).m);
+        drop(Box::from_raw(w));
+    }
+
+    // Waits for `state > 0`, then consumes one unit unless this is a manual-reset event.
+    unsafe fn wait(w: *mut Waitable, timeout_ms: u32) -> Result<(), ThreadingError> {
+        let w = &mut *w;
+        let until = deadline(if timeout_ms == INFINITE { 0 } else { timeout_ms });
+        pthread_mutex_lock(&mut w.m);
+        let mut timed_out = false;
+        while w.state == 0 && !timed_out {
+            if timeout_ms == INFINITE { pthread_cond_wait(&mut w.c, &mut w.m); }
+            else { timed_out = pthread_cond_timedwait(&mut w.c, &mut w.m, &until) == ETIMEDOUT; }
+        }
+        let got = w.state > 0;
+        if got && !w.manual { w.state -= 1; }
+        pthread_mutex_unlock(&mut w.m);
+        if got { Ok(()) } else { Err(ThreadingError::Failed) }
+    }
+
+    // Adds `n` to the state (clamped to `max`); fails without changing it if that would overflow.
+    unsafe fn post(w: *mut Waitable, n: u32, strict: bool) -> Result<(), ThreadingError> {
+        let w = &mut *w;
+        pthread_mutex_lock(&mut w.m);
+        let next = w.state.saturating_add(n);
+        let r = if strict && next > w.max { Err(ThreadingError::Failed) } else { w.state = next.min(w.max); Ok(()) };
+        if r.is_ok() { if n > 1 || w.manual { pthread_cond_broadcast(&mut w.c); } else { pthread_cond_signal(&mut w.c); } }
+        pthread_mutex_unlock(&mut w.m);
+        r
+    }
+}
+
+pub fn impl_semaphore_create(initial: u32, max: u32) -> Result<SemaphoreHandle, ThreadingError> {
+    if max == 0 || initial > max { return Err(ThreadingError::Failed); }
+    Ok(SemaphoreHandle(Waitable::create(initial, max, false)? as *mut c_void))
+}
+
+pub fn impl_semaphore_destroy(h: SemaphoreHandle) -> Result<(), ThreadingError> { unsafe { Waitable::destroy(h.0 as *mut Waitable); } Ok(()) }
+pub fn impl_semaphore_acquire(h: SemaphoreHandle, timeout_ms: u32) -> Result<(), ThreadingError> { unsafe { Waitable::wait(h.0 as *mut Waitable, timeout_ms) } }
+pub fn impl_semaphore_release(h: SemaphoreHandle, count: u32) -> Result<(), ThreadingError> { unsafe { Waitable::post(h.0 as *mut Waitable, count, true) } }
+
+pub fn impl_event_create(manual_reset: bool, initial_state: bool) -> Result<EventHandle, ThreadingError> {
+    Ok(EventHandle(Waitable::create(initial_state as u32, 1, manual_reset)? as *mut c_void))
+}
+
+pub fn impl_event_destroy(h: EventHandle) -> Result<(), ThreadingError> { unsafe { Waitable::destroy(h.0 as *mut Waitable); } Ok(()) }
+pub fn impl_event_wait(h: EventHandle, timeout_ms: u32) -> Result<(), ThreadingError> { unsafe { Waitable::wait(h.0 as *mut Waitable, timeout_ms) } }
+pub fn impl_event_signal(h: EventHandle) -> Result<(), ThreadingError> { unsafe { Waitable::post(h.0 as *mut Waitable, 1, false) } }
+
+pub fn impl_event_reset(h: EventHandle) -> Result<(), ThreadingError> {
+    let w = unsafe { &mut *(h.0 as *mut Waitable) };
+    unsafe { pthread_mutex_lock(&mut w.m); w.state = 0; pthread_mutex_unlock(&mut w.m); }
+    Ok(())
+}
+
+// Returns false on timeout; a value mismatch or (spurious) wake counts as woken.
+fn futex_wait(word: *const AtomicU32, expected: u32, timeout_ms: u32) -> bool {
+    let ts = timespec { tv_sec: timeout_ms as i64 / 1000, tv_nsec: (timeout_ms as i64 % 1000) * 1_000_000 };
+    let tp: *const timespec = if timeout_ms == INFINITE { std::ptr::null() } else { &ts };
+    let r = unsafe { syscall(SYS_FUTEX, word, FUTEX_WAIT_PRIVATE, expected, tp) };
+    !(r != 0 && errno() == ETIMEDOUT)
+}
+
+fn futex_wake(word: *const AtomicU32, n: i32) { unsafe { syscall(SYS_FUTEX, word, FUTEX_WAKE_PRIVATE, n); } }
+
+// `WaitOnAddress` compares 1 to 8 bytes; futexes only watch aligned 32-bit words. Each address
+// hashes to a bucket whose sequence word every wake bumps: a waiter reads the sequence before
+// comparing, so a wake landing between its compare and its sleep makes the futex return at once.
+// Addresses sharing a bucket just see spurious wakes.
+#[repr(C, align(64))]
+struct Bucket { seq: AtomicU32, waiters: AtomicU32 }
+
+const BUCKETS: usize = 256;
+
+static TABLE: [Bucket; BUCKETS] = [const { Bucket { seq: AtomicU32::new(0), waiters: AtomicU32::new(0) } }; BUCKETS];
+
+fn bucket(addr: *const u8) -> &'static Bucket {
+    let a = addr as usize;
+    &TABLE[(a ^ (a >> 8) ^ (a >> 16)) / 4 % BUCKETS]
+}
+
+fn same(addr: *const u8, cmp: *const u8, size: usize) -> bool {
+    unsafe {
+        match size {
+            1 => (*(addr as *const std::sync::atomic::AtomicU8)).load(Ordering::SeqCst) == *cmp,
+            2 => (*(addr as *const std::sync::atomic::AtomicU16)).load(Ordering::SeqCst) == *(cmp as *const u16),
+            4 => (*(addr as *const AtomicU32)).load(Ordering::SeqCst) == *(cmp as *const u32),
+            8 => (*(addr as *const std::sync::atomic::AtomicU64)).load(Ordering::SeqCst) == *(cmp as *const u64),
+            _ => false,
+        }
+    }
+}
+
+pub fn impl_wait_on_address(addr: *const u8, cmp: *const u8, size: usize, timeout_ms: u32) -> Result<(), ThreadingError> {
+    if !matches!(size, 1 | 2 | 4 | 8) || (addr as usize) & (size - 1) != 0 { return Err(ThreadingError::Failed); }
+    let b = bucket(addr);
+    b.waiters.fetch_add(1, Ordering::SeqCst);
+    let seq = b.seq.load(Ordering::SeqCst);
+    let woken = !same(addr, cmp, size) || futex_wait(&b.seq, seq, timeout_ms);
+    b.waiters.fetch_sub(1, Ordering::SeqCst);
+    if woken { Ok(()) } else { Err(ThreadingError::Failed) }
+}
+
+fn wake(addr: *mut u8, n: i32) {
+    let b = bucket(addr);
+    b.seq.fetch_add(1, Ordering::SeqCst);
+    if b.waiters.load(Ordering::SeqCst) != 0 { futex_wake(&b.seq, n); }
+}
+
+// With other addresses sharing the bucket, waking "one" could pick a waiter on the wrong address;
+// wake them all and let the rest re-check and sleep again.
+pub fn impl_wake_by_address_single(addr: *mut u8) { wake(addr, i32::MAX) }
+pub fn impl_wake_by_address_all(addr: *mut u8) { wake(addr, i32::MAX) }
@@ -14,10 +14,9 @@ thread_local! { static HOST_FIBER: std::cell::Cell<*mut c_void> = std::cell::Cel
 struct FiberStart { f: ThreadFunc, u: *mut c_void }
 
 extern "system" fn fiber_trampoline(ctx: *mut c_void) {
-    unsafe {
-        let b: Box<FiberStart> = Box::from_raw(ctx as *mut FiberStart);
-        (b.f)(b.u);
-    }
+    // Unbox before calling: a pooled fiber's entry never returns.
+    let FiberStart { f, u } = unsafe { *Box::from_raw(ctx as *mut FiberStart) };
+    f(u);
 }
 
 pub fn impl_ensure_thread_is_fiber() {
@@ -0,0 +1,42 @@
+use std::time::Instant;
+use std::hint::black_box;
+use core::ffi::c_void;
+use prm_threading::*;
+
+const STACK: usize = 64 << 10;
+
+// Bounces straight back to the host forever; each round trip is two switches.
+fn ping(arg: *mut c_void) {
+    let host = FiberHandle(arg);
+    loop { switch_to_fiber(host); }
+}
+
+fn nop(arg: *mut c_void) { black_box(arg); }
+
+fn ns_per(t: Instant, n: usize) -> f64 { t.elapsed().as_nanos() as f64 / n as f64 }
+
+fn main() {
+    ensure_thread_is_fiber();
+    let host = host_fiber();
+
+    let n = 10_000_000usize;
+    let f = create_fiber(STACK, ping as ThreadFunc, host.0).unwrap();
+    switch_to_fiber(f);
+    let t0 = Instant::now();
+    for _ in 0..n { switch_to_fiber(f); }
+    let switch = ns_per(t0, 2 * n);
+    delete_fiber(f);
+
+    // Create, run to completion, delete: what a fresh fiber per job costs.
+    let n_create = 100_000usize;
+    let t1 = Instant::now();
+    for _ in 0..n_create {
+        let f = create_fiber(STACK, nop as ThreadFunc, std::ptr::null_mut()).unwrap();
+        switch_to_fiber(f);
+        delete_fiber(f);
+    }
+    let create = ns_per(t1, n_create);
+
+    println!("switch_to_fiber : {:.1} ns/switch ({} round trips)", switch, n);
+    println!("create+run+free : {:.1} ns/fiber ({} fibers, {} KiB stacks)", create, n_create, STACK >> 10);
+}
@@ -19,7 +19,11 @@ pub fn default_source() -> MonotonicSource { MonotonicSource }
 #[path = "../Impl/Windows/Time.rs"]
 mod backend;
 
-#[cfg(not(target_os = "windows"))]
+#[cfg(target_os = "linux")]
+#[path = "../Impl/Linux/Time.rs"]
+mod backend;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
 mod backend {
     pub fn impl_now_ns() -> i64 { 0 }
     pub fn impl_sleep_ms(_ms: u32) {}
@@ -0,0 +1,29 @@
+#![allow(non_camel_case_types)]
+
+#[repr(C)]
+struct timespec { tv_sec: i64, tv_nsec: i64 }
+
+const CLOCK_MONOTONIC: i32 = 1;
+const TIMER_ABSTIME: i32 = 1;
+const EINTR: i32 = 4;
+
+extern "C" {
+    fn clock_gettime(clock: i32, tp: *mut timespec) -> i32;
+    fn clock_nanosleep(clock: i32, flags: i32, req: *const timespec, rem: *mut timespec) -> i32;
+}
+
+pub fn impl_now_ns() -> i64 {
+    let mut t = timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { clock_gettime(CLOCK_MONOTONIC, &mut t); }
+    t.tv_sec.saturating_mul(1_000_000_000).saturating_add(t.tv_nsec)
+}
+
+pub fn impl_sleep_ms(ms: u32) { impl_sleep_precise_ns(ms as i64 * 1_000_000) }
+
+// Sleeps until an absolute deadline, so wake-ups from signals resume without drifting.
+pub fn impl_sleep_precise_ns(ns: i64) {
+    if ns <= 0 { return; }
+    let end = impl_now_ns().saturating_add(ns);
+    let until = timespec { tv_sec: end / 1_000_000_000, tv_nsec: end % 1_000_000_000 };
+    while unsafe { clock_nanosleep(CLOCK_MONOTONIC, TIMER_ABSTIME, &until, core::ptr::null_mut()) } == EINTR {}
+}
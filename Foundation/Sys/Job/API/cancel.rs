@@ -0,0 +1,126 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use cap_concurrency::Mutex;
+use cap_containers::{CapArc, Vector};
+use cap_memory::{Allocator, MemoryError};
+use prm_sync::{ScopedLock, SpinLock};
+use crate::fiber_sync::{unpark_all, WaitList};
+use crate::scheduler::Waiter;
+
+/// How a cancellable wait ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitStatus { Completed, Cancelled }
+
+// A waiter parked on some primitive's wait list, to be pulled back out if the token is cancelled.
+struct Hook { lock: *const SpinLock, list: *const WaitList, waiter: *const Waiter, fired: *const AtomicBool }
+
+struct Links<'a> { children: Vector<'a, CapArc<'a, State<'a>>>, hooks: Vector<'a, Hook> }
+
+struct State<'a> {
+    alloc: Allocator<'a>,
+    cancelled: AtomicBool,
+    // Held while hooks run, so a waiter unregistering after wake-up cannot return (and free its
+    // stack) while `cancel` still looks at its hook.
+    links: Mutex<Links<'a>>,
+    wait_lock: SpinLock,
+    waiters: WaitList,
+}
+
+unsafe impl<'a> Send for State<'a> {}
+unsafe impl<'a> Sync for State<'a> {}
+
+/// Shared cancellation flag. Clones observe the same flag; `child` tokens are cancelled with their
+/// parent (but not the other way round).
+///
+/// Jobs poll `is_cancelled`; fibers blocked in `wait`, `FiberSemaphore::acquire_or_cancel` or
+/// `FiberLatch::wait_or_cancel` are woken when it fires.
+#[derive(Clone)]
+pub struct CancelToken<'a> { state: CapArc<'a, State<'a>> }
+
+impl<'a> CancelToken<'a> {
+    /// The token's state, and that of every child, is allocated from `alloc`.
+    pub fn new(alloc: Allocator<'a>) -> Result<Self, MemoryError> {
+        let links = Links { children: Vector::with_capacity(alloc, 0)?, hooks: Vector::with_capacity(alloc, 0)? };
+        Ok(Self { state: CapArc::new(alloc, State { alloc, cancelled: AtomicBool::new(false), links: Mutex::new(links), wait_lock: SpinLock::new(), waiters: WaitList::new() })? })
+    }
+
+    /// A token cancelled together with this one; already cancelled if this one is.
+    pub fn child(&self) -> Result<CancelToken<'a>, MemoryError> {
+        let c = CancelToken::new(self.state.alloc)?;
+        let mut l = self.state.links.lock();
+        if self.is_cancelled() {
+            c.state.cancelled.store(true, Ordering::Release);
+        } else {
+            // Children only this list still holds can never be observed again.
+            let mut i = 0;
+            while i < l.children.len() {
+                if CapArc::strong_count(&l.children[i]) == 1 { l.children.swap_remove(i); } else { i += 1; }
+            }
+            l.children.push(c.state.clone())?;
+        }
+        Ok(c)
+    }
+
+    pub fn is_cancelled(&self) -> bool { self.state.cancelled.load(Ordering::Acquire) }
+
+    /// Sets the flag, wakes everything waiting on it, then cancels all children. Idempotent.
+    pub fn cancel(&self) { cancel_state(&self.state) }
+
+    /// Blocks the calling fiber (or thread) until the token is cancelled.
+    pub fn wait(&self) {
+        let w = Waiter::new();
+        {
+            let _g = ScopedLock::new(&self.state.wait_lock);
+            if self.is_cancelled() { return; }
+            self.state.waiters.push(&w);
+        }
+        w.park();
+    }
+
+    // Parks `w`, already pushed on `list` under `lock`, until the primitive pops and unparks it or
+    // the token fires. Returns true if cancellation took it off the list instead.
+    pub(crate) fn park_or_cancel(&self, lock: &SpinLock, list: &WaitList, w: &Waiter) -> bool {
+        let fired = AtomicBool::new(false);
+        {
+            let mut l = self.state.links.lock();
+            if self.is_cancelled() {
+                let removed = { let _g = ScopedLock::new(lock); list.remove(w) };
+                // Not on the list any more: the primitive already chose us and will unpark.
+                if removed { return true; }
+            } else {
+                // Without room for the hook the wait cannot be cut short, but still ends normally.
+                let _ = l.hooks.push(Hook { lock, list, waiter: w, fired: &fired });
+            }
+        }
+        w.park();
+        let mut l = self.state.links.lock();
+        if let Some(i) = l.hooks.iter().position(|h| core::ptr::eq(h.waiter, w)) { l.hooks.swap_remove(i); }
+        fired.load(Ordering::Acquire)
+    }
+}
+
+impl<'a> core::fmt::Debug for CancelToken<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result { f.debug_struct("CancelToken").field("cancelled", &self.is_cancelled()).finish() }
+}
+
+fn cancel_state(s: &State) {
+    // Waiters pulled off their primitives' lists; released once `links` is unlocked.
+    let woken = WaitList::new();
+    {
+        let mut l = s.links.lock();
+        if s.cancelled.swap(true, Ordering::AcqRel) { return; }
+        while let Some(h) = l.hooks.pop() {
+            let removed = unsafe { let _g = ScopedLock::new(&*h.lock); (*h.list).remove(&*h.waiter) };
+            // `fired` lives on the waiter's stack: set it before the waiter can run.
+            if removed { unsafe { (*h.fired).store(true, Ordering::Release); woken.push(&*h.waiter); } }
+        }
+    }
+    unpark_all(woken.take());
+    let all = { let _g = ScopedLock::new(&s.wait_lock); s.waiters.take() };
+    unpark_all(all);
+    // `child` adds nothing once the flag is set, so the list only shrinks from here.
+    loop {
+        // Pop under the lock, recurse outside it.
+        let Some(c) = s.links.lock().children.pop() else { break };
+        cancel_state(&c);
+    }
+}
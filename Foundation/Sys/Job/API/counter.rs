@@ -1,18 +1,51 @@
-use core::sync::atomic::{AtomicUsize, Ordering};
-use cap_concurrency::Parker;
-use prm_threading::wait_on_address;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use cap_memory::MemoryError;
+use prm_threading::{wait_on_address, wake_by_address_all};
+use crate::cancel::{CancelToken, WaitStatus};
 
-pub struct TaskGroup { n: AtomicUsize, p: Parker }
+/// Counts outstanding jobs; waiters block on the counter's address.
+///
+/// `task_done` only wakes by address and never touches the group afterwards, so a waiter may free
+/// the group (e.g. a stack frame) as soon as it observes zero.
+///
+/// A group can be cancelled. Cancelling does not drop queued jobs: cooperating work (scope spawns,
+/// `TaskGraph` tasks, anything checking `is_cancelled`) skips its body but still counts down, so
+/// `wait` keeps its guarantee that nothing started by the group is still running.
+pub struct TaskGroup<'a> { n: AtomicUsize, cancelled: AtomicBool, token: Option<CancelToken<'a>> }
 
-impl TaskGroup {
-    pub fn new() -> Self { Self { n: AtomicUsize::new(0), p: Parker::new().unwrap() } }
-    pub fn add_tasks(&self, n: usize) { self.n.fetch_add(n, Ordering::Relaxed); let _ = self.p.reset(); }
-    pub fn task_done(&self) { if self.n.fetch_sub(1, Ordering::Relaxed) == 1 { let _ = self.p.unpark(); } }
-    pub fn wait(&self) {
-        loop {
-            if self.n.load(Ordering::Acquire) == 0 { break; }
-            let _ = self.p.park(1000);
-        }
+impl<'a> TaskGroup<'a> {
+    pub const fn new() -> Self { Self { n: AtomicUsize::new(0), cancelled: AtomicBool::new(false), token: None } }
+    /// A group sharing `token`'s cancellation: cancelling either cancels both.
+    pub fn with_token(token: CancelToken<'a>) -> Self { Self { token: Some(token), ..Self::new() } }
+    /// A group cancelled whenever `parent` is, e.g. one per streaming request under a level's token.
+    pub fn with_parent(parent: &CancelToken<'a>) -> Result<Self, MemoryError> { Ok(Self::with_token(parent.child()?)) }
+    pub fn add_tasks(&self, n: usize) { self.n.fetch_add(n, Ordering::Relaxed); }
+    pub fn task_done(&self) {
+        let addr = self.n.as_ptr() as *mut u8;
+        if self.n.fetch_sub(1, Ordering::AcqRel) == 1 { wake_by_address_all(addr); }
+    }
+    /// Waits until every task has finished or been skipped, then reports whether the group was cancelled.
+    pub fn wait(&self) -> WaitStatus {
+        while !self.wait_timeout(1000) {}
+        self.status()
+    }
+    /// Blocks for at most `timeout_ms` (or until woken); returns whether the group is done.
+    pub fn wait_timeout(&self, timeout_ms: u32) -> bool {
+        let v = self.n.load(Ordering::Acquire);
+        if v == 0 { return true; }
+        if wait_on_address(self.n.as_ptr() as *const u8, &v as *const usize as *const u8, core::mem::size_of::<usize>(), timeout_ms).is_err() { prm_threading::thread_yield(); }
+        self.is_done()
     }
     pub fn is_done(&self) -> bool { self.n.load(Ordering::Acquire) == 0 }
+
+    /// The group's token, if it was built with one. Hand out `token.child()` for nested groups.
+    pub fn token(&self) -> Option<&CancelToken<'a>> { self.token.as_ref() }
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        if let Some(t) = &self.token { t.cancel(); }
+    }
+    pub fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::Acquire) || self.token.as_ref().is_some_and(|t| t.is_cancelled()) }
+    pub(crate) fn status(&self) -> WaitStatus { if self.is_cancelled() { WaitStatus::Cancelled } else { WaitStatus::Completed } }
 }
+
+impl<'a> Default for TaskGroup<'a> { fn default() -> Self { Self::new() } }
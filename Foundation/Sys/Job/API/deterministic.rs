@@ -0,0 +1,73 @@
+use core::cell::UnsafeCell;
+use cap_containers::Vector;
+use cap_memory::{Allocator, MemoryError};
+use prm_sync::{ScopedLock, SpinLock};
+use crate::job::Job;
+
+/// One scheduling decision of a deterministic `Scheduler`: job `pick` was run out of `ready` runnable ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ScheduleStep { pub pick: u32, pub ready: u32 }
+
+// splitmix64: tiny, seedable, and good enough to spread picks over small ready sets.
+fn next_rand(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+struct DetInner<'a> { ready: Vector<'a, Job>, rng: u64, log: Vector<'a, ScheduleStep>, replay: Vector<'a, ScheduleStep>, cursor: usize, diverged: bool }
+
+// Ready pool of a deterministic scheduler. Jobs may be submitted from anywhere (a stray thread just
+// makes the run irreproducible), so the pool is still locked.
+pub(crate) struct DetQueue<'a> { lock: SpinLock, inner: UnsafeCell<DetInner<'a>> }
+
+impl<'a> DetQueue<'a> {
+    pub(crate) fn new(alloc: Allocator<'a>, seed: u64, capacity: usize) -> Result<Self, MemoryError> {
+        let inner = DetInner { ready: Vector::with_capacity(alloc, capacity.max(1))?, rng: seed, log: Vector::with_capacity(alloc, 256)?, replay: Vector::with_capacity(alloc, 1)?, cursor: 0, diverged: false };
+        Ok(Self { lock: SpinLock::new(), inner: UnsafeCell::new(inner) })
+    }
+
+    pub(crate) fn push(&self, j: Job) -> Result<(), MemoryError> {
+        let _g = ScopedLock::new(&self.lock);
+        unsafe { (*self.inner.get()).ready.push(j) }
+    }
+
+    // Next job to run: the replayed choice while one is left and still fits, otherwise a seeded pick.
+    pub(crate) fn pick(&self) -> Option<Job> {
+        let _g = ScopedLock::new(&self.lock);
+        let s = unsafe { &mut *self.inner.get() };
+        let ready = s.ready.len() as u32;
+        if ready == 0 { return None; }
+        let pick = match s.replay.get(s.cursor).copied() {
+            Some(r) if !s.diverged && r.ready == ready && r.pick < ready => { s.cursor += 1; r.pick }
+            Some(_) if !s.diverged => { s.diverged = true; (next_rand(&mut s.rng) % ready as u64) as u32 }
+            _ => (next_rand(&mut s.rng) % ready as u64) as u32,
+        };
+        // Without room to log the step the run could not be replayed; stop as a divergence rather than lie.
+        if s.log.push(ScheduleStep { pick, ready }).is_err() { s.diverged = true; }
+        s.ready.swap_remove(pick as usize)
+    }
+
+    pub(crate) fn schedule<'b>(&self, alloc: Allocator<'b>) -> Result<Vector<'b, ScheduleStep>, MemoryError> {
+        let _g = ScopedLock::new(&self.lock);
+        let log = unsafe { &(*self.inner.get()).log };
+        let mut out = Vector::with_capacity(alloc, log.len())?;
+        for &st in log.iter() { out.push(st)?; }
+        Ok(out)
+    }
+
+    pub(crate) fn load_replay(&self, steps: &[ScheduleStep]) -> Result<(), MemoryError> {
+        let _g = ScopedLock::new(&self.lock);
+        let s = unsafe { &mut *self.inner.get() };
+        while s.replay.pop().is_some() {}
+        while s.log.pop().is_some() {}
+        for &st in steps { s.replay.push(st)?; }
+        s.cursor = 0;
+        s.diverged = false;
+        Ok(())
+    }
+
+    pub(crate) fn diverged(&self) -> bool { let _g = ScopedLock::new(&self.lock); unsafe { (*self.inner.get()).diverged } }
+}
@@ -1,98 +1,105 @@
 use core::ffi::c_void;
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::sync::atomic::AtomicU32;
 use cap_io::*;
 use crate::scheduler::resume_fiber;
 use cap_concurrency::fiber::Fiber;
+use cap_memory::{Allocator, MemoryError};
+use crate::timer_wheel::{TimerAction, TimerId, TimerWheel};
 
 pub type ResumeFiberFn = fn(*mut Fiber);
 
 static mut RESUME_CB: Option<ResumeFiberFn> = None;
 
-pub struct TimerItem { pub start_ms: u64, pub wait_ms: u64, pub fiber: *mut Fiber, pub cb: Option<fn(*mut c_void)>, pub ctx: *mut c_void }
 pub struct EventItem { pub h: *mut c_void, pub fiber: *mut Fiber }
-pub struct IocpMapItem { pub ov: *mut c_void, pub fiber: *mut Fiber }
+pub struct IocpMapItem { pub ov: *mut c_void, pub fiber: *mut Fiber, pub cb: Option<fn(*mut c_void)>, pub ctx: *mut c_void }
 
-pub struct Driver {
-    pub timers: heapless::Vec<TimerItem, 64>,
+pub struct Driver<'a> {
+    pub timers: TimerWheel<'a>,
     pub events: heapless::Vec<EventItem, 64>,
     pub iocp: Option<Iocp>,
     pub maps: heapless::Vec<IocpMapItem, 64>,
     pub poller: AtomicU32,
+    // Deadline the last `next_timeout_ms` reported; an earlier timer means sleeping pollers must be woken.
+    wake_at: u64,
+    rearm: bool,
+    // Deterministic schedulers run timers on a clock that only moves in `advance_virtual`.
+    virtual_now: Option<u64>,
 }
 
-impl Driver {
-    pub fn new() -> Self { Self { timers: heapless::Vec::new(), events: heapless::Vec::new(), iocp: attach(), maps: heapless::Vec::new(), poller: AtomicU32::new(0) } }
-    fn rem_wait(&self, idx: usize, now: u64) -> u64 { self.timers[idx].wait_ms.saturating_sub(now.saturating_sub(self.timers[idx].start_ms)) }
-    fn heap_up(&mut self, mut i: usize) {
-        let now = now_ms();
-        while i > 0 {
-            let p = (i - 1) >> 1;
-            if self.rem_wait(i, now) < self.rem_wait(p, now) { self.timers.swap(i, p); i = p; } else { break; }
-        }
+impl<'a> Driver<'a> {
+    pub fn new(alloc: Allocator<'a>) -> Result<Self, MemoryError> { Self::with_clock(alloc, None) }
+    // A virtual clock starts at zero so timer placement, and with it firing order, is the same every run.
+    pub(crate) fn with_clock(alloc: Allocator<'a>, virtual_now: Option<u64>) -> Result<Self, MemoryError> {
+        let start = virtual_now.unwrap_or_else(now_ms);
+        Ok(Self { timers: TimerWheel::new(alloc, start, 256)?, events: heapless::Vec::new(), iocp: attach(), maps: heapless::Vec::new(), poller: AtomicU32::new(0), wake_at: u64::MAX, rearm: false, virtual_now })
     }
-    fn heap_down(&mut self, mut i: usize) {
-        let now = now_ms();
-        let len = self.timers.len();
-        loop {
-            let l = (i << 1) + 1;
-            if l >= len { break; }
-            let r = l + 1;
-            let mut m = l;
-            if r < len && self.rem_wait(r, now) < self.rem_wait(l, now) { m = r; }
-            if self.rem_wait(m, now) < self.rem_wait(i, now) { self.timers.swap(i, m); i = m; } else { break; }
-        }
+    /// Milliseconds until the earliest timer, 0 if one is due, `u32::MAX` if nothing is waiting.
+    /// Outstanding I/O caps it at 1 since completions are polled rather than waited on.
+    pub fn next_timeout_ms(&mut self) -> u32 {
+        let now = self.now();
+        let next = self.timers.next_deadline();
+        self.wake_at = next.unwrap_or(u64::MAX);
+        let t = next.map_or(u32::MAX, |d| d.saturating_sub(now).min(u32::MAX as u64) as u32);
+        if self.maps.is_empty() { t } else { t.min(1) }
     }
-    pub fn next_timeout_ms(&self) -> u32 {
-        if self.timers.is_empty() { 1 } else {
-            let now = now_ms();
-            let t = &self.timers[0];
-            let elapsed = now.saturating_sub(t.start_ms);
-            let rem = if elapsed >= t.wait_ms { 0 } else { t.wait_ms - elapsed };
-            rem as u32
-        }
+    fn insert(&mut self, delay_ms: u32, period_ms: u32, action: TimerAction) -> Option<TimerId> {
+        let deadline = self.now().max(self.timers.now()) + delay_ms as u64;
+        let id = self.timers.insert(deadline, period_ms as u64, action).ok()?;
+        if deadline < self.wake_at { self.wake_at = deadline; self.rearm = true; }
+        Some(id)
     }
-    pub fn add_timer(&mut self, fiber: *mut Fiber, delay_ms: u32) -> bool {
-        if self.timers.push(TimerItem { start_ms: now_ms(), wait_ms: delay_ms as u64, fiber, cb: None, ctx: core::ptr::null_mut() }).is_err() { return false; }
-        let i = self.timers.len() - 1;
-        self.heap_up(i);
-        true
+    /// Resumes `fiber` after `delay_ms`. `None` if the timer could not be allocated.
+    pub fn add_timer(&mut self, fiber: *mut Fiber, delay_ms: u32) -> Option<TimerId> { self.insert(delay_ms, 0, TimerAction::Resume(fiber)) }
+    /// Calls `cb(ctx)` after `delay_ms`, from whichever worker polls the driver.
+    pub fn add_timeout(&mut self, cb: fn(*mut c_void), ctx: *mut c_void, delay_ms: u32) -> Option<TimerId> { self.insert(delay_ms, 0, TimerAction::Call(cb, ctx)) }
+    /// Calls `cb(ctx)` every `period_ms` until cancelled. Late polls skip missed ticks rather than bursting.
+    pub fn add_periodic(&mut self, cb: fn(*mut c_void), ctx: *mut c_void, period_ms: u32) -> Option<TimerId> {
+        if period_ms == 0 { return None; }
+        self.insert(period_ms, period_ms, TimerAction::Call(cb, ctx))
     }
-    pub fn add_timeout(&mut self, cb: fn(*mut c_void), ctx: *mut c_void, delay_ms: u32) -> bool {
-        if self.timers.push(TimerItem { start_ms: now_ms(), wait_ms: delay_ms as u64, fiber: core::ptr::null_mut(), cb: Some(cb), ctx }).is_err() { return false; }
-        let i = self.timers.len() - 1;
-        self.heap_up(i);
+    /// Stops a pending timer. False if it already fired or was cancelled; only on true may the caller reclaim `ctx`.
+    pub fn cancel(&mut self, id: TimerId) -> bool { self.timers.cancel(id) }
+    pub fn timer_count(&self) -> usize { self.timers.len() }
+    /// Current time on the driver's clock, in milliseconds.
+    pub fn now(&self) -> u64 { self.virtual_now.unwrap_or_else(now_ms) }
+    // Jumps the virtual clock to the earliest timer and fires it; false if no timer is pending.
+    pub(crate) fn advance_virtual(&mut self) -> bool {
+        let (Some(now), Some(next)) = (self.virtual_now, self.timers.next_deadline()) else { return false };
+        self.virtual_now = Some(now.max(next));
+        self.poll();
         true
     }
+    // True once after a timer earlier than the pollers' current wake-up was added.
+    pub(crate) fn take_rearm(&mut self) -> bool { core::mem::replace(&mut self.rearm, false) }
     pub fn add_event(&mut self, h: *mut c_void, fiber: *mut Fiber) -> bool { self.events.push(EventItem { h, fiber }).is_ok() }
     pub fn attach_iocp(&mut self) -> bool { if self.iocp.is_some() { true } else { self.iocp = attach(); self.iocp.is_some() } }
     pub fn register_iocp_handle(&mut self, h: *mut c_void) -> bool { if let Some(ref i) = self.iocp { register_handle(i, h) } else { false } }
-    pub fn await_iocp(&mut self, ov: *mut c_void, fiber: *mut Fiber) -> bool { self.maps.push(IocpMapItem { ov, fiber }).is_ok() }
+    pub fn await_iocp(&mut self, ov: *mut c_void, fiber: *mut Fiber) -> bool { self.maps.push(IocpMapItem { ov, fiber, cb: None, ctx: core::ptr::null_mut() }).is_ok() }
+    /// Drops the registration for `ov`, e.g. when the operation could not be submitted after all.
+    pub fn forget_iocp(&mut self, ov: *mut c_void) -> bool {
+        match self.maps.iter().position(|m| m.ov == ov) { Some(i) => { self.maps.swap_remove(i); true } None => false }
+    }
+    pub fn await_iocp_cb(&mut self, ov: *mut c_void, cb: fn(*mut c_void), ctx: *mut c_void) -> bool { self.maps.push(IocpMapItem { ov, fiber: core::ptr::null_mut(), cb: Some(cb), ctx }).is_ok() }
     pub fn poll(&mut self) {
         if let Some(ref i) = self.iocp {
             if let Some((ov, _bytes, _key)) = get_queued(i, 0) {
                 for idx in 0..self.maps.len() {
                     if self.maps[idx].ov == ov {
-                        let fb = self.maps[idx].fiber;
-                        self.maps.swap_remove(idx);
-                        unsafe { if let Some(cb) = RESUME_CB { cb(fb) } else { resume_fiber(fb) } }
+                        let it = self.maps.swap_remove(idx);
+                        if let Some(cb) = it.cb { cb(it.ctx) } else { resume(it.fiber) }
                         break;
                     }
                 }
             }
         }
-        let now = now_ms();
-        while let Some(t) = self.timers.first() {
-            let rem = t.wait_ms.saturating_sub(now.saturating_sub(t.start_ms));
-            if rem == 0 {
-                let it = self.timers.swap_remove(0);
-                if let Some(cb) = it.cb { cb(it.ctx) } else { unsafe { if let Some(rcb) = RESUME_CB { rcb(it.fiber) } else { resume_fiber(it.fiber) } } }
-                if !self.timers.is_empty() { self.heap_down(0); }
-            } else { break; }
-        }
+        let now = self.now();
+        self.timers.advance(now, |a| match a { TimerAction::Call(cb, ctx) => cb(ctx), TimerAction::Resume(f) => resume(f) });
     }
     pub fn pending_count(&self) -> u32 { (self.timers.len() + self.events.len() + self.maps.len()) as u32 }
 }
 
+fn resume(f: *mut Fiber) { unsafe { if let Some(rcb) = RESUME_CB { rcb(f) } else { resume_fiber(f) } } }
+
 pub fn set_resume_cb(cb: ResumeFiberFn) { unsafe { RESUME_CB = Some(cb); } }
 
 fn now_ms() -> u64 { (prm_time::now() as u64) / 1_000_000 }
@@ -1,7 +1,11 @@
 use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
 use cap_concurrency::fiber::Fiber;
 
-struct WaitNode { next: *mut WaitNode, fiber: *mut Fiber }
+// A waiter is either a suspended fiber or an async task's waker.
+struct WaitNode { next: *mut WaitNode, fiber: *mut Fiber, waker: Option<Waker> }
 
 pub struct FiberEvent { signaled: AtomicBool, head: AtomicPtr<WaitNode> }
 
@@ -15,23 +19,40 @@ impl FiberEvent {
             unsafe {
                 let mut cur = h;
                 while !cur.is_null() {
-                    let fb = (*cur).fiber;
-                    crate::scheduler::resume_fiber(fb);
-                    let nx = (*cur).next;
-                    let _ = Box::from_raw(cur);
-                    cur = nx;
+                    let node = Box::from_raw(cur);
+                    cur = node.next;
+                    match node.waker { Some(w) => w.wake(), None => crate::scheduler::resume_fiber(node.fiber) }
                 }
             }
         }
     }
     pub fn await_one(&self, fiber: *mut Fiber) -> bool {
         if self.signaled.load(Ordering::Acquire) { return true; }
-        let n = Box::into_raw(Box::new(WaitNode { next: core::ptr::null_mut(), fiber }));
+        self.push(WaitNode { next: core::ptr::null_mut(), fiber, waker: None });
+        false
+    }
+    /// Future that completes once the event has been signalled.
+    pub fn wait_async(&self) -> EventWait<'_> { EventWait { ev: self } }
+    fn push(&self, node: WaitNode) {
+        let n = Box::into_raw(Box::new(node));
         loop {
             let h = self.head.load(Ordering::Acquire);
             unsafe { (*n).next = h; }
             if self.head.compare_exchange(h, n, Ordering::Release, Ordering::Relaxed).is_ok() { break; }
         }
-        false
+    }
+}
+
+impl Default for FiberEvent { fn default() -> Self { Self::new() } }
+
+pub struct EventWait<'e> { ev: &'e FiberEvent }
+
+impl<'e> Future for EventWait<'e> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.ev.signaled.load(Ordering::Acquire) { return Poll::Ready(()); }
+        self.ev.push(WaitNode { next: core::ptr::null_mut(), fiber: core::ptr::null_mut(), waker: Some(cx.waker().clone()) });
+        // `signal_all` sets the flag before draining the list, so a node pushed after its drain sees the flag here.
+        if self.ev.signaled.load(Ordering::Acquire) { Poll::Ready(()) } else { Poll::Pending }
     }
 }
@@ -0,0 +1,380 @@
+use core::cell::{Cell, UnsafeCell};
+use core::ffi::c_void;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use cap_concurrency::Mutex;
+use cap_containers::{CapArc, Vector};
+use cap_io::Overlapped;
+use cap_memory::{Allocator, MemoryError};
+use prm_file::{FileError, FileHandle, FileOpenMode, FileShareMode};
+use crate::job::Job;
+use crate::scheduler::Scheduler;
+use crate::timer_wheel::TimerId;
+
+// Scheduler whose task (or `block_on`) is being polled on this thread; lets leaf futures reach its driver.
+thread_local! { static CURRENT_SCHED: Cell<*const c_void> = const { Cell::new(core::ptr::null()) }; }
+
+struct CurrentGuard(*const c_void);
+impl Drop for CurrentGuard { fn drop(&mut self) { CURRENT_SCHED.with(|c| c.set(self.0)); } }
+
+fn enter_sched(s: &Scheduler) -> CurrentGuard {
+    CurrentGuard(CURRENT_SCHED.with(|c| c.replace(s as *const Scheduler as *const c_void)))
+}
+
+fn with_current<R>(f: impl FnOnce(&Scheduler<'static>) -> R) -> R {
+    let p = CURRENT_SCHED.with(|c| c.get());
+    assert!(!p.is_null(), "sys_job future polled outside spawn_async / block_on");
+    f(unsafe { &*(p as *const Scheduler<'static>) })
+}
+
+// One-shot completion flag plus the waker of whoever is waiting on it.
+struct Signal { fired: AtomicBool, waker: Mutex<Option<Waker>> }
+
+impl Signal {
+    fn new() -> Self { Self { fired: AtomicBool::new(false), waker: Mutex::new(None) } }
+    fn fire(&self) {
+        self.fired.store(true, Ordering::Release);
+        let w = self.waker.lock().take();
+        if let Some(w) = w { w.wake(); }
+    }
+    fn poll(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.fired.load(Ordering::Acquire) { return Poll::Ready(()); }
+        *self.waker.lock() = Some(cx.waker().clone());
+        // `fire` may have run between the check and storing the waker.
+        if self.fired.load(Ordering::Acquire) { Poll::Ready(()) } else { Poll::Pending }
+    }
+}
+
+// Driver callbacks get a `CapArc::into_raw` reference, so whatever the kernel or timer touches stays alive
+// until the callback runs, even if the future that started it was dropped.
+trait HasSignal { fn signal(&self) -> &Signal; }
+impl HasSignal for Signal { fn signal(&self) -> &Signal { self } }
+
+fn fire_arc<T: HasSignal>(ctx: *mut c_void) {
+    unsafe { CapArc::from_raw(ctx as *const T) }.signal().fire();
+}
+
+// `std::task::Wake` only accepts `std::sync::Arc`; this is the same contract over `CapArc`.
+trait CapWake<'a>: Sized { fn wake_by_ref(this: &CapArc<'a, Self>); }
+
+struct WakerVtable<'a, W>(PhantomData<CapArc<'a, W>>);
+
+impl<'a, W: CapWake<'a>> WakerVtable<'a, W> {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(Self::clone, Self::wake, Self::wake_by_ref, Self::drop);
+
+    unsafe fn clone(p: *const ()) -> RawWaker {
+        let w = ManuallyDrop::new(CapArc::<W>::from_raw(p as *const W));
+        RawWaker::new(CapArc::into_raw((*w).clone()) as *const (), &Self::VTABLE)
+    }
+    unsafe fn wake(p: *const ()) { W::wake_by_ref(&CapArc::from_raw(p as *const W)); }
+    unsafe fn wake_by_ref(p: *const ()) { W::wake_by_ref(&ManuallyDrop::new(CapArc::from_raw(p as *const W))); }
+    unsafe fn drop(p: *const ()) { drop(CapArc::<W>::from_raw(p as *const W)); }
+}
+
+fn cap_waker<'a, W: CapWake<'a>>(w: CapArc<'a, W>) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(CapArc::into_raw(w) as *const (), &WakerVtable::<'a, W>::VTABLE)) }
+}
+
+const IDLE: u8 = 0;
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+const NOTIFIED: u8 = 3;
+const DONE: u8 = 4;
+
+type BoxedFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+// A spawned future. The state machine guarantees at most one job polls it at a time.
+struct Task<'a> { fut: UnsafeCell<Option<BoxedFuture<'a>>>, state: AtomicU8, sched: *const c_void }
+unsafe impl Send for Task<'_> {}
+unsafe impl Sync for Task<'_> {}
+
+impl Task<'_> {
+    // Caller has moved `state` to SCHEDULED.
+    fn schedule(this: CapArc<'_, Self>) {
+        let sched = unsafe { &*(this.sched as *const Scheduler<'static>) };
+        let arg = CapArc::into_raw(this) as *mut c_void;
+        if sched.enqueue(Job::new(run_task, arg)).is_err() { run_task(arg); }
+    }
+}
+
+impl<'a> CapWake<'a> for Task<'a> {
+    fn wake_by_ref(this: &CapArc<'a, Self>) {
+        let mut s = this.state.load(Ordering::Acquire);
+        loop {
+            let next = match s { IDLE => SCHEDULED, RUNNING => NOTIFIED, _ => return };
+            match this.state.compare_exchange_weak(s, next, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => { if next == SCHEDULED { Task::schedule(this.clone()); } return; }
+                Err(v) => s = v,
+            }
+        }
+    }
+}
+
+fn run_task(arg: *mut c_void) {
+    let task = unsafe { CapArc::from_raw(arg as *const Task) };
+    let sched = unsafe { &*(task.sched as *const Scheduler<'static>) };
+    let _cur = enter_sched(sched);
+    let waker = cap_waker(task.clone());
+    let mut cx = Context::from_waker(&waker);
+    task.state.store(RUNNING, Ordering::Release);
+    let fut = unsafe { (*task.fut.get()).as_mut().expect("task polled after completion") };
+    if fut.as_mut().poll(&mut cx).is_ready() {
+        unsafe { *task.fut.get() = None; }
+        task.state.store(DONE, Ordering::Release);
+        return;
+    }
+    if task.state.compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire).is_ok() { return; }
+    // Woken while running: requeue instead of polling again here, so other jobs get a turn.
+    task.state.store(SCHEDULED, Ordering::Release);
+    Task::schedule(task);
+}
+
+struct JoinInner<T> { sig: Signal, value: Mutex<Option<T>> }
+
+/// Future for the output of a task started with `Scheduler::spawn_async`.
+pub struct JoinHandle<'a, T> { inner: CapArc<'a, JoinInner<T>> }
+
+impl<T> JoinHandle<'_, T> {
+    pub fn is_finished(&self) -> bool { self.inner.sig.fired.load(Ordering::Acquire) }
+}
+
+impl<T> Future for JoinHandle<'_, T> {
+    type Output = T;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if self.inner.sig.poll(cx).is_pending() { return Poll::Pending; }
+        Poll::Ready(self.inner.value.lock().take().expect("JoinHandle polled after completion"))
+    }
+}
+
+// `block_on`'s waker: a word the blocked thread waits on.
+struct BlockFlag(AtomicU32);
+impl CapWake<'_> for BlockFlag {
+    fn wake_by_ref(this: &CapArc<'_, Self>) {
+        this.0.store(1, Ordering::Release);
+        prm_threading::wake_by_address_all(this.0.as_ptr() as *mut u8);
+    }
+}
+
+impl<'a> Scheduler<'a> {
+    /// Runs `fut` as a task on the workers; each poll is one job, re-enqueued whenever its waker fires.
+    ///
+    /// Tasks must finish before the scheduler is dropped.
+    pub fn spawn_async<F>(&self, fut: F) -> Result<JoinHandle<'a, F::Output>, MemoryError> where F: Future + Send + 'static, F::Output: Send + 'static {
+        let inner = CapArc::new(self.allocator(), JoinInner { sig: Signal::new(), value: Mutex::new(None) })?;
+        let out = inner.clone();
+        let body = async move {
+            let v = fut.await;
+            *out.value.lock() = Some(v);
+            out.sig.fire();
+        };
+        let task = CapArc::new(self.allocator(), Task { fut: UnsafeCell::new(Some(Box::pin(body))), state: AtomicU8::new(SCHEDULED), sched: self as *const Self as *const c_void })?;
+        Task::schedule(task);
+        Ok(JoinHandle { inner })
+    }
+
+    /// Drives `fut` to completion on the calling thread, running queued jobs while it is pending.
+    pub fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        let _cur = enter_sched(self);
+        let flag = CapArc::new(self.allocator(), BlockFlag(AtomicU32::new(1))).expect("block_on: waker allocation failed");
+        let waker = cap_waker(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = core::pin::pin!(fut);
+        loop {
+            if flag.0.swap(0, Ordering::AcqRel) != 0 {
+                if let Poll::Ready(v) = fut.as_mut().poll(&mut cx) { return v; }
+                continue;
+            }
+            if self.is_deterministic() {
+                if !self.det_progress() { panic!("deterministic scheduler deadlocked: block_on future can never complete"); }
+                continue;
+            }
+            match self.try_take() {
+                Some(j) => (j.func)(j.arg),
+                None => {
+                    let zero = 0u32;
+                    let _ = prm_threading::wait_on_address(flag.0.as_ptr() as *const u8, &zero as *const u32 as *const u8, 4, 1);
+                }
+            }
+        }
+    }
+}
+
+/// Future that completes `ms` milliseconds after it is first polled, using the scheduler's timer driver.
+pub fn sleep(ms: u32) -> Sleep { Sleep { ms, sig: None, timer: None } }
+
+pub struct Sleep { ms: u32, sig: Option<CapArc<'static, Signal>>, timer: Option<TimerId> }
+
+impl Future for Sleep {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.sig.is_none() {
+            let Ok(sig) = CapArc::new(with_current(|s| s.allocator()), Signal::new()) else {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            };
+            let ctx = CapArc::into_raw(sig.clone()) as *mut c_void;
+            let ms = self.ms;
+            let Some(id) = with_current(|s| s.with_driver(|d| d.add_timeout(fire_arc::<Signal>, ctx, ms))) else {
+                // Timer allocation failed: give the reference back and retry on the next poll.
+                drop(unsafe { CapArc::from_raw(ctx as *const Signal) });
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            };
+            self.sig = Some(sig);
+            self.timer = Some(id);
+        }
+        self.sig.as_ref().unwrap().poll(cx)
+    }
+}
+
+// A sleep dropped early (e.g. the losing side of `select`) takes its timer out of the driver.
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        let (Some(sig), Some(id)) = (&self.sig, self.timer) else { return };
+        if sig.fired.load(Ordering::Acquire) || CURRENT_SCHED.with(|c| c.get()).is_null() { return; }
+        if with_current(|s| s.with_driver(|d| d.cancel(id))) {
+            // The timer never ran, so its reference is still ours to release.
+            drop(unsafe { CapArc::from_raw(CapArc::as_ptr(sig)) });
+        }
+    }
+}
+
+/// Waits for every future and returns their outputs in input order.
+///
+/// Both the futures and the output vector are allocated up front, so polling never allocates.
+pub fn join_all<'a, F: Future>(alloc: Allocator<'a>, futs: impl IntoIterator<Item = F>) -> Result<JoinAll<'a, F>, MemoryError> {
+    let futs = futs.into_iter();
+    let mut items = Vector::with_capacity(alloc, futs.size_hint().0)?;
+    for f in futs { items.push((Box::pin(f), None))?; }
+    let outputs = Vector::with_capacity(alloc, items.len())?;
+    Ok(JoinAll { items, outputs: Some(outputs) })
+}
+
+// Each future with its output once it has finished.
+type JoinSlot<F> = (Pin<Box<F>>, Option<<F as Future>::Output>);
+
+pub struct JoinAll<'a, F: Future> { items: Vector<'a, JoinSlot<F>>, outputs: Option<Vector<'a, F::Output>> }
+
+// The futures are boxed; finished outputs are never pinned.
+impl<F: Future> Unpin for JoinAll<'_, F> {}
+
+impl<'a, F: Future> Future for JoinAll<'a, F> {
+    type Output = Vector<'a, F::Output>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut pending = false;
+        for (f, out) in self.items.iter_mut() {
+            if out.is_some() { continue; }
+            match f.as_mut().poll(cx) { Poll::Ready(v) => *out = Some(v), Poll::Pending => pending = true }
+        }
+        if pending { return Poll::Pending; }
+        let mut outputs = self.outputs.take().expect("JoinAll polled after completion");
+        // Capacity was reserved in `join_all`, so these pushes cannot fail.
+        for (_, o) in self.items.iter_mut() { let _ = outputs.push(o.take().unwrap()); }
+        Poll::Ready(outputs)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Either<A, B> { Left(A), Right(B) }
+
+/// Completes with whichever future finishes first (`a` wins ties); the other one is dropped with the `Select`.
+pub fn select<A: Future, B: Future>(a: A, b: B) -> Select<A, B> { Select { a: Box::pin(a), b: Box::pin(b) } }
+
+pub struct Select<A, B> { a: Pin<Box<A>>, b: Pin<Box<B>> }
+
+impl<A: Future, B: Future> Future for Select<A, B> {
+    type Output = Either<A::Output, B::Output>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(v) = self.a.as_mut().poll(cx) { return Poll::Ready(Either::Left(v)); }
+        if let Poll::Ready(v) = self.b.as_mut().poll(cx) { return Poll::Ready(Either::Right(v)); }
+        Poll::Pending
+    }
+}
+
+/// File opened for overlapped reads that complete through the scheduler's driver.
+///
+/// Read buffers come from the scheduler's allocator.
+pub struct AsyncFile<'a> { h: FileHandle, alloc: Allocator<'a> }
+
+unsafe impl Send for AsyncFile<'_> {}
+unsafe impl Sync for AsyncFile<'_> {}
+
+impl<'a> AsyncFile<'a> {
+    pub fn open(sched: &Scheduler<'a>, path: &str) -> Result<Self, FileError> {
+        let h = prm_file::open_overlapped(path, FileOpenMode::Read, FileShareMode::Read)?;
+        if !sched.with_driver(|d| d.attach_iocp() && d.register_iocp_handle(h.0)) {
+            let _ = prm_file::close(h);
+            return Err(FileError::Unsupported);
+        }
+        Ok(Self { h, alloc: sched.allocator() })
+    }
+
+    pub fn size(&self) -> Result<u64, FileError> { prm_file::size(self.h) }
+
+    /// Reads up to `len` bytes at `offset`; the result is shorter at end of file.
+    pub fn read_at(&self, offset: u64, len: usize) -> ReadAt<'_, 'a> { ReadAt { file: self, offset, len: len.min(u32::MAX as usize), op: None } }
+
+    pub async fn read_all(&self) -> Result<Vector<'a, u8>, FileError> {
+        let n = self.size()?;
+        self.read_at(0, n as usize).await
+    }
+}
+
+impl Drop for AsyncFile<'_> {
+    fn drop(&mut self) { let _ = prm_file::close(self.h); }
+}
+
+// Kernel-visible state of one read; the driver holds a reference until the completion arrives.
+struct ReadOp<'a> { ov: UnsafeCell<Overlapped>, buf: UnsafeCell<Option<Vector<'a, u8>>>, sig: Signal }
+unsafe impl Send for ReadOp<'_> {}
+unsafe impl Sync for ReadOp<'_> {}
+impl HasSignal for ReadOp<'_> { fn signal(&self) -> &Signal { &self.sig } }
+
+impl<'a> ReadOp<'a> {
+    fn new(alloc: Allocator<'a>, len: usize) -> Result<CapArc<'a, Self>, MemoryError> {
+        let mut buf = Vector::with_capacity(alloc, len)?;
+        for _ in 0..len { buf.push(0u8)?; }
+        CapArc::new(alloc, ReadOp { ov: UnsafeCell::new(Overlapped::new()), buf: UnsafeCell::new(Some(buf)), sig: Signal::new() })
+    }
+}
+
+pub struct ReadAt<'f, 'a> { file: &'f AsyncFile<'a>, offset: u64, len: usize, op: Option<CapArc<'a, ReadOp<'a>>> }
+
+impl<'a> Future for ReadAt<'_, 'a> {
+    type Output = Result<Vector<'a, u8>, FileError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.op.is_none() {
+            let Ok(op) = ReadOp::new(self.file.alloc, self.len) else { return Poll::Ready(Err(FileError::Failed)) };
+            let ov = op.ov.get();
+            let ctx = CapArc::into_raw(op.clone()) as *mut c_void;
+            let (h, offset) = (self.file.h, self.offset);
+            // Register before submitting, under one lock, so the completion is never polled unmapped.
+            let ok = with_current(|s| s.with_driver(|d| unsafe {
+                if !d.await_iocp_cb(ov as *mut c_void, fire_arc::<ReadOp>, ctx) { return false; }
+                let buf = (*op.buf.get()).as_mut().unwrap();
+                if cap_io::read_overlapped(h, buf, offset, &mut *ov) { return true; }
+                d.forget_iocp(ov as *mut c_void);
+                false
+            }));
+            if !ok {
+                drop(unsafe { CapArc::from_raw(ctx as *const ReadOp) });
+                return Poll::Ready(Err(FileError::Failed));
+            }
+            self.op = Some(op);
+        }
+        let op = self.op.as_ref().unwrap();
+        if op.sig.poll(cx).is_pending() { return Poll::Pending; }
+        match unsafe { (*op.ov.get()).transferred() } {
+            Some(n) => {
+                let mut buf = unsafe { (*op.buf.get()).take() }.expect("ReadAt polled after completion");
+                buf.truncate(n);
+                Poll::Ready(Ok(buf))
+            }
+            None => Poll::Ready(Err(FileError::Failed)),
+        }
+    }
+}
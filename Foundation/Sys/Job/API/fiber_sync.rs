@@ -0,0 +1,355 @@
+use core::cell::{Cell, UnsafeCell};
+use cap_containers::Vector;
+use cap_memory::{Allocator, MemoryError};
+use prm_sync::{ScopedLock, SpinLock};
+use crate::cancel::{CancelToken, WaitStatus};
+use crate::scheduler::Waiter;
+
+// FIFO of parked waiters. Only touched with the owning primitive's `SpinLock` held, and always
+// released (unparked) after that lock is dropped.
+pub(crate) struct WaitList { head: Cell<*const Waiter>, tail: Cell<*const Waiter> }
+
+impl WaitList {
+    pub(crate) const fn new() -> Self { Self { head: Cell::new(core::ptr::null()), tail: Cell::new(core::ptr::null()) } }
+    pub(crate) fn is_empty(&self) -> bool { self.head.get().is_null() }
+    pub(crate) fn push(&self, w: &Waiter) {
+        w.next.set(core::ptr::null());
+        let t = self.tail.get();
+        if t.is_null() { self.head.set(w); } else { unsafe { (*t).next.set(w); } }
+        self.tail.set(w);
+    }
+    pub(crate) fn pop(&self) -> Option<*const Waiter> {
+        let h = self.head.get();
+        if h.is_null() { return None; }
+        let n = unsafe { (*h).next.get() };
+        self.head.set(n);
+        if n.is_null() { self.tail.set(core::ptr::null()); }
+        Some(h)
+    }
+    // Unlinks `w` if it is still queued; false if someone already popped it.
+    pub(crate) fn remove(&self, w: &Waiter) -> bool {
+        let (mut prev, mut cur): (*const Waiter, *const Waiter) = (core::ptr::null(), self.head.get());
+        while !cur.is_null() {
+            let next = unsafe { (*cur).next.get() };
+            if core::ptr::eq(cur, w) {
+                if prev.is_null() { self.head.set(next); } else { unsafe { (*prev).next.set(next); } }
+                if next.is_null() { self.tail.set(prev); }
+                return true;
+            }
+            prev = cur;
+            cur = next;
+        }
+        false
+    }
+    // Detaches every waiter; walk the result with `unpark_all` once the lock is released.
+    pub(crate) fn take(&self) -> *const Waiter {
+        let h = self.head.get();
+        self.head.set(core::ptr::null());
+        self.tail.set(core::ptr::null());
+        h
+    }
+}
+
+fn unpark(w: Option<*const Waiter>) { if let Some(w) = w { unsafe { (*w).unpark(); } } }
+
+pub(crate) fn unpark_all(mut w: *const Waiter) {
+    while !w.is_null() {
+        // Read `next` first: the waiter may be gone as soon as it is unparked.
+        let next = unsafe { (*w).next.get() };
+        unsafe { (*w).unpark(); }
+        w = next;
+    }
+}
+
+/// Counting semaphore whose `acquire` suspends the calling fiber instead of blocking the worker.
+///
+/// Permits are handed to waiters in FIFO order.
+pub struct FiberSemaphore { lock: SpinLock, permits: Cell<usize>, waiters: WaitList }
+
+unsafe impl Send for FiberSemaphore {}
+unsafe impl Sync for FiberSemaphore {}
+
+impl FiberSemaphore {
+    pub const fn new(permits: usize) -> Self { Self { lock: SpinLock::new(), permits: Cell::new(permits), waiters: WaitList::new() } }
+    pub fn try_acquire(&self) -> bool {
+        let _g = ScopedLock::new(&self.lock);
+        if self.permits.get() == 0 { return false; }
+        self.permits.set(self.permits.get() - 1);
+        true
+    }
+    pub fn acquire(&self) {
+        let w = Waiter::new();
+        {
+            let _g = ScopedLock::new(&self.lock);
+            if self.permits.get() > 0 { self.permits.set(self.permits.get() - 1); return; }
+            self.waiters.push(&w);
+        }
+        // `release` hands its permit straight to us.
+        w.park();
+    }
+    /// `acquire` that gives up, without a permit, once `token` is cancelled.
+    pub fn acquire_or_cancel(&self, token: &CancelToken) -> WaitStatus {
+        if token.is_cancelled() { return WaitStatus::Cancelled; }
+        let w = Waiter::new();
+        {
+            let _g = ScopedLock::new(&self.lock);
+            if self.permits.get() > 0 { self.permits.set(self.permits.get() - 1); return WaitStatus::Completed; }
+            self.waiters.push(&w);
+        }
+        if token.park_or_cancel(&self.lock, &self.waiters, &w) { WaitStatus::Cancelled } else { WaitStatus::Completed }
+    }
+    pub fn release(&self) {
+        let next = {
+            let _g = ScopedLock::new(&self.lock);
+            let next = self.waiters.pop();
+            if next.is_none() { self.permits.set(self.permits.get() + 1); }
+            next
+        };
+        unpark(next);
+    }
+    pub fn available(&self) -> usize { let _g = ScopedLock::new(&self.lock); self.permits.get() }
+}
+
+/// Reader-writer lock for fibers. New readers queue behind a waiting writer, and a writer's unlock
+/// admits all queued readers before the next writer, so neither side starves.
+pub struct FiberRwLock { lock: SpinLock, readers: Cell<usize>, writer: Cell<bool>, read_waiters: WaitList, write_waiters: WaitList }
+
+unsafe impl Send for FiberRwLock {}
+unsafe impl Sync for FiberRwLock {}
+
+impl FiberRwLock {
+    pub const fn new() -> Self { Self { lock: SpinLock::new(), readers: Cell::new(0), writer: Cell::new(false), read_waiters: WaitList::new(), write_waiters: WaitList::new() } }
+    pub fn read(&self) {
+        let w = Waiter::new();
+        {
+            let _g = ScopedLock::new(&self.lock);
+            if !self.writer.get() && self.write_waiters.is_empty() { self.readers.set(self.readers.get() + 1); return; }
+            self.read_waiters.push(&w);
+        }
+        w.park();
+    }
+    pub fn read_unlock(&self) {
+        let next = {
+            let _g = ScopedLock::new(&self.lock);
+            self.readers.set(self.readers.get() - 1);
+            if self.readers.get() != 0 { return; }
+            let next = self.write_waiters.pop();
+            if next.is_some() { self.writer.set(true); }
+            next
+        };
+        unpark(next);
+    }
+    pub fn write(&self) {
+        let w = Waiter::new();
+        {
+            let _g = ScopedLock::new(&self.lock);
+            if !self.writer.get() && self.readers.get() == 0 { self.writer.set(true); return; }
+            self.write_waiters.push(&w);
+        }
+        w.park();
+    }
+    pub fn write_unlock(&self) {
+        let (readers, writer) = {
+            let _g = ScopedLock::new(&self.lock);
+            let mut n = 0;
+            let mut w = self.read_waiters.head.get();
+            while !w.is_null() { n += 1; w = unsafe { (*w).next.get() }; }
+            if n > 0 {
+                self.writer.set(false);
+                self.readers.set(n);
+                (self.read_waiters.take(), None)
+            } else {
+                let next = self.write_waiters.pop();
+                self.writer.set(next.is_some());
+                (core::ptr::null(), next)
+            }
+        };
+        unpark_all(readers);
+        unpark(writer);
+    }
+}
+
+impl Default for FiberRwLock { fn default() -> Self { Self::new() } }
+
+/// Condition variable paired with a `FiberMutex`.
+pub struct FiberCondvar { lock: SpinLock, waiters: WaitList }
+
+unsafe impl Send for FiberCondvar {}
+unsafe impl Sync for FiberCondvar {}
+
+impl FiberCondvar {
+    pub const fn new() -> Self { Self { lock: SpinLock::new(), waiters: WaitList::new() } }
+    /// Unlocks `m`, waits for a notify, then locks `m` again. Wakeups may be spurious; re-check the condition.
+    pub fn wait(&self, m: &crate::mutex::FiberMutex) {
+        let w = Waiter::new();
+        { let _g = ScopedLock::new(&self.lock); self.waiters.push(&w); }
+        m.unlock();
+        w.park();
+        m.lock(crate::scheduler::current_fiber());
+    }
+    pub fn notify_one(&self) {
+        let next = { let _g = ScopedLock::new(&self.lock); self.waiters.pop() };
+        unpark(next);
+    }
+    pub fn notify_all(&self) {
+        let all = { let _g = ScopedLock::new(&self.lock); self.waiters.take() };
+        unpark_all(all);
+    }
+}
+
+impl Default for FiberCondvar { fn default() -> Self { Self::new() } }
+
+/// Countdown latch: `wait` returns once the count reaches zero. `reset` re-arms it for the next round.
+///
+/// With `add` / `done` it doubles as a wait group.
+pub struct FiberLatch { lock: SpinLock, count: Cell<usize>, waiters: WaitList }
+
+pub type WaitGroup = FiberLatch;
+
+unsafe impl Send for FiberLatch {}
+unsafe impl Sync for FiberLatch {}
+
+impl FiberLatch {
+    pub const fn new(count: usize) -> Self { Self { lock: SpinLock::new(), count: Cell::new(count), waiters: WaitList::new() } }
+    pub fn add(&self, n: usize) { let _g = ScopedLock::new(&self.lock); self.count.set(self.count.get() + n); }
+    pub fn count_down(&self) {
+        let all = {
+            let _g = ScopedLock::new(&self.lock);
+            let c = self.count.get();
+            debug_assert!(c > 0, "FiberLatch counted below zero");
+            self.count.set(c.saturating_sub(1));
+            if c != 1 { return; }
+            self.waiters.take()
+        };
+        unpark_all(all);
+    }
+    pub fn done(&self) { self.count_down() }
+    pub fn count(&self) -> usize { let _g = ScopedLock::new(&self.lock); self.count.get() }
+    pub fn wait(&self) {
+        let w = Waiter::new();
+        {
+            let _g = ScopedLock::new(&self.lock);
+            if self.count.get() == 0 { return; }
+            self.waiters.push(&w);
+        }
+        w.park();
+    }
+    /// `wait` that returns early once `token` is cancelled.
+    pub fn wait_or_cancel(&self, token: &CancelToken) -> WaitStatus {
+        let w = Waiter::new();
+        {
+            let _g = ScopedLock::new(&self.lock);
+            if self.count.get() == 0 { return WaitStatus::Completed; }
+            if token.is_cancelled() { return WaitStatus::Cancelled; }
+            self.waiters.push(&w);
+        }
+        if token.park_or_cancel(&self.lock, &self.waiters, &w) { WaitStatus::Cancelled } else { WaitStatus::Completed }
+    }
+    /// Re-arms a released latch. Returns false (and changes nothing) while the count is still above zero.
+    pub fn reset(&self, count: usize) -> bool {
+        let _g = ScopedLock::new(&self.lock);
+        if self.count.get() != 0 { return false; }
+        self.count.set(count);
+        true
+    }
+}
+
+/// Bounded MPMC channel for fibers: `send` suspends while full, `recv` while empty.
+///
+/// After `close`, sends fail and receivers drain what is left, then get `None`.
+pub struct FiberChannel<'a, T> {
+    lock: SpinLock,
+    slots: UnsafeCell<Vector<'a, Option<T>>>,
+    head: Cell<usize>,
+    len: Cell<usize>,
+    closed: Cell<bool>,
+    senders: WaitList,
+    receivers: WaitList,
+}
+
+unsafe impl<'a, T: Send> Send for FiberChannel<'a, T> {}
+unsafe impl<'a, T: Send> Sync for FiberChannel<'a, T> {}
+
+impl<'a, T> FiberChannel<'a, T> {
+    pub fn with_capacity(alloc: Allocator<'a>, capacity: usize) -> Result<Self, MemoryError> {
+        if capacity == 0 { return Err(MemoryError::InvalidArgument); }
+        let mut slots = Vector::with_capacity(alloc, capacity)?;
+        for _ in 0..capacity { slots.push(None)?; }
+        Ok(Self { lock: SpinLock::new(), slots: UnsafeCell::new(slots), head: Cell::new(0), len: Cell::new(0), closed: Cell::new(false), senders: WaitList::new(), receivers: WaitList::new() })
+    }
+
+    pub fn capacity(&self) -> usize { unsafe { (*self.slots.get()).len() } }
+
+    /// Fails with the value if the channel is full or closed.
+    pub fn try_send(&self, v: T) -> Result<(), T> {
+        let next = {
+            let _g = ScopedLock::new(&self.lock);
+            if self.closed.get() || self.len.get() == self.capacity() { return Err(v); }
+            let slots = unsafe { &mut *self.slots.get() };
+            let tail = (self.head.get() + self.len.get()) % slots.len();
+            slots[tail] = Some(v);
+            self.len.set(self.len.get() + 1);
+            self.receivers.pop()
+        };
+        unpark(next);
+        Ok(())
+    }
+
+    /// Fails with the value only if the channel is closed.
+    pub fn send(&self, mut v: T) -> Result<(), T> {
+        loop {
+            v = match self.try_send(v) { Ok(()) => return Ok(()), Err(v) => v };
+            let w = Waiter::new();
+            {
+                let _g = ScopedLock::new(&self.lock);
+                if self.closed.get() { return Err(v); }
+                // A slot may have opened between `try_send` and taking the lock.
+                if self.len.get() < self.capacity() { continue; }
+                self.senders.push(&w);
+            }
+            w.park();
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        let (v, next) = {
+            let _g = ScopedLock::new(&self.lock);
+            if self.len.get() == 0 { return None; }
+            let slots = unsafe { &mut *self.slots.get() };
+            let v = slots[self.head.get()].take();
+            self.head.set((self.head.get() + 1) % slots.len());
+            self.len.set(self.len.get() - 1);
+            (v, self.senders.pop())
+        };
+        unpark(next);
+        v
+    }
+
+    /// `None` once the channel is closed and drained.
+    pub fn recv(&self) -> Option<T> {
+        loop {
+            if let Some(v) = self.try_recv() { return Some(v); }
+            let w = Waiter::new();
+            {
+                let _g = ScopedLock::new(&self.lock);
+                if self.len.get() > 0 { continue; }
+                if self.closed.get() { return None; }
+                self.receivers.push(&w);
+            }
+            w.park();
+        }
+    }
+
+    /// Wakes every blocked sender and receiver.
+    pub fn close(&self) {
+        let (s, r) = {
+            let _g = ScopedLock::new(&self.lock);
+            self.closed.set(true);
+            (self.senders.take(), self.receivers.take())
+        };
+        unpark_all(s);
+        unpark_all(r);
+    }
+
+    pub fn is_closed(&self) -> bool { let _g = ScopedLock::new(&self.lock); self.closed.get() }
+}
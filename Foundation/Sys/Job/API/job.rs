@@ -1,6 +1,21 @@
 use core::ffi::c_void;
 
+/// Where a job may run.
+///
+/// `Main` jobs only run on the thread that calls `Scheduler::pump_main`, or on the thread that started
+/// the scheduler while it waits in `wait_helping`; `Compute(n)` jobs run on worker group
+/// `n % compute_groups`; `Any` jobs run on whichever worker gets to them first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadAffinity { Main, Any, Compute(u16) }
+
+/// `qos` at or above this goes to the high-priority queues.
+pub const QOS_HIGH: u8 = 1;
+
 #[derive(Clone, Copy)]
-pub struct Job { pub func: fn(*mut c_void), pub arg: *mut c_void, pub qos: u8 }
+pub struct Job { pub func: fn(*mut c_void), pub arg: *mut c_void, pub qos: u8, pub affinity: ThreadAffinity }
 
-impl Job { pub const fn new(func: fn(*mut c_void), arg: *mut c_void) -> Self { Self { func, arg, qos: 0 } } }
+impl Job {
+    pub const fn new(func: fn(*mut c_void), arg: *mut c_void) -> Self { Self { func, arg, qos: 0, affinity: ThreadAffinity::Any } }
+    pub const fn with_qos(mut self, qos: u8) -> Self { self.qos = qos; self }
+    pub const fn with_affinity(mut self, affinity: ThreadAffinity) -> Self { self.affinity = affinity; self }
+}
@@ -1,12 +1,26 @@
 pub mod job;
 pub mod driver;
+pub mod timer_wheel;
 pub mod scheduler;
 pub mod event;
 pub mod mutex;
+pub mod fiber_sync;
 pub mod counter;
+pub mod parallel;
+pub mod executor;
+pub mod deterministic;
+pub mod cancel;
+pub mod stats;
 pub use job::*;
 pub use driver::*;
+pub use timer_wheel::*;
 pub use scheduler::*;
 pub use event::*;
 pub use mutex::*;
+pub use fiber_sync::*;
 pub use counter::*;
+pub use parallel::*;
+pub use executor::*;
+pub use deterministic::*;
+pub use cancel::*;
+pub use stats::*;
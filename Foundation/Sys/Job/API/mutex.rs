@@ -1,22 +1,44 @@
-use core::sync::atomic::{AtomicPtr, Ordering};
+use core::cell::Cell;
 use cap_concurrency::fiber::Fiber;
+use prm_sync::{ScopedLock, SpinLock};
+use crate::fiber_sync::WaitList;
+use crate::scheduler::Waiter;
 
-pub struct FiberMutex { owner: AtomicPtr<Fiber>, waiters: AtomicPtr<Fiber> }
+/// Mutex whose `lock` suspends the calling fiber instead of blocking the worker.
+///
+/// `unlock` hands ownership straight to the longest waiter. The `fiber` arguments are kept for
+/// callers of the old API; ownership is tracked by the lock itself.
+pub struct FiberMutex { lock: SpinLock, locked: Cell<bool>, waiters: WaitList }
+
+unsafe impl Send for FiberMutex {}
+unsafe impl Sync for FiberMutex {}
 
 impl FiberMutex {
-    pub fn new() -> Self { Self { owner: AtomicPtr::new(core::ptr::null_mut()), waiters: AtomicPtr::new(core::ptr::null_mut()) } }
-    pub fn try_lock(&self, fiber: *mut Fiber) -> bool { self.owner.compare_exchange(core::ptr::null_mut(), fiber, Ordering::Acquire, Ordering::Relaxed).is_ok() }
-    pub fn lock(&self, fiber: *mut Fiber) {
-        if self.try_lock(fiber) { return; }
-        loop {
-            let h = self.waiters.load(Ordering::Acquire);
-            if self.waiters.compare_exchange(h, fiber, Ordering::Release, Ordering::Relaxed).is_ok() { break; }
+    pub const fn new() -> Self { Self { lock: SpinLock::new(), locked: Cell::new(false), waiters: WaitList::new() } }
+    pub fn try_lock(&self, _fiber: *mut Fiber) -> bool {
+        let _g = ScopedLock::new(&self.lock);
+        if self.locked.get() { return false; }
+        self.locked.set(true);
+        true
+    }
+    pub fn lock(&self, _fiber: *mut Fiber) {
+        let w = Waiter::new();
+        {
+            let _g = ScopedLock::new(&self.lock);
+            if !self.locked.get() { self.locked.set(true); return; }
+            self.waiters.push(&w);
         }
-        crate::scheduler::suspend_current();
+        w.park();
     }
     pub fn unlock(&self) {
-        let _ = self.owner.swap(core::ptr::null_mut(), Ordering::Release);
-        let w = self.waiters.swap(core::ptr::null_mut(), Ordering::AcqRel);
-        if !w.is_null() { crate::scheduler::resume_fiber(w); }
-}
+        let next = {
+            let _g = ScopedLock::new(&self.lock);
+            let next = self.waiters.pop();
+            if next.is_none() { self.locked.set(false); }
+            next
+        };
+        if let Some(w) = next { unsafe { (*w).unpark(); } }
+    }
 }
+
+impl Default for FiberMutex { fn default() -> Self { Self::new() } }
@@ -0,0 +1,166 @@
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use cap_containers::{CapBox, Vector};
+use cap_memory::{Allocator, MemoryError};
+use crate::cancel::CancelToken;
+use crate::counter::TaskGroup;
+use crate::job::Job;
+use crate::scheduler::Scheduler;
+
+// Raw base pointer handed to chunk bodies; each chunk touches a disjoint range.
+struct SyncPtr<T>(*mut T);
+unsafe impl<T: Send> Send for SyncPtr<T> {}
+unsafe impl<T: Send> Sync for SyncPtr<T> {}
+impl<T> SyncPtr<T> {
+    // A method (not `.0`) so closures capture the wrapper, not the bare pointer.
+    fn get(&self) -> *mut T { self.0 }
+}
+
+// Lives on the caller's stack; `run_chunks` does not return until every runner has called `task_done`.
+struct ForCtx<'f> { body: &'f (dyn Fn(usize) + Sync + 'f), chunks: usize, next: AtomicUsize, tg: TaskGroup<'static> }
+
+fn drain(ctx: &ForCtx) {
+    loop {
+        let c = ctx.next.fetch_add(1, Ordering::Relaxed);
+        if c >= ctx.chunks { break; }
+        (ctx.body)(c);
+    }
+}
+
+fn run_for(arg: *mut c_void) {
+    let ctx = unsafe { &*(arg as *const ForCtx) };
+    drain(ctx);
+    ctx.tg.task_done();
+}
+
+// Waits in `drop`, so borrowed state outlives the jobs using it even if the caller unwinds.
+struct WaitOnDrop<'s, 'a, 't>(&'s Scheduler<'a>, &'s TaskGroup<'t>);
+impl<'s, 'a, 't> Drop for WaitOnDrop<'s, 'a, 't> { fn drop(&mut self) { let _ = self.0.wait_helping(self.1); } }
+
+impl<'a> Scheduler<'a> {
+    // Runs `body(0..chunks)` on the workers and the calling thread; chunks are claimed dynamically.
+    fn run_chunks(&self, chunks: usize, body: &(dyn Fn(usize) + Sync)) {
+        if chunks == 0 { return; }
+        let ctx = ForCtx { body, chunks, next: AtomicUsize::new(0), tg: TaskGroup::new() };
+        let arg = &ctx as *const ForCtx as *mut c_void;
+        let _wait = WaitOnDrop(self, &ctx.tg);
+        for _ in 0..(chunks - 1).min(self.worker_count()) {
+            ctx.tg.add_tasks(1);
+            if self.enqueue(Job::new(run_for, arg)).is_err() { ctx.tg.task_done(); break; }
+        }
+        drain(&ctx);
+    }
+
+    /// Calls `f` on consecutive chunks of at most `grain` elements, in parallel; returns when all are done.
+    pub fn parallel_for<T: Sync, F: Fn(&[T]) + Sync>(&self, data: &[T], grain: usize, f: F) {
+        let grain = grain.max(1);
+        self.run_chunks(data.len().div_ceil(grain), &|c| {
+            let lo = c * grain;
+            f(&data[lo..(lo + grain).min(data.len())])
+        });
+    }
+
+    /// `parallel_for` with exclusive access to each chunk.
+    pub fn parallel_for_mut<T: Send, F: Fn(&mut [T]) + Sync>(&self, data: &mut [T], grain: usize, f: F) {
+        let grain = grain.max(1);
+        let len = data.len();
+        let base = SyncPtr(data.as_mut_ptr());
+        self.run_chunks(len.div_ceil(grain), &|c| {
+            let lo = c * grain;
+            f(unsafe { core::slice::from_raw_parts_mut(base.get().add(lo), grain.min(len - lo)) })
+        });
+    }
+
+    /// Maps every chunk in parallel, then folds the results in chunk order starting from `identity`.
+    ///
+    /// `combine` only needs to be associative; the result does not depend on which worker ran which
+    /// chunk. Falls back to a serial fold if the per-chunk result slots cannot be allocated.
+    pub fn parallel_reduce<T, R, M, C>(&self, data: &[T], grain: usize, identity: R, map: M, combine: C) -> R
+    where T: Sync, R: Send, M: Fn(&[T]) -> R + Sync, C: Fn(R, R) -> R {
+        let grain = grain.max(1);
+        let chunks = data.len().div_ceil(grain);
+        let mut slots: Vector<Option<R>> = match Vector::with_capacity(self.allocator(), chunks) {
+            Ok(v) => v,
+            Err(_) => return data.chunks(grain).fold(identity, |acc, c| combine(acc, map(c))),
+        };
+        for _ in 0..chunks { let _ = slots.push(None); }
+        let base = SyncPtr(slots.as_mut_ptr());
+        self.run_chunks(chunks, &|c| {
+            let lo = c * grain;
+            let r = map(&data[lo..(lo + grain).min(data.len())]);
+            unsafe { *base.get().add(c) = Some(r); }
+        });
+        slots.into_iter().fold(identity, |acc, r| combine(acc, r.unwrap()))
+    }
+
+    /// Runs `f` with a `Scope` whose spawned jobs may borrow from the caller; returns after all of them finish.
+    /// The scope gets a token from the scheduler's allocator; without memory for it, `Scope::token` is `None`.
+    pub fn scope<'env, R, F>(&self, f: F) -> R where F: for<'scope> FnOnce(&'scope Scope<'scope, 'env, 'a>) -> R {
+        let tg = CancelToken::new(self.allocator()).map_or_else(|_| TaskGroup::new(), TaskGroup::with_token);
+        self.run_scope(tg, f)
+    }
+
+    /// `scope` whose jobs are cancelled along with `parent`, e.g. `s.token()` of an enclosing scope.
+    /// Fails without running `f` if the child token cannot be allocated.
+    pub fn scope_with_parent<'env, R, F>(&self, parent: &CancelToken<'a>, f: F) -> Result<R, MemoryError> where F: for<'scope> FnOnce(&'scope Scope<'scope, 'env, 'a>) -> R {
+        Ok(self.run_scope(TaskGroup::with_parent(parent)?, f))
+    }
+
+    fn run_scope<'env, R, F>(&self, tg: TaskGroup<'a>, f: F) -> R where F: for<'scope> FnOnce(&'scope Scope<'scope, 'env, 'a>) -> R {
+        let s = Scope { sched: self, tg, _env: PhantomData };
+        let _wait = WaitOnDrop(self, &s.tg);
+        f(&s)
+    }
+}
+
+/// Spawn handle passed to the closure given to `Scheduler::scope`.
+pub struct Scope<'scope, 'env: 'scope, 'a> { sched: &'scope Scheduler<'a>, tg: TaskGroup<'a>, _env: PhantomData<&'scope mut &'env ()> }
+
+unsafe impl<'scope, 'env, 'a> Send for Scope<'scope, 'env, 'a> {}
+unsafe impl<'scope, 'env, 'a> Sync for Scope<'scope, 'env, 'a> {}
+
+struct Spawned<'a, F> { f: F, tg: *const TaskGroup<'a>, alloc: Allocator<'a> }
+
+impl<'a, F: FnOnce()> Spawned<'a, F> {
+    // Frees the storage and hands back the closure and its group.
+    fn take(arg: *mut c_void) -> (F, *const TaskGroup<'a>) {
+        let p = arg as *mut Self;
+        let Spawned { f, tg, .. } = CapBox::into_inner(unsafe { CapBox::from_raw((*p).alloc, p) });
+        (f, tg)
+    }
+    fn run(arg: *mut c_void) {
+        let (f, tg) = Self::take(arg);
+        if unsafe { !(*tg).is_cancelled() } { f(); } else { drop(f); }
+        unsafe { (*tg).task_done(); }
+    }
+}
+
+impl<'scope, 'env, 'a> Scope<'scope, 'env, 'a> {
+    /// Runs `f` on a worker. It may borrow anything that outlives the scope, including the scope itself
+    /// to spawn more work. The closure is stored in the scheduler's allocator; if that allocation or the
+    /// enqueue fails, `f` comes back unrun and the scope does not wait for it. Once the scope is
+    /// cancelled, jobs that have not started yet are dropped without running.
+    pub fn spawn<F: FnOnce() + Send + 'scope>(&'scope self, f: F) -> Result<(), F> {
+        let alloc = self.sched.allocator();
+        // Reserve the slot before moving `f` in, so a failed allocation can still hand `f` back.
+        let slot = match CapBox::new(alloc, MaybeUninit::<Spawned<'a, F>>::uninit()) { Ok(b) => b, Err(_) => return Err(f) };
+        self.tg.add_tasks(1);
+        let (p, _) = CapBox::into_raw(slot);
+        unsafe { (*p).write(Spawned { f, tg: &self.tg, alloc }); }
+        let arg = p as *mut c_void;
+        if self.sched.enqueue(Job::new(Spawned::<'a, F>::run, arg)).is_err() {
+            let (f, _) = Spawned::<'a, F>::take(arg);
+            self.tg.task_done();
+            return Err(f);
+        }
+        Ok(())
+    }
+
+    /// Cancels this scope and every scope nested under its token. Running jobs finish; see `is_cancelled`.
+    pub fn cancel(&self) { self.tg.cancel() }
+    /// For long-running jobs to poll so they can stop early.
+    pub fn is_cancelled(&self) -> bool { self.tg.is_cancelled() }
+    pub fn token(&self) -> Option<&CancelToken<'a>> { self.tg.token() }
+}
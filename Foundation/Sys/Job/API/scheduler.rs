@@ -1,104 +1,667 @@
 use core::ffi::c_void;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use cap_memory::*;
 use cap_containers::*;
-use cap_concurrency::{fiber, Parker};
-use crate::job::Job;
+use cap_concurrency::{fiber, FiberPool, Parker, PooledFiber};
+use crate::job::{Job, ThreadAffinity, QOS_HIGH};
+use crate::cancel::WaitStatus;
+use crate::fiber_sync::WaitList;
+use crate::counter::TaskGroup;
+use crate::deterministic::{DetQueue, ScheduleStep};
 use crate::driver::{Driver, set_resume_cb};
-use prm_threading::{ThreadHandle, thread_create_with_stack, thread_join};
+use crate::stats::{SchedulerStats, StatsLog, WorkerCounters};
+use prm_sync::{ScopedLock, SpinLock};
+use prm_threading::{ThreadHandle, thread_create_with_stack, thread_join, thread_set_affinity_mask, thread_set_group_affinity};
 
-thread_local! { static CURRENT_FIBER: std::cell::Cell<*mut fiber::Fiber> = std::cell::Cell::new(core::ptr::null_mut()); }
+thread_local! { static CURRENT_FIBER: std::cell::Cell<*mut fiber::Fiber> = const { std::cell::Cell::new(core::ptr::null_mut()) }; }
+// (scheduler shared state, worker index) for worker threads; lets `enqueue` tell whether it may push to a local deque.
+thread_local! { static CURRENT_WORKER: std::cell::Cell<(*const c_void, usize)> = const { std::cell::Cell::new((core::ptr::null(), 0)) }; }
+// The worker's `FiberPool`, so fibers finished by `resume_fiber` go back to the thread that resumed them.
+thread_local! { static CURRENT_POOL: std::cell::Cell<*mut c_void> = const { std::cell::Cell::new(core::ptr::null_mut()) }; }
 
-pub fn suspend_current() { let host = prm_threading::host_fiber(); CURRENT_FIBER.with(|c| { let f = c.get(); if !f.is_null() { prm_threading::switch_to_fiber(host); } }); }
-pub fn resume_fiber(fb: *mut fiber::Fiber) { unsafe { (*fb).start_switch(); } }
+// Set by `Waiter::park` just before switching away; read back by `enter` once the fiber is off its stack.
+thread_local! { static PARKING: std::cell::Cell<*const Waiter> = const { std::cell::Cell::new(core::ptr::null()) }; }
 
-struct Worker<'a> { high: ChaseLevDeque<'a, Job>, norm: ChaseLevDeque<'a, Job>, parker: Parker }
+// Address of a per-thread slot: tells the thread that started a scheduler apart from every other.
+thread_local! { static THREAD_TAG: u8 = const { 0 }; }
+fn thread_tag() -> usize { THREAD_TAG.with(|t| t as *const u8 as usize) }
 
-struct WorkerCtx<'a> { self_idx: usize, workers: *mut Worker<'a>, worker_count: usize, stop: *const AtomicBool, stack_size: usize, driver_ptr: *mut Driver }
+// Idle fibers each worker keeps around; suspended jobs hold theirs outside the pool.
+const IDLE_FIBERS_PER_WORKER: usize = 16;
 
-fn worker_main<'a>(arg: *mut c_void) {
+pub fn suspend_current() {
+    let f = CURRENT_FIBER.with(|c| c.get());
+    if f.is_null() { return; }
+    let pf = unsafe { PooledFiber::from_fiber(f) };
+    prm_threading::switch_to_fiber(pf.return_to());
+}
+
+/// The pooled fiber running the current job, or null off the workers (e.g. inside `pump_main`).
+pub fn current_fiber() -> *mut fiber::Fiber { CURRENT_FIBER.with(|c| c.get()) }
+
+/// Index of the scheduler worker running on this thread, or `None` on any other thread.
+pub fn current_worker_index() -> Option<usize> {
+    let (sh, idx) = CURRENT_WORKER.with(|c| c.get());
+    if sh.is_null() { None } else { Some(idx) }
+}
+
+pub(crate) fn resume_fiber(fb: *mut fiber::Fiber) {
+    let pf = unsafe { PooledFiber::from_fiber(fb) };
+    if enter(fb, |ret| pf.resume(ret)) { retire(pf as *const PooledFiber as *mut PooledFiber); }
+}
+
+// Makes `fb` the current fiber while `f` switches into it; `f` gets the context to come back to.
+fn enter<R>(fb: *mut fiber::Fiber, f: impl FnOnce(prm_threading::FiberHandle) -> R) -> R {
+    let prev = CURRENT_FIBER.with(|c| c.replace(fb));
+    let ret = if prev.is_null() { prm_threading::host_fiber() } else { unsafe { (*prev).handle } };
+    let r = f(ret);
+    CURRENT_FIBER.with(|c| c.set(prev));
+    let parked = PARKING.with(|c| c.replace(core::ptr::null()));
+    if !parked.is_null() { unsafe { (*parked).suspended(); } }
+    r
+}
+
+const PARK_NOTIFIED: u32 = 1;
+const PARK_SUSPENDED: u32 = 2;
+
+/// One fiber (or thread) blocked in a `sys_job` sync primitive. Lives on the waiter's stack.
+///
+/// `park` suspends the current fiber; a later `unpark` from any thread posts a job that resumes it,
+/// so the waking thread never runs the waiter itself. Outside a worker fiber `park` blocks the thread.
+pub struct Waiter { fiber: *mut fiber::Fiber, shared: *const c_void, state: AtomicU32, pub(crate) next: std::cell::Cell<*const Waiter> }
+
+impl Waiter {
+    pub fn new() -> Self {
+        Self { fiber: CURRENT_FIBER.with(|c| c.get()), shared: CURRENT_WORKER.with(|c| c.get().0), state: AtomicU32::new(0), next: std::cell::Cell::new(core::ptr::null()) }
+    }
+    fn on_fiber(&self) -> bool { !self.fiber.is_null() && !self.shared.is_null() }
+
+    /// Blocks until `unpark`; `unpark` may already have happened.
+    pub fn park(&self) {
+        if self.on_fiber() {
+            PARKING.with(|c| c.set(self as *const Waiter));
+            suspend_current();
+            return;
+        }
+        while self.state.load(Ordering::Acquire) & PARK_NOTIFIED == 0 {
+            let zero = 0u32;
+            if prm_threading::wait_on_address(self.state.as_ptr() as *const u8, &zero as *const u32 as *const u8, 4, 1).is_err() { prm_threading::thread_yield(); }
+        }
+    }
+
+    /// Releases a parked (or about to park) waiter. Call once; the waiter may be gone when this returns.
+    pub fn unpark(&self) {
+        let addr = self.state.as_ptr();
+        let on_fiber = self.on_fiber();
+        let prev = self.state.fetch_or(PARK_NOTIFIED, Ordering::AcqRel);
+        if !on_fiber { prm_threading::wake_by_address_all(addr as *mut u8); }
+        // Already suspended, so only this call can resume it: the waiter is still alive here.
+        else if prev & PARK_SUSPENDED != 0 { post_resume(self); }
+    }
+
+    // The fiber is off its stack; whoever of this and `unpark` comes second schedules the resume.
+    fn suspended(&self) {
+        if self.state.fetch_or(PARK_SUSPENDED, Ordering::AcqRel) & PARK_NOTIFIED != 0 { post_resume(self); }
+    }
+}
+
+impl Default for Waiter { fn default() -> Self { Self::new() } }
+
+fn resume_job(arg: *mut c_void) { resume_fiber(arg as *mut fiber::Fiber); }
+
+// Queues a resume on the waiter's scheduler without needing the `Scheduler` itself. The waiter stays
+// alive until its fiber resumes, so it serves as its own list node and queueing it cannot fail.
+fn post_resume(w: &Waiter) {
+    let sh = unsafe { &*(w.shared as *const Shared) };
+    // The seed picks among ready jobs, resumes included; the list only takes what the pool cannot.
+    if let Some(det) = &sh.det { if det.push(Job::new(resume_job, w.fiber as *mut c_void)).is_ok() { return; } }
+    {
+        let _g = ScopedLock::new(&sh.resume_lock);
+        sh.resumes.push(w);
+        sh.resumes_queued.fetch_add(1, Ordering::Release);
+    }
+    if sh.det.is_some() { return; }
+    let idx = sh.wake.fetch_add(1, Ordering::Relaxed) % sh.worker_count;
+    unsafe { let _ = (*sh.workers.add(idx)).parker.unpark(); }
+}
+
+// Oldest resume queued by `post_resume`, as a job that switches into the waiter's fiber.
+fn take_resume(sh: &Shared) -> Option<Job> {
+    if sh.resumes_queued.load(Ordering::Acquire) == 0 { return None; }
+    let w = {
+        let _g = ScopedLock::new(&sh.resume_lock);
+        let w = sh.resumes.pop()?;
+        sh.resumes_queued.fetch_sub(1, Ordering::Release);
+        w
+    };
+    Some(Job::new(resume_job, unsafe { (*w).fiber } as *mut c_void))
+}
+
+// A suspended job's fiber finished after being resumed: recycle it on this worker, or free it off-worker.
+fn retire(pf: *mut PooledFiber) {
+    let b = unsafe { PooledFiber::reclaim(pf) };
+    let pool = CURRENT_POOL.with(|c| c.get()) as *mut FiberPool;
+    if pool.is_null() { drop(b); } else { unsafe { (*pool).release(b); } }
+}
+
+struct Worker<'a> { high: ChaseLevDeque<'a, Job>, norm: ChaseLevDeque<'a, Job>, parker: Parker, stats: WorkerCounters }
+
+// `Compute(n)` jobs for one worker group. They never enter a worker deque, where anyone could steal them.
+struct GroupQueue<'a> { high: Overflow<'a>, norm: Overflow<'a> }
+
+// A bounded MPMC queue that spills into a growable list once full, so a push fails only when memory
+// runs out. While anything is spilled, pushes keep going to the list so older jobs are taken first.
+struct Overflow<'a> { fast: MPMCQueue<'a, Job>, spill_lock: SpinLock, spill: core::cell::UnsafeCell<Spill<'a>>, spilled: AtomicUsize }
+struct Spill<'a> { jobs: Vector<'a, Job>, head: usize }
+
+impl<'a> Overflow<'a> {
+    fn with_capacity(alloc: Allocator<'a>, capacity_pow2: usize) -> Result<Self, MemoryError> {
+        Ok(Self { fast: MPMCQueue::with_capacity(alloc, capacity_pow2)?, spill_lock: SpinLock::new(), spill: core::cell::UnsafeCell::new(Spill { jobs: Vector::with_capacity(alloc, 0)?, head: 0 }), spilled: AtomicUsize::new(0) })
+    }
+    fn enqueue(&self, j: Job) -> Result<(), MemoryError> {
+        if self.spilled.load(Ordering::Acquire) == 0 && self.fast.enqueue(j).is_ok() { return Ok(()); }
+        let _g = ScopedLock::new(&self.spill_lock);
+        unsafe { (*self.spill.get()).jobs.push(j)?; }
+        self.spilled.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+    fn dequeue(&self) -> Option<Job> {
+        if let Some(j) = self.fast.dequeue() { return Some(j); }
+        if self.spilled.load(Ordering::Acquire) == 0 { return None; }
+        let _g = ScopedLock::new(&self.spill_lock);
+        let s = unsafe { &mut *self.spill.get() };
+        let j = *s.jobs.get(s.head)?;
+        s.head += 1;
+        // Reuse the storage once drained instead of letting the list creep forward.
+        if s.head == s.jobs.len() { s.jobs.truncate(0); s.head = 0; }
+        self.spilled.fetch_sub(1, Ordering::Release);
+        Some(j)
+    }
+}
+
+// Chase-Lev deques only accept pushes from their owner, so submissions from other threads, and
+// anything a worker deque cannot take, go through these overflow queues instead.
+struct Shared<'a> {
+    stop: AtomicBool,
+    // Parked fibers ready to resume, linked through their `Waiter`s; taken before any other job.
+    resume_lock: SpinLock,
+    resumes: WaitList,
+    resumes_queued: AtomicUsize,
+    overflow_high: Overflow<'a>,
+    overflow: Overflow<'a>,
+    groups: Vector<'a, GroupQueue<'a>>,
+    main: Overflow<'a>,
+    main_parker: Parker,
+    // `thread_tag` of the thread that started the scheduler; `wait_helping` pumps `main` there.
+    main_thread: usize,
+    // Serialises `Driver` access: workers take turns polling, and registrations wait for the poll to finish.
+    driver_lock: SpinLock,
+    // Round-robin cursor for picking which worker to unpark.
+    wake: AtomicUsize,
+    // Same array as `Scheduler::workers`, so threads holding only `Shared` can unpark a worker.
+    workers: *mut Worker<'a>,
+    worker_count: usize,
+    // Ready pool of a deterministic scheduler; when set, every submission goes here.
+    det: Option<DetQueue<'a>>,
+    // Debug toggle set by `set_debug_log`; idle workers try the lock and skip logging if it is busy.
+    stats_lock: SpinLock,
+    stats_log: core::cell::UnsafeCell<Option<StatsLog>>,
+}
+
+// Writes a snapshot if a debug log is set and its interval has passed (or `force`).
+fn log_stats(sh: &Shared, force: bool) {
+    if !sh.stats_lock.try_lock() { return; }
+    if let Some(l) = unsafe { &mut *sh.stats_log.get() } {
+        let now = prm_time::now();
+        if force || now - l.last >= l.interval_ns {
+            l.last = now;
+            for i in 0..sh.worker_count { let _ = unsafe { (*sh.workers.add(i)).stats.snapshot() }.log(i, &mut l.log); }
+            if force { let _ = l.log.flush(); }
+        }
+    }
+    sh.stats_lock.unlock();
+}
+
+struct WorkerCtx<'a> { alloc: Allocator<'a>, self_idx: usize, group: usize, workers: *mut Worker<'a>, worker_count: usize, shared: *const Shared<'a>, stack_size: usize, driver_ptr: *mut Driver<'a> }
+
+/// Where worker threads are pinned. Pinning is best effort: platforms without affinity support leave workers unpinned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkerPinning { None, Cores, NumaNode(u16) }
+
+#[derive(Clone, Copy, Debug)]
+pub struct SchedulerConfig {
+    pub worker_count: usize,
+    pub deque_capacity: usize,
+    pub stack_size: usize,
+    /// Number of worker groups `ThreadAffinity::Compute(n)` maps onto (clamped to `1..=worker_count`).
+    pub compute_groups: usize,
+    pub pinning: WorkerPinning,
+    /// Runs the scheduler deterministically: no worker threads, one ready pool drained on the calling
+    /// thread in an order drawn from this seed, and timers on a virtual clock. See `Scheduler::step`.
+    /// Nothing runs unless that thread drives it, so wait with `wait_helping` or `block_on`, never `TaskGroup::wait`.
+    pub seed: Option<u64>,
+}
+
+impl SchedulerConfig {
+    pub const fn new(worker_count: usize, deque_capacity: usize, stack_size: usize) -> Self {
+        Self { worker_count, deque_capacity, stack_size, compute_groups: 1, pinning: WorkerPinning::None, seed: None }
+    }
+    /// Single-threaded, reproducible configuration for tests and replays.
+    pub const fn deterministic(seed: u64, deque_capacity: usize, stack_size: usize) -> Self {
+        Self { worker_count: 1, deque_capacity, stack_size, compute_groups: 1, pinning: WorkerPinning::None, seed: Some(seed) }
+    }
+}
+
+// Workers are split into contiguous, near-equal ranges, one per compute group.
+fn group_range(g: usize, groups: usize, worker_count: usize) -> (usize, usize) { (g * worker_count / groups, (g + 1) * worker_count / groups) }
+
+// One (processor group, processor index) per logical core the workers may use, in topology order.
+fn pin_targets<'a>(alloc: Allocator<'a>, pinning: WorkerPinning) -> Result<(Vector<'a, (u16, u32)>, bool), MemoryError> {
+    let mut out = Vector::with_capacity(alloc, 0)?;
+    if pinning == WorkerPinning::None { return Ok((out, false)); }
+    let topo = prm_system::query_topology();
+    let mut push_mask = |group: u16, mask: usize| -> Result<(), MemoryError> {
+        for bit in 0..usize::BITS { if mask & (1usize << bit) != 0 { out.push((group, bit))?; } }
+        Ok(())
+    };
+    match pinning {
+        WorkerPinning::None => {}
+        WorkerPinning::Cores => { for g in &topo.groups { push_mask(g.group, g.mask)?; } }
+        WorkerPinning::NumaNode(id) => {
+            if let Some(node) = topo.numa_nodes.iter().find(|n| n.node_id == id) { for p in &node.processors { push_mask(p.group, p.mask)?; } }
+        }
+    }
+    Ok((out, topo.groups.len() > 1))
+}
+
+fn noop(_: *mut c_void) {}
+
+fn next_job(ctx: &WorkerCtx, shared: &Shared, j: &mut Job) -> bool {
+    let w: &Worker = unsafe { &*ctx.workers.add(ctx.self_idx) };
+    let g = &shared.groups[ctx.group];
+    let take = |q: &Overflow, j: &mut Job| if let Some(x) = q.dequeue() { *j = x; true } else { false };
+    if let Some(x) = take_resume(shared) { *j = x; return true; }
+    if w.high.steal(j) || w.high.pop_bottom(j) || take(&shared.overflow_high, j) || take(&g.high, j) { return true; }
+    if w.norm.steal(j) || w.norm.pop_bottom(j) || take(&shared.overflow, j) || take(&g.norm, j) { return true; }
+    for i in 0..ctx.worker_count {
+        if i == ctx.self_idx { continue; }
+        let other: &Worker = unsafe { &*ctx.workers.add(i) };
+        let ok = other.high.steal(j) || other.norm.steal(j);
+        w.stats.steal(ok);
+        if ok { return true; }
+    }
+    false
+}
+
+fn worker_main(arg: *mut c_void) {
     let ctx: &WorkerCtx = unsafe { &*(arg as *const WorkerCtx) };
+    let shared: &Shared = unsafe { &*ctx.shared };
+    CURRENT_WORKER.with(|c| c.set((ctx.shared as *const c_void, ctx.self_idx)));
+    // Without a pool every job runs on the worker's own stack, as when `acquire` fails below.
+    let pool = match FiberPool::new(ctx.alloc, ctx.stack_size, IDLE_FIBERS_PER_WORKER).and_then(|p| CapBox::new(ctx.alloc, p)) { Ok(b) => CapBox::into_raw(b).0, Err(_) => core::ptr::null_mut() };
+    CURRENT_POOL.with(|c| c.set(pool as *mut c_void));
     loop {
-        let should_stop = unsafe { (*ctx.stop).load(Ordering::Relaxed) };
+        let should_stop = shared.stop.load(Ordering::Relaxed);
         if should_stop { break; }
         let w: &Worker = unsafe { &*ctx.workers.add(ctx.self_idx) };
-        fn noop(_: *mut c_void) {}
-        let mut j: Job = Job { func: noop, arg: core::ptr::null_mut(), qos: 0 };
-        let got = if w.high.steal(&mut j) || w.high.pop_bottom(&mut j) { true } else if w.norm.steal(&mut j) || w.norm.pop_bottom(&mut j) { true } else {
-            let mut found = false;
-            for i in 0..ctx.worker_count { if i == ctx.self_idx { continue; } let other: &Worker = unsafe { &*ctx.workers.add(i) }; if other.high.steal(&mut j) || other.norm.steal(&mut j) { found = true; break; } }
-            found
-        };
-        if got {
-            println!("worker {} got job", ctx.self_idx);
-            let host = prm_threading::host_fiber();
-            struct FiberScope(*mut fiber::Fiber);
-            impl FiberScope { fn new(func: fn(*mut c_void), arg: *mut c_void, host: prm_threading::FiberHandle, stack: usize) -> Self { let f = fiber::Fiber::setup(func, arg, host, stack).unwrap(); let fb = Box::into_raw(Box::new(f)) as *mut fiber::Fiber; CURRENT_FIBER.with(|c| c.set(fb)); Self(fb) } fn run(&self) { unsafe { (*self.0).start_switch(); } } }
-            impl Drop for FiberScope { fn drop(&mut self) { CURRENT_FIBER.with(|c| c.set(core::ptr::null_mut())); unsafe { let _ = Box::from_raw(self.0); } } }
-            let scope = FiberScope::new(j.func, j.arg, host, ctx.stack_size);
-            scope.run();
-            println!("worker {} finished job", ctx.self_idx);
+        let mut j = Job::new(noop, core::ptr::null_mut());
+        if next_job(ctx, shared, &mut j) {
+            w.stats.job();
+            let f = if pool.is_null() { None } else { unsafe { (*pool).acquire() }.ok() };
+            match f {
+                Some(f) => {
+                    let finished = enter(f.as_fiber(), |ret| f.run(j.func, j.arg, ret));
+                    // A suspended job keeps the fiber until `resume_fiber` sees it finish.
+                    if finished { unsafe { (*pool).release(f); } } else { let _ = CapBox::into_raw(f); }
+                }
+                // No fiber to spare: run on the worker's own stack, where sync primitives block the thread.
+                None => (j.func)(j.arg),
+            }
         } else {
-            unsafe { (*ctx.driver_ptr).poll(); }
-            let timeout = unsafe { (*ctx.driver_ptr).next_timeout_ms() };
+            // Someone else is polling; check back shortly rather than sleeping through their timers.
+            let mut timeout = 1;
+            if shared.driver_lock.try_lock() {
+                unsafe { (*ctx.driver_ptr).poll(); timeout = (*ctx.driver_ptr).next_timeout_ms(); }
+                shared.driver_lock.unlock();
+            }
+            log_stats(shared, false);
+            let t0 = prm_time::now();
             let _ = w.parker.park(timeout);
+            w.stats.parked((prm_time::now() - t0).max(0) as u64);
         }
     }
+    CURRENT_POOL.with(|c| c.set(core::ptr::null_mut()));
+    if !pool.is_null() { drop(unsafe { CapBox::from_raw(ctx.alloc, pool) }); }
+    CURRENT_WORKER.with(|c| c.set((core::ptr::null(), 0)));
+    drop(unsafe { CapBox::from_raw(ctx.alloc, arg as *mut WorkerCtx) });
+}
+
+// A `Scheduler::spawn` closure with the allocator that has to free it.
+struct SpawnedFn<'a, F> { f: F, alloc: Allocator<'a> }
+
+impl<'a, F: FnOnce()> SpawnedFn<'a, F> {
+    // Frees the storage and hands back the closure.
+    fn take(arg: *mut c_void) -> F {
+        let p = arg as *mut Self;
+        let SpawnedFn { f, .. } = CapBox::into_inner(unsafe { CapBox::from_raw((*p).alloc, p) });
+        f
+    }
+    fn run(arg: *mut c_void) { Self::take(arg)() }
 }
 
-pub struct Scheduler<'a> { alloc: Allocator<'a>, workers: *mut Worker<'a>, workers_blk: MemoryBlock, worker_count: usize, stop: AtomicBool, driver_ptr: *mut Driver, driver_blk: MemoryBlock, stack_size: usize, handles: *mut ThreadHandle, handles_blk: MemoryBlock }
+pub struct Scheduler<'a> { alloc: Allocator<'a>, workers: *mut Worker<'a>, workers_blk: MemoryBlock, worker_count: usize, shared: CapBox<'a, Shared<'a>>, driver_ptr: *mut Driver<'a>, driver_blk: MemoryBlock, handles: *mut ThreadHandle, handles_blk: MemoryBlock, det_pool: *mut FiberPool<'a> }
 
 impl<'a> Scheduler<'a> {
+    /// Workers allocate from `alloc` concurrently (fibers, spawned closures, spilled jobs), so its
+    /// resource must be safe to use from several threads at once.
     pub fn start(alloc: Allocator<'a>, worker_count: usize, deque_capacity: usize, stack_size: usize) -> Result<Self, MemoryError> {
+        Self::start_with(alloc, SchedulerConfig::new(worker_count, deque_capacity, stack_size))
+    }
+
+    pub fn start_with(alloc: Allocator<'a>, cfg: SchedulerConfig) -> Result<Self, MemoryError> {
+        let SchedulerConfig { deque_capacity, stack_size, seed, .. } = cfg;
+        // A deterministic scheduler keeps one worker's bookkeeping but starts no thread for it.
+        let worker_count = if seed.is_some() { 1 } else { cfg.worker_count };
+        if worker_count == 0 { return Err(MemoryError::InvalidArgument); }
+        let group_count = cfg.compute_groups.clamp(1, worker_count);
         let ws_blk = alloc.alloc(core::mem::size_of::<Worker>() * worker_count, core::mem::align_of::<Worker>())?;
         let workers = ws_blk.ptr as *mut Worker;
         for i in 0..worker_count {
             unsafe {
-                workers.add(i).write(Worker { high: ChaseLevDeque::with_capacity(alloc, deque_capacity)?, norm: ChaseLevDeque::with_capacity(alloc, deque_capacity)?, parker: Parker::new().map_err(|_| MemoryError::Failed)? });
+                workers.add(i).write(Worker { high: ChaseLevDeque::with_capacity(alloc, deque_capacity)?, norm: ChaseLevDeque::with_capacity(alloc, deque_capacity)?, parker: Parker::new().map_err(|_| MemoryError::Failed)?, stats: WorkerCounters::default() });
             }
         }
+        let driver = Driver::with_clock(alloc, seed.map(|_| 0))?;
         let d_blk = alloc.alloc(core::mem::size_of::<Driver>(), core::mem::align_of::<Driver>())?;
         let d_ptr = d_blk.ptr.cast::<Driver>();
-        unsafe { core::ptr::write(d_ptr, Driver::new()); }
+        unsafe { core::ptr::write(d_ptr, driver); }
         let h_blk = alloc.alloc(core::mem::size_of::<ThreadHandle>() * worker_count, core::mem::align_of::<ThreadHandle>())?;
         let handles = h_blk.ptr.cast::<ThreadHandle>();
-        let s = Self { alloc, workers, workers_blk: ws_blk, worker_count, stop: AtomicBool::new(false), driver_ptr: d_ptr, driver_blk: d_blk, stack_size, handles, handles_blk: h_blk };
+        let overflow_cap = deque_capacity.saturating_mul(worker_count).next_power_of_two();
+        let mut groups = Vector::with_capacity(alloc, group_count)?;
+        for _ in 0..group_count { groups.push(GroupQueue { high: Overflow::with_capacity(alloc, overflow_cap)?, norm: Overflow::with_capacity(alloc, overflow_cap)? })?; }
+        let shared = CapBox::new(alloc, Shared {
+            stop: AtomicBool::new(false),
+            resume_lock: SpinLock::new(),
+            resumes: WaitList::new(),
+            resumes_queued: AtomicUsize::new(0),
+            overflow_high: Overflow::with_capacity(alloc, overflow_cap)?,
+            overflow: Overflow::with_capacity(alloc, overflow_cap)?,
+            groups,
+            main: Overflow::with_capacity(alloc, overflow_cap)?,
+            main_parker: Parker::new().map_err(|_| MemoryError::Failed)?,
+            main_thread: thread_tag(),
+            driver_lock: SpinLock::new(),
+            wake: AtomicUsize::new(0),
+            workers,
+            worker_count,
+            det: match seed { Some(seed) => Some(DetQueue::new(alloc, seed, overflow_cap)?), None => None },
+            stats_lock: SpinLock::new(),
+            stats_log: core::cell::UnsafeCell::new(None),
+        })?;
+        let (pins, multi_group) = pin_targets(alloc, cfg.pinning)?;
+        let det_pool = if seed.is_some() { CapBox::into_raw(CapBox::new(alloc, FiberPool::new(alloc, stack_size, IDLE_FIBERS_PER_WORKER)?)?).0 } else { core::ptr::null_mut() };
+        let s = Self { alloc, workers, workers_blk: ws_blk, worker_count, shared, driver_ptr: d_ptr, driver_blk: d_blk, handles, handles_blk: h_blk, det_pool };
         set_resume_cb(resume_fiber);
+        if s.is_deterministic() { return Ok(s); }
         for i in 0..worker_count {
-            let ctx = Box::new(WorkerCtx { self_idx: i, workers, worker_count, stop: &s.stop, stack_size, driver_ptr: s.driver_ptr });
-            let arg = Box::into_raw(ctx) as *mut c_void;
+            let group = (0..group_count).find(|&g| i < group_range(g, group_count, worker_count).1).unwrap();
+            let ctx = CapBox::new(alloc, WorkerCtx { alloc, self_idx: i, group, workers, worker_count, shared: &*s.shared, stack_size, driver_ptr: s.driver_ptr })?;
+            let arg = CapBox::into_raw(ctx).0 as *mut c_void;
             let h = match thread_create_with_stack(8 << 20, worker_main as fn(*mut c_void), arg) { Ok(h) => h, Err(_) => return Err(MemoryError::Failed) };
+            if !pins.is_empty() {
+                let (pg, bit) = pins[i % pins.len()];
+                let _ = if multi_group { thread_set_group_affinity(h, pg, 1u64 << bit) } else { thread_set_affinity_mask(h, 1u64 << bit) };
+            }
             unsafe { s.handles.add(i).write(h); }
         }
         Ok(s)
     }
     pub fn stop(&mut self) -> Result<(), ()> {
-        self.stop.store(true, Ordering::Relaxed);
+        self.shared.stop.store(true, Ordering::Relaxed);
         for i in 0..self.worker_count { unsafe { let w = &*self.workers.add(i); let _ = w.parker.unpark(); } }
         Ok(())
     }
     pub fn join(&mut self) {
-        for i in 0..self.worker_count { unsafe { let h = self.handles.add(i).read(); let _ = thread_join(h); } }
+        if self.is_deterministic() { return; }
+        // Joined handles are cleared so a second `join` (e.g. from `Drop`) does not reuse them.
+        for i in 0..self.worker_count {
+            unsafe {
+                let h = self.handles.add(i).read();
+                if h.0.is_null() { continue; }
+                let _ = thread_join(h);
+                self.handles.add(i).write(ThreadHandle(core::ptr::null_mut()));
+            }
+        }
     }
-    pub fn enqueue(&self, j: Job) -> Result<(), MemoryError> {
-        let idx = (j.qos as usize).min(self.worker_count - 1);
-        unsafe { (*self.workers.add(idx)).norm.push_bottom(j); let _ = (*self.workers.add(idx)).parker.unpark(); }
+    // `Main` jobs go to the pump queue and `Compute(n)` jobs to their group's queues. `Any` jobs go to
+    // the local deque when called on one of our workers, otherwise (or if the deque cannot grow) the
+    // overflow queue. Fails only when memory runs out; a job is never dropped silently.
+    fn submit(&self, j: Job, high: bool) -> Result<(), MemoryError> {
+        let high = high || j.qos >= QOS_HIGH;
+        let sh = &*self.shared;
+        // Affinity and priority are ignored: the seed alone decides the order.
+        if let Some(det) = &sh.det { return det.push(j); }
+        let (lo, hi) = match j.affinity {
+            ThreadAffinity::Main => {
+                sh.main.enqueue(j)?;
+                let _ = sh.main_parker.unpark();
+                return Ok(());
+            }
+            ThreadAffinity::Compute(n) => {
+                let g = n as usize % sh.groups.len();
+                let q = &sh.groups[g];
+                if high { q.high.enqueue(j)?; } else { q.norm.enqueue(j)?; }
+                group_range(g, sh.groups.len(), self.worker_count)
+            }
+            ThreadAffinity::Any => {
+                let me = CURRENT_WORKER.with(|c| c.get());
+                let local = if me.0 == sh as *const Shared as *const c_void {
+                    let w = unsafe { &*self.workers.add(me.1) };
+                    let ok = if high { w.high.push_bottom(j).is_ok() } else { w.norm.push_bottom(j).is_ok() };
+                    if ok { w.stats.depth((w.high.len() + w.norm.len()) as u64); }
+                    ok
+                } else { false };
+                if !local {
+                    if high { sh.overflow_high.enqueue(j)?; } else { sh.overflow.enqueue(j)?; }
+                }
+                (0, self.worker_count)
+            }
+        };
+        let idx = lo + sh.wake.fetch_add(1, Ordering::Relaxed) % (hi - lo);
+        unsafe { let _ = (*self.workers.add(idx)).parker.unpark(); }
         Ok(())
     }
-    pub fn enqueue_high(&self, j: Job) -> Result<(), MemoryError> {
-        let idx = (j.qos as usize).min(self.worker_count - 1);
-        unsafe { (*self.workers.add(idx)).high.push_bottom(j); let _ = (*self.workers.add(idx)).parker.unpark(); }
+    pub fn enqueue(&self, j: Job) -> Result<(), MemoryError> { self.submit(j, false) }
+    pub fn enqueue_high(&self, j: Job) -> Result<(), MemoryError> { self.submit(j, true) }
+
+    /// Runs `f` on a worker. The closure is moved into storage from the scheduler's allocator and
+    /// freed after it runs. Fails with `f`, not yet run, if that allocation or the enqueue fails.
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, f: F) -> Result<(), F> {
+        // Reserve the slot before moving `f` in, so a failed allocation can still hand `f` back.
+        let slot = match CapBox::new(self.alloc, core::mem::MaybeUninit::<SpawnedFn<'a, F>>::uninit()) { Ok(b) => b, Err(_) => return Err(f) };
+        let (p, _) = CapBox::into_raw(slot);
+        unsafe { (*p).write(SpawnedFn { f, alloc: self.alloc }); }
+        let arg = p as *mut c_void;
+        if self.enqueue(Job::new(SpawnedFn::<'a, F>::run, arg)).is_err() { return Err(SpawnedFn::<'a, F>::take(arg)); }
         Ok(())
     }
     pub fn worker_count(&self) -> usize { self.worker_count }
+    pub fn compute_groups(&self) -> usize { self.shared.groups.len() }
+
+    /// Per-worker counters. Each worker updates its own without synchronisation, so a snapshot taken
+    /// while jobs run is approximate. Jobs run by `wait_helping` on a worker count for that worker.
+    pub fn stats(&self) -> Result<SchedulerStats<'a>, MemoryError> {
+        let mut workers = Vector::with_capacity(self.alloc, self.worker_count)?;
+        for i in 0..self.worker_count { workers.push(unsafe { (*self.workers.add(i)).stats.snapshot() })?; }
+        Ok(SchedulerStats { workers })
+    }
+    pub fn reset_stats(&self) { for i in 0..self.worker_count { unsafe { (*self.workers.add(i)).stats.reset(); } } }
+
+    /// Debug toggle: with a logger set, idle workers write `stats` to it at `Debug` level every
+    /// `interval_ms`, and dropping the scheduler writes a final snapshot. `None` turns it off. Returns the previous logger.
+    pub fn set_debug_log(&self, log: Option<cap_log::Logger>, interval_ms: u32) -> Option<cap_log::Logger> {
+        let _g = ScopedLock::new(&self.shared.stats_lock);
+        let next = log.map(|log| StatsLog { log, interval_ns: interval_ms as i64 * 1_000_000, last: prm_time::now() });
+        unsafe { core::mem::replace(&mut *self.shared.stats_log.get(), next) }.map(|l| l.log)
+    }
+
+    fn count_job(&self) {
+        let me = CURRENT_WORKER.with(|c| c.get());
+        if me.0 == &*self.shared as *const Shared as *const c_void { unsafe { (*self.workers.add(me.1)).stats.job(); } }
+    }
+
+    // A runnable `Any` job for a thread that is waiting: its own deques first when it is one of our
+    // workers, then the overflow queues, then anything stealable. Group and main queues are left alone.
+    pub(crate) fn try_take(&self) -> Option<Job> {
+        let sh = &*self.shared;
+        let mut j = Job::new(noop, core::ptr::null_mut());
+        let me = CURRENT_WORKER.with(|c| c.get());
+        if me.0 == sh as *const Shared as *const c_void {
+            let w = unsafe { &*self.workers.add(me.1) };
+            if w.high.pop_bottom(&mut j) || w.norm.pop_bottom(&mut j) { return Some(j); }
+        }
+        if let Some(x) = take_resume(sh).or_else(|| sh.overflow_high.dequeue()).or_else(|| sh.overflow.dequeue()) { return Some(x); }
+        for i in 0..self.worker_count {
+            let w = unsafe { &*self.workers.add(i) };
+            if w.high.steal(&mut j) || w.norm.steal(&mut j) { return Some(j); }
+        }
+        None
+    }
+
+    /// Waits for `tg` while running other queued jobs on this thread, so it is safe to call from inside a job.
+    ///
+    /// On the thread that started the scheduler this also pumps `ThreadAffinity::Main` jobs, so a
+    /// group holding main-thread tasks completes without a separate `wait_pumping`.
+    pub fn wait_helping(&self, tg: &TaskGroup) -> WaitStatus {
+        if self.is_deterministic() {
+            while !tg.is_done() { if !self.det_progress() { panic!("deterministic scheduler deadlocked: TaskGroup never completes"); } }
+            return tg.status();
+        }
+        let on_main = thread_tag() == self.shared.main_thread;
+        while !tg.is_done() {
+            if on_main && self.pump_main() > 0 { continue; }
+            match self.try_take() { Some(j) => { self.count_job(); (j.func)(j.arg) } None => { tg.wait_timeout(1); } }
+        }
+        tg.status()
+    }
+
+    pub(crate) fn allocator(&self) -> Allocator<'a> { self.alloc }
+
+    /// Runs `f` with exclusive access to the I/O and timer driver.
+    ///
+    /// Callbacks registered here run during a worker's poll with the driver locked, so they must not
+    /// call `with_driver` themselves; waking a task or enqueueing a job is fine.
+    pub fn with_driver<R>(&self, f: impl FnOnce(&mut Driver<'a>) -> R) -> R {
+        let (r, rearm) = {
+            let _g = ScopedLock::new(&self.shared.driver_lock);
+            let d = unsafe { &mut *self.driver_ptr };
+            let r = f(d);
+            (r, d.take_rearm())
+        };
+        // Idle workers sleep until the previous earliest deadline; wake them to pick up a sooner one.
+        if rearm { for i in 0..self.worker_count { unsafe { let _ = (*self.workers.add(i)).parker.unpark(); } } }
+        r
+    }
+
+    pub fn is_deterministic(&self) -> bool { self.shared.det.is_some() }
+
+    /// Deterministic mode: runs one job, chosen by the loaded replay or else the seed, on the calling
+    /// thread. Returns false if nothing is runnable (or the scheduler is not deterministic).
+    ///
+    /// Jobs run on fibers so `sys_job` sync primitives can suspend them; without fiber support they run
+    /// inline and must not block.
+    pub fn step(&self) -> bool {
+        let Some(det) = &self.shared.det else { return false };
+        let Some(j) = det.pick().or_else(|| take_resume(&self.shared)) else { return false };
+        let prev_worker = CURRENT_WORKER.with(|c| c.replace((&*self.shared as *const Shared as *const c_void, 0)));
+        let prev_pool = CURRENT_POOL.with(|c| c.replace(self.det_pool as *mut c_void));
+        unsafe { (*self.workers).stats.job(); }
+        match unsafe { (*self.det_pool).acquire() } {
+            Ok(f) => {
+                let finished = enter(f.as_fiber(), |ret| f.run(j.func, j.arg, ret));
+                if finished { unsafe { (*self.det_pool).release(f); } } else { let _ = CapBox::into_raw(f); }
+            }
+            Err(_) => (j.func)(j.arg),
+        }
+        CURRENT_POOL.with(|c| c.set(prev_pool));
+        CURRENT_WORKER.with(|c| c.set(prev_worker));
+        true
+    }
+
+    /// Deterministic mode: steps until no job is runnable and no timer is pending, jumping the virtual
+    /// clock to each next timer. Returns how many jobs ran.
+    pub fn run_until_idle(&self) -> usize {
+        let mut n = 0;
+        loop {
+            while self.step() { n += 1; }
+            if !self.advance_clock() { return n; }
+        }
+    }
+
+    // One unit of deterministic progress; false only when nothing could ever become runnable.
+    pub(crate) fn det_progress(&self) -> bool { self.step() || self.advance_clock() }
+
+    // Fires the next virtual timer, or polls outstanding I/O (which stays nondeterministic).
+    fn advance_clock(&self) -> bool {
+        if !self.is_deterministic() { return false; }
+        let _g = ScopedLock::new(&self.shared.driver_lock);
+        let d = unsafe { &mut *self.driver_ptr };
+        if d.advance_virtual() { return true; }
+        if d.maps.is_empty() { return false; }
+        d.poll();
+        prm_threading::thread_yield();
+        true
+    }
+
+    /// Every decision taken so far (since the last `replay`); feed it to `replay` to rerun the same interleaving.
+    /// Copied into the scheduler's allocator; empty on a threaded scheduler.
+    pub fn schedule(&self) -> Result<Vector<'a, ScheduleStep>, MemoryError> {
+        match &self.shared.det { Some(d) => d.schedule(self.alloc), None => Vector::with_capacity(self.alloc, 0) }
+    }
+
+    /// Makes the next steps follow `steps` exactly, then continue from the seed. Start from the same
+    /// submissions (and seed) as the recorded run. `NotSupported` on a threaded scheduler.
+    pub fn replay(&self, steps: &[ScheduleStep]) -> Result<(), MemoryError> {
+        match &self.shared.det { Some(d) => d.load_replay(steps), None => Err(MemoryError::NotSupported) }
+    }
+
+    /// True once the run stopped matching the replayed schedule, e.g. because the code under test changed.
+    pub fn replay_diverged(&self) -> bool { self.shared.det.as_ref().is_some_and(|d| d.diverged()) }
+
+    /// Runs queued `ThreadAffinity::Main` jobs on the calling thread and returns how many ran.
+    ///
+    /// Call it from the one thread that owns main-thread work. These jobs run inline rather than on
+    /// a fiber, so `suspend_current` returns immediately inside them.
+    pub fn pump_main(&self) -> usize {
+        let mut n = 0;
+        while let Some(j) = self.shared.main.dequeue() { (j.func)(j.arg); n += 1; }
+        n
+    }
+
+    /// Pumps main-thread jobs until `tg` completes; use instead of `tg.wait()` when any task in the group has `Main` affinity.
+    pub fn wait_pumping(&self, tg: &TaskGroup) -> WaitStatus {
+        if self.is_deterministic() { return self.wait_helping(tg); }
+        loop {
+            self.pump_main();
+            if tg.is_done() { break; }
+            let _ = self.shared.main_parker.park(1);
+        }
+        tg.status()
+    }
 }
 
 impl<'a> Drop for Scheduler<'a> {
     fn drop(&mut self) {
         let _ = self.stop();
         self.join();
-        if !self.workers_blk.is_empty() { self.alloc.free(self.workers_blk, core::mem::align_of::<Worker>()); }
+        log_stats(&self.shared, true);
+        if !self.workers_blk.is_empty() {
+            for i in 0..self.worker_count { unsafe { core::ptr::drop_in_place(self.workers.add(i)); } }
+            self.alloc.free(self.workers_blk, core::mem::align_of::<Worker>());
+        }
         if !self.handles_blk.is_empty() { self.alloc.free(self.handles_blk, core::mem::align_of::<ThreadHandle>()); }
+        if !self.det_pool.is_null() { drop(unsafe { CapBox::from_raw(self.alloc, self.det_pool) }); }
         if !self.driver_blk.is_empty() { unsafe { core::ptr::drop_in_place(self.driver_ptr); } self.alloc.free(self.driver_blk, core::mem::align_of::<Driver>()); }
     }
 }
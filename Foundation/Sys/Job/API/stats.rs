@@ -0,0 +1,103 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+use cap_containers::Vector;
+use cap_log::{LogError, LogLevel, Logger};
+
+/// Counters of one worker since start (or the last `Scheduler::reset_stats`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    pub jobs_executed: u64,
+    /// Jobs taken from another worker's deques.
+    pub steals: u64,
+    /// Visits to another worker that found nothing to take.
+    pub failed_steals: u64,
+    /// Time spent parked while idle.
+    pub park_ns: u64,
+    /// Most jobs seen queued in this worker's own deques at once.
+    pub queue_high_water: u64,
+}
+
+impl WorkerStats {
+    fn add(&mut self, o: &WorkerStats) {
+        self.jobs_executed += o.jobs_executed;
+        self.steals += o.steals;
+        self.failed_steals += o.failed_steals;
+        self.park_ns += o.park_ns;
+        self.queue_high_water = self.queue_high_water.max(o.queue_high_water);
+    }
+
+    /// Writes the counters of worker `index` as one `Debug` line.
+    pub fn log(&self, index: usize, log: &mut Logger) -> Result<(), LogError> {
+        let mut line = Line { buf: [0; 192], len: 0 };
+        write!(line, "job worker {} jobs {} steals {} failed_steals {} park_ms {} queue_high_water {}", index, self.jobs_executed, self.steals, self.failed_steals, self.park_ns / 1_000_000, self.queue_high_water).map_err(|_| LogError::BufferTooSmall)?;
+        log.log(LogLevel::Debug, line.as_str())
+    }
+}
+
+// A log line formatted on the stack, so logging from idle workers never allocates.
+struct Line { buf: [u8; 192], len: usize }
+
+impl Line {
+    // Only whole `str`s are ever copied in.
+    fn as_str(&self) -> &str { unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) } }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() { return Err(core::fmt::Error); }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Snapshot returned by `Scheduler::stats`, one entry per worker.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SchedulerStats<'a> { pub workers: Vector<'a, WorkerStats> }
+
+impl<'a> SchedulerStats<'a> {
+    /// Sums over all workers; `queue_high_water` is the largest of them.
+    pub fn total(&self) -> WorkerStats {
+        let mut t = WorkerStats::default();
+        for w in self.workers.iter() { t.add(w); }
+        t
+    }
+
+    /// Writes one `Debug` line per worker.
+    pub fn log(&self, log: &mut Logger) -> Result<(), LogError> {
+        for (i, w) in self.workers.iter().enumerate() { w.log(i, log)?; }
+        Ok(())
+    }
+}
+
+// Live counters, written only by the owning worker thread and read by anyone taking a snapshot.
+#[derive(Default)]
+pub(crate) struct WorkerCounters { jobs: AtomicU64, steals: AtomicU64, failed_steals: AtomicU64, park_ns: AtomicU64, high_water: AtomicU64 }
+
+impl WorkerCounters {
+    // Single writer, so a load and store is enough and keeps the hot path free of locked instructions.
+    fn bump(c: &AtomicU64, n: u64) { c.store(c.load(Ordering::Relaxed) + n, Ordering::Relaxed); }
+    pub(crate) fn job(&self) { Self::bump(&self.jobs, 1); }
+    pub(crate) fn steal(&self, ok: bool) { Self::bump(if ok { &self.steals } else { &self.failed_steals }, 1); }
+    pub(crate) fn parked(&self, ns: u64) { Self::bump(&self.park_ns, ns); }
+    pub(crate) fn depth(&self, n: u64) { if n > self.high_water.load(Ordering::Relaxed) { self.high_water.store(n, Ordering::Relaxed); } }
+
+    pub(crate) fn snapshot(&self) -> WorkerStats {
+        WorkerStats {
+            jobs_executed: self.jobs.load(Ordering::Relaxed),
+            steals: self.steals.load(Ordering::Relaxed),
+            failed_steals: self.failed_steals.load(Ordering::Relaxed),
+            park_ns: self.park_ns.load(Ordering::Relaxed),
+            queue_high_water: self.high_water.load(Ordering::Relaxed),
+        }
+    }
+
+    // Racy against the owner's load-and-store; a count in flight may survive the reset.
+    pub(crate) fn reset(&self) {
+        for c in [&self.jobs, &self.steals, &self.failed_steals, &self.park_ns, &self.high_water] { c.store(0, Ordering::Relaxed); }
+    }
+}
+
+// The debug toggle: where idle workers write periodic snapshots.
+pub(crate) struct StatsLog { pub(crate) log: Logger, pub(crate) interval_ns: i64, pub(crate) last: i64 }
@@ -0,0 +1,189 @@
+use core::ffi::c_void;
+use cap_concurrency::fiber::Fiber;
+use cap_containers::Vector;
+use cap_memory::{Allocator, MemoryError};
+
+// Four levels of 64 one-millisecond slots: level `l` slots are 64^l ms wide, so the wheel spans
+// ~4.6 hours. Longer timers park in the top level and are re-placed each time they come round.
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 4;
+// Extra list for timers that are already due when added (zero delay).
+const DUE: usize = LEVELS * SLOTS;
+const LISTS: usize = DUE + 1;
+const NIL: u32 = u32::MAX;
+
+/// Handle returned when a timer is added. Cancelling a timer that already fired (or was cancelled) is a no-op.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimerId { index: u32, generation: u32 }
+
+/// What happens when a timer expires.
+#[derive(Clone, Copy, Debug)]
+pub enum TimerAction { Resume(*mut Fiber), Call(fn(*mut c_void), *mut c_void) }
+
+#[derive(Clone, Copy)]
+struct TimerNode { deadline: u64, period: u64, action: TimerAction, generation: u32, prev: u32, next: u32, list: u32 }
+
+/// Hierarchical timing wheel keyed by absolute millisecond deadlines.
+///
+/// Insert and cancel are O(1); `advance` costs one step per elapsed millisecond while timers are
+/// pending in the first level and skips ahead otherwise. Nodes live in one allocator-backed slab
+/// that grows on demand and is reused through a free list.
+pub struct TimerWheel<'a> { nodes: Vector<'a, TimerNode>, heads: [u32; LISTS], free: u32, now: u64, count: usize }
+
+impl<'a> TimerWheel<'a> {
+    pub fn new(alloc: Allocator<'a>, now_ms: u64, capacity: usize) -> Result<Self, MemoryError> {
+        Ok(Self { nodes: Vector::with_capacity(alloc, capacity.max(1))?, heads: [NIL; LISTS], free: NIL, now: now_ms, count: 0 })
+    }
+
+    pub fn len(&self) -> usize { self.count }
+    pub fn is_empty(&self) -> bool { self.count == 0 }
+    /// The last time passed to `advance`.
+    pub fn now(&self) -> u64 { self.now }
+
+    /// Adds a timer firing at `deadline` (ms, same clock as `advance`), then every `period` ms if non-zero.
+    pub fn insert(&mut self, deadline: u64, period: u64, action: TimerAction) -> Result<TimerId, MemoryError> {
+        let node = TimerNode { deadline, period, action, generation: 0, prev: NIL, next: NIL, list: NIL };
+        let i = if self.free != NIL {
+            let i = self.free;
+            self.free = self.nodes[i as usize].next;
+            let generation = self.nodes[i as usize].generation;
+            self.nodes[i as usize] = TimerNode { generation, ..node };
+            i
+        } else {
+            if self.nodes.len() >= NIL as usize { return Err(MemoryError::OutOfMemory); }
+            self.nodes.push(node)?;
+            (self.nodes.len() - 1) as u32
+        };
+        self.count += 1;
+        self.place(i);
+        Ok(TimerId { index: i, generation: self.nodes[i as usize].generation })
+    }
+
+    /// Removes a pending timer; false if it already fired, was cancelled, or never existed.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        match self.nodes.get(id.index as usize) {
+            Some(n) if n.generation == id.generation && n.list != NIL => {}
+            _ => return false,
+        }
+        self.unlink(id.index);
+        self.release(id.index);
+        true
+    }
+
+    /// Earliest pending deadline, found from the first occupied slot on each level.
+    pub fn next_deadline(&self) -> Option<u64> {
+        if self.count == 0 { return None; }
+        if self.heads[DUE] != NIL { return Some(self.now); }
+        let mut best: Option<u64> = None;
+        for l in 0..LEVELS {
+            let cur = (self.now >> (SLOT_BITS * l as u32)) as usize & (SLOTS - 1);
+            // Slots after the current one come round first; the current slot itself only holds timers a full turn away.
+            for k in 1..=SLOTS {
+                let list = l * SLOTS + ((cur + k) & (SLOTS - 1));
+                if self.heads[list] == NIL { continue; }
+                let m = self.list_min(list);
+                best = Some(best.map_or(m, |b| b.min(m)));
+                break;
+            }
+        }
+        best
+    }
+
+    /// Moves the wheel to `now_ms`, calling `fire` for every timer whose deadline has passed.
+    /// Periodic timers are re-armed after firing; missed periods are skipped, not replayed.
+    pub fn advance(&mut self, now_ms: u64, mut fire: impl FnMut(TimerAction)) {
+        self.expire(DUE, &mut fire);
+        while self.now < now_ms {
+            if self.count == 0 { self.now = now_ms; break; }
+            if self.level_empty(0) {
+                // Nothing can fire before the next first-level wrap, where the cascade happens.
+                let skip_to = self.now | (SLOTS as u64 - 1);
+                if skip_to > self.now { self.now = skip_to.min(now_ms); continue; }
+            }
+            self.now += 1;
+            for l in (1..LEVELS).rev() {
+                if self.now & ((1u64 << (SLOT_BITS * l as u32)) - 1) != 0 { continue; }
+                let list = l * SLOTS + ((self.now >> (SLOT_BITS * l as u32)) as usize & (SLOTS - 1));
+                let mut i = self.detach(list);
+                while i != NIL { let next = self.nodes[i as usize].next; self.place(i); i = next; }
+            }
+            self.expire(self.now as usize & (SLOTS - 1), &mut fire);
+            self.expire(DUE, &mut fire);
+        }
+    }
+
+    fn expire(&mut self, list: usize, fire: &mut impl FnMut(TimerAction)) {
+        let mut i = self.detach(list);
+        while i != NIL {
+            let next = self.nodes[i as usize].next;
+            let n = self.nodes[i as usize];
+            if n.deadline > self.now {
+                // A top-level timer beyond the wheel's span: not due yet, go round again.
+                self.place(i);
+            } else {
+                fire(n.action);
+                if n.period > 0 {
+                    let d = n.deadline + n.period;
+                    self.nodes[i as usize].deadline = if d > self.now { d } else { self.now + n.period };
+                    self.place(i);
+                } else {
+                    self.release(i);
+                }
+            }
+            i = next;
+        }
+    }
+
+    fn place(&mut self, i: u32) {
+        let d = self.nodes[i as usize].deadline;
+        let list = if d <= self.now { DUE } else {
+            let delta = d - self.now;
+            let l = (0..LEVELS).find(|&l| delta < 1u64 << (SLOT_BITS * (l as u32 + 1))).unwrap_or(LEVELS - 1);
+            let span = 1u64 << (SLOT_BITS * LEVELS as u32);
+            let d = if delta < span { d } else { self.now + span - 1 };
+            l * SLOTS + ((d >> (SLOT_BITS * l as u32)) as usize & (SLOTS - 1))
+        };
+        let head = self.heads[list];
+        let n = &mut self.nodes[i as usize];
+        n.prev = NIL;
+        n.next = head;
+        n.list = list as u32;
+        if head != NIL { self.nodes[head as usize].prev = i; }
+        self.heads[list] = i;
+    }
+
+    fn unlink(&mut self, i: u32) {
+        let TimerNode { prev, next, list, .. } = self.nodes[i as usize];
+        if prev != NIL { self.nodes[prev as usize].next = next; } else { self.heads[list as usize] = next; }
+        if next != NIL { self.nodes[next as usize].prev = prev; }
+        self.nodes[i as usize].list = NIL;
+    }
+
+    // Empties `list` and returns its first node; `next` links stay valid for the walk.
+    fn detach(&mut self, list: usize) -> u32 {
+        let h = self.heads[list];
+        self.heads[list] = NIL;
+        let mut i = h;
+        while i != NIL { self.nodes[i as usize].list = NIL; i = self.nodes[i as usize].next; }
+        h
+    }
+
+    fn release(&mut self, i: u32) {
+        let n = &mut self.nodes[i as usize];
+        n.generation = n.generation.wrapping_add(1);
+        n.list = NIL;
+        n.next = self.free;
+        self.free = i;
+        self.count -= 1;
+    }
+
+    fn level_empty(&self, l: usize) -> bool { self.heads[l * SLOTS..(l + 1) * SLOTS].iter().all(|&h| h == NIL) }
+
+    fn list_min(&self, list: usize) -> u64 {
+        let mut m = u64::MAX;
+        let mut i = self.heads[list];
+        while i != NIL { m = m.min(self.nodes[i as usize].deadline); i = self.nodes[i as usize].next; }
+        m
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Instant;
+use std::hint::black_box;
+use core::ffi::c_void;
+use cap_memory::*;
+use cap_concurrency::{Fiber, FiberPool, PooledFiber};
+use sys_job::{Scheduler, Job, TaskGroup};
+
+const STACK: usize = 64 << 10;
+
+fn job_nop(arg: *mut c_void) { black_box(arg); }
+
+fn job_done(arg: *mut c_void) {
+    let tg: &TaskGroup = unsafe { &*(arg as *const TaskGroup) };
+    tg.task_done();
+}
+
+// Parks once by switching back to whoever ran it, like `suspend_current` does.
+fn job_yield(arg: *mut c_void) {
+    let pf: &PooledFiber = unsafe { &*(arg as *const PooledFiber) };
+    prm_threading::switch_to_fiber(pf.return_to());
+}
+
+fn ns_per(t: Instant, n: usize) -> f64 { t.elapsed().as_nanos() as f64 / n as f64 }
+
+fn main() {
+    let mut sys = SystemMemoryResource;
+    let a = Allocator::new(&mut sys);
+    prm_threading::ensure_thread_is_fiber();
+    let host = prm_threading::host_fiber();
+
+    // Old path: a fresh OS fiber and stack per job.
+    let n_fresh = 10_000usize;
+    let t0 = Instant::now();
+    for _ in 0..n_fresh {
+        let f = Fiber::setup(job_nop, core::ptr::null_mut(), host, STACK).unwrap();
+        f.start_switch();
+    }
+    let fresh = ns_per(t0, n_fresh);
+
+    // Pooled: after the first job every acquire is a pop from the idle list.
+    let n = 1_000_000usize;
+    let mut pool = FiberPool::new(a, STACK, 16).unwrap();
+    let t1 = Instant::now();
+    for _ in 0..n {
+        let f = pool.acquire().unwrap();
+        assert!(f.run(job_nop, core::ptr::null_mut(), host));
+        pool.release(f);
+    }
+    let pooled = ns_per(t1, n);
+
+    // A job that suspends keeps its fiber; the next job gets another one from the pool.
+    let n_susp = 100_000usize;
+    let t2 = Instant::now();
+    for _ in 0..n_susp {
+        let f = pool.acquire().unwrap();
+        let arg = &*f as *const PooledFiber as *mut c_void;
+        assert!(!f.run(job_yield, arg, host));
+        let g = pool.acquire().unwrap();
+        assert!(g.run(job_nop, core::ptr::null_mut(), host));
+        pool.release(g);
+        assert!(f.resume(host));
+        pool.release(f);
+    }
+    let susp = ns_per(t2, n_susp);
+
+    // End to end: enqueue from a non-worker thread, run on the pool, signal a TaskGroup.
+    let n_sched = 200_000usize;
+    let sched = Scheduler::start(a, 3, 1024, STACK).unwrap();
+    let tg = TaskGroup::new();
+    let tg_ptr = &tg as *const TaskGroup as *mut c_void;
+    tg.add_tasks(n_sched);
+    let t3 = Instant::now();
+    for _ in 0..n_sched { sched.enqueue(Job::new(job_done, tg_ptr)).unwrap(); }
+    let submit = ns_per(t3, n_sched);
+    tg.wait();
+    let e2e = ns_per(t3, n_sched);
+    drop(sched);
+
+    println!("fresh fiber per job : {:.1} ns/job ({} jobs)", fresh, n_fresh);
+    println!("pooled fiber        : {:.1} ns/job ({} jobs, {} fibers created)", pooled, n, pool.created_count());
+    println!("suspend + resume    : {:.1} ns/job ({} jobs)", susp, n_susp);
+    println!("scheduler enqueue   : {:.1} ns/job", submit);
+    println!("scheduler end-to-end: {:.1} ns/job ({} jobs, 3 workers)", e2e, n_sched);
+    if pooled > 0.0 { println!("pooled speedup: {:.1}x", fresh / pooled); }
+}
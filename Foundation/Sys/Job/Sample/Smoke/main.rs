@@ -1,9 +1,16 @@
 use cap_memory::*;
-use sys_job::{Scheduler, Job, TaskGroup};
+use prm_file::FileError;
+use sys_job::{Scheduler, SchedulerConfig, WorkerPinning, Job, TaskGroup, ThreadAffinity, AsyncFile, Either, join_all, select, sleep};
+use sys_job::{TimerAction, TimerWheel};
+use sys_job::{CancelToken, WaitStatus};
+use sys_job::{FiberChannel, FiberCondvar, FiberLatch, FiberMutex, FiberRwLock, FiberSemaphore, current_fiber};
 use cap_concurrency::FiberStackPool;
 use core::ffi::c_void;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+fn on_tick(_: *mut c_void) { TICKS.fetch_add(1, Ordering::Relaxed); }
+
 static SUM: AtomicUsize = AtomicUsize::new(0);
 
 fn job_add(arg: *mut c_void) {
@@ -12,9 +19,24 @@ fn job_add(arg: *mut c_void) {
     tg.task_done();
 }
 
+static ON_MAIN: AtomicUsize = AtomicUsize::new(0);
+static COMPUTE: AtomicUsize = AtomicUsize::new(0);
+std::thread_local! { static IS_MAIN: core::cell::Cell<bool> = const { core::cell::Cell::new(false) }; }
+
+fn job_main(arg: *mut c_void) {
+    if IS_MAIN.with(|c| c.get()) { ON_MAIN.fetch_add(1, Ordering::Relaxed); }
+    job_add(arg);
+}
+
+fn job_compute(arg: *mut c_void) {
+    if !IS_MAIN.with(|c| c.get()) { COMPUTE.fetch_add(1, Ordering::Relaxed); }
+    job_add(arg);
+}
+
 fn main() {
-    let mut frame = FrameAllocatorResource::new(8 << 20);
-    let a = Allocator::new(&mut frame);
+    // Workers allocate from it concurrently (fibers, spawned closures), so it has to be thread-safe.
+    let mut sys = SystemMemoryResource;
+    let a = Allocator::new(&mut sys);
     let pool = FiberStackPool::new(64 << 10);
     println!("start");
     let sched = Scheduler::start(a, 3, 16, pool.stack_size()).unwrap();
@@ -29,4 +51,203 @@ fn main() {
     println!("wait");
     tg.wait();
     println!("sum {}", SUM.load(Ordering::Relaxed));
+    let st = sched.stats().unwrap();
+    assert_eq!(st.workers.len(), 3);
+    assert!(st.total().jobs_executed >= n as u64);
+    sched.reset_stats();
+    assert_eq!(sched.stats().unwrap().total().jobs_executed, 0);
+    // Final snapshot goes to the log when the scheduler drops.
+    if let Ok(log) = cap_log::Logger::create("job_stats.log", cap_log::LogLevel::Debug) { assert!(sched.set_debug_log(Some(log), 100).is_none()); }
+
+    drop(sched);
+
+    IS_MAIN.with(|c| c.set(true));
+    let mut cfg = SchedulerConfig::new(4, 16, pool.stack_size());
+    cfg.compute_groups = 2;
+    cfg.pinning = WorkerPinning::Cores;
+    let sched = Scheduler::start_with(a, cfg).unwrap();
+    let tg = TaskGroup::new();
+    let ptr_tg = &tg as *const _ as *mut c_void;
+    tg.add_tasks(8);
+    for i in 0..4u16 {
+        let _ = sched.enqueue(Job::new(job_main, ptr_tg).with_affinity(ThreadAffinity::Main));
+        let _ = sched.enqueue(Job::new(job_compute, ptr_tg).with_affinity(ThreadAffinity::Compute(i)));
+    }
+    sched.wait_pumping(&tg);
+    println!("affinity main {} compute {} groups {}", ON_MAIN.load(Ordering::Relaxed), COMPUTE.load(Ordering::Relaxed), sched.compute_groups());
+
+    let mut data: Vec<u32> = (0..10_000).collect();
+    sched.parallel_for_mut(&mut data, 256, |c| for v in c.iter_mut() { *v *= 2; });
+    let total = sched.parallel_reduce(&data, 256, 0u64, |c| c.iter().map(|&v| v as u64).sum(), |a, b| a + b);
+    let hits = AtomicUsize::new(0);
+    sched.parallel_for(&data, 1000, |c| { hits.fetch_add(c.len(), Ordering::Relaxed); });
+    let mut halves = [0u64; 2];
+    sched.scope(|s| {
+        let (lo, hi) = data.split_at(data.len() / 2);
+        let (h0, h1) = halves.split_at_mut(1);
+        assert!(s.spawn(move || h0[0] = lo.iter().map(|&v| v as u64).sum()).is_ok());
+        assert!(s.spawn(move || h1[0] = hi.iter().map(|&v| v as u64).sum()).is_ok());
+    });
+    println!("parallel reduce {} for {} scope {}", total, hits.load(Ordering::Relaxed), halves[0] + halves[1]);
+
+    let tasks: Vec<_> = (0..8u32).map(|i| sched.spawn_async(async move { sleep(i).await; i * i }).expect("spawn_async")).collect();
+    let squares = sched.block_on(join_all(a, tasks).expect("join_all"));
+    let raced = sched.block_on(select(sleep(200), async { sleep(1).await; 7 }));
+    println!("async join {:?} select {:?}", squares.as_slice(), raced);
+    assert_eq!(squares.as_slice(), &[0, 1, 4, 9, 16, 25, 36, 49]);
+    assert_eq!(raced, Either::Right(7));
+
+    let path = std::env::temp_dir().join("sys_job_smoke.bin");
+    std::fs::write(&path, b"async read via driver").unwrap();
+    // Overlapped reads need IOCP; other targets report Unsupported.
+    match AsyncFile::open(&sched, path.to_str().unwrap()) {
+        Ok(file) => {
+            let bytes = sched.block_on(file.read_all()).expect("async read");
+            let tail = sched.block_on(file.read_at(6, 64)).expect("async read_at");
+            println!("async file {:?} tail {}", String::from_utf8_lossy(&bytes), tail.len());
+        }
+        Err(FileError::Unsupported) => println!("async file unsupported"),
+        Err(e) => panic!("async open: {:?}", e),
+    }
+
+    let spawned = std::sync::Arc::new((TaskGroup::new(), AtomicUsize::new(0)));
+    for i in 0..64usize {
+        let st = spawned.clone();
+        st.0.add_tasks(1);
+        assert!(sched.spawn(move || { st.1.fetch_add(i, Ordering::Relaxed); st.0.task_done(); }).is_ok());
+    }
+    sched.wait_helping(&spawned.0);
+    println!("spawn closures {}", spawned.1.load(Ordering::Relaxed));
+
+    // Fiber sync stress: every primitive under many concurrently suspended jobs.
+    let sem = FiberSemaphore::new(3);
+    let (active, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let rw = FiberRwLock::new();
+    let shared_val = AtomicUsize::new(0);
+    let torn = AtomicUsize::new(0);
+    let (m, cv, ready) = (FiberMutex::new(), FiberCondvar::new(), AtomicUsize::new(0));
+    let latch = FiberLatch::new(200);
+    let chan: FiberChannel<usize> = FiberChannel::with_capacity(a, 4).unwrap();
+    let producers = FiberLatch::new(4);
+    let received = AtomicUsize::new(0);
+    sched.scope(|s| {
+        for i in 0..200usize {
+            let (sem, active, peak, rw, shared_val, torn, latch) = (&sem, &active, &peak, &rw, &shared_val, &torn, &latch);
+            assert!(s.spawn(move || {
+                sem.acquire();
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                for _ in 0..100 { core::hint::spin_loop(); }
+                active.fetch_sub(1, Ordering::SeqCst);
+                sem.release();
+                if i % 4 == 0 {
+                    rw.write();
+                    // Split read-modify-write: only safe because writers are exclusive.
+                    let v = shared_val.load(Ordering::Relaxed);
+                    for _ in 0..50 { core::hint::spin_loop(); }
+                    shared_val.store(v + 1, Ordering::Relaxed);
+                    rw.write_unlock();
+                } else {
+                    rw.read();
+                    let v = shared_val.load(Ordering::Relaxed);
+                    for _ in 0..50 { core::hint::spin_loop(); }
+                    if shared_val.load(Ordering::Relaxed) != v { torn.fetch_add(1, Ordering::Relaxed); }
+                    rw.read_unlock();
+                }
+                latch.count_down();
+            }).is_ok());
+        }
+        for _ in 0..8 {
+            let (m, cv, ready) = (&m, &cv, &ready);
+            assert!(s.spawn(move || {
+                m.lock(current_fiber());
+                while ready.load(Ordering::Relaxed) == 0 { cv.wait(m); }
+                ready.fetch_add(1, Ordering::Relaxed);
+                m.unlock();
+            }).is_ok());
+        }
+        {
+            let (m, cv, ready, latch) = (&m, &cv, &ready, &latch);
+            assert!(s.spawn(move || {
+                latch.wait();
+                m.lock(current_fiber());
+                ready.store(1, Ordering::Relaxed);
+                m.unlock();
+                cv.notify_all();
+            }).is_ok());
+        }
+        for p in 0..4usize {
+            let (chan, producers) = (&chan, &producers);
+            assert!(s.spawn(move || {
+                for k in 0..250 { chan.send(p * 250 + k + 1).unwrap(); }
+                producers.done();
+                if producers.count() == 0 { chan.close(); }
+            }).is_ok());
+        }
+        for _ in 0..4 {
+            let (chan, received) = (&chan, &received);
+            assert!(s.spawn(move || { while let Some(v) = chan.recv() { received.fetch_add(v, Ordering::Relaxed); } }).is_ok());
+        }
+    });
+    assert!(peak.load(Ordering::SeqCst) <= 3);
+    assert_eq!(shared_val.load(Ordering::Relaxed), 50);
+    assert_eq!(torn.load(Ordering::Relaxed), 0);
+    assert_eq!(ready.load(Ordering::Relaxed), 9);
+    assert_eq!(received.load(Ordering::Relaxed), 1000 * 1001 / 2);
+    assert!(latch.reset(1) && !latch.reset(2));
+    println!("fiber sync peak {} writes {} condvar {} channel {}", peak.load(Ordering::SeqCst), shared_val.load(Ordering::Relaxed), ready.load(Ordering::Relaxed), received.load(Ordering::Relaxed));
+
+    // Timer wheel on a synthetic clock: thousands of timers across every level, cancellation and periodic re-arming.
+    let mut wheel = TimerWheel::new(a, 1000, 16).unwrap();
+    let mut ids = Vec::new();
+    for i in 0..5000u64 { ids.push(wheel.insert(1000 + (i * 7919) % 300_000, 0, TimerAction::Call(on_tick, i as *mut c_void)).unwrap()); }
+    let far = wheel.insert(1000 + 40_000_000, 0, TimerAction::Call(on_tick, core::ptr::null_mut())).unwrap();
+    let cancelled = ids.iter().step_by(2).filter(|&&id| wheel.cancel(id)).count();
+    assert_eq!(cancelled, 2500);
+    assert!(!wheel.cancel(ids[0]));
+    let periodic = wheel.insert(1010, 10, TimerAction::Call(on_tick, core::ptr::null_mut())).unwrap();
+    assert_eq!(wheel.next_deadline(), Some(1010));
+    let mut now = 1000;
+    let (mut fired, mut late) = (0usize, 0u64);
+    while now < 1000 + 300_000 {
+        now += 37;
+        wheel.advance(now, |act| if let TimerAction::Call(_, ctx) = act {
+            if ctx.is_null() { return; }
+            let i = ctx as u64;
+            assert!(i % 2 == 1, "cancelled timer fired");
+            late = late.max(now - (1000 + (i * 7919) % 300_000));
+            fired += 1;
+        });
+        if let Some(d) = wheel.next_deadline() { assert!(d > now); }
+    }
+    assert_eq!(fired, 2500);
+    assert!(late < 37);
+    assert_eq!(wheel.len(), 2);
+    assert!(wheel.cancel(periodic) && wheel.cancel(far) && wheel.is_empty());
+    assert_eq!(wheel.next_deadline(), None);
+
+    // Periodic timer through the scheduler's driver.
+    let id = sched.with_driver(|d| d.add_periodic(on_tick, core::ptr::null_mut(), 5)).unwrap();
+    sched.block_on(sleep(60));
+    assert!(sched.with_driver(|d| d.cancel(id)));
+    let ticks = TICKS.load(Ordering::Relaxed);
+    assert!(ticks >= 5, "periodic timer ticked {} times", ticks);
+    println!("timers fired {} max late {}ms periodic {}", fired, late, ticks);
+
+    // Cancelling a token wakes fibers blocked in cancellable waits without handing them anything.
+    let gate = FiberSemaphore::new(0);
+    let stop = CancelToken::new(a).unwrap();
+    let latch = FiberLatch::new(1);
+    let outcomes = AtomicUsize::new(0);
+    sched.scope(|s| {
+        assert!(s.spawn(|| { if gate.acquire_or_cancel(&stop.child().unwrap()) == WaitStatus::Cancelled { outcomes.fetch_add(1, Ordering::Relaxed); } }).is_ok());
+        assert!(s.spawn(|| { if latch.wait_or_cancel(&stop) == WaitStatus::Cancelled { outcomes.fetch_add(10, Ordering::Relaxed); } }).is_ok());
+        assert!(s.spawn(|| { stop.wait(); outcomes.fetch_add(100, Ordering::Relaxed); }).is_ok());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        stop.cancel();
+    });
+    assert_eq!(outcomes.load(Ordering::Relaxed), 111);
+    assert_eq!(gate.available(), 0);
+    assert_eq!(gate.acquire_or_cancel(&stop), WaitStatus::Cancelled);
+    println!("cancel outcomes {}", outcomes.load(Ordering::Relaxed));
 }
@@ -1,3 +1,4 @@
+use cap_containers::CapBox;
 use cap_memory::{Allocator, MemoryBlock, MemoryError};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -34,7 +35,7 @@ impl<'a> ResourceManager<'a> {
     pub fn create_buffer(&mut self, size: usize, align: usize) -> Result<ResHandle<'a>, MemoryError> {
         if self.len >= self.cap { return Err(MemoryError::Failed); }
         let vb = VersionedBuffer::new(self.alloc, size, align)?;
-        let ptr = Box::into_raw(Box::new(vb));
+        let (ptr, _) = CapBox::into_raw(CapBox::new(self.alloc, vb)?);
         let h = ResHandle { buf: ptr };
         unsafe { self.entries.add(self.len).write(h); }
         self.len += 1;
@@ -48,7 +49,7 @@ impl<'a> Drop for ResourceManager<'a> {
         unsafe {
             for i in 0..self.len {
                 let h = self.entries.add(i).read();
-                if !h.buf.is_null() { let _ = Box::from_raw(h.buf); }
+                if !h.buf.is_null() { drop(CapBox::from_raw(self.alloc, h.buf)); }
             }
             if !self.blk.is_empty() { let _ = self.alloc.free(self.blk, core::mem::align_of::<ResHandle>()); }
         }
@@ -2,7 +2,9 @@ pub mod task;
 pub mod task_graph;
 pub mod runtime;
 pub mod pcsc;
+pub mod profile;
 pub use task::*;
 pub use task_graph::*;
 pub use runtime::*;
 pub use pcsc::*;
+pub use profile::*;
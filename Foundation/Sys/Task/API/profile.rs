@@ -0,0 +1,127 @@
+use core::fmt::Write;
+use cap_containers::{CapString, Vector};
+use cap_memory::{Allocator, MemoryError};
+use prm_file::{FileError, FileOpenMode, FileShareMode};
+use prm_time::{Duration, TimePoint};
+use crate::task::TaskHandle;
+use crate::task_graph::TaskGraph;
+
+/// `TaskSample::worker` for tasks run off the scheduler's worker threads (e.g. `dispatch_inline`).
+pub const NO_WORKER: u32 = u32::MAX;
+
+/// When and where one task ran during the last profiled dispatch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaskSample { pub begin: TimePoint, pub end: TimePoint, pub worker: u32, pub ran: bool }
+
+impl TaskSample {
+    pub fn duration(&self) -> Duration { if self.ran { self.end - self.begin } else { 0 } }
+}
+
+/// Result of `TaskGraph::critical_path`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CriticalPath<'a> {
+    /// Tasks from the first root to the last sink of the chain.
+    pub tasks: Vector<'a, TaskHandle>,
+    /// Sum of the chain's task durations: the frame time with unlimited workers.
+    pub total_ns: Duration,
+    /// Wall time from the first task start to the last task end.
+    pub span_ns: Duration,
+}
+
+// A task's name escaped for a quoted DOT or JSON string, or `task<id>` when it has none.
+pub(crate) struct Label<'s> { pub name: &'s str, pub id: usize }
+
+impl core::fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.name.is_empty() { return write!(f, "task{}", self.id); }
+        for c in self.name.chars() {
+            if c == '\\' || c == '"' { f.write_char('\\')?; }
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects profiled dispatches as Chrome Trace Event JSON (`chrome://tracing`, Perfetto).
+///
+/// Call `record_frame` after each frame's `TaskGroup::wait`, then `write` once the capture window ends.
+/// Each worker is one trace thread; tasks on the critical path carry `"critical": true` in their args.
+pub struct TraceCapture<'a> { events: CapString<'a>, alloc: Allocator<'a>, frames: usize, threads: u64, external: bool }
+
+impl<'a> TraceCapture<'a> {
+    /// Event text is kept in `alloc` until the capture is dropped.
+    pub fn new(alloc: Allocator<'a>) -> Result<Self, MemoryError> {
+        Ok(Self { events: CapString::with_capacity(alloc, 0)?, alloc, frames: 0, threads: 0, external: false })
+    }
+    pub fn frames(&self) -> usize { self.frames }
+
+    /// Appends the graph's last dispatch as frame `frame`. Does nothing if profiling is off.
+    pub fn record_frame(&mut self, g: &TaskGraph, frame: u64) -> Result<(), MemoryError> {
+        let samples = g.samples();
+        if samples.is_empty() { return Ok(()); }
+        let critical = g.critical_path();
+        let on_path = |h: &TaskHandle| critical.as_ref().is_some_and(|c| c.tasks.contains(h));
+        for (i, s) in samples.iter().enumerate() {
+            if !s.ran { continue; }
+            let h = TaskHandle { id: i + 1 };
+            let name = Label { name: g.task_name(h), id: h.id };
+            let tid = self.tid(s.worker);
+            let sep = if self.events.is_empty() { "" } else { ",\n" };
+            write!(self.events, "{}{{\"name\":\"{}\",\"cat\":\"task\",\"ph\":\"X\",\"pid\":0,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3},\"args\":{{\"frame\":{},\"task\":{},\"critical\":{}}}}}",
+                sep, name, tid, s.begin as f64 / 1000.0, s.duration() as f64 / 1000.0, frame, h.id, on_path(&h)).map_err(|_| MemoryError::OutOfMemory)?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Trace thread for a worker; tid 0 is reserved for the dispatching thread.
+    fn tid(&mut self, worker: u32) -> u32 {
+        if worker == NO_WORKER { self.external = true; return 0; }
+        if worker < 64 { self.threads |= 1 << worker; }
+        worker + 1
+    }
+
+    pub fn to_json(&self) -> Result<CapString<'a>, MemoryError> {
+        let mut out = CapString::with_capacity(self.alloc, self.events.len() + 128)?;
+        // `CapString` only fails a write when it cannot grow.
+        self.write_json(&mut out).map_err(|_| MemoryError::OutOfMemory)?;
+        Ok(out)
+    }
+
+    fn write_json(&self, out: &mut impl Write) -> core::fmt::Result {
+        out.write_str("{\"displayTimeUnit\":\"ns\",\"traceEvents\":[\n")?;
+        let mut sep = "";
+        if self.external { out.write_str("{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":0,\"args\":{\"name\":\"dispatch\"}}")?; sep = ",\n"; }
+        for w in 0..64 {
+            if self.threads & (1 << w) != 0 {
+                write!(out, "{}{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":{},\"args\":{{\"name\":\"worker {}\"}}}}", sep, w + 1, w)?;
+                sep = ",\n";
+            }
+        }
+        if !self.events.is_empty() { out.write_str(sep)?; out.write_str(&self.events)?; }
+        out.write_str("\n]}\n")
+    }
+
+    /// Writes `to_json()` to `path`, replacing any existing file.
+    pub fn write(&self, path: &str) -> Result<(), FileError> {
+        let json = self.to_json().map_err(|_| FileError::Failed)?;
+        let h = prm_file::open(path, FileOpenMode::CreateNew, FileShareMode::Read)
+            .or_else(|_| prm_file::open(path, FileOpenMode::Truncate, FileShareMode::Read))?;
+        let mut rest = json.as_bytes();
+        let r = loop {
+            if rest.is_empty() { break Ok(()); }
+            match prm_file::write(h, rest) { Ok(0) => break Err(FileError::Failed), Ok(n) => rest = &rest[n..], Err(e) => break Err(e) }
+        };
+        let _ = prm_file::close(h);
+        r
+    }
+}
+
+impl<'a> TaskGraph<'a> {
+    /// Writes the last profiled dispatch as a single-frame Chrome trace.
+    pub fn write_chrome_trace(&self, path: &str, frame: u64) -> Result<(), FileError> {
+        let mut cap = TraceCapture::new(self.allocator()).map_err(|_| FileError::Failed)?;
+        cap.record_frame(self, frame).map_err(|_| FileError::Failed)?;
+        cap.write(path)
+    }
+}
@@ -3,12 +3,11 @@ use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use cap_concurrency::Parker;
 use prm_threading::{ThreadHandle, thread_create, thread_join};
 use crate::task_graph::TaskGraph;
-use crate::task::ThreadAffinity;
 use sys_job::{Scheduler, TaskGroup};
 
 struct RuntimeCtx<'a> { rt: *mut TaskRuntime<'a> }
 
-fn runtime_entry<'a>(arg: *mut c_void) {
+fn runtime_entry(arg: *mut c_void) {
     let ctx: &RuntimeCtx = unsafe { &*(arg as *const RuntimeCtx) };
     let rt: &TaskRuntime = unsafe { &*ctx.rt };
     loop {
@@ -30,13 +29,24 @@ fn runtime_entry<'a>(arg: *mut c_void) {
     }
 }
 
-pub struct TaskRuntime<'a> { stop: AtomicBool, sched: *const Scheduler<'a>, graph: *mut TaskGraph<'a>, tg: *const TaskGroup, parker: Parker, handle: Option<ThreadHandle>, frame_idx: AtomicUsize, gpu_done: AtomicUsize, max_ahead: usize, target_frames: usize }
+pub struct TaskRuntime<'a> { stop: AtomicBool, sched: *const Scheduler<'a>, graph: *mut TaskGraph<'a>, tg: *const TaskGroup<'a>, parker: Parker, handle: Option<ThreadHandle>, frame_idx: AtomicUsize, gpu_done: AtomicUsize, max_ahead: usize, target_frames: usize }
 
 impl<'a> TaskRuntime<'a> {
     pub fn new(sched: &Scheduler<'a>) -> Self { Self { stop: AtomicBool::new(false), sched: sched as *const Scheduler<'a>, graph: core::ptr::null_mut(), tg: core::ptr::null(), parker: Parker::new().unwrap(), handle: None, frame_idx: AtomicUsize::new(0), gpu_done: AtomicUsize::new(0), max_ahead: 1, target_frames: 1 } }
-    pub fn bind(&mut self, g: &mut TaskGraph<'a>, tg: &TaskGroup) { self.graph = g as *mut TaskGraph<'a>; self.tg = tg as *const TaskGroup; }
+    pub fn bind(&mut self, g: &mut TaskGraph<'a>, tg: &TaskGroup<'a>) { self.graph = g as *mut TaskGraph<'a>; self.tg = tg as *const TaskGroup<'a>; }
     pub fn configure(&mut self, max_ahead: usize, target_frames: usize) { self.max_ahead = max_ahead; self.target_frames = target_frames; }
-    pub fn start(&mut self) { let ctx = Box::new(RuntimeCtx { rt: self as *const TaskRuntime<'a> as *mut TaskRuntime<'a> }); let arg = Box::into_raw(ctx) as *mut c_void; match thread_create(runtime_entry as fn(*mut c_void), arg) { Ok(h) => { self.handle = Some(h); } Err(_) => {} } }
-    pub fn stop_and_join(&mut self) { self.stop.store(true, Ordering::Relaxed); let _ = self.parker.unpark(); if let Some(h) = self.handle.take() { let _ = thread_join(h); } }
+    pub fn start(&mut self) { let ctx = Box::new(RuntimeCtx { rt: self as *const TaskRuntime<'a> as *mut TaskRuntime<'a> }); let arg = Box::into_raw(ctx) as *mut c_void; if let Ok(h) = thread_create(runtime_entry as fn(*mut c_void), arg) { self.handle = Some(h); } }
+    /// Stops dispatching frames, cancels the bound group so queued graph tasks are skipped, and waits
+    /// for the tasks already running. The group stays cancelled; bind a fresh one to start again.
+    /// Call it on the thread that started the scheduler so queued `Main` tasks drain as well.
+    pub fn stop_and_join(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.parker.unpark();
+        if let Some(h) = self.handle.take() { let _ = thread_join(h); }
+        if self.tg.is_null() { return; }
+        let tg = unsafe { &*self.tg };
+        tg.cancel();
+        let _ = unsafe { (*self.sched).wait_helping(tg) };
+    }
     pub fn mark_gpu_completed(&self) { self.gpu_done.fetch_add(1, Ordering::Relaxed); }
 }
@@ -1,12 +1,11 @@
 use core::ffi::c_void;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct TaskHandle { pub id: usize }
 
 pub type TaskFunc = fn(*mut c_void);
 
-#[derive(Clone, Copy)]
-pub enum ThreadAffinity { Main, Any, Compute(u16) }
+pub use sys_job::ThreadAffinity;
 
 #[derive(Clone, Copy)]
 pub enum Trigger { NextTask(usize), NextFrameRoot(usize) }
@@ -1,9 +1,15 @@
 use core::ffi::c_void;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use cap_memory::{Allocator, MemoryBlock, MemoryError};
+use cap_containers::{CapBox, CapString, Vector};
 use crate::task::{TaskHandle, TaskFunc, ThreadAffinity, Trigger};
-use sys_job::{Job, Scheduler, TaskGroup};
+use crate::profile::{CriticalPath, Label, TaskSample, NO_WORKER};
+use sys_job::{Scheduler, TaskGroup};
 
-struct TaskNode { f: TaskFunc, arg: *mut c_void, qos: u8, affinity: ThreadAffinity, trigger: Option<Trigger> }
+// Drops and frees a closure stored by `add_fn`.
+type DropArgFn = unsafe fn(Allocator<'_>, *mut c_void);
+
+struct TaskNode { f: TaskFunc, arg: *mut c_void, qos: u8, affinity: ThreadAffinity, trigger: Option<Trigger>, name: &'static str, drop_arg: Option<DropArgFn> }
 struct Edge { from: usize, to: usize }
 
 pub struct TaskGraph<'a> {
@@ -27,10 +33,12 @@ pub struct TaskGraph<'a> {
     pcsc_computes_cap: usize,
     pcsc_computes_count: usize,
     pcsc_computes_blk: MemoryBlock,
+    samples: *mut TaskSample,
+    samples_blk: MemoryBlock,
 }
 
 impl<'a> TaskGraph<'a> {
-    pub fn reserve(mut alloc: Allocator<'a>, nodes: usize, edges: usize) -> Result<Self, MemoryError> {
+    pub fn reserve(alloc: Allocator<'a>, nodes: usize, edges: usize) -> Result<Self, MemoryError> {
         let n_bytes = nodes.checked_mul(core::mem::size_of::<TaskNode>()).ok_or(MemoryError::Failed)?;
         let e_bytes = edges.checked_mul(core::mem::size_of::<Edge>()).ok_or(MemoryError::Failed)?;
         let n_blk = if n_bytes > 0 { alloc.alloc(n_bytes, core::mem::align_of::<TaskNode>())? } else { MemoryBlock::empty() };
@@ -40,16 +48,35 @@ impl<'a> TaskGraph<'a> {
         let r_bytes = nodes.checked_mul(core::mem::size_of::<usize>()).ok_or(MemoryError::Failed)?;
         let r_blk = if r_bytes > 0 { alloc.alloc(r_bytes, core::mem::align_of::<usize>())? } else { MemoryBlock::empty() };
         let r_ptr = if r_bytes > 0 { r_blk.ptr.cast::<usize>() } else { core::ptr::null_mut() };
-        Ok(Self { nodes: nodes_ptr, edges: edges_ptr, node_cap: nodes, edge_cap: edges, node_count: 0, edge_count: 0, nodes_blk: n_blk, edges_blk: e_blk, alloc, next_roots: r_ptr, next_roots_cap: nodes, next_roots_count: 0, next_roots_blk: r_blk, dyn_indeg: core::ptr::null_mut(), dyn_blk: MemoryBlock::empty(), pcsc_commit_idx: usize::MAX, pcsc_computes: core::ptr::null_mut(), pcsc_computes_cap: 0, pcsc_computes_count: 0, pcsc_computes_blk: MemoryBlock::empty() })
+        Ok(Self { nodes: nodes_ptr, edges: edges_ptr, node_cap: nodes, edge_cap: edges, node_count: 0, edge_count: 0, nodes_blk: n_blk, edges_blk: e_blk, alloc, next_roots: r_ptr, next_roots_cap: nodes, next_roots_count: 0, next_roots_blk: r_blk, dyn_indeg: core::ptr::null_mut(), dyn_blk: MemoryBlock::empty(), pcsc_commit_idx: usize::MAX, pcsc_computes: core::ptr::null_mut(), pcsc_computes_cap: 0, pcsc_computes_count: 0, pcsc_computes_blk: MemoryBlock::empty(), samples: core::ptr::null_mut(), samples_blk: MemoryBlock::empty() })
     }
     pub fn add(&mut self, f: TaskFunc, arg: *mut c_void, qos: u8, affinity: ThreadAffinity) -> TaskHandle {
         if f as usize == 0 { return TaskHandle { id: 0 }; }
         if self.node_count >= self.node_cap { return TaskHandle { id: 0 }; }
         let idx = self.node_count;
-        unsafe { self.nodes.add(idx).write(TaskNode { f, arg, qos, affinity, trigger: None }); }
+        unsafe { self.nodes.add(idx).write(TaskNode { f, arg, qos, affinity, trigger: None, name: "", drop_arg: None }); }
         self.node_count += 1;
         TaskHandle { id: idx + 1 }
     }
+    /// Adds a task that calls `f` every time it runs, including `NextFrameRoot` re-runs.
+    ///
+    /// The closure is moved into the graph's allocator (typically the frame arena) and dropped with
+    /// the graph. Returns a null handle if the graph is full or the closure cannot be stored.
+    pub fn add_fn<F: FnMut() + Send + 'a>(&mut self, f: F, qos: u8, affinity: ThreadAffinity) -> TaskHandle {
+        fn call<F: FnMut()>(arg: *mut c_void) { unsafe { (*(arg as *mut F))() } }
+        unsafe fn drop_arg<F>(alloc: Allocator<'_>, arg: *mut c_void) { drop(CapBox::from_raw(alloc, arg as *mut F)); }
+        if self.node_count >= self.node_cap { return TaskHandle { id: 0 }; }
+        let (p, _) = match CapBox::new(self.alloc, f) { Ok(b) => CapBox::into_raw(b), Err(_) => return TaskHandle { id: 0 } };
+        let h = self.add(call::<F>, p as *mut c_void, qos, affinity);
+        unsafe { (*self.nodes.add(h.id - 1)).drop_arg = Some(drop_arg::<F>); }
+        h
+    }
+    /// Label used by `to_dot` and in `validate` reports.
+    pub fn set_name(&mut self, h: TaskHandle, name: &'static str) -> bool {
+        if h.id == 0 || h.id > self.node_count { return false; }
+        unsafe { (*self.nodes.add(h.id - 1)).name = name; }
+        true
+    }
     pub fn set_trigger(&mut self, src: TaskHandle, t: Trigger) -> bool {
         if src.id == 0 { return false; }
         match t {
@@ -77,6 +104,8 @@ impl<'a> TaskGraph<'a> {
     pub fn chain_frames(&mut self, src: TaskHandle, dst: TaskHandle) -> bool { self.depends_on(dst, src) }
     pub fn set_arg(&mut self, h: TaskHandle, arg: *mut c_void) -> bool {
         if h.id == 0 { return false; }
+        // Closure tasks own their argument.
+        if unsafe { (*self.nodes.add(h.id - 1)).drop_arg.is_some() } { return false; }
         unsafe { let mut n = self.nodes.add(h.id - 1).read(); n.arg = arg; self.nodes.add(h.id - 1).write(n); }
         true
     }
@@ -112,13 +141,11 @@ impl<'a> TaskGraph<'a> {
         }
         true
     }
-    fn enqueue_node(&self, sched: &Scheduler, tg: &TaskGroup, idx: usize) {
+    fn enqueue_node(&self, sched: &Scheduler, tg: &TaskGroup, idx: usize) -> bool {
         unsafe {
-            let wc = sched.worker_count();
             let n = self.nodes.add(idx).read();
-            let qos = match n.affinity { ThreadAffinity::Main => 0usize, ThreadAffinity::Any => 0usize, ThreadAffinity::Compute(k) => { if wc > 1 { 1 + ((k as usize) % (wc - 1)) } else { 0usize } } } as u8;
             #[repr(C)]
-            struct TaskCtx { g: *mut c_void, node: usize, tg: *const TaskGroup, sched: *const c_void }
+            struct TaskCtx { g: *mut c_void, node: usize, tg: *const TaskGroup<'static>, sched: *const c_void }
             fn job_trampoline(arg: *mut c_void) {
                 unsafe {
                     let ctx: Box<TaskCtx> = Box::from_raw(arg as *mut TaskCtx);
@@ -126,26 +153,18 @@ impl<'a> TaskGraph<'a> {
                     let idx = ctx.node;
                     let tg = &*ctx.tg;
                     let sched = &*(ctx.sched as *const Scheduler<'static>);
+                    // A cancelled group skips the task and stops releasing its successors; the count still drops.
+                    if tg.is_cancelled() { tg.task_done(); return; }
                     let n = (*g).nodes.add(idx).read();
-                    (n.f)(n.arg);
-                    tg.task_done();
+                    (*g).run_node(idx, &n);
                     for k in 0..(*g).edge_count {
                         let e = (*g).edges.add(k).read();
                         if e.from == idx {
-                            let p = (*g).dyn_indeg.add(e.to);
-                            let dv = p.read();
-                            if dv > 0 {
-                                p.write(dv - 1);
-                                if dv - 1 == 0 {
-                                    let wc = sched.worker_count();
-                                    let sn = (*g).nodes.add(e.to).read();
-                                    let qos = match sn.affinity { ThreadAffinity::Main => 0usize, ThreadAffinity::Any => 0usize, ThreadAffinity::Compute(k) => { if wc > 1 { 1 + ((k as usize) % (wc - 1)) } else { 0usize } } } as u8;
-                                    let next_ctx = Box::new(TaskCtx { g: g as *mut c_void, node: e.to, tg: tg as *const TaskGroup, sched: sched as *const _ as *const c_void });
-                                    let next_arg = Box::into_raw(next_ctx) as *mut c_void;
-                                    let j = sys_job::Job { func: job_trampoline as fn(*mut c_void), arg: next_arg, qos };
-                                    match sn.affinity { ThreadAffinity::Main => { let _ = sched.enqueue_high(j); } _ => { let _ = sched.enqueue(j); } }
-                                    tg.add_tasks(1);
-                                }
+                            // Predecessors finishing on different workers race on the count.
+                            let p = &*((*g).dyn_indeg.add(e.to) as *const AtomicUsize);
+                            if p.fetch_sub(1, Ordering::AcqRel) == 1 {
+                                let sn = (*g).nodes.add(e.to).read();
+                                submit(TaskCtx { g: g as *mut c_void, node: e.to, tg: tg as *const TaskGroup, sched: sched as *const _ as *const c_void }, &sn, tg, sched);
                             }
                         }
                     }
@@ -156,17 +175,29 @@ impl<'a> TaskGraph<'a> {
                             (*g).next_roots_count += 1;
                         }
                     }
+                    // Last, so the group cannot reach zero while successors are still being released.
+                    tg.task_done();
                 }
             }
-            let ctx = Box::new(TaskCtx { g: self as *const _ as *mut c_void, node: idx, tg: tg as *const TaskGroup, sched: sched as *const _ as *const c_void });
-            let arg = Box::into_raw(ctx) as *mut c_void;
-            let j = sys_job::Job { func: job_trampoline as fn(*mut c_void), arg, qos };
-            match n.affinity { ThreadAffinity::Main => { let _ = sched.enqueue_high(j); } _ => { let _ = sched.enqueue(j); } }
-            tg.add_tasks(1);
+            // Counted before the enqueue so the group cannot reach zero early. If the scheduler cannot take
+            // the job (out of memory), the count is undone, the context freed and the group cancelled, so
+            // waiting on it ends as `Cancelled` instead of hanging on a task that never runs.
+            fn submit(ctx: TaskCtx, n: &TaskNode, tg: &TaskGroup, sched: &Scheduler) -> bool {
+                let arg = Box::into_raw(Box::new(ctx)) as *mut c_void;
+                tg.add_tasks(1);
+                if sched.enqueue(sys_job::Job { func: job_trampoline as fn(*mut c_void), arg, qos: n.qos, affinity: n.affinity }).is_ok() { return true; }
+                drop(unsafe { Box::from_raw(arg as *mut TaskCtx) });
+                tg.cancel();
+                tg.task_done();
+                false
+            }
+            submit(TaskCtx { g: self as *const _ as *mut c_void, node: idx, tg: (tg as *const TaskGroup).cast::<TaskGroup<'static>>(), sched: sched as *const _ as *const c_void }, &n, tg, sched)
         }
     }
     pub fn dispatch(&mut self, sched: &Scheduler, tg: &TaskGroup) -> bool {
         if self.node_count == 0 { return true; }
+        debug_assert_eq!(self.validate(), Ok(()));
+        self.clear_samples();
         if self.dyn_indeg.is_null() {
             let bytes = self.node_count.checked_mul(core::mem::size_of::<usize>()).unwrap();
             match self.alloc.alloc(bytes, core::mem::align_of::<usize>()) { Ok(b) => { self.dyn_blk = b; self.dyn_indeg = b.ptr.cast::<usize>(); } Err(_) => return false }
@@ -174,9 +205,13 @@ impl<'a> TaskGraph<'a> {
         unsafe {
             for i in 0..self.node_count { self.dyn_indeg.add(i).write(0); }
             for i in 0..self.edge_count { let e = self.edges.add(i).read(); let v = self.dyn_indeg.add(e.to).read(); self.dyn_indeg.add(e.to).write(v + 1); }
-            for i in 0..self.node_count { if self.dyn_indeg.add(i).read() == 0 { self.enqueue_node(sched, tg, i); } }
+            // Mark the roots before enqueueing any: once the first one runs, its successors can drop to
+            // zero while this loop is still going and would be enqueued a second time.
+            for i in 0..self.node_count { if self.dyn_indeg.add(i).read() == 0 { self.dyn_indeg.add(i).write(usize::MAX); } }
+            let mut ok = true;
+            for i in 0..self.node_count { if self.dyn_indeg.add(i).read() == usize::MAX { self.dyn_indeg.add(i).write(0); ok &= self.enqueue_node(sched, tg, i); } }
+            ok
         }
-        true
     }
     pub fn dispatch_next_frame_roots(&mut self, sched: &Scheduler, tg: &TaskGroup) -> usize {
         if self.next_roots_count == 0 { return 0; }
@@ -186,8 +221,7 @@ impl<'a> TaskGraph<'a> {
                 let id = self.next_roots.add(i).read();
                 if id == 0 || id > self.node_count { continue; }
                 let idx = id - 1;
-                self.enqueue_node(sched, tg, idx);
-                count += 1;
+                if self.enqueue_node(sched, tg, idx) { count += 1; }
             }
             self.next_roots_count = 0;
             count
@@ -195,6 +229,8 @@ impl<'a> TaskGraph<'a> {
     }
     pub fn dispatch_inline(&mut self, tg: &TaskGroup) -> bool {
         if self.node_count == 0 { return true; }
+        debug_assert_eq!(self.validate(), Ok(()));
+        self.clear_samples();
         let indeg_bytes = self.node_count.checked_mul(core::mem::size_of::<usize>()).unwrap();
         let indeg_blk = match self.alloc.alloc(indeg_bytes, core::mem::align_of::<usize>()) { Ok(b) => b, Err(_) => return false };
         let indeg = indeg_blk.ptr.cast::<usize>();
@@ -203,7 +239,7 @@ impl<'a> TaskGraph<'a> {
             for i in 0..self.edge_count { let e = self.edges.add(i).read(); let v = indeg.add(e.to).read(); indeg.add(e.to).write(v + 1); }
             // simple queue using raw array indices
             let q_bytes = self.node_count.checked_mul(core::mem::size_of::<usize>()).unwrap();
-            let q_blk = match self.alloc.alloc(q_bytes, core::mem::align_of::<usize>()) { Ok(b) => b, Err(_) => { let _ = self.alloc.free(indeg_blk, core::mem::align_of::<usize>()); return false; } };
+            let q_blk = match self.alloc.alloc(q_bytes, core::mem::align_of::<usize>()) { Ok(b) => b, Err(_) => { self.alloc.free(indeg_blk, core::mem::align_of::<usize>()); return false; } };
             let q = q_blk.ptr.cast::<usize>();
             let mut qh = 0usize; let mut qt = 0usize;
             for i in 0..self.node_count { if indeg.add(i).read() == 0 { q.add(qt).write(i); qt += 1; } }
@@ -211,23 +247,266 @@ impl<'a> TaskGraph<'a> {
                 let idx = q.add(qh).read(); qh += 1;
                 tg.add_tasks(1);
                 let n = self.nodes.add(idx).read();
-                (n.f)(n.arg);
+                self.run_node(idx, &n);
                 tg.task_done();
                 for k in 0..self.edge_count { let e = self.edges.add(k).read(); if e.from == idx { let dv = indeg.add(e.to).read(); if dv > 0 { indeg.add(e.to).write(dv - 1); if dv - 1 == 0 { q.add(qt).write(e.to); qt += 1; } } } }
                 if let Some(Trigger::NextFrameRoot(dst_id)) = n.trigger { if self.next_roots_count < self.next_roots_cap { self.next_roots.add(self.next_roots_count).write(dst_id); self.next_roots_count += 1; } }
             }
-            let _ = self.alloc.free(q_blk, core::mem::align_of::<usize>());
+            self.alloc.free(q_blk, core::mem::align_of::<usize>());
         }
-        let _ = self.alloc.free(indeg_blk, core::mem::align_of::<usize>());
+        self.alloc.free(indeg_blk, core::mem::align_of::<usize>());
         true
     }
 }
 
+/// Why `TaskGraph::validate` rejected a graph.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GraphError<'a> {
+    /// An edge or trigger names a task that was never added.
+    InvalidHandle(TaskHandle),
+    /// Dependency cycle, listed in edge order; the first task is repeated at the end.
+    Cycle(Vector<'a, TaskHandle>),
+    /// `pcsc_add_compute` was called but no commit task was set.
+    MissingCommit { compute: TaskHandle },
+    /// A compute task is not yet wired to its commit; `pcsc_finalize_edges` was not called after adding it.
+    UnfinalizedCompute { compute: TaskHandle, commit: TaskHandle },
+    /// A `NextTask` continuation runs under a different affinity than the task that triggers it.
+    AffinityConflict { from: TaskHandle, to: TaskHandle },
+    /// The graph's allocator could not provide scratch space for the check.
+    OutOfMemory,
+}
+
+impl From<MemoryError> for GraphError<'_> { fn from(_: MemoryError) -> Self { GraphError::OutOfMemory } }
+
+impl core::fmt::Display for GraphError<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            GraphError::InvalidHandle(h) => write!(f, "unknown task {}", h.id),
+            GraphError::Cycle(path) => {
+                write!(f, "cycle: ")?;
+                for (i, h) in path.iter().enumerate() { write!(f, "{}{}", if i > 0 { " -> " } else { "" }, h.id)?; }
+                Ok(())
+            }
+            GraphError::MissingCommit { compute } => write!(f, "compute task {} has no pcsc commit", compute.id),
+            GraphError::UnfinalizedCompute { compute, commit } => write!(f, "compute task {} is not wired to commit {}", compute.id, commit.id),
+            GraphError::AffinityConflict { from, to } => write!(f, "task {} continues into task {} with a different affinity", from.id, to.id),
+            GraphError::OutOfMemory => write!(f, "out of memory"),
+        }
+    }
+}
+
+// `n` copies of `v`; scratch arrays for the graph walks.
+fn filled<T: Copy>(alloc: Allocator<'_>, n: usize, v: T) -> Result<Vector<'_, T>, MemoryError> {
+    let mut out = Vector::with_capacity(alloc, n)?;
+    for _ in 0..n { out.push(v)?; }
+    Ok(out)
+}
+
+impl<'a> TaskGraph<'a> {
+    fn node(&self, idx: usize) -> &TaskNode { unsafe { &*self.nodes.add(idx) } }
+    fn edge(&self, idx: usize) -> &Edge { unsafe { &*self.edges.add(idx) } }
+    fn handle(idx: usize) -> TaskHandle { TaskHandle { id: idx + 1 } }
+    fn has_edge(&self, from: usize, to: usize) -> bool { (0..self.edge_count).any(|k| { let e = self.edge(k); e.from == from && e.to == to }) }
+
+    /// Checks the graph before `dispatch`: dangling handles, cycles (reported with their path),
+    /// pcsc computes without a wired commit, and `NextTask` continuations that change affinity.
+    ///
+    /// A graph that passes cannot deadlock `TaskGroup::wait` on its own structure.
+    pub fn validate(&self) -> Result<(), GraphError<'a>> {
+        let n = self.node_count;
+        for k in 0..self.edge_count {
+            let e = self.edge(k);
+            if e.from >= n { return Err(GraphError::InvalidHandle(Self::handle(e.from))); }
+            if e.to >= n { return Err(GraphError::InvalidHandle(Self::handle(e.to))); }
+        }
+        for i in 0..n {
+            match self.node(i).trigger {
+                Some(Trigger::NextTask(id)) | Some(Trigger::NextFrameRoot(id)) if id == 0 || id > n => return Err(GraphError::InvalidHandle(TaskHandle { id })),
+                Some(Trigger::NextTask(id)) if self.node(id - 1).affinity != self.node(i).affinity => {
+                    return Err(GraphError::AffinityConflict { from: Self::handle(i), to: TaskHandle { id } });
+                }
+                _ => {}
+            }
+        }
+        self.find_cycle()?;
+        for i in 0..self.pcsc_computes_count {
+            let c = unsafe { self.pcsc_computes.add(i).read() };
+            if self.pcsc_commit_idx == usize::MAX { return Err(GraphError::MissingCommit { compute: Self::handle(c) }); }
+            if !self.has_edge(c, self.pcsc_commit_idx) {
+                return Err(GraphError::UnfinalizedCompute { compute: Self::handle(c), commit: Self::handle(self.pcsc_commit_idx) });
+            }
+        }
+        Ok(())
+    }
+
+    // Iterative DFS; the grey nodes on the stack are the current path, so a back edge yields the cycle directly.
+    fn find_cycle(&self) -> Result<(), GraphError<'a>> {
+        let n = self.node_count;
+        let mut start = filled(self.alloc, n + 1, 0usize)?;
+        for k in 0..self.edge_count { start[self.edge(k).from + 1] += 1; }
+        for i in 0..n { start[i + 1] += start[i]; }
+        let mut adj = filled(self.alloc, self.edge_count, 0usize)?;
+        let mut fill = filled(self.alloc, n + 1, 0usize)?;
+        fill.copy_from_slice(&start);
+        for k in 0..self.edge_count { let e = self.edge(k); adj[fill[e.from]] = e.to; fill[e.from] += 1; }
+
+        const WHITE: u8 = 0;
+        const GREY: u8 = 1;
+        const BLACK: u8 = 2;
+        let mut color = filled(self.alloc, n, WHITE)?;
+        // Each node is on the stack at most once, so this never grows.
+        let mut stack = Vector::with_capacity(self.alloc, n)?;
+        for root in 0..n {
+            if color[root] != WHITE { continue; }
+            color[root] = GREY;
+            stack.push((root, start[root]))?;
+            while let Some(top) = stack.last_mut() {
+                let (v, next) = *top;
+                if next == start[v + 1] { color[v] = BLACK; stack.pop(); continue; }
+                top.1 += 1;
+                let w = adj[next];
+                match color[w] {
+                    WHITE => { color[w] = GREY; stack.push((w, start[w]))?; }
+                    GREY => {
+                        let from = stack.iter().position(|&(u, _)| u == w).unwrap();
+                        let mut path = Vector::with_capacity(self.alloc, stack.len() - from + 1)?;
+                        for &(u, _) in &stack.as_slice()[from..] { path.push(Self::handle(u))?; }
+                        path.push(Self::handle(w))?;
+                        return Err(GraphError::Cycle(path));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Graphviz source for the graph: one box per task (name and affinity), solid dependency edges,
+    /// dashed next-frame triggers. The pcsc commit is drawn with a double border.
+    pub fn to_dot(&self) -> Result<CapString<'a>, MemoryError> {
+        let mut out = CapString::with_capacity(self.alloc, 64 + 32 * (self.node_count + self.edge_count))?;
+        // `CapString` only fails a write when it cannot grow.
+        self.write_dot(&mut out).map_err(|_| MemoryError::OutOfMemory)?;
+        Ok(out)
+    }
+
+    fn write_dot(&self, out: &mut impl core::fmt::Write) -> core::fmt::Result {
+        out.write_str("digraph TaskGraph {\n    node [shape=box];\n")?;
+        for i in 0..self.node_count {
+            let nd = self.node(i);
+            write!(out, "    n{} [label=\"{}\\n", i + 1, Label { name: nd.name, id: i + 1 })?;
+            match nd.affinity { ThreadAffinity::Main => out.write_str("main")?, ThreadAffinity::Any => out.write_str("any")?, ThreadAffinity::Compute(g) => write!(out, "compute {}", g)? }
+            let extra = if i == self.pcsc_commit_idx { ", peripheries=2" } else { "" };
+            writeln!(out, "\"{}];", extra)?;
+        }
+        for k in 0..self.edge_count {
+            let e = self.edge(k);
+            writeln!(out, "    n{} -> n{};", e.from + 1, e.to + 1)?;
+        }
+        for i in 0..self.node_count {
+            if let Some(Trigger::NextFrameRoot(id)) = self.node(i).trigger {
+                writeln!(out, "    n{} -> n{} [style=dashed, label=\"next frame\"];", i + 1, id)?;
+            }
+        }
+        out.write_str("}\n")
+    }
+}
+
+impl<'a> TaskGraph<'a> {
+    /// Starts recording a `TaskSample` for every task run by `dispatch` / `dispatch_inline`.
+    pub fn enable_profiling(&mut self) -> Result<(), MemoryError> {
+        if !self.samples.is_null() || self.node_cap == 0 { return Ok(()); }
+        let bytes = self.node_cap.checked_mul(core::mem::size_of::<TaskSample>()).ok_or(MemoryError::Failed)?;
+        self.samples_blk = self.alloc.alloc(bytes, core::mem::align_of::<TaskSample>())?;
+        self.samples = self.samples_blk.ptr.cast::<TaskSample>();
+        self.clear_samples();
+        Ok(())
+    }
+    pub fn disable_profiling(&mut self) {
+        if self.samples_blk.is_empty() { return; }
+        self.alloc.free(self.samples_blk, core::mem::align_of::<TaskSample>());
+        self.samples_blk = MemoryBlock::empty();
+        self.samples = core::ptr::null_mut();
+    }
+    pub fn is_profiling(&self) -> bool { !self.samples.is_null() }
+
+    pub fn task_count(&self) -> usize { self.node_count }
+    pub(crate) fn allocator(&self) -> Allocator<'a> { self.alloc }
+    pub fn task_name(&self, h: TaskHandle) -> &'static str { if h.id == 0 || h.id > self.node_count { "" } else { self.node(h.id - 1).name } }
+
+    /// Timings of the last dispatch, indexed by `TaskHandle::id - 1`; empty unless profiling is on.
+    /// Tasks that did not run have `ran == false`.
+    pub fn samples(&self) -> &[TaskSample] {
+        if self.samples.is_null() { &[] } else { unsafe { core::slice::from_raw_parts(self.samples, self.node_count) } }
+    }
+
+    fn clear_samples(&mut self) {
+        for i in 0..if self.samples.is_null() { 0 } else { self.node_cap } {
+            unsafe { self.samples.add(i).write(TaskSample { begin: 0, end: 0, worker: NO_WORKER, ran: false }); }
+        }
+    }
+
+    // Runs node `idx`, timing it when profiling is on. Each node has its own slot, so workers never share one.
+    fn run_node(&self, idx: usize, n: &TaskNode) {
+        if self.samples.is_null() { (n.f)(n.arg); return; }
+        let begin = prm_time::now();
+        (n.f)(n.arg);
+        let end = prm_time::now();
+        let worker = sys_job::current_worker_index().map_or(NO_WORKER, |w| w as u32);
+        unsafe { self.samples.add(idx).write(TaskSample { begin, end, worker, ran: true }); }
+    }
+
+    /// Longest chain of dependent tasks in the last profiled dispatch, weighted by measured duration.
+    ///
+    /// `None` if profiling is off, the graph has a cycle, or the graph's allocator is out of scratch space.
+    pub fn critical_path(&self) -> Option<CriticalPath<'a>> {
+        let samples = self.samples();
+        if samples.is_empty() { return None; }
+        let n = self.node_count;
+        let mut indeg = filled(self.alloc, n, 0usize).ok()?;
+        for k in 0..self.edge_count { indeg[self.edge(k).to] += 1; }
+        let mut order = Vector::with_capacity(self.alloc, n).ok()?;
+        for i in 0..n { if indeg[i] == 0 { order.push(i).ok()?; } }
+        // `before[v]`: longest chain ending in a predecessor of `v`, reached through `prev[v]`.
+        let mut before = filled(self.alloc, n, 0i64).ok()?;
+        let mut dist = filled(self.alloc, n, 0i64).ok()?;
+        let mut prev = filled(self.alloc, n, usize::MAX).ok()?;
+        let mut head = 0;
+        while head < order.len() {
+            let v = order[head];
+            head += 1;
+            dist[v] = before[v] + samples[v].duration();
+            for k in 0..self.edge_count {
+                let e = self.edge(k);
+                if e.from != v { continue; }
+                if prev[e.to] == usize::MAX || dist[v] > before[e.to] { before[e.to] = dist[v]; prev[e.to] = v; }
+                indeg[e.to] -= 1;
+                if indeg[e.to] == 0 { order.push(e.to).ok()?; }
+            }
+        }
+        if order.len() < n { return None; }
+        let mut end = (0..n).max_by_key(|&i| dist[i])?;
+        let total_ns = dist[end];
+        let mut tasks = Vector::with_capacity(self.alloc, 1).ok()?;
+        tasks.push(TaskGraph::handle(end)).ok()?;
+        while prev[end] != usize::MAX { end = prev[end]; tasks.push(TaskGraph::handle(end)).ok()?; }
+        tasks.reverse();
+        let ran = samples.iter().filter(|s| s.ran);
+        let span_ns = match (ran.clone().map(|s| s.begin).min(), ran.map(|s| s.end).max()) { (Some(b), Some(e)) => e - b, _ => 0 };
+        Some(CriticalPath { tasks, total_ns, span_ns })
+    }
+}
+
 impl<'a> Drop for TaskGraph<'a> {
     fn drop(&mut self) {
-        if !self.nodes_blk.is_empty() { let _ = self.alloc.free(self.nodes_blk, core::mem::align_of::<TaskNode>()); }
-        if !self.edges_blk.is_empty() { let _ = self.alloc.free(self.edges_blk, core::mem::align_of::<Edge>()); }
-        if !self.next_roots_blk.is_empty() { let _ = self.alloc.free(self.next_roots_blk, core::mem::align_of::<usize>()); }
-        if !self.dyn_blk.is_empty() { let _ = self.alloc.free(self.dyn_blk, core::mem::align_of::<usize>()); }
+        for i in 0..self.node_count {
+            let n = self.node(i);
+            if let Some(d) = n.drop_arg { unsafe { d(self.alloc, n.arg); } }
+        }
+        if !self.nodes_blk.is_empty() { self.alloc.free(self.nodes_blk, core::mem::align_of::<TaskNode>()); }
+        if !self.edges_blk.is_empty() { self.alloc.free(self.edges_blk, core::mem::align_of::<Edge>()); }
+        if !self.next_roots_blk.is_empty() { self.alloc.free(self.next_roots_blk, core::mem::align_of::<usize>()); }
+        if !self.dyn_blk.is_empty() { self.alloc.free(self.dyn_blk, core::mem::align_of::<usize>()); }
+        self.disable_profiling();
     }
 }
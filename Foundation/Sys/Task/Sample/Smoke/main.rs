@@ -14,8 +14,9 @@ fn map_job(buf_ptr: *mut c_void) {
 fn reduce_job(agg_ptr: *mut c_void) { let agg: &mut CommandAggregator = unsafe { &mut *(agg_ptr as *mut CommandAggregator) }; agg.apply_all(); }
 
 fn main() {
-    let mut frame = FrameAllocatorResource::new(8 << 20);
-    let a = Allocator::new(&mut frame);
+    // Workers allocate from it concurrently (fibers, spawned closures), so it has to be thread-safe.
+    let mut sys = SystemMemoryResource;
+    let a = Allocator::new(&mut sys);
     let tg = TaskGroup::new();
     let mut g = TaskGraph::reserve(a, 8, 8).unwrap();
     let mut b1 = CommandBuffer::new(a, 32).unwrap();
@@ -29,8 +30,131 @@ fn main() {
     let _ = g.pcsc_set_commit(h_reduce);
     let _ = g.pcsc_add_compute(h_map1);
     let _ = g.pcsc_add_compute(h_map2);
+    let _ = g.set_name(h_map1, "map 1");
+    let _ = g.set_name(h_map2, "map 2");
+    let _ = g.set_name(h_reduce, "reduce");
+    assert_eq!(g.validate(), Err(GraphError::UnfinalizedCompute { compute: h_map1, commit: h_reduce }));
     let _ = g.pcsc_finalize_edges();
+    assert_eq!(g.validate(), Ok(()));
+    print!("{}", g.to_dot().unwrap());
+    g.enable_profiling().unwrap();
     let _ = g.dispatch_inline(&tg);
     tg.wait();
     println!("sum {}", SUM.load(Ordering::Relaxed));
+    assert!(g.samples().iter().all(|s| s.ran && s.worker == NO_WORKER));
+    let cp = g.critical_path().unwrap();
+    assert_eq!(cp.tasks.last(), Some(&h_reduce));
+    let mut trace = TraceCapture::new(a).unwrap();
+    trace.record_frame(&g, 0).unwrap();
+    assert!(trace.to_json().unwrap().contains("\"name\":\"reduce\"") && trace.frames() == 1);
+    let path = std::env::temp_dir().join("sys_task_trace.json");
+    let written = trace.write(path.to_str().unwrap()).is_ok();
+    println!("critical path {:?} {} ns of {} ns, trace written {}", cp.tasks.iter().map(|h| g.task_name(*h)).collect::<Vec<_>>(), cp.total_ns, cp.span_ns, written);
+
+    fn nop(_: *mut c_void) {}
+    let mut bad = TaskGraph::reserve(a, 4, 4).unwrap();
+    let t1 = bad.add(nop as fn(*mut c_void), core::ptr::null_mut(), 0, ThreadAffinity::Any);
+    let t2 = bad.add(nop as fn(*mut c_void), core::ptr::null_mut(), 0, ThreadAffinity::Any);
+    let t3 = bad.add(nop as fn(*mut c_void), core::ptr::null_mut(), 0, ThreadAffinity::Main);
+    let _ = bad.depends_on(t2, t1);
+    let _ = bad.depends_on(t3, t2);
+    let _ = bad.depends_on(t2, t3);
+    let err = bad.validate().unwrap_err();
+    assert!(matches!(&err, GraphError::Cycle(path) if path.as_slice() == [t2, t3, t2]));
+    println!("validate: {}", err);
+
+    struct Tally(std::sync::Arc<AtomicUsize>);
+    impl Drop for Tally { fn drop(&mut self) { self.0.fetch_add(100, Ordering::Relaxed); } }
+    let count = std::sync::Arc::new(AtomicUsize::new(0));
+    {
+        let mut fg = TaskGraph::reserve(a, 4, 4).unwrap();
+        let tally = Tally(count.clone());
+        let first = fg.add_fn(move || { tally.0.fetch_add(1, Ordering::Relaxed); }, 0, ThreadAffinity::Any);
+        let c = count.clone();
+        let second = fg.add_fn(move || { c.fetch_add(10, Ordering::Relaxed); }, 0, ThreadAffinity::Any);
+        let _ = fg.depends_on(second, first);
+        assert!(!fg.set_arg(first, core::ptr::null_mut()));
+        let _ = fg.dispatch_inline(&tg);
+        let _ = fg.dispatch_inline(&tg);
+    }
+    assert_eq!(count.load(Ordering::Relaxed), 122);
+    println!("closure tasks {}", count.load(Ordering::Relaxed));
+
+    // Deterministic scheduler: seeds explore different orders of the same graph; a logged schedule replays exactly.
+    let run = |seed: u64, replay: Option<&[ScheduleStep]>| {
+        let sched = Scheduler::start_with(a, SchedulerConfig::deterministic(seed, 64, 64 << 10)).unwrap();
+        if let Some(steps) = replay { sched.replay(steps).unwrap(); }
+        let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut dg = TaskGraph::reserve(a, 8, 8).unwrap();
+        let hs: Vec<_> = (0..6u32).map(|i| { let o = order.clone(); dg.add_fn(move || o.lock().unwrap().push(i), 0, ThreadAffinity::Any) }).collect();
+        let _ = dg.depends_on(hs[3], hs[0]);
+        let _ = dg.depends_on(hs[4], hs[1]);
+        let _ = dg.depends_on(hs[5], hs[3]);
+        let _ = dg.depends_on(hs[5], hs[4]);
+        let dtg = TaskGroup::new();
+        let _ = dg.dispatch(&sched, &dtg);
+        sched.wait_helping(&dtg);
+        let order = order.lock().unwrap().clone();
+        (order, sched.schedule().unwrap(), sched.replay_diverged())
+    };
+    let mut orders = std::collections::HashSet::new();
+    for seed in 0..32 {
+        let (order, steps, _) = run(seed, None);
+        let pos = |t: u32| order.iter().position(|&x| x == t).unwrap();
+        assert!(order.len() == 6 && pos(0) < pos(3) && pos(1) < pos(4) && pos(3) < pos(5) && pos(4) < pos(5));
+        assert_eq!(run(seed, None).0, order);
+        let (replayed, _, diverged) = run(seed ^ 0x5eed, Some(steps.as_slice()));
+        assert!(!diverged && replayed == order);
+        orders.insert(order);
+    }
+    assert!(orders.len() > 1);
+    println!("deterministic orders {}", orders.len());
+
+    // Cancellation: a task cancels its own group, so the rest of the chain is skipped but the wait still drains.
+    let sched = Scheduler::start_with(a, SchedulerConfig::deterministic(7, 64, 64 << 10)).unwrap();
+    let ran = std::sync::Arc::new(AtomicUsize::new(0));
+    let ctg = std::sync::Arc::new(TaskGroup::new());
+    let mut cg = TaskGraph::reserve(a, 4, 4).unwrap();
+    let chain: Vec<_> = (0..4).map(|i| {
+        let (r, t) = (ran.clone(), ctg.clone());
+        cg.add_fn(move || { r.fetch_add(1, Ordering::Relaxed); if i == 1 { t.cancel(); } }, 0, ThreadAffinity::Any)
+    }).collect();
+    for w in chain.windows(2) { let _ = cg.depends_on(w[1], w[0]); }
+    let _ = cg.dispatch(&sched, &ctg);
+    assert_eq!(sched.wait_helping(&ctg), WaitStatus::Cancelled);
+    assert_eq!(ran.load(Ordering::Relaxed), 2);
+    let level = CancelToken::new(a).unwrap();
+    let (child, grandchild) = (level.child().unwrap(), TaskGroup::with_parent(&level).unwrap());
+    let started = AtomicUsize::new(0);
+    sched.scope_with_parent(&level, |s| {
+        sched.scope_with_parent(s.token().unwrap(), |inner| { for _ in 0..4 { assert!(inner.spawn(|| { started.fetch_add(1, Ordering::Relaxed); }).is_ok()); } }).unwrap();
+        for _ in 0..4 { assert!(s.spawn(|| { started.fetch_add(1, Ordering::Relaxed); }).is_ok()); }
+        level.cancel();
+    }).unwrap();
+    assert_eq!(started.load(Ordering::Relaxed), 4);
+    assert!(child.is_cancelled() && grandchild.is_cancelled() && level.child().unwrap().is_cancelled());
+    assert_eq!(grandchild.wait(), WaitStatus::Cancelled);
+    assert_eq!(TaskGroup::new().wait(), WaitStatus::Completed);
+    println!("cancel ran {} scoped {}", ran.load(Ordering::Relaxed), started.load(Ordering::Relaxed));
+
+    // `Main` tasks on a threaded scheduler: `wait_helping` on the starting thread runs them, and
+    // `stop_and_join` drains queued ones without anyone calling `pump_main`.
+    let sched = Scheduler::start(a, 2, 64, 64 << 10).unwrap();
+    let on_main = std::sync::Arc::new(AtomicUsize::new(0));
+    let mut mg = TaskGraph::reserve(a, 4, 4).unwrap();
+    let o = on_main.clone();
+    let first = mg.add_fn(|| {}, 0, ThreadAffinity::Any);
+    let last = mg.add_fn(move || { if current_worker_index().is_none() { o.fetch_add(1, Ordering::Relaxed); } }, 0, ThreadAffinity::Main);
+    let _ = mg.depends_on(last, first);
+    let mtg = TaskGroup::new();
+    let _ = mg.dispatch(&sched, &mtg);
+    assert_eq!(sched.wait_helping(&mtg), WaitStatus::Completed);
+    assert_eq!(on_main.load(Ordering::Relaxed), 1);
+    let rtg = TaskGroup::new();
+    let _ = mg.dispatch(&sched, &rtg);
+    let mut rt = TaskRuntime::new(&sched);
+    rt.bind(&mut mg, &rtg);
+    rt.stop_and_join();
+    assert!(rtg.is_done());
+    println!("main affinity {}", on_main.load(Ordering::Relaxed));
 }
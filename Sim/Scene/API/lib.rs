@@ -1,5 +1,5 @@
 use std::any::{Any, TypeId};
-use cap_containers::{Vector, HashMap};
+use cap_containers::{cap_unsize, CapBox, PersistentVector, Vector, HashMap};
 use cap_memory::{Allocator, MemoryError};
 use sim_schema::EntityId;
 use sim_component::Transform;
@@ -12,14 +12,16 @@ pub trait Storage<'a>: Send + Sync {
     fn element_type_id(&self) -> TypeId;
     fn as_raw(&self) -> *const ();
     fn as_raw_mut(&mut self) -> *mut ();
-    fn push_any(&mut self, component: Box<dyn Any>);
+    /// `component` is an `Option<T>`; the value is taken out of it, leaving `None`.
+    fn push_any(&mut self, component: &mut dyn Any);
     fn swap_remove(&mut self, index: usize);
-    fn fork(&self, alloc: Allocator<'a>) -> Result<Box<dyn Storage<'a> + 'a>, MemoryError>;
+    fn fork(&self, alloc: Allocator<'a>) -> Result<CapBox<'a, dyn Storage<'a> + 'a>, MemoryError>;
     fn len(&self) -> usize;
 }
 
-struct ComponentVec<'a, T> {
-    data: Vector<'a, T>,
+// Columns are copy-on-write so a world fork shares every chunk it has not written to since.
+struct ComponentVec<'a, T: Clone> {
+    data: PersistentVector<'a, T>,
 }
 
 impl<'a, T: 'static + Clone + Send + Sync> Storage<'a> for ComponentVec<'a, T> {
@@ -27,21 +29,16 @@ impl<'a, T: 'static + Clone + Send + Sync> Storage<'a> for ComponentVec<'a, T> {
     fn as_raw(&self) -> *const () { self as *const _ as *const () }
     fn as_raw_mut(&mut self) -> *mut () { self as *mut _ as *mut () }
     
-    fn push_any(&mut self, component: Box<dyn Any>) {
-        let val = *component.downcast::<T>().expect("Invalid component type");
+    fn push_any(&mut self, component: &mut dyn Any) {
+        let val = component.downcast_mut::<Option<T>>().and_then(Option::take).expect("Invalid component type");
         self.data.push(val).expect("OOM in push_any"); 
     }
     fn swap_remove(&mut self, index: usize) {
-        self.data.swap_remove(index);
+        self.data.swap_remove(index).expect("OOM in swap_remove");
     }
-    fn fork(&self, alloc: Allocator<'a>) -> Result<Box<dyn Storage<'a> + 'a>, MemoryError> {
-        let mut new_vec = Vector::with_capacity(alloc, self.data.capacity())?;
-        for i in 0..self.data.len() {
-            if let Some(val) = self.data.get(i) {
-                new_vec.push(val.clone())?;
-            }
-        }
-        Ok(Box::new(ComponentVec { data: new_vec }))
+    fn fork(&self, alloc: Allocator<'a>) -> Result<CapBox<'a, dyn Storage<'a> + 'a>, MemoryError> {
+        let data = self.data.fork(alloc)?;
+        Ok(cap_unsize!(CapBox::new(alloc, ComponentVec { data })?, dyn Storage<'a> + 'a))
     }
     fn len(&self) -> usize { self.data.len() }
 }
@@ -51,8 +48,8 @@ impl<'a, T: 'static + Clone + Send + Sync> Storage<'a> for ComponentVec<'a, T> {
 pub struct Archetype<'a> {
     pub id: u64,
     pub types: Vector<'a, TypeId>,
-    pub storages: HashMap<'a, TypeId, Box<dyn Storage<'a> + 'a>>,
-    pub entities: Vector<'a, EntityId>,
+    pub storages: HashMap<'a, TypeId, CapBox<'a, dyn Storage<'a> + 'a>>,
+    pub entities: PersistentVector<'a, EntityId>,
     alloc: Allocator<'a>,
 }
 
@@ -62,7 +59,7 @@ impl<'a> Archetype<'a> {
             id: 0,
             types,
             storages: HashMap::with_capacity(alloc, 16)?,
-            entities: Vector::with_capacity(alloc, 16)?,
+            entities: PersistentVector::with_capacity(alloc, 16)?,
             alloc,
         })
     }
@@ -77,21 +74,19 @@ impl<'a> Archetype<'a> {
         let mut new_types = Vector::with_capacity(alloc, self.types.len())?;
         for t in self.types.iter() { new_types.push(*t)?; }
         
-        let mut new_entities = Vector::with_capacity(alloc, self.entities.len())?;
-        for e in self.entities.iter() { new_entities.push(*e)?; }
-
         Ok(Self {
             id: self.id,
             types: new_types,
             storages: new_storages,
-            entities: new_entities,
+            entities: self.entities.fork(alloc)?,
             alloc,
         })
     }
 
     pub fn add_storage<T: 'static + Clone + Send + Sync>(&mut self) -> Result<(), MemoryError> {
-        let vec: Vector<'_, T> = Vector::with_capacity(self.alloc, 16)?;
-        self.storages.insert(TypeId::of::<T>(), Box::new(ComponentVec { data: vec }))?;
+        let vec = PersistentVector::<T>::with_capacity(self.alloc, 16)?;
+        let storage = cap_unsize!(CapBox::new(self.alloc, ComponentVec { data: vec })?, dyn Storage<'a> + 'a);
+        self.storages.insert(TypeId::of::<T>(), storage)?;
         Ok(())
     }
     
@@ -102,7 +97,7 @@ impl<'a> Archetype<'a> {
     pub fn push_component<T: 'static + Clone + Send + Sync>(&mut self, component: T) {
         let tid = TypeId::of::<T>();
         if let Some(storage) = self.storages.get_mut(&tid) {
-            storage.push_any(Box::new(component));
+            storage.push_any(&mut Some(component));
         }
     }
     
@@ -113,7 +108,7 @@ impl<'a> Archetype<'a> {
         } else {
              None
         };
-        self.entities.swap_remove(row);
+        self.entities.swap_remove(row).expect("OOM in swap_remove");
         
         for storage in self.storages.values_mut() {
             storage.swap_remove(row);
@@ -132,9 +127,9 @@ struct EntityRecord {
 }
 
 pub struct SimWorld<'a> {
-    entities: Vector<'a, Option<EntityRecord>>,
-    generations: Vector<'a, u32>,
-    free_indices: Vector<'a, u32>,
+    entities: PersistentVector<'a, Option<EntityRecord>>,
+    generations: PersistentVector<'a, u32>,
+    free_indices: PersistentVector<'a, u32>,
     pub archetypes: Vector<'a, Archetype<'a>>,
     alloc: Allocator<'a>,
 }
@@ -142,9 +137,9 @@ pub struct SimWorld<'a> {
 impl<'a> SimWorld<'a> {
     pub fn new(alloc: Allocator<'a>) -> Result<Self, MemoryError> {
         Ok(Self {
-            entities: Vector::with_capacity(alloc, 256)?,
-            generations: Vector::with_capacity(alloc, 256)?,
-            free_indices: Vector::with_capacity(alloc, 64)?,
+            entities: PersistentVector::with_capacity(alloc, 256)?,
+            generations: PersistentVector::with_capacity(alloc, 256)?,
+            free_indices: PersistentVector::with_capacity(alloc, 64)?,
             archetypes: Vector::with_capacity(alloc, 16)?,
             alloc,
         })
@@ -152,20 +147,20 @@ impl<'a> SimWorld<'a> {
     
     pub fn with_capacity(alloc: Allocator<'a>, capacity: usize) -> Result<Self, MemoryError> {
         Ok(Self {
-            entities: Vector::with_capacity(alloc, capacity)?,
-            generations: Vector::with_capacity(alloc, capacity)?,
-            free_indices: Vector::with_capacity(alloc, 64)?,
+            entities: PersistentVector::with_capacity(alloc, capacity)?,
+            generations: PersistentVector::with_capacity(alloc, capacity)?,
+            free_indices: PersistentVector::with_capacity(alloc, 64)?,
             archetypes: Vector::with_capacity(alloc, 16)?,
             alloc,
         })
     }
     
     pub fn len(&self) -> usize {
-        self.entities.as_slice().iter().filter(|e| e.is_some()).count()
+        self.entities.iter().filter(|e| e.is_some()).count()
     }
     
     pub fn alloc_entity(&mut self) -> Result<EntityId, MemoryError> {
-        if let Some(idx) = self.free_indices.pop() {
+        if let Some(idx) = self.free_indices.pop()? {
             let gen = *self.generations.get(idx as usize).unwrap();
             Ok(EntityId::new(idx, gen))
         } else {
@@ -194,7 +189,7 @@ impl<'a> SimWorld<'a> {
         let arch = self.archetypes.get(arch_idx).unwrap();
         let row = arch.entities.len() - 1; 
         
-        if let Some(rec) = self.entities.get_mut(eid.index() as usize) {
+        if let Some(rec) = self.entities.get_mut(eid.index() as usize)? {
             *rec = Some(EntityRecord { archetype_idx: arch_idx, row });
         }
         Ok(eid)
@@ -214,10 +209,12 @@ impl<'a> SimWorld<'a> {
         Ok(idx)
     }
     
-    pub fn set_position(&mut self, id: EntityId, p: Vec3) {
-        if let Some(t) = self.get_component_mut::<Transform>(id) {
+    /// Does nothing for a dead entity. Fails only if copying a chunk shared with a fork runs out of memory.
+    pub fn set_position(&mut self, id: EntityId, p: Vec3) -> Result<(), MemoryError> {
+        if let Some(t) = self.get_component_mut::<Transform>(id)? {
             t.px = p.x; t.py = p.y; t.pz = p.z;
         }
+        Ok(())
     }
     
     pub fn get_position(&self, id: EntityId) -> Option<Vec3> {
@@ -239,30 +236,29 @@ impl<'a> SimWorld<'a> {
         }
     }
     
-    pub fn get_component_mut<T: 'static + Clone + Send + Sync>(&mut self, id: EntityId) -> Option<&mut T> {
-        let rec = self.entities.get(id.index() as usize)?.as_ref()?;
-        if *self.generations.get(id.index() as usize)? != id.generation() { return None; }
+    /// `Ok(None)` if the entity is dead or has no `T`. Writing through a fork copies the shared chunk
+    /// first, which is the only way this fails.
+    pub fn get_component_mut<T: 'static + Clone + Send + Sync>(&mut self, id: EntityId) -> Result<Option<&mut T>, MemoryError> {
+        let Some(rec) = self.entities.get(id.index() as usize).and_then(|r| r.as_ref()) else { return Ok(None) };
+        if self.generations.get(id.index() as usize) != Some(&id.generation()) { return Ok(None); }
         
-        let arch = self.archetypes.get_mut(rec.archetype_idx)?;
-        let storage = arch.storages.get_mut(&TypeId::of::<T>())?;
+        let Some(arch) = self.archetypes.get_mut(rec.archetype_idx) else { return Ok(None) };
+        let Some(storage) = arch.storages.get_mut(&TypeId::of::<T>()) else { return Ok(None) };
         
         if storage.element_type_id() == TypeId::of::<T>() {
              let vec_storage = unsafe { &mut *(storage.as_raw_mut() as *mut ComponentVec<T>) };
              vec_storage.data.get_mut(rec.row)
         } else {
-            None
+            Ok(None)
         }
     }
     
+    /// Snapshot the world. Cost scales with the number of chunks, not entities; chunks are shared
+    /// with `self` until one side writes to them.
     pub fn fork(&self, alloc: Allocator<'a>) -> Result<SimWorld<'a>, MemoryError> {
-        let mut new_entities = Vector::with_capacity(alloc, self.entities.len())?;
-        for e in self.entities.iter() { new_entities.push(*e)?; }
-        
-        let mut new_gens = Vector::with_capacity(alloc, self.generations.len())?;
-        for g in self.generations.iter() { new_gens.push(*g)?; }
-        
-        let mut new_free = Vector::with_capacity(alloc, self.free_indices.len())?;
-        for f in self.free_indices.iter() { new_free.push(*f)?; }
+        let new_entities = self.entities.fork(alloc)?;
+        let new_gens = self.generations.fork(alloc)?;
+        let new_free = self.free_indices.fork(alloc)?;
         
         let mut new_archetypes = Vector::with_capacity(alloc, self.archetypes.len())?;
         for a in self.archetypes.iter() { new_archetypes.push(a.fork(alloc)?)?; }
@@ -286,7 +282,7 @@ impl<'a> SimWorld<'a> {
                         let x = m.get("x").and_then(|v| v.as_f64()).unwrap_or(0.0) as f32;
                         let y = m.get("y").and_then(|v| v.as_f64()).unwrap_or(0.0) as f32;
                         let z = m.get("z").and_then(|v| v.as_f64()).unwrap_or(0.0) as f32;
-                        self.set_position(eid, Vec3::new(x, y, z));
+                        self.set_position(eid, Vec3::new(x, y, z)).map_err(|e| format!("{:?}", e))?;
                         Ok(Value::Null)
                     },
                     "get_pos" => {
@@ -345,7 +341,7 @@ impl<'a> SimWorld<'a> {
                 Command::Move(e, dp) => {
                     if let Some(pos) = self.get_position(*e) {
                          let p1 = Vec3 { x: pos.x + dp.x, y: pos.y + dp.y, z: pos.z + dp.z };
-                         self.set_position(*e, p1);
+                         self.set_position(*e, p1)?;
                     }
                 },
             }
@@ -8,5 +8,14 @@ fn main() {
     let mut world = SimWorld::with_capacity(a, 32).unwrap();
     let _e = world.spawn_transform(Vec3::new(0.0,0.0,0.0), Quat::identity(), Vec3::new(1.0,1.0,1.0)).unwrap();
     println!("{}", world.len());
+
+    let mut sys = SystemMemoryResource;
+    let sa = Allocator::new(&mut sys);
+    let mut big = SimWorld::with_capacity(sa, 1000).unwrap();
+    let ids: Vec<_> = (0..1000).map(|i| big.spawn_transform(Vec3::new(i as f32, 0.0, 0.0), Quat::identity(), Vec3::new(1.0,1.0,1.0)).unwrap()).collect();
+    let mut snap = big.fork(sa).unwrap();
+    snap.set_position(ids[10], Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+    assert_eq!((big.get_position(ids[10]).unwrap().x, snap.get_position(ids[10]).unwrap().x), (10.0, -1.0));
+    println!("fork {} orig={} snap={}", snap.len(), big.get_position(ids[10]).unwrap().x, snap.get_position(ids[10]).unwrap().x);
 }
 